@@ -0,0 +1,327 @@
+//! Attributes of paths, read from `.gitattributes` files, `.git/info/attributes` and
+//! `core.attributesFile`.
+//!
+//! Each line of an attributes file is a pattern, matched like ignore patterns and possibly
+//! double-quoted, followed by attributes: `name` sets the attribute, `-name` unsets it, `!name`
+//! makes it unspecified again and `name=value` gives it a value. For each attribute, the last
+//! matching line mentioning it decides, lines of deeper `.gitattributes` files coming last and
+//! those of `.git/info/attributes` last of all.
+//!
+//! `[attr]<name> <attributes>` lines define macros, attributes that set others when set. They
+//! are only allowed at the top of the work tree, and `binary` is always defined as
+//! `-diff -merge -text`.
+
+use std::collections::HashMap;
+
+use crate::ignore::Pattern;
+
+/// The state of an attribute for a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeState {
+    Set,
+    Unset,
+    Unspecified,
+    Value(String),
+}
+
+impl std::fmt::Display for AttributeState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Set => write!(f, "set"),
+            Self::Unset => write!(f, "unset"),
+            Self::Unspecified => write!(f, "unspecified"),
+            Self::Value(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+type Attributes = Vec<(String, AttributeState)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    pattern: Pattern,
+    attributes: Attributes,
+}
+
+/// A parsed line of an attributes file.
+enum Line {
+    Rule(Rule),
+    Macro(String, Attributes),
+}
+
+impl Line {
+    fn parse(base: &str, line: &str) -> Option<Self> {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (pattern, rest) = match line.strip_prefix('"') {
+            Some(quoted) => unquote(quoted)?,
+            None => {
+                let (pattern, rest) = line
+                    .split_once(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or((line, ""));
+                (pattern.to_string(), rest)
+            }
+        };
+        let attributes = parse_attributes(rest);
+
+        if let Some(name) = pattern.strip_prefix("[attr]") {
+            return is_valid_name(name).then(|| Self::Macro(name.to_string(), attributes));
+        }
+        // Negative patterns are meaningless for attributes, and ignored.
+        let pattern = Pattern::parse(base, &pattern).filter(|pattern| !pattern.negated)?;
+        Some(Self::Rule(Rule {
+            pattern,
+            attributes,
+        }))
+    }
+}
+
+/// Parse a C-style quoted pattern, whose opening quote is already stripped. Returns the pattern
+/// and the rest of the line, or `None` if the closing quote is missing.
+fn unquote(quoted: &str) -> Option<(String, &str)> {
+    let mut bytes = vec![];
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                let pattern = String::from_utf8_lossy(&bytes).into_owned();
+                return Some((pattern, &quoted[i + 1..]));
+            }
+            '\\' => {
+                let (_, escaped) = chars.next()?;
+                let byte = match escaped {
+                    'a' => 0x07,
+                    'b' => 0x08,
+                    'f' => 0x0c,
+                    'n' => b'\n',
+                    'r' => b'\r',
+                    't' => b'\t',
+                    'v' => 0x0b,
+                    '0'..='3' => {
+                        let digits: String = [
+                            Some(escaped),
+                            chars.next().map(|(_, c)| c),
+                            chars.next().map(|(_, c)| c),
+                        ]
+                        .into_iter()
+                        .collect::<Option<_>>()?;
+                        u8::from_str_radix(&digits, 8).ok()?
+                    }
+                    c => {
+                        bytes.extend_from_slice(c.to_string().as_bytes());
+                        continue;
+                    }
+                };
+                bytes.push(byte);
+            }
+            c => bytes.extend_from_slice(c.to_string().as_bytes()),
+        }
+    }
+    None
+}
+
+fn parse_attributes(words: &str) -> Attributes {
+    words
+        .split_ascii_whitespace()
+        .map(|word| match word.split_once('=') {
+            Some((name, value)) => (name, AttributeState::Value(value.to_string())),
+            None => match (word.strip_prefix('-'), word.strip_prefix('!')) {
+                (Some(name), _) => (name, AttributeState::Unset),
+                (_, Some(name)) => (name, AttributeState::Unspecified),
+                _ => (word, AttributeState::Set),
+            },
+        })
+        .filter(|(name, _)| is_valid_name(name))
+        .map(|(name, state)| (name.to_string(), state))
+        .collect()
+}
+
+/// Attribute names are made of letters, digits, `-`, `_` and `.`, not starting with `-`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.'))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeRules {
+    rules: Vec<Rule>,
+    macros: Vec<(String, Attributes)>,
+    /// Attribute names, in the order they first appeared.
+    names: Vec<String>,
+}
+
+impl Default for AttributeRules {
+    fn default() -> Self {
+        let mut rules = Self {
+            rules: vec![],
+            macros: vec![],
+            names: vec![],
+        };
+        rules.add_macro("binary".to_string(), parse_attributes("-diff -merge -text"));
+        rules
+    }
+}
+
+impl AttributeRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_names(&mut self, attributes: &[(String, AttributeState)]) {
+        for (name, _) in attributes {
+            if !self.names.contains(name) {
+                self.names.push(name.clone());
+            }
+        }
+    }
+
+    fn add_macro(&mut self, name: String, attributes: Attributes) {
+        self.add_names(&[(name.clone(), AttributeState::Set)]);
+        self.add_names(&attributes);
+        self.macros.push((name, attributes));
+    }
+
+    /// Add the lines of an attributes file found in the directory `base`, relative to the top
+    /// of the work tree. They take precedence over the lines already added. Macros defined
+    /// below the top of the work tree are ignored.
+    pub fn add_rules(&mut self, base: &str, content: &str) {
+        let base = match base {
+            "" => String::new(),
+            base => format!("{}/", base.trim_end_matches('/')),
+        };
+        for line in content.lines().filter_map(|line| Line::parse(&base, line)) {
+            match line {
+                Line::Rule(rule) => {
+                    self.add_names(&rule.attributes);
+                    self.rules.push(rule);
+                }
+                Line::Macro(name, attributes) if base.is_empty() => {
+                    self.add_macro(name, attributes)
+                }
+                Line::Macro(..) => {}
+            }
+        }
+    }
+
+    /// Record the states of `attributes` that aren't decided yet, expanding the macros set.
+    fn fill(&self, attributes: &Attributes, states: &mut HashMap<String, AttributeState>) {
+        for (name, state) in attributes.iter().rev() {
+            if states.contains_key(name) {
+                continue;
+            }
+            states.insert(name.clone(), state.clone());
+            if *state == AttributeState::Set {
+                if let Some((_, expansion)) = self.macros.iter().rev().find(|(m, _)| m == name) {
+                    self.fill(expansion, states);
+                }
+            }
+        }
+    }
+
+    /// The states of the attributes mentioned by the lines matching `path`.
+    fn states(&self, path: &str) -> HashMap<String, AttributeState> {
+        let mut states = HashMap::new();
+        for rule in self.rules.iter().rev() {
+            if rule.pattern.matches(path, false) {
+                self.fill(&rule.attributes, &mut states);
+            }
+        }
+        states
+    }
+
+    /// The state of the attribute `name` for the file at `path`, relative to the top of the
+    /// work tree.
+    pub fn get(&self, path: &str, name: &str) -> AttributeState {
+        self.states(path)
+            .remove(name)
+            .unwrap_or(AttributeState::Unspecified)
+    }
+
+    /// Every attribute of the file at `path` that isn't unspecified, in the order their names
+    /// first appeared.
+    pub fn all(&self, path: &str) -> Vec<(String, AttributeState)> {
+        let mut states = self.states(path);
+        self.names
+            .iter()
+            .filter_map(|name| match states.remove(name) {
+                None | Some(AttributeState::Unspecified) => None,
+                Some(state) => Some((name.clone(), state)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttributeRules, AttributeState};
+
+    #[test]
+    fn attribute_rules_should_let_later_and_deeper_lines_win() {
+        let mut rules = AttributeRules::new();
+        rules.add_rules("", "# comment\n* text=auto\n*.sh eol=lf\n*.png -text\n");
+        rules.add_rules("docs", "*.txt text eol=crlf\nold.png !text\n");
+
+        assert_eq!(
+            rules.get("a.c", "text"),
+            AttributeState::Value("auto".to_string())
+        );
+        assert_eq!(
+            rules.get("run.sh", "eol"),
+            AttributeState::Value("lf".to_string())
+        );
+        assert_eq!(rules.get("img/a.png", "text"), AttributeState::Unset);
+        assert_eq!(rules.get("docs/a.txt", "text"), AttributeState::Set);
+        assert_eq!(
+            rules.get("docs/a.txt", "eol"),
+            AttributeState::Value("crlf".to_string())
+        );
+        assert_eq!(rules.get("a.txt", "eol"), AttributeState::Unspecified);
+        assert_eq!(
+            rules.get("docs/old.png", "text"),
+            AttributeState::Unspecified
+        );
+    }
+
+    #[test]
+    fn attribute_rules_should_expand_macros_set_on_a_path() {
+        let mut rules = AttributeRules::new();
+        rules.add_rules(
+            "",
+            "[attr]mine foo -bar baz=1\n*.png binary\n*.c mine\n\"a b\" quoted\n",
+        );
+        rules.add_rules("sub", "[attr]ignored x\n*.c -foo ignored\n");
+
+        let all = |path| {
+            rules
+                .all(path)
+                .into_iter()
+                .map(|(name, state)| format!("{}: {}", name, state))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            all("a.png"),
+            vec!["binary: set", "diff: unset", "merge: unset", "text: unset"]
+        );
+        assert_eq!(
+            all("a.c"),
+            vec!["mine: set", "foo: set", "bar: unset", "baz: 1"]
+        );
+        assert_eq!(
+            all("sub/a.c"),
+            vec![
+                "mine: set",
+                "foo: unset",
+                "bar: unset",
+                "baz: 1",
+                "ignored: set"
+            ]
+        );
+        assert_eq!(all("a b"), vec!["quoted: set"]);
+        assert_eq!(rules.get("sub/x", "x"), AttributeState::Unspecified);
+    }
+}
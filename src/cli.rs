@@ -1,23 +1,230 @@
 use std::path::PathBuf;
 
 use anyhow::anyhow;
-use clap::{command, Arg, ArgAction, Command as ClapCommand};
+use clap::{command, Arg, ArgAction, ArgGroup, ArgMatches, Command as ClapCommand};
 
-use crate::{error::ParseArgumentsError, git_object::Type};
+use crate::{
+    diff::{rename, CopyDetection, OutputFormat, RenameOptions},
+    error::ParseArgumentsError,
+    git_object::Type,
+    ref_format::{RefFilter, SortKey},
+    ref_name::RefNameOptions,
+    repository::ref_backend::RefStorage,
+};
 
 type Sha1 = String;
 
 #[derive(Debug)]
 pub enum TagSubCommand {
-    ListTags,
-    CreateTagObject { name: String, object: String },
-    CreateLightweightTag { name: String, object: String },
+    ListTags {
+        /// The `for-each-ref` format of each line, the tag name by default.
+        format: Option<String>,
+        /// The keys to sort on, the last one first.
+        sort: Vec<SortKey>,
+        filter: RefFilter,
+    },
+    CreateTagObject {
+        name: String,
+        object: String,
+    },
+    CreateLightweightTag {
+        name: String,
+        object: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum BranchSubCommand {
+    List {
+        /// List the remote-tracking branches too.
+        all: bool,
+        verbose: bool,
+        /// Only list the branches matching one of these globs.
+        patterns: Vec<String>,
+        /// Only list the branches that contain this commit.
+        contains: Option<String>,
+        /// Only list the branches merged into this commit.
+        merged: Option<String>,
+        /// The `for-each-ref` format of each line.
+        format: Option<String>,
+        /// The keys to sort on, the last one first.
+        sort: Vec<SortKey>,
+    },
+    /// Create the branch `name` at `start`, `HEAD` by default.
+    Create {
+        name: String,
+        start: Option<String>,
+        force: bool,
+    },
+    Delete {
+        names: Vec<String>,
+        /// Delete branches that aren't merged too.
+        force: bool,
+    },
+    /// Rename the branch `old`, the current one by default, to `new`.
+    Rename {
+        old: Option<String>,
+        new: String,
+        force: bool,
+    },
+    /// Make `branch`, the current one by default, track `upstream`.
+    SetUpstream {
+        upstream: String,
+        branch: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+pub enum StashSubCommand {
+    Push {
+        message: Option<String>,
+        include_untracked: bool,
+    },
+    Apply {
+        stash: Option<String>,
+        index: bool,
+    },
+    /// Apply the stash, then drop it unless it conflicted.
+    Pop {
+        stash: Option<String>,
+        index: bool,
+    },
+    List,
+    Show {
+        stash: Option<String>,
+        patch: bool,
+    },
+    Drop {
+        stash: Option<String>,
+    },
+    Clear,
+}
+
+#[derive(Debug)]
+pub enum ReflogSubCommand {
+    /// List the entries of the reflog of `reference`, `HEAD` by default, latest first.
+    Show { reference: Option<String> },
+    Expire {
+        /// Override `gc.reflogExpire`.
+        expire: Option<String>,
+        /// Override `gc.reflogExpireUnreachable`.
+        expire_unreachable: Option<String>,
+        /// Expire the entries of every reflog rather than those of `refs`.
+        all: bool,
+        dry_run: bool,
+        refs: Vec<String>,
+    },
+    /// Remove entries given as `<ref>@{<n>}`.
+    Delete { entries: Vec<String> },
+}
+
+#[derive(Debug)]
+pub enum SparseCheckoutSubCommand {
+    /// Turn sparse checkout on, `cone` being `None` to keep the current mode.
+    Init {
+        cone: Option<bool>,
+    },
+    /// Replace the patterns, directories in cone mode.
+    Set {
+        cone: Option<bool>,
+        patterns: Vec<String>,
+        stdin: bool,
+    },
+    Add {
+        patterns: Vec<String>,
+        stdin: bool,
+    },
+    List,
+    /// Apply the patterns again, to files that were left because of local changes.
+    Reapply,
+    Disable,
+}
+
+#[derive(Debug)]
+pub enum WorktreeSubCommand {
+    /// Create a work tree at `path` with `commit` checked out, on a new branch `branch` if
+    /// given.
+    Add {
+        path: String,
+        commit: Option<String>,
+        branch: Option<String>,
+        detach: bool,
+    },
+    List {
+        porcelain: bool,
+    },
+    Remove {
+        worktree: String,
+        /// How many times `-f` was given, twice to remove a locked work tree.
+        force: u8,
+    },
+    Lock {
+        worktree: String,
+        reason: Option<String>,
+    },
+    Unlock {
+        worktree: String,
+    },
+    /// Delete the administrative files of work trees that are gone.
+    Prune {
+        dry_run: bool,
+        verbose: bool,
+    },
+}
+
+/// When to color the output of porcelain commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorWhen {
+    Always,
+    Never,
+    /// Only if the output is a terminal.
+    Auto,
+}
+
+impl std::str::FromStr for ColorWhen {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "always" | "true" | "yes" | "on" => Ok(Self::Always),
+            "never" | "false" | "no" | "off" => Ok(Self::Never),
+            "auto" => Ok(Self::Auto),
+            _ => Err(anyhow!("Invalid color setting {}", s)),
+        }
+    }
+}
+
+/// What `reset` updates besides the current branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Only move the branch.
+    Soft,
+    /// Also reset the index.
+    Mixed,
+    /// Also reset the index and the work tree, discarding local changes.
+    Hard,
+    /// Reset the index, and update the files that differ between the commits if they have no
+    /// local changes.
+    Keep,
+}
+
+impl std::fmt::Display for ResetMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ResetMode::Soft => "soft",
+            ResetMode::Mixed => "mixed",
+            ResetMode::Hard => "hard",
+            ResetMode::Keep => "keep",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug)]
 pub enum Command {
     Init {
         path: String,
+        ref_storage: RefStorage,
     },
     CatFile {
         object_type: Type,
@@ -27,6 +234,8 @@ pub enum Command {
         object_type: Type,
         file_path: PathBuf,
         write: bool,
+        /// Hash the file as is, without the conversions of work tree files.
+        no_filters: bool,
     },
     Log {
         commit: Sha1,
@@ -38,18 +247,370 @@ pub enum Command {
     },
     Checkout {
         commit: Sha1,
-        path: String,
+        /// Extract the commit into this empty directory instead of switching to it.
+        path: Option<String>,
+        detach: bool,
+    },
+    Switch {
+        branch: String,
+        detach: bool,
+    },
+    Branch {
+        command: BranchSubCommand,
     },
     ShowRef,
+    SymbolicRef {
+        /// Fail silently if `name` isn't a symbolic ref.
+        quiet: bool,
+        /// Print the target without its `refs/heads/` or similar prefix.
+        short: bool,
+        /// The reason of the change, recorded in the reflog.
+        message: Option<String>,
+        name: String,
+        /// The ref to point `name` to, or `None` to read `name`.
+        target: Option<String>,
+    },
+    ForEachRef {
+        /// The format of each line, `%(objectname) %(objecttype)\t%(refname)` by default.
+        format: Option<String>,
+        /// The keys to sort on, the last one first.
+        sort: Vec<SortKey>,
+        /// Only show the first refs.
+        count: Option<usize>,
+        filter: RefFilter,
+    },
+    RevParse {
+        /// Fail unless exactly one revision is given and it names an object.
+        verify: bool,
+        /// With `verify`, fail silently.
+        quiet: bool,
+        /// Print hashes abbreviated to at least this many digits, implies `verify`.
+        short: Option<usize>,
+        /// Print the short name of the ref each revision names.
+        abbrev_ref: bool,
+        /// Print the full name of the ref each revision names.
+        symbolic_full_name: bool,
+        revisions: Vec<String>,
+    },
+    UpdateRef {
+        /// Overwrite symbolic refs rather than the refs they point to.
+        no_deref: bool,
+        /// Read a transaction of updates from the standard input, one per line.
+        stdin: bool,
+        delete: bool,
+        /// The reason of the update, recorded in the reflog.
+        message: Option<String>,
+        /// `<ref> <new> [<old>]`, or `<ref> [<old>]` to delete.
+        args: Vec<String>,
+    },
+    CheckRefFormat {
+        /// Check `name` as a branch name, expanding `@{-<n>}`, and print the branch.
+        branch: bool,
+        options: RefNameOptions,
+        name: String,
+    },
+    PackRefs {
+        /// Pack every ref rather than the tags and the refs already packed.
+        all: bool,
+        /// Delete the loose files of the refs packed.
+        prune: bool,
+    },
     Tag {
         command: TagSubCommand,
     },
+    DiffTree {
+        old_tree: String,
+        new_tree: String,
+        recursive: bool,
+        format: OutputFormat,
+        renames: Option<RenameOptions>,
+    },
+    Diff {
+        cached: bool,
+        /// Revisions and paths, to be told apart once the repository is known.
+        args: Vec<String>,
+        /// Paths given after `--`.
+        paths: Vec<String>,
+        format: OutputFormat,
+        renames: Option<RenameOptions>,
+        no_renames: bool,
+        color: Option<ColorWhen>,
+    },
+    Restore {
+        /// Tree-ish to restore from, the index or `HEAD` by default.
+        source: Option<String>,
+        staged: bool,
+        worktree: bool,
+        paths: Vec<String>,
+    },
+    Stash {
+        command: StashSubCommand,
+    },
+    Reflog {
+        command: ReflogSubCommand,
+    },
+    Clean {
+        dry_run: bool,
+        /// How many times `-f` was given, twice to remove nested repositories.
+        force: u8,
+        directories: bool,
+        /// Remove ignored files as well (`-x`).
+        ignored: bool,
+        /// Remove ignored files only (`-X`).
+        ignored_only: bool,
+        paths: Vec<String>,
+    },
+    SparseCheckout {
+        command: SparseCheckoutSubCommand,
+    },
+    Worktree {
+        command: WorktreeSubCommand,
+    },
+    UpdateIndex {
+        /// Refresh the stat data of the entries whose file is unchanged.
+        refresh: bool,
+        /// Don't fail when some files need an update.
+        quiet: bool,
+    },
+    CheckAttr {
+        all: bool,
+        /// Attributes and paths, unless paths are given after `--`.
+        args: Vec<String>,
+        /// Paths given after `--`.
+        paths: Vec<String>,
+    },
+    Reset {
+        /// `None` unless a mode is given explicitly, `--mixed` being the default.
+        mode: Option<ResetMode>,
+        quiet: bool,
+        /// A commit and paths, to be told apart once the repository is known.
+        args: Vec<String>,
+        /// Paths given after `--`.
+        paths: Vec<String>,
+    },
+}
+
+/// `--format` and `--sort` of the ref listings.
+fn ref_format_args() -> Vec<Arg> {
+    vec![
+        Arg::new("format")
+            .long("format")
+            .value_name("FORMAT")
+            .help("Show each ref in FORMAT, such as '%(refname:short) %(subject)'"),
+        Arg::new("sort")
+            .long("sort")
+            .value_name("KEY")
+            .action(ArgAction::Append)
+            .help("Sort on KEY, such as -creatordate or version:refname; the last key comes first"),
+    ]
+}
+
+/// `--points-at`, `--contains` and `--merged` of the ref listings.
+fn ref_filter_args() -> Vec<Arg> {
+    vec![
+        Arg::new("points_at")
+            .long("points-at")
+            .value_name("OBJECT")
+            .help("Only list the refs pointing to OBJECT, directly or through tags"),
+        Arg::new("contains")
+            .long("contains")
+            .value_name("COMMIT")
+            .num_args(0..=1)
+            .default_missing_value("HEAD")
+            .help("Only list the refs that contain the commit, HEAD by default"),
+        Arg::new("merged")
+            .long("merged")
+            .value_name("COMMIT")
+            .num_args(0..=1)
+            .default_missing_value("HEAD")
+            .help("Only list the refs merged into the commit, HEAD by default"),
+    ]
+}
+
+/// Build the filter of a ref listing out of `patterns` and the arguments of
+/// [`ref_filter_args`].
+fn parse_ref_filter(subcommand: &clap::ArgMatches, patterns: Vec<String>) -> RefFilter {
+    RefFilter {
+        patterns,
+        points_at: subcommand.get_one::<String>("points_at").cloned(),
+        merged: subcommand.get_one::<String>("merged").cloned(),
+        contains: subcommand.get_one::<String>("contains").cloned(),
+    }
+}
+
+/// The keys of `--sort`, in the order given.
+fn parse_sort_keys(subcommand: &clap::ArgMatches) -> Result<Vec<SortKey>, ParseArgumentsError> {
+    let keys = subcommand.get_many::<String>("sort").unwrap_or_default();
+    let keys: Result<Vec<SortKey>, _> = keys.map(|key| SortKey::parse(key)).collect();
+    Ok(keys.map_err(anyhow::Error::from)?)
+}
+
+fn rename_detection_args() -> Vec<Arg> {
+    vec![
+        Arg::new("find_renames")
+            .short('M')
+            .long("find-renames")
+            .value_name("N")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("")
+            .help("Detect renames, optionally setting the similarity threshold"),
+        Arg::new("find_copies")
+            .short('C')
+            .long("find-copies")
+            .value_name("N")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("")
+            .help("Detect copies as well as renames"),
+        Arg::new("find_copies_harder")
+            .long("find-copies-harder")
+            .help("Consider unmodified files as copy sources too")
+            .action(ArgAction::SetTrue),
+        Arg::new("rename_limit")
+            .short('l')
+            .value_name("NUM")
+            .value_parser(clap::value_parser!(usize))
+            .help("Skip inexact rename detection above NUM² candidate pairs"),
+    ]
+}
+
+/// Build the rename detection options out of `-M`, `-C`, `--find-copies-harder` and `-l`.
+/// Returns `None` if rename detection wasn't asked for.
+fn parse_rename_detection_args(
+    subcommand: &clap::ArgMatches,
+) -> Result<Option<RenameOptions>, ParseArgumentsError> {
+    let renames = subcommand.get_one::<String>("find_renames");
+    let copies = subcommand.get_one::<String>("find_copies");
+    let copies_harder = subcommand.get_flag("find_copies_harder");
+    if renames.is_none() && copies.is_none() && !copies_harder {
+        return Ok(None);
+    }
+
+    // Like git, the last of -M and -C decides whether copies are detected.
+    let copies_last = match (
+        subcommand.index_of("find_copies"),
+        subcommand.index_of("find_renames"),
+    ) {
+        (Some(copies), Some(renames)) => copies > renames,
+        (copies, _) => copies.is_some(),
+    };
+
+    let mut options = RenameOptions::default();
+    let score = if copies_last {
+        copies
+    } else {
+        renames.or(copies)
+    };
+    if let Some(score) = score {
+        options.minimum_score = rename::parse_score(score)
+            .ok_or_else(|| anyhow!("Invalid similarity score {}", score))?;
+    }
+    options.copies = match (copies_harder, copies_last) {
+        (true, _) => CopyDetection::Harder,
+        (false, true) => CopyDetection::Modified,
+        (false, false) => CopyDetection::Off,
+    };
+    if let Some(limit) = subcommand.get_one::<usize>("rename_limit") {
+        options.rename_limit = Some(*limit);
+    }
+
+    Ok(Some(options))
+}
+
+/// Options whose value may be glued to the short flag (`-M50%`) but never follows it as a
+/// separate argument, so they're rewritten to their `--long=value` form before parsing.
+const ATTACHED_VALUE_OPTIONS: [(&str, &str); 2] =
+    [("-M", "--find-renames"), ("-C", "--find-copies")];
+
+fn normalize_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    args.map(|arg| {
+        ATTACHED_VALUE_OPTIONS
+            .iter()
+            .find_map(|(short, long)| {
+                arg.strip_prefix(short)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| format!("{}={}", long, value))
+            })
+            .unwrap_or(arg)
+    })
+    .collect()
+}
+
+/// The options of `stash push`, also accepted by `stash` alone.
+fn stash_push_args() -> [Arg; 2] {
+    [
+        Arg::new("message")
+            .short('m')
+            .long("message")
+            .value_name("MESSAGE")
+            .help("Describe the stash with this message"),
+        Arg::new("include_untracked")
+            .short('u')
+            .long("include-untracked")
+            .help("Stash untracked files too, and remove them")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+fn sparse_checkout_cone_args() -> [Arg; 2] {
+    [
+        Arg::new("cone")
+            .long("cone")
+            .help("Take directories instead of patterns (the default)")
+            .action(ArgAction::SetTrue),
+        Arg::new("no_cone")
+            .long("no-cone")
+            .help("Take gitignore-like patterns")
+            .conflicts_with("cone")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+fn sparse_checkout_pattern_args() -> [Arg; 2] {
+    [
+        Arg::new("patterns")
+            .value_name("PATTERN")
+            .num_args(0..)
+            .help("Directories in cone mode, patterns otherwise"),
+        Arg::new("stdin")
+            .long("stdin")
+            .help("Read the patterns from the standard input, one per line")
+            .action(ArgAction::SetTrue),
+    ]
+}
+
+fn reflog_ref_arg() -> Arg {
+    Arg::new("ref")
+        .value_name("REF")
+        .help("The ref whose reflog to show, HEAD by default")
+}
+
+fn stash_arg() -> Arg {
+    Arg::new("stash")
+        .value_name("STASH")
+        .help("The stash, stash@{0} by default")
+}
+
+fn stash_index_arg() -> Arg {
+    Arg::new("index")
+        .long("index")
+        .help("Restore the staged changes as well")
+        .action(ArgAction::SetTrue)
 }
 
 pub fn parse_args() -> Result<Command, ParseArgumentsError> {
     let matches = command!()
         .subcommand(
-            ClapCommand::new("init").arg(Arg::new("path").value_name("PATH").required(true)),
+            ClapCommand::new("init")
+                .arg(Arg::new("path").value_name("PATH").required(true))
+                .arg(
+                    Arg::new("ref-format")
+                        .long("ref-format")
+                        .value_name("FORMAT")
+                        .value_parser(clap::value_parser!(RefStorage))
+                        .help("How to store the refs: files (the default) or reftable"),
+                ),
         )
         .subcommand(
             ClapCommand::new("cat-file")
@@ -72,6 +633,12 @@ pub fn parse_args() -> Result<Command, ParseArgumentsError> {
                         .long("type")
                         .default_value("blob"),
                 )
+                .arg(
+                    Arg::new("no_filters")
+                        .long("no-filters")
+                        .help("Hash the file as is, ignoring line ending conversions")
+                        .action(ArgAction::SetTrue),
+                )
                 .arg(Arg::new("file").value_name("FILE").required(true)),
         )
         .subcommand(
@@ -110,19 +677,334 @@ pub fn parse_args() -> Result<Command, ParseArgumentsError> {
         )
         .subcommand(
             ClapCommand::new("checkout")
-                .about("Checkout a commit inside a directory")
+                .about("Switch branches, or checkout a commit inside a directory")
                 .arg(
                     Arg::new("commit")
                         .value_name("COMMIT")
-                        .help("The commit or tree to checkout"),
+                        .required(true)
+                        .help("The branch, commit or tree to checkout"),
                 )
                 .arg(
                     Arg::new("path")
                         .value_name("PATH")
-                        .help("An EMPTY directory to checkout on"),
+                        .help("An EMPTY directory to checkout on, leaving HEAD alone"),
+                )
+                .arg(
+                    Arg::new("detach")
+                        .long("detach")
+                        .help("Detach HEAD at the commit, even if it's a branch")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("switch")
+                .about("Switch branches")
+                .arg(
+                    Arg::new("branch")
+                        .value_name("BRANCH")
+                        .required(true)
+                        .help("The branch to switch to, or a commit with --detach"),
+                )
+                .arg(
+                    Arg::new("detach")
+                        .short('d')
+                        .long("detach")
+                        .help("Detach HEAD at the commit")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("branch")
+                .about("List, create, delete or rename branches")
+                .arg(
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("List the remote-tracking branches too"),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue)
+                        .help("Show the commit of each branch and how it compares to its upstream"),
+                )
+                .arg(
+                    Arg::new("list")
+                        .short('l')
+                        .long("list")
+                        .action(ArgAction::SetTrue)
+                        .help("List the branches, those matching the patterns if given"),
+                )
+                .arg(
+                    Arg::new("delete")
+                        .short('d')
+                        .long("delete")
+                        .action(ArgAction::SetTrue)
+                        .help("Delete branches, which must be merged"),
+                )
+                .arg(
+                    Arg::new("force_delete")
+                        .short('D')
+                        .action(ArgAction::SetTrue)
+                        .help("Delete branches, merged or not"),
+                )
+                .arg(
+                    Arg::new("move")
+                        .short('m')
+                        .long("move")
+                        .action(ArgAction::SetTrue)
+                        .help("Rename a branch, with its reflog and configuration"),
+                )
+                .arg(
+                    Arg::new("force_move")
+                        .short('M')
+                        .action(ArgAction::SetTrue)
+                        .help("Rename a branch even if the new name exists"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Move an existing branch, or delete or rename with -d or -m"),
+                )
+                .arg(
+                    Arg::new("set_upstream_to")
+                        .short('u')
+                        .long("set-upstream-to")
+                        .value_name("UPSTREAM")
+                        .help("Make the branch track UPSTREAM"),
+                )
+                .arg(
+                    Arg::new("contains")
+                        .long("contains")
+                        .value_name("COMMIT")
+                        .num_args(0..=1)
+                        .default_missing_value("HEAD")
+                        .help("Only list the branches that contain the commit, HEAD by default"),
+                )
+                .arg(
+                    Arg::new("merged")
+                        .long("merged")
+                        .value_name("COMMIT")
+                        .num_args(0..=1)
+                        .default_missing_value("HEAD")
+                        .help("Only list the branches merged into the commit, HEAD by default"),
+                )
+                .args(ref_format_args())
+                .arg(
+                    Arg::new("args")
+                        .value_name("ARGS")
+                        .num_args(0..)
+                        .help("<name> [<start>], the branches to delete or rename, or patterns"),
                 ),
         )
         .subcommand(ClapCommand::new("show-ref").about("List references."))
+        .subcommand(
+            ClapCommand::new("symbolic-ref")
+                .about("Read or change what a symbolic ref such as HEAD points to")
+                .arg(
+                    Arg::new("quiet")
+                        .short('q')
+                        .long("quiet")
+                        .action(ArgAction::SetTrue)
+                        .help("Don't report an error if the ref isn't symbolic, only fail"),
+                )
+                .arg(
+                    Arg::new("short")
+                        .long("short")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the target shortened, such as master for refs/heads/master"),
+                )
+                .arg(
+                    Arg::new("message")
+                        .short('m')
+                        .value_name("REASON")
+                        .help("Record the reason of the change in the reflog"),
+                )
+                .arg(
+                    Arg::new("name")
+                        .value_name("NAME")
+                        .required(true)
+                        .help("The symbolic ref, such as HEAD"),
+                )
+                .arg(
+                    Arg::new("target")
+                        .value_name("REF")
+                        .help("The ref to point NAME to"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("for-each-ref")
+                .about("List refs in a format of your choosing, sorted and filtered")
+                .args(ref_format_args())
+                .args(ref_filter_args())
+                .arg(
+                    Arg::new("count")
+                        .long("count")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Only show the first N refs"),
+                )
+                .arg(
+                    Arg::new("patterns")
+                        .value_name("PATTERN")
+                        .num_args(0..)
+                        .help("Only list the refs matching a glob or below a prefix, such as refs/tags"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("rev-parse")
+                .about("Resolve revisions, such as HEAD~2 or main:src/lib.rs, to object names")
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .action(ArgAction::SetTrue)
+                        .help("Check that exactly one revision is given and names an object"),
+                )
+                .arg(
+                    Arg::new("quiet")
+                        .short('q')
+                        .long("quiet")
+                        .action(ArgAction::SetTrue)
+                        .help("With --verify, only fail rather than report an error"),
+                )
+                .arg(
+                    Arg::new("short")
+                        .long("short")
+                        .value_name("LENGTH")
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .default_missing_value("7")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("Abbreviate the object name, keeping it unique; implies --verify"),
+                )
+                .arg(
+                    Arg::new("abbrev_ref")
+                        .long("abbrev-ref")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the short name of the ref, such as master"),
+                )
+                .arg(
+                    Arg::new("symbolic_full_name")
+                        .long("symbolic-full-name")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the full name of the ref, such as refs/heads/master"),
+                )
+                .arg(
+                    Arg::new("revisions")
+                        .value_name("REVISION")
+                        .num_args(0..)
+                        .help("The revisions to resolve"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("update-ref")
+                .about("Update a ref safely, checking its old value under a lock")
+                .arg(
+                    Arg::new("delete")
+                        .short('d')
+                        .action(ArgAction::SetTrue)
+                        .help("Delete the ref"),
+                )
+                .arg(
+                    Arg::new("no_deref")
+                        .long("no-deref")
+                        .action(ArgAction::SetTrue)
+                        .help("Overwrite symbolic refs rather than the refs they point to"),
+                )
+                .arg(
+                    Arg::new("message")
+                        .short('m')
+                        .value_name("REASON")
+                        .help("Record the reason of the update in the reflog"),
+                )
+                .arg(
+                    Arg::new("stdin")
+                        .long("stdin")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["delete", "args"])
+                        .help("Read updates from the standard input, made all or none"),
+                )
+                .arg(
+                    Arg::new("args")
+                        .value_name("ARGS")
+                        .num_args(1..=3)
+                        .required_unless_present("stdin")
+                        .help("<ref> <new> [<old>], or <ref> [<old>] with -d"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("pack-refs")
+                .about("Pack refs into .git/packed-refs")
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("Pack every ref, not only the tags and the refs already packed"),
+                )
+                .arg(
+                    Arg::new("prune")
+                        .long("prune")
+                        .action(ArgAction::SetTrue)
+                        .overrides_with("no_prune")
+                        .help("Delete the loose refs once packed, the default"),
+                )
+                .arg(
+                    Arg::new("no_prune")
+                        .long("no-prune")
+                        .action(ArgAction::SetTrue)
+                        .overrides_with("prune")
+                        .help("Keep the loose refs once packed"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("check-ref-format")
+                .about("Check that a name is a valid ref name, and exit with 1 if it isn't")
+                .arg(
+                    Arg::new("branch")
+                        .long("branch")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["allow_onelevel", "refspec_pattern", "normalize"])
+                        .help("Check a branch name, such as @{-1}, and print the branch it names"),
+                )
+                .arg(
+                    Arg::new("normalize")
+                        .long("normalize")
+                        .visible_alias("print")
+                        .action(ArgAction::SetTrue)
+                        .help("Collapse consecutive slashes, drop leading ones and print the name"),
+                )
+                .arg(
+                    Arg::new("allow_onelevel")
+                        .long("allow-onelevel")
+                        .action(ArgAction::SetTrue)
+                        .overrides_with("no_allow_onelevel")
+                        .help("Accept names of a single component, such as HEAD"),
+                )
+                .arg(
+                    Arg::new("no_allow_onelevel")
+                        .long("no-allow-onelevel")
+                        .action(ArgAction::SetTrue)
+                        .overrides_with("allow_onelevel")
+                        .help("Require at least two components, the default"),
+                )
+                .arg(
+                    Arg::new("refspec_pattern")
+                        .long("refspec-pattern")
+                        .action(ArgAction::SetTrue)
+                        .help("Accept one * in the name, as in a refspec"),
+                )
+                .arg(
+                    Arg::new("name")
+                        .value_name("REFNAME")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                        .help("The name to check"),
+                ),
+        )
         .subcommand(
             ClapCommand::new("tag")
                 .about("List and create tags")
@@ -143,13 +1025,545 @@ pub fn parse_args() -> Result<Command, ParseArgumentsError> {
                         .requires("name")
                         .help("Whether to create a tag object")
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("list")
+                        .short('l')
+                        .long("list")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("tag_object")
+                        .help("List the tags, those matching NAME and OBJECT as patterns if given"),
+                )
+                .args(ref_format_args())
+                .args(ref_filter_args()),
+        )
+        .subcommand(
+            ClapCommand::new("diff-tree")
+                .about("Compares the content and mode of blobs found via two tree objects")
+                .arg(
+                    Arg::new("recursive")
+                        .short('r')
+                        .help("Recurse into sub-trees")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("name_status")
+                        .long("name-status")
+                        .help("Show only names and status of changed files")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("patch")
+                        .short('p')
+                        .long("patch")
+                        .help("Generate patch")
+                        .conflicts_with("name_status")
+                        .action(ArgAction::SetTrue),
+                )
+                .args(rename_detection_args())
+                .arg(
+                    Arg::new("old_tree")
+                        .value_name("TREE-ISH")
+                        .required(true)
+                        .help("The tree-ish to compare from"),
+                )
+                .arg(
+                    Arg::new("new_tree")
+                        .value_name("TREE-ISH")
+                        .required(true)
+                        .help("The tree-ish to compare to"),
                 ),
         )
-        .get_matches();
+        .subcommand(
+            ClapCommand::new("diff")
+                .about("Show changes between the working tree, the index and commits")
+                .arg(
+                    Arg::new("cached")
+                        .long("cached")
+                        .visible_alias("staged")
+                        .help("Compare the index with HEAD or the given commit")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("patch")
+                        .short('p')
+                        .long("patch")
+                        .help("Generate patch (the default)")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("stat")
+                        .long("stat")
+                        .help("Show a histogram of changed lines per file")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("numstat")
+                        .long("numstat")
+                        .help("Show the number of added and deleted lines per file")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("shortstat")
+                        .long("shortstat")
+                        .help("Show only the total of changed files and lines")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("name_only")
+                        .long("name-only")
+                        .help("Show only names of changed files")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("name_status")
+                        .long("name-status")
+                        .help("Show only names and status of changed files")
+                        .action(ArgAction::SetTrue),
+                )
+                .group(
+                    ArgGroup::new("format")
+                        .args([
+                            "patch",
+                            "stat",
+                            "numstat",
+                            "shortstat",
+                            "name_only",
+                            "name_status",
+                        ])
+                        .multiple(false),
+                )
+                .args(rename_detection_args())
+                .arg(
+                    Arg::new("no_renames")
+                        .long("no-renames")
+                        .help("Turn off rename detection")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("color")
+                        .long("color")
+                        .value_name("WHEN")
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .default_missing_value("always")
+                        .value_parser(clap::value_parser!(ColorWhen))
+                        .help("Color the output: always, never or auto"),
+                )
+                .arg(
+                    Arg::new("no_color")
+                        .long("no-color")
+                        .help("Turn off colored output")
+                        .overrides_with("color")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("args")
+                        .value_name("COMMIT")
+                        .num_args(0..)
+                        .help("Commits, a range A..B or A...B, or paths"),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .num_args(0..)
+                        .last(true)
+                        .help("Limit the diff to these paths"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("reset")
+                .about("Reset the current branch, and possibly the index and the work tree")
+                .arg(
+                    Arg::new("soft")
+                        .long("soft")
+                        .help("Only move the current branch")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("mixed")
+                        .long("mixed")
+                        .help("Move the current branch and reset the index (the default)")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("hard")
+                        .long("hard")
+                        .help("Reset the index and the work tree, discarding local changes")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("keep")
+                        .long("keep")
+                        .help("Reset the index and update files that differ, keeping local changes")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("quiet")
+                        .short('q')
+                        .long("quiet")
+                        .help("Only report errors")
+                        .action(ArgAction::SetTrue),
+                )
+                .group(
+                    ArgGroup::new("mode")
+                        .args(["soft", "mixed", "hard", "keep"])
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::new("args")
+                        .value_name("COMMIT")
+                        .num_args(0..)
+                        .help("The commit to reset to, HEAD by default, followed by paths"),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .num_args(0..)
+                        .last(true)
+                        .help("Only reset the index entries of these paths"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("restore")
+                .about("Restore work tree files, or index entries with --staged")
+                .arg(
+                    Arg::new("source")
+                        .short('s')
+                        .long("source")
+                        .value_name("TREE")
+                        .help("Restore from this tree-ish instead of the index, or HEAD with --staged"),
+                )
+                .arg(
+                    Arg::new("staged")
+                        .short('S')
+                        .long("staged")
+                        .help("Restore the index")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("worktree")
+                        .short('W')
+                        .long("worktree")
+                        .help("Restore the work tree (the default without --staged)")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .num_args(1..)
+                        .required(true)
+                        .help("The paths to restore"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("clean")
+                .about("Remove untracked files from the work tree")
+                .arg(
+                    Arg::new("dry_run")
+                        .short('n')
+                        .long("dry-run")
+                        .help("Only show what would be removed")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("force")
+                        .short('f')
+                        .long("force")
+                        .help("Remove the files, twice to remove nested repositories too")
+                        .action(ArgAction::Count),
+                )
+                .arg(
+                    Arg::new("directories")
+                        .short('d')
+                        .help("Remove untracked directories too")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("ignored")
+                        .short('x')
+                        .help("Don't use the ignore rules, removing ignored files too")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("ignored_only")
+                        .short('X')
+                        .help("Remove ignored files only")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .num_args(0..)
+                        .help("Only clean these paths"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("update-index")
+                .about("Register file contents in the index")
+                .arg(
+                    Arg::new("refresh")
+                        .long("refresh")
+                        .action(ArgAction::SetTrue)
+                        .help("Refresh the stat data of unchanged files"),
+                )
+                .arg(
+                    Arg::new("quiet")
+                        .short('q')
+                        .action(ArgAction::SetTrue)
+                        .help("Don't fail when files need an update"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("check-attr")
+                .about("Show the attributes of paths")
+                .arg(
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .help("Show every attribute that is specified")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("args")
+                        .value_name("ATTRIBUTE")
+                        .num_args(0..)
+                        .help("The attributes to show, followed by paths unless given after --"),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .num_args(0..)
+                        .last(true)
+                        .help("The paths to show attributes of"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("sparse-checkout")
+                .about("Only check out some of the files of the work tree")
+                .subcommand_required(true)
+                .subcommand(
+                    ClapCommand::new("init")
+                        .about("Turn sparse checkout on, with the files at the top only")
+                        .args(sparse_checkout_cone_args()),
+                )
+                .subcommand(
+                    ClapCommand::new("set")
+                        .about("Check out the files matching these patterns only")
+                        .args(sparse_checkout_cone_args())
+                        .args(sparse_checkout_pattern_args()),
+                )
+                .subcommand(
+                    ClapCommand::new("add")
+                        .about("Check out the files matching these patterns too")
+                        .args(sparse_checkout_pattern_args()),
+                )
+                .subcommand(ClapCommand::new("list").about("List the patterns"))
+                .subcommand(
+                    ClapCommand::new("reapply")
+                        .about("Apply the patterns to files left because of local changes"),
+                )
+                .subcommand(
+                    ClapCommand::new("disable").about("Turn sparse checkout off, with every file"),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("worktree")
+                .about("Manage the work trees of the repository")
+                .subcommand_required(true)
+                .subcommand(
+                    ClapCommand::new("add")
+                        .about("Create a work tree")
+                        .arg(
+                            Arg::new("branch")
+                                .short('b')
+                                .value_name("BRANCH")
+                                .help("Create a branch and check it out in the work tree"),
+                        )
+                        .arg(
+                            Arg::new("detach")
+                                .long("detach")
+                                .action(ArgAction::SetTrue)
+                                .conflicts_with("branch")
+                                .help("Detach HEAD in the work tree"),
+                        )
+                        .arg(
+                            Arg::new("path")
+                                .value_name("PATH")
+                                .required(true)
+                                .help("Where to create the work tree"),
+                        )
+                        .arg(
+                            Arg::new("commit")
+                                .value_name("COMMIT-ISH")
+                                .help("The branch or commit to check out"),
+                        ),
+                )
+                .subcommand(
+                    ClapCommand::new("list")
+                        .about("List the work trees")
+                        .arg(
+                            Arg::new("porcelain")
+                                .long("porcelain")
+                                .action(ArgAction::SetTrue)
+                                .help("Output in an easy-to-parse format for scripts"),
+                        ),
+                )
+                .subcommand(
+                    ClapCommand::new("remove")
+                        .about("Remove a work tree")
+                        .arg(
+                            Arg::new("force")
+                                .short('f')
+                                .long("force")
+                                .action(ArgAction::Count)
+                                .help("Remove it despite local changes, or if locked when given twice"),
+                        )
+                        .arg(Arg::new("worktree").value_name("WORKTREE").required(true)),
+                )
+                .subcommand(
+                    ClapCommand::new("lock")
+                        .about("Keep a work tree from being pruned or removed")
+                        .arg(
+                            Arg::new("reason")
+                                .long("reason")
+                                .value_name("STRING")
+                                .help("Why the work tree is locked"),
+                        )
+                        .arg(Arg::new("worktree").value_name("WORKTREE").required(true)),
+                )
+                .subcommand(
+                    ClapCommand::new("unlock")
+                        .about("Unlock a work tree")
+                        .arg(Arg::new("worktree").value_name("WORKTREE").required(true)),
+                )
+                .subcommand(
+                    ClapCommand::new("prune")
+                        .about("Delete the administrative files of work trees that are gone")
+                        .arg(
+                            Arg::new("dry_run")
+                                .short('n')
+                                .long("dry-run")
+                                .action(ArgAction::SetTrue)
+                                .help("Don't delete anything, only report"),
+                        )
+                        .arg(
+                            Arg::new("verbose")
+                                .short('v')
+                                .long("verbose")
+                                .action(ArgAction::SetTrue)
+                                .help("Report what is deleted"),
+                        ),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("stash")
+                .about("Stash away the changes of the work tree and the index")
+                .args_conflicts_with_subcommands(true)
+                .args(stash_push_args())
+                .subcommand(
+                    ClapCommand::new("push")
+                        .about("Save the local changes and reset to HEAD (the default)")
+                        .args(stash_push_args()),
+                )
+                .subcommand(
+                    ClapCommand::new("apply")
+                        .about("Apply a stash on top of the work tree")
+                        .arg(stash_arg())
+                        .arg(stash_index_arg()),
+                )
+                .subcommand(
+                    ClapCommand::new("pop")
+                        .about("Apply a stash and remove it from the stash list")
+                        .arg(stash_arg())
+                        .arg(stash_index_arg()),
+                )
+                .subcommand(ClapCommand::new("list").about("List the stashes"))
+                .subcommand(
+                    ClapCommand::new("show")
+                        .about("Show the changes recorded in a stash")
+                        .arg(stash_arg())
+                        .arg(
+                            Arg::new("patch")
+                                .short('p')
+                                .long("patch")
+                                .help("Show the changes as a patch instead of a diffstat")
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    ClapCommand::new("drop")
+                        .about("Remove a stash from the stash list")
+                        .arg(stash_arg()),
+                )
+                .subcommand(ClapCommand::new("clear").about("Remove every stash")),
+        )
+        .subcommand(
+            ClapCommand::new("reflog")
+                .about("Manage the logs of the updates of refs")
+                .args_conflicts_with_subcommands(true)
+                .arg(reflog_ref_arg())
+                .subcommand(
+                    ClapCommand::new("show")
+                        .about("List the entries of a reflog, latest first (the default)")
+                        .arg(reflog_ref_arg()),
+                )
+                .subcommand(
+                    ClapCommand::new("expire")
+                        .about("Remove the old entries of reflogs")
+                        .arg(
+                            Arg::new("expire")
+                                .long("expire")
+                                .value_name("TIME")
+                                .help("Remove the entries older than this, gc.reflogExpire by default"),
+                        )
+                        .arg(
+                            Arg::new("expire_unreachable")
+                                .long("expire-unreachable")
+                                .value_name("TIME")
+                                .help("Remove the entries not reachable from the ref older than this, gc.reflogExpireUnreachable by default"),
+                        )
+                        .arg(
+                            Arg::new("all")
+                                .long("all")
+                                .action(ArgAction::SetTrue)
+                                .help("Expire the entries of every reflog"),
+                        )
+                        .arg(
+                            Arg::new("dry_run")
+                                .short('n')
+                                .long("dry-run")
+                                .action(ArgAction::SetTrue)
+                                .help("Only show the entries that would be removed"),
+                        )
+                        .arg(
+                            Arg::new("refs")
+                                .value_name("REF")
+                                .num_args(0..)
+                                .help("The refs whose reflog to expire"),
+                        ),
+                )
+                .subcommand(
+                    ClapCommand::new("delete")
+                        .about("Remove entries from a reflog")
+                        .arg(
+                            Arg::new("entries")
+                                .value_name("REF@{N}")
+                                .num_args(1..)
+                                .required(true)
+                                .help("The entries to remove, such as HEAD@{2}"),
+                        ),
+                ),
+        )
+        .get_matches_from(normalize_args(std::env::args()));
 
     if let Some(subcommand) = matches.subcommand_matches("init") {
         let path = subcommand.get_one::<String>("path").unwrap().clone();
-        Ok(Command::Init { path })
+        let ref_storage = subcommand
+            .get_one::<RefStorage>("ref-format")
+            .copied()
+            .unwrap_or_default();
+        Ok(Command::Init { path, ref_storage })
     } else if let Some(subcommand) = matches.subcommand_matches("cat-file") {
         let object_type: String = subcommand.get_one::<String>("type").unwrap().clone();
         let object_hash = subcommand.get_one::<String>("object").unwrap().clone();
@@ -165,6 +1579,7 @@ pub fn parse_args() -> Result<Command, ParseArgumentsError> {
             file_path: PathBuf::from(filename),
             object_type: object_type.parse()?,
             write,
+            no_filters: subcommand.get_flag("no_filters"),
         })
     } else if let Some(subcommand) = matches.subcommand_matches("log") {
         let commit: String = subcommand.get_one::<String>("commit").unwrap().clone();
@@ -176,35 +1591,420 @@ pub fn parse_args() -> Result<Command, ParseArgumentsError> {
         Ok(Command::LsTree { tree, recursive })
     } else if let Some(subcommand) = matches.subcommand_matches("checkout") {
         let commit: String = subcommand.get_one::<String>("commit").unwrap().clone();
-        let path = subcommand.get_one::<String>("path").unwrap().clone();
-        Ok(Command::Checkout { commit, path })
-    } else if let Some(_) = matches.subcommand_matches("show-ref") {
+        let path = subcommand.get_one::<String>("path").cloned();
+        let detach = subcommand.get_flag("detach");
+        Ok(Command::Checkout {
+            commit,
+            path,
+            detach,
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("switch") {
+        let branch = subcommand.get_one::<String>("branch").unwrap().clone();
+        let detach = subcommand.get_flag("detach");
+        Ok(Command::Switch { branch, detach })
+    } else if let Some(subcommand) = matches.subcommand_matches("branch") {
+        let mut args: Vec<String> = subcommand
+            .get_many::<String>("args")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default();
+        let flag = |id: &str| subcommand.get_flag(id);
+        let upstream = subcommand.get_one::<String>("set_upstream_to").cloned();
+        let contains = subcommand.get_one::<String>("contains").cloned();
+        let merged = subcommand.get_one::<String>("merged").cloned();
+
+        let command = if flag("delete") || flag("force_delete") {
+            if args.is_empty() {
+                Err(anyhow!("branch name required"))?
+            }
+            BranchSubCommand::Delete {
+                names: args,
+                force: flag("force") || flag("force_delete"),
+            }
+        } else if flag("move") || flag("force_move") {
+            if args.is_empty() || args.len() > 2 {
+                Err(anyhow!("branch -m takes one or two branch names"))?
+            }
+            BranchSubCommand::Rename {
+                new: args.pop().unwrap_or_default(),
+                old: args.pop(),
+                force: flag("force") || flag("force_move"),
+            }
+        } else if let Some(upstream) = upstream {
+            if args.len() > 1 {
+                Err(anyhow!("too many arguments to set new upstream"))?
+            }
+            BranchSubCommand::SetUpstream {
+                upstream,
+                branch: args.pop(),
+            }
+        } else if flag("list")
+            || flag("verbose")
+            || flag("all")
+            || args.is_empty()
+            || contains.is_some()
+            || merged.is_some()
+        {
+            BranchSubCommand::List {
+                all: flag("all"),
+                verbose: flag("verbose"),
+                patterns: args,
+                contains,
+                merged,
+                format: subcommand.get_one::<String>("format").cloned(),
+                sort: parse_sort_keys(subcommand)?,
+            }
+        } else {
+            if args.len() > 2 {
+                Err(anyhow!("branch takes a name and a start point"))?
+            }
+            let start = (args.len() == 2).then(|| args.remove(1));
+            BranchSubCommand::Create {
+                name: args.remove(0),
+                start,
+                force: flag("force"),
+            }
+        };
+        Ok(Command::Branch { command })
+    } else if matches.subcommand_matches("show-ref").is_some() {
         Ok(Command::ShowRef)
+    } else if let Some(subcommand) = matches.subcommand_matches("symbolic-ref") {
+        Ok(Command::SymbolicRef {
+            quiet: subcommand.get_flag("quiet"),
+            short: subcommand.get_flag("short"),
+            message: subcommand.get_one::<String>("message").cloned(),
+            name: subcommand
+                .get_one::<String>("name")
+                .cloned()
+                .unwrap_or_default(),
+            target: subcommand.get_one::<String>("target").cloned(),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("for-each-ref") {
+        let patterns = subcommand
+            .get_many::<String>("patterns")
+            .map(|patterns| patterns.cloned().collect())
+            .unwrap_or_default();
+        Ok(Command::ForEachRef {
+            format: subcommand.get_one::<String>("format").cloned(),
+            sort: parse_sort_keys(subcommand)?,
+            count: subcommand.get_one::<usize>("count").copied(),
+            filter: parse_ref_filter(subcommand, patterns),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("rev-parse") {
+        Ok(Command::RevParse {
+            verify: subcommand.get_flag("verify"),
+            quiet: subcommand.get_flag("quiet"),
+            short: subcommand.get_one::<usize>("short").copied(),
+            abbrev_ref: subcommand.get_flag("abbrev_ref"),
+            symbolic_full_name: subcommand.get_flag("symbolic_full_name"),
+            revisions: subcommand
+                .get_many::<String>("revisions")
+                .map(|revisions| revisions.cloned().collect())
+                .unwrap_or_default(),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("update-ref") {
+        Ok(Command::UpdateRef {
+            no_deref: subcommand.get_flag("no_deref"),
+            stdin: subcommand.get_flag("stdin"),
+            delete: subcommand.get_flag("delete"),
+            message: subcommand.get_one::<String>("message").cloned(),
+            args: subcommand
+                .get_many::<String>("args")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("pack-refs") {
+        Ok(Command::PackRefs {
+            all: subcommand.get_flag("all"),
+            prune: !subcommand.get_flag("no_prune"),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("check-ref-format") {
+        Ok(Command::CheckRefFormat {
+            branch: subcommand.get_flag("branch"),
+            options: RefNameOptions {
+                allow_onelevel: subcommand.get_flag("allow_onelevel"),
+                refspec_pattern: subcommand.get_flag("refspec_pattern"),
+                normalize: subcommand.get_flag("normalize"),
+            },
+            name: subcommand
+                .get_one::<String>("name")
+                .cloned()
+                .unwrap_or_default(),
+        })
     } else if let Some(subcommand) = matches.subcommand_matches("tag") {
         let name = subcommand.get_one::<String>("name");
         let object = subcommand.get_one::<String>("object");
         let add_tag_object = subcommand.get_flag("tag_object");
-        let add_lightweight_tag = add_tag_object == false && name.is_some();
+        let listing = subcommand.get_flag("list")
+            || ["format", "sort", "points_at", "contains", "merged"]
+                .iter()
+                .any(|id| subcommand.contains_id(id));
+        let add_lightweight_tag = !add_tag_object && !listing && name.is_some();
 
         if add_tag_object {
             Ok(Command::Tag {
                 command: TagSubCommand::CreateTagObject {
                     name: name.unwrap().clone(), // Safe to call unwrap, we specified that if -a presents, name must too.
-                    object: object.map(|val| val.clone()).unwrap_or("HEAD".to_string()),
+                    object: object.cloned().unwrap_or("HEAD".to_string()),
                 },
             })
         } else if add_lightweight_tag {
             Ok(Command::Tag {
                 command: TagSubCommand::CreateLightweightTag {
                     name: name.unwrap().clone(), // Safe to call unwrap, add_lightweight_tag has a check for presence of name
-                    object: object.map(|val| val.clone()).unwrap_or("HEAD".to_string()),
+                    object: object.cloned().unwrap_or("HEAD".to_string()),
                 },
             })
         } else {
+            let patterns = [name, object]
+                .into_iter()
+                .flatten()
+                .map(|pattern| format!("refs/tags/{}", pattern))
+                .collect();
             Ok(Command::Tag {
-                command: TagSubCommand::ListTags,
+                command: TagSubCommand::ListTags {
+                    format: subcommand.get_one::<String>("format").cloned(),
+                    sort: parse_sort_keys(subcommand)?,
+                    filter: parse_ref_filter(subcommand, patterns),
+                },
             })
         }
+    } else if let Some(subcommand) = matches.subcommand_matches("diff-tree") {
+        let old_tree = subcommand.get_one::<String>("old_tree").unwrap().clone();
+        let new_tree = subcommand.get_one::<String>("new_tree").unwrap().clone();
+        let format = if subcommand.get_flag("patch") {
+            OutputFormat::Patch
+        } else if subcommand.get_flag("name_status") {
+            OutputFormat::NameStatus
+        } else {
+            OutputFormat::Raw
+        };
+        Ok(Command::DiffTree {
+            old_tree,
+            new_tree,
+            recursive: subcommand.get_flag("recursive"),
+            format,
+            renames: parse_rename_detection_args(subcommand)?,
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("diff") {
+        let strings = |id: &str| -> Vec<String> {
+            subcommand
+                .get_many::<String>(id)
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default()
+        };
+        let format = if subcommand.get_flag("stat") {
+            OutputFormat::Stat
+        } else if subcommand.get_flag("numstat") {
+            OutputFormat::NumStat
+        } else if subcommand.get_flag("shortstat") {
+            OutputFormat::ShortStat
+        } else if subcommand.get_flag("name_only") {
+            OutputFormat::NameOnly
+        } else if subcommand.get_flag("name_status") {
+            OutputFormat::NameStatus
+        } else {
+            OutputFormat::Patch
+        };
+        let color = if subcommand.get_flag("no_color") {
+            Some(ColorWhen::Never)
+        } else {
+            subcommand.get_one::<ColorWhen>("color").copied()
+        };
+        Ok(Command::Diff {
+            cached: subcommand.get_flag("cached"),
+            args: strings("args"),
+            paths: strings("paths"),
+            format,
+            renames: parse_rename_detection_args(subcommand)?,
+            no_renames: subcommand.get_flag("no_renames"),
+            color,
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("restore") {
+        Ok(Command::Restore {
+            source: subcommand.get_one::<String>("source").cloned(),
+            staged: subcommand.get_flag("staged"),
+            worktree: subcommand.get_flag("worktree"),
+            paths: subcommand
+                .get_many::<String>("paths")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("clean") {
+        Ok(Command::Clean {
+            dry_run: subcommand.get_flag("dry_run"),
+            force: subcommand.get_count("force"),
+            directories: subcommand.get_flag("directories"),
+            ignored: subcommand.get_flag("ignored"),
+            ignored_only: subcommand.get_flag("ignored_only"),
+            paths: subcommand
+                .get_many::<String>("paths")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("check-attr") {
+        let strings = |name: &str| {
+            subcommand
+                .get_many::<String>(name)
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default()
+        };
+        Ok(Command::CheckAttr {
+            all: subcommand.get_flag("all"),
+            args: strings("args"),
+            paths: strings("paths"),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("update-index") {
+        Ok(Command::UpdateIndex {
+            refresh: subcommand.get_flag("refresh"),
+            quiet: subcommand.get_flag("quiet"),
+        })
+    } else if let Some(subcommand) = matches.subcommand_matches("sparse-checkout") {
+        let cone =
+            |matches: &ArgMatches| match (matches.get_flag("cone"), matches.get_flag("no_cone")) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            };
+        let patterns = |matches: &ArgMatches| {
+            matches
+                .get_many::<String>("patterns")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default()
+        };
+        let command = match subcommand.subcommand() {
+            Some(("init", matches)) => SparseCheckoutSubCommand::Init {
+                cone: cone(matches),
+            },
+            Some(("set", matches)) => SparseCheckoutSubCommand::Set {
+                cone: cone(matches),
+                patterns: patterns(matches),
+                stdin: matches.get_flag("stdin"),
+            },
+            Some(("add", matches)) => SparseCheckoutSubCommand::Add {
+                patterns: patterns(matches),
+                stdin: matches.get_flag("stdin"),
+            },
+            Some(("list", _)) => SparseCheckoutSubCommand::List,
+            Some(("reapply", _)) => SparseCheckoutSubCommand::Reapply,
+            _ => SparseCheckoutSubCommand::Disable,
+        };
+        Ok(Command::SparseCheckout { command })
+    } else if let Some(subcommand) = matches.subcommand_matches("worktree") {
+        let worktree = |matches: &ArgMatches| {
+            matches
+                .get_one::<String>("worktree")
+                .cloned()
+                .unwrap_or_default()
+        };
+        let command = match subcommand.subcommand() {
+            Some(("add", matches)) => WorktreeSubCommand::Add {
+                path: matches
+                    .get_one::<String>("path")
+                    .cloned()
+                    .unwrap_or_default(),
+                commit: matches.get_one::<String>("commit").cloned(),
+                branch: matches.get_one::<String>("branch").cloned(),
+                detach: matches.get_flag("detach"),
+            },
+            Some(("list", matches)) => WorktreeSubCommand::List {
+                porcelain: matches.get_flag("porcelain"),
+            },
+            Some(("remove", matches)) => WorktreeSubCommand::Remove {
+                worktree: worktree(matches),
+                force: matches.get_count("force"),
+            },
+            Some(("lock", matches)) => WorktreeSubCommand::Lock {
+                worktree: worktree(matches),
+                reason: matches.get_one::<String>("reason").cloned(),
+            },
+            Some(("unlock", matches)) => WorktreeSubCommand::Unlock {
+                worktree: worktree(matches),
+            },
+            Some(("prune", matches)) => WorktreeSubCommand::Prune {
+                dry_run: matches.get_flag("dry_run"),
+                verbose: matches.get_flag("verbose"),
+            },
+            _ => WorktreeSubCommand::List { porcelain: false },
+        };
+        Ok(Command::Worktree { command })
+    } else if let Some(subcommand) = matches.subcommand_matches("stash") {
+        let stash = |matches: &ArgMatches| matches.get_one::<String>("stash").cloned();
+        let push = |matches: &ArgMatches| StashSubCommand::Push {
+            message: matches.get_one::<String>("message").cloned(),
+            include_untracked: matches.get_flag("include_untracked"),
+        };
+        let command = match subcommand.subcommand() {
+            Some(("push", matches)) => push(matches),
+            Some(("apply", matches)) => StashSubCommand::Apply {
+                stash: stash(matches),
+                index: matches.get_flag("index"),
+            },
+            Some(("pop", matches)) => StashSubCommand::Pop {
+                stash: stash(matches),
+                index: matches.get_flag("index"),
+            },
+            Some(("list", _)) => StashSubCommand::List,
+            Some(("show", matches)) => StashSubCommand::Show {
+                stash: stash(matches),
+                patch: matches.get_flag("patch"),
+            },
+            Some(("drop", matches)) => StashSubCommand::Drop {
+                stash: stash(matches),
+            },
+            Some(("clear", _)) => StashSubCommand::Clear,
+            // `stash` alone is `stash push`.
+            _ => push(subcommand),
+        };
+        Ok(Command::Stash { command })
+    } else if let Some(subcommand) = matches.subcommand_matches("reflog") {
+        let strings = |matches: &ArgMatches, id: &str| -> Vec<String> {
+            matches
+                .get_many::<String>(id)
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default()
+        };
+        let command = match subcommand.subcommand() {
+            Some(("show", matches)) => ReflogSubCommand::Show {
+                reference: matches.get_one::<String>("ref").cloned(),
+            },
+            Some(("expire", matches)) => ReflogSubCommand::Expire {
+                expire: matches.get_one::<String>("expire").cloned(),
+                expire_unreachable: matches.get_one::<String>("expire_unreachable").cloned(),
+                all: matches.get_flag("all"),
+                dry_run: matches.get_flag("dry_run"),
+                refs: strings(matches, "refs"),
+            },
+            Some(("delete", matches)) => ReflogSubCommand::Delete {
+                entries: strings(matches, "entries"),
+            },
+            // `reflog` alone is `reflog show`.
+            _ => ReflogSubCommand::Show {
+                reference: subcommand.get_one::<String>("ref").cloned(),
+            },
+        };
+        Ok(Command::Reflog { command })
+    } else if let Some(subcommand) = matches.subcommand_matches("reset") {
+        let strings = |id: &str| -> Vec<String> {
+            subcommand
+                .get_many::<String>(id)
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default()
+        };
+        let mode = [
+            ("soft", ResetMode::Soft),
+            ("mixed", ResetMode::Mixed),
+            ("hard", ResetMode::Hard),
+            ("keep", ResetMode::Keep),
+        ]
+        .into_iter()
+        .find(|(id, _)| subcommand.get_flag(id))
+        .map(|(_, mode)| mode);
+        Ok(Command::Reset {
+            mode,
+            quiet: subcommand.get_flag("quiet"),
+            args: strings("args"),
+            paths: strings("paths"),
+        })
     } else {
         Err(anyhow!("Argument parse failed"))?
     }
@@ -0,0 +1,432 @@
+//! Conversions between the content of blobs and of work tree files.
+//!
+//! Line endings of text files are stored as LF and may be written out as CRLF. Which files are
+//! text and how their line endings are written depends on the `text`, `eol` and legacy `crlf`
+//! attributes, then on `core.autocrlf` and `core.eol`. With `text=auto` or `core.autocrlf`,
+//! files that look binary are left alone, and so are files whose line endings can't survive a
+//! round trip, such as files already stored with CRLF.
+//!
+//! Files with a `filter` attribute naming a configured driver also go through its clean filter
+//! before their line endings are converted, and through its smudge filter after.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    sync::Mutex,
+};
+
+use crate::{
+    attributes::{AttributeRules, AttributeState},
+    error::ConvertError,
+    filter::{Direction, FilterDriver},
+    git_config::GitConfig,
+};
+
+/// What is checked about line endings that wouldn't survive a round trip through the object
+/// database (`core.safecrlf`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SafeCrlf {
+    Off,
+    #[default]
+    Warn,
+    Fail,
+}
+
+/// How the line endings of a file are converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineEndings {
+    /// Not converted.
+    Binary,
+    /// Converted, written out as CRLF with `crlf`.
+    Text { crlf: bool },
+    /// Converted if the file looks like text and its line endings survive a round trip.
+    Auto { crlf: bool },
+}
+
+/// Counts of the kinds of bytes of some content.
+#[derive(Debug, Clone, Copy, Default)]
+struct Stats {
+    lone_cr: usize,
+    lone_lf: usize,
+    crlf: usize,
+    nul: usize,
+    printable: usize,
+    non_printable: usize,
+}
+
+impl Stats {
+    fn gather(data: &[u8]) -> Self {
+        let mut stats = Self::default();
+        let mut i = 0;
+        while i < data.len() {
+            match data[i] {
+                b'\r' if data.get(i + 1) == Some(&b'\n') => {
+                    stats.crlf += 1;
+                    i += 1;
+                }
+                b'\r' => stats.lone_cr += 1,
+                b'\n' => stats.lone_lf += 1,
+                0x7f => stats.non_printable += 1,
+                b'\x08' | b'\t' | b'\x1b' | b'\x0c' => stats.printable += 1,
+                0 => {
+                    stats.nul += 1;
+                    stats.non_printable += 1;
+                }
+                c if c < 32 => stats.non_printable += 1,
+                _ => stats.printable += 1,
+            }
+            i += 1;
+        }
+        // A trailing DOS end-of-file marker doesn't count.
+        if data.last() == Some(&0x1a) {
+            stats.non_printable -= 1;
+        }
+        stats
+    }
+
+    /// Whether the content looks binary: it has lone CRs or NULs, or more than one byte in 128
+    /// isn't printable.
+    fn is_binary(&self) -> bool {
+        self.lone_cr > 0 || self.nul > 0 || (self.printable >> 7) < self.non_printable
+    }
+}
+
+/// The paths warned about already, so that reading a file again doesn't repeat the warning.
+static WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Warnings held back by [`with_ordered_warnings`], by path.
+static HELD_WARNINGS: Mutex<Option<BTreeMap<String, String>>> = Mutex::new(None);
+
+/// Run `f`, which converts files on several threads, holding back the warnings about them until
+/// it's done. They are then printed in the order of their paths, as converting the files one
+/// after the other would.
+pub fn with_ordered_warnings<R>(f: impl FnOnce() -> R) -> R {
+    let lock = || {
+        HELD_WARNINGS
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+    };
+    *lock() = Some(BTreeMap::new());
+    let result = f();
+    let held = lock().take().unwrap_or_default();
+    for warning in held.into_values() {
+        eprintln!("{}", warning);
+    }
+    result
+}
+
+/// How a file is converted between the object database and the work tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    line_endings: LineEndings,
+    safe_crlf: SafeCrlf,
+    filter: Option<FilterDriver>,
+}
+
+impl Conversion {
+    /// The conversion of the file at `path`, given the repository configuration and the
+    /// attributes that apply to it. Filters run in `work_tree`.
+    pub fn new(
+        config: &GitConfig,
+        attributes: &AttributeRules,
+        work_tree: &Path,
+        path: &str,
+    ) -> Self {
+        let auto_crlf = config.get("core", "autocrlf").map(str::to_lowercase);
+        let auto_crlf = match auto_crlf.as_deref() {
+            Some("input") => Some(false),
+            _ => config
+                .get_bool("core", "autocrlf")
+                .and_then(|auto_crlf| auto_crlf.then_some(true)),
+        };
+        let eol_is_crlf = match auto_crlf {
+            Some(crlf) => crlf,
+            None => config
+                .get("core", "eol")
+                .is_some_and(|eol| eol.eq_ignore_ascii_case("crlf")),
+        };
+
+        let text = |name| match attributes.get(path, name) {
+            AttributeState::Set => Some(LineEndings::Text { crlf: eol_is_crlf }),
+            AttributeState::Unset => Some(LineEndings::Binary),
+            AttributeState::Value(value) if value == "input" => {
+                Some(LineEndings::Text { crlf: false })
+            }
+            AttributeState::Value(value) if value == "auto" => {
+                Some(LineEndings::Auto { crlf: eol_is_crlf })
+            }
+            _ => None,
+        };
+        let eol = match attributes.get(path, "eol") {
+            AttributeState::Value(value) if value == "lf" => Some(false),
+            AttributeState::Value(value) if value == "crlf" => Some(true),
+            _ => None,
+        };
+        let line_endings = match (text("text").or_else(|| text("crlf")), eol) {
+            (Some(LineEndings::Binary), _) => LineEndings::Binary,
+            (Some(LineEndings::Auto { .. }), Some(crlf)) => LineEndings::Auto { crlf },
+            (_, Some(crlf)) => LineEndings::Text { crlf },
+            (Some(line_endings), None) => line_endings,
+            (None, None) => match auto_crlf {
+                Some(crlf) => LineEndings::Auto { crlf },
+                None => LineEndings::Binary,
+            },
+        };
+
+        let safe_crlf = config.get("core", "safecrlf").map(str::to_lowercase);
+        let safe_crlf = match safe_crlf.as_deref() {
+            Some("warn") => SafeCrlf::Warn,
+            _ => match config.get_bool("core", "safecrlf") {
+                Some(true) => SafeCrlf::Fail,
+                Some(false) => SafeCrlf::Off,
+                None => SafeCrlf::Warn,
+            },
+        };
+
+        let filter = match attributes.get(path, "filter") {
+            AttributeState::Value(name) => FilterDriver::from_config(config, &name, work_tree),
+            _ => None,
+        };
+
+        Self {
+            line_endings,
+            safe_crlf,
+            filter,
+        }
+    }
+
+    /// Whether files are converted at all.
+    pub fn is_identity(&self) -> bool {
+        self.line_endings == LineEndings::Binary && self.filter.is_none()
+    }
+
+    /// Whether LFs of content with these stats are written out as CRLF.
+    fn converts_lf_to_crlf(&self, stats: &Stats) -> bool {
+        match self.line_endings {
+            LineEndings::Binary => false,
+            LineEndings::Text { crlf } => crlf && stats.lone_lf > 0,
+            LineEndings::Auto { crlf } => {
+                crlf && stats.lone_lf > 0
+                    && stats.lone_cr == 0
+                    && stats.crlf == 0
+                    && !stats.is_binary()
+            }
+        }
+    }
+
+    /// Convert the content of the work tree file at `path` to the content of its blob.
+    /// `index_has_cr` tells whether the blob recorded in the index has CRs, whose line endings
+    /// are then left alone with `text=auto`. Line endings that wouldn't survive a round trip
+    /// are warned about, or refused, depending on `core.safecrlf`.
+    pub fn to_git(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        index_has_cr: impl FnOnce() -> bool,
+    ) -> Result<Vec<u8>, ConvertError> {
+        let data = match &self.filter {
+            Some(filter) => filter.apply(Direction::Clean, path, data)?,
+            None => data,
+        };
+        self.crlf_to_git(path, data, index_has_cr)
+    }
+
+    fn crlf_to_git(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        index_has_cr: impl FnOnce() -> bool,
+    ) -> Result<Vec<u8>, ConvertError> {
+        if self.line_endings == LineEndings::Binary || data.is_empty() {
+            return Ok(data);
+        }
+
+        let stats = Stats::gather(&data);
+        let mut convert = stats.crlf > 0;
+        if let LineEndings::Auto { .. } = self.line_endings {
+            if stats.is_binary() {
+                return Ok(data);
+            }
+            if convert && index_has_cr() {
+                convert = false;
+            }
+        }
+
+        if self.safe_crlf != SafeCrlf::Off {
+            // What a checkout after storing the file would write out.
+            let mut round_trip = stats;
+            if convert {
+                round_trip.lone_lf += round_trip.crlf;
+                round_trip.crlf = 0;
+            }
+            if self.converts_lf_to_crlf(&round_trip) {
+                round_trip.crlf += round_trip.lone_lf;
+                round_trip.lone_lf = 0;
+            }
+            self.check_round_trip(path, &stats, &round_trip)?;
+        }
+
+        if !convert {
+            return Ok(data);
+        }
+        let mut converted = Vec::with_capacity(data.len());
+        for (i, &c) in data.iter().enumerate() {
+            // Content guessed to be text has no lone CRs, so every CR can go.
+            let strip = match self.line_endings {
+                LineEndings::Auto { .. } => c == b'\r',
+                _ => c == b'\r' && data.get(i + 1) == Some(&b'\n'),
+            };
+            if !strip {
+                converted.push(c);
+            }
+        }
+        Ok(converted)
+    }
+
+    fn check_round_trip(
+        &self,
+        path: &str,
+        stats: &Stats,
+        round_trip: &Stats,
+    ) -> Result<(), ConvertError> {
+        let (from, to) = if stats.crlf > 0 && round_trip.crlf == 0 {
+            ("CRLF", "LF")
+        } else if stats.lone_lf > 0 && round_trip.lone_lf == 0 {
+            ("LF", "CRLF")
+        } else {
+            return Ok(());
+        };
+
+        match self.safe_crlf {
+            SafeCrlf::Fail => Err(ConvertError::LineEndingsReplaced(
+                from.to_string(),
+                to.to_string(),
+                path.to_string(),
+            )),
+            _ => {
+                let mut warned = WARNED.lock().unwrap_or_else(|error| error.into_inner());
+                if warned.insert(path.to_string()) {
+                    let warning = format!(
+                        "warning: in the working copy of '{}', {} will be replaced by {} the next time rit touches it",
+                        path, from, to
+                    );
+                    let mut held = HELD_WARNINGS
+                        .lock()
+                        .unwrap_or_else(|error| error.into_inner());
+                    match held.as_mut() {
+                        Some(held) => {
+                            held.insert(path.to_string(), warning);
+                        }
+                        None => eprintln!("{}", warning),
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Convert the content of a blob to the content of the work tree file at `path`.
+    pub fn to_work_tree(&self, path: &str, data: Vec<u8>) -> Result<Vec<u8>, ConvertError> {
+        let data = self.crlf_to_work_tree(data);
+        match &self.filter {
+            Some(filter) => filter.apply(Direction::Smudge, path, data),
+            None => Ok(data),
+        }
+    }
+
+    fn crlf_to_work_tree(&self, data: Vec<u8>) -> Vec<u8> {
+        if !self.converts_lf_to_crlf(&Stats::gather(&data)) {
+            return data;
+        }
+        let mut converted = Vec::with_capacity(data.len() * 2);
+        for (i, &c) in data.iter().enumerate() {
+            if c == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+                converted.push(b'\r');
+            }
+            converted.push(c);
+        }
+        converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{attributes::AttributeRules, git_config::GitConfig};
+
+    use super::Conversion;
+
+    fn conversion(config: &str, attributes: &str, path: &str) -> Conversion {
+        let config: GitConfig = format!("[core]\n{}", config).parse().unwrap();
+        let mut rules = AttributeRules::new();
+        rules.add_rules("", attributes);
+        Conversion::new(&config, &rules, Path::new("."), path)
+    }
+
+    #[test]
+    fn autocrlf_should_convert_text_files_only() {
+        let conversion = conversion("autocrlf = true\nsafecrlf = false", "", "file");
+
+        assert_eq!(
+            conversion
+                .to_git("file", b"a\r\nb\r\n".to_vec(), || false)
+                .unwrap(),
+            b"a\nb\n"
+        );
+        assert_eq!(
+            conversion.to_work_tree("file", b"a\nb\n".to_vec()).unwrap(),
+            b"a\r\nb\r\n"
+        );
+        // Binary content and content already stored with CRs are left alone.
+        assert_eq!(
+            conversion
+                .to_git("file", b"a\0\r\n".to_vec(), || false)
+                .unwrap(),
+            b"a\0\r\n"
+        );
+        assert_eq!(
+            conversion
+                .to_git("file", b"a\r\n".to_vec(), || true)
+                .unwrap(),
+            b"a\r\n"
+        );
+        assert_eq!(
+            conversion
+                .to_work_tree("file", b"a\r\nb\n".to_vec())
+                .unwrap(),
+            b"a\r\nb\n"
+        );
+    }
+
+    #[test]
+    fn text_attributes_should_take_precedence_over_the_configuration() {
+        let attributes = "*.txt text\n*.bat eol=crlf\n*.bin -text\n";
+
+        let text = conversion("autocrlf = false", attributes, "a.txt");
+        assert_eq!(
+            text.to_git("a.txt", b"a\r\nb\rc\r\n".to_vec(), || true)
+                .unwrap(),
+            b"a\nb\rc\n"
+        );
+        assert_eq!(text.to_work_tree("file", b"a\n".to_vec()).unwrap(), b"a\n");
+
+        let bat = conversion("autocrlf = input", attributes, "run.bat");
+        assert_eq!(bat.to_work_tree("file", b"a\n".to_vec()).unwrap(), b"a\r\n");
+
+        let binary = conversion("autocrlf = true", attributes, "a.bin");
+        assert!(binary.is_identity());
+    }
+
+    #[test]
+    fn safecrlf_should_refuse_irreversible_conversions() {
+        let conversion = conversion("autocrlf = input\nsafecrlf = true", "", "file");
+
+        let error = conversion
+            .to_git("file", b"a\r\n".to_vec(), || false)
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "CRLF would be replaced by LF in file");
+        assert!(conversion.to_git("file", b"a\n".to_vec(), || false).is_ok());
+    }
+}
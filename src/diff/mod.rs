@@ -0,0 +1,250 @@
+pub mod patch;
+pub mod rename;
+pub mod stat;
+pub mod tree;
+pub mod work_tree;
+
+pub use rename::{detect_renames, CopyDetection, RenameDetection, RenameOptions};
+pub use tree::TreeDiff;
+pub use work_tree::{index_files, work_tree_files};
+
+use std::{cmp::Ordering, fmt::Display, io::Write, iter::Peekable};
+
+use crate::{
+    error::DiffError,
+    git_object::{
+        mode::{Mode, Type},
+        SerializedGitObject, Tree, Type as ObjectType,
+    },
+    pathspec::Pathspec,
+    repository::GitRepository,
+    GitObject,
+};
+
+pub const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+pub const NULL_MODE: &str = "000000";
+
+/// One side (pre- or post-image) of a changed path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffSide {
+    pub path: String,
+    pub mode: Mode,
+    pub hash: String,
+}
+
+/// How a list of changes is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Raw,
+    NameOnly,
+    NameStatus,
+    Patch,
+    Stat,
+    NumStat,
+    ShortStat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Added,
+    Deleted,
+    Modified,
+    TypeChanged,
+    /// A rename, with the similarity of both files in percent.
+    Renamed(u8),
+    /// A copy, with the similarity of both files in percent.
+    Copied(u8),
+}
+
+impl Status {
+    pub fn letter(&self) -> char {
+        match self {
+            Status::Added => 'A',
+            Status::Deleted => 'D',
+            Status::Modified => 'M',
+            Status::TypeChanged => 'T',
+            Status::Renamed(_) => 'R',
+            Status::Copied(_) => 'C',
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Renamed(score) | Status::Copied(score) => {
+                write!(f, "{}{:03}", self.letter(), score)
+            }
+            _ => write!(f, "{}", self.letter()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub status: Status,
+    pub old: Option<DiffSide>,
+    pub new: Option<DiffSide>,
+}
+
+impl FileChange {
+    /// Classify the change between `old` and `new`. At least one of them must be present.
+    pub fn new(old: Option<DiffSide>, new: Option<DiffSide>) -> Self {
+        let status = match (&old, &new) {
+            (None, _) => Status::Added,
+            (_, None) => Status::Deleted,
+            (Some(old), Some(new)) if old.mode.type_ != new.mode.type_ => Status::TypeChanged,
+            _ => Status::Modified,
+        };
+
+        Self { status, old, new }
+    }
+
+    pub fn path(&self) -> &str {
+        match (&self.new, &self.old) {
+            (Some(side), _) | (None, Some(side)) => &side.path,
+            (None, None) => "",
+        }
+    }
+
+    /// Whether the change moves content from one path to another.
+    pub fn is_rename_or_copy(&self) -> bool {
+        matches!(self.status, Status::Renamed(_) | Status::Copied(_))
+    }
+
+    /// The `--name-status` representation of the change.
+    pub fn name_status(&self) -> String {
+        match (&self.old, self.is_rename_or_copy()) {
+            (Some(old), true) => format!("{}\t{}\t{}", self.status, old.path, self.path()),
+            _ => format!("{}\t{}", self.status, self.path()),
+        }
+    }
+
+    /// The raw representation of the change, as printed by `git diff-tree`.
+    pub fn raw(&self) -> String {
+        let (old_mode, old_hash) = mode_and_hash(self.old.as_ref());
+        let (new_mode, new_hash) = mode_and_hash(self.new.as_ref());
+        format!(
+            ":{} {} {} {} {}",
+            old_mode,
+            new_mode,
+            old_hash,
+            new_hash,
+            self.name_status()
+        )
+    }
+}
+
+fn mode_and_hash(side: Option<&DiffSide>) -> (&str, &str) {
+    side.map(|side| (side.mode.permissions(), side.hash.as_str()))
+        .unwrap_or((NULL_MODE, NULL_HASH))
+}
+
+/// Every file of `tree`, recursively, in tree order.
+pub fn list_tree_files(repo: &GitRepository, tree: Tree) -> Result<Vec<DiffSide>, DiffError> {
+    TreeDiff::new(repo, None, Some(tree))
+        .recursive(true)
+        .filter_map(|change| change.map(|change| change.new).transpose())
+        .collect()
+}
+
+/// Every file of `tree` matching `pathspec`, in tree order. A missing tree has no files.
+pub fn tree_files(
+    repo: &GitRepository,
+    tree: Option<Tree>,
+    pathspec: &Pathspec,
+) -> Result<Vec<DiffSide>, DiffError> {
+    let files = match tree {
+        Some(tree) => list_tree_files(repo, tree)?,
+        None => vec![],
+    };
+    Ok(files
+        .into_iter()
+        .filter(|side| pathspec.matches(&side.path))
+        .collect())
+}
+
+/// Compare two lists of files sorted by path, such as the files of a tree, of the index or of
+/// the work tree. Files with the same mode and hash on both sides are unchanged.
+pub fn diff_files(old: Vec<DiffSide>, new: Vec<DiffSide>) -> Vec<FileChange> {
+    let mut old: Peekable<_> = old.into_iter().peekable();
+    let mut new: Peekable<_> = new.into_iter().peekable();
+    let mut changes = vec![];
+
+    loop {
+        let ordering = match (old.peek(), new.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(o), Some(n)) => o.path.as_bytes().cmp(n.path.as_bytes()),
+        };
+
+        match ordering {
+            Ordering::Less => changes.push(FileChange::new(old.next(), None)),
+            Ordering::Greater => changes.push(FileChange::new(None, new.next())),
+            Ordering::Equal => {
+                let (o, n) = (old.next().unwrap(), new.next().unwrap());
+                if o.hash != n.hash || o.mode != n.mode {
+                    changes.push(FileChange::new(Some(o), Some(n)));
+                }
+            }
+        }
+    }
+
+    changes
+}
+
+/// Load the content of one side of a change. Sides that aren't in the object database were
+/// hashed from the work tree, and are read from there.
+pub fn read_side(repo: &GitRepository, side: &DiffSide) -> Result<Vec<u8>, DiffError> {
+    if side.mode.type_ == Type::Submodule {
+        return Ok(format!("Subproject commit {}\n", side.hash).into_bytes());
+    }
+
+    if !repo.has_object(&side.hash) {
+        // Whether line endings were normalized depends on the blob recorded in the index, which
+        // `side` doesn't know about: the content matching its hash is the one.
+        for index_has_cr in [false, true] {
+            let file =
+                repo.read_work_tree_file_with(&side.path, Some(&side.mode), || index_has_cr)?;
+            if let Some((_, data)) = file {
+                if index_has_cr
+                    || SerializedGitObject::hash_of(ObjectType::Blob, &data) == side.hash
+                {
+                    return Ok(data);
+                }
+            }
+        }
+    }
+
+    match repo.read_object(&side.hash)? {
+        GitObject::Blob(blob) => Ok(blob.blob),
+        _ => Err(DiffError::NotABlob(side.hash.clone())),
+    }
+}
+
+/// Write the patch of `change`, reading both sides from the object database.
+pub fn write_patch(
+    repo: &GitRepository,
+    out: &mut impl Write,
+    change: &FileChange,
+) -> Result<(), DiffError> {
+    let old = change
+        .old
+        .as_ref()
+        .map(|side| read_side(repo, side))
+        .transpose()?;
+    let new = change
+        .new
+        .as_ref()
+        .map(|side| read_side(repo, side))
+        .transpose()?;
+
+    patch::write_file_patch(
+        out,
+        change,
+        old.as_deref().unwrap_or_default(),
+        new.as_deref().unwrap_or_default(),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,656 @@
+use std::{collections::HashMap, io::Write, ops::Range};
+
+use colored::{Color, Colorize};
+
+use super::{FileChange, Status, NULL_HASH};
+
+/// Number of unchanged lines shown around each change.
+pub const CONTEXT_LINES: usize = 3;
+
+/// Only this many leading bytes are inspected when guessing if a file is binary.
+const FIRST_FEW_BYTES: usize = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal { old: usize, new: usize },
+    Delete { old: usize },
+    Insert { new: usize },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hunk {
+    /// Range of the edit script covered by this hunk, context included.
+    pub edits: Range<usize>,
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+/// Git's heuristic: a file is binary if a NUL byte appears in its first few bytes.
+pub fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(FIRST_FEW_BYTES)].contains(&0)
+}
+
+/// Split `data` into lines, keeping the line terminators.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|byte| *byte == b'\n').collect()
+}
+
+/// Compute a minimal line edit script turning `old` into `new`.
+pub fn diff_lines(old: &[&[u8]], new: &[&[u8]]) -> Vec<Edit> {
+    // Intern lines so the diff compares integers instead of byte slices.
+    let mut ids = HashMap::new();
+    let mut intern = |lines: &[&[u8]]| -> Vec<usize> {
+        lines
+            .iter()
+            .map(|line| {
+                let next_id = ids.len();
+                *ids.entry(line.to_vec()).or_insert(next_id)
+            })
+            .collect()
+    };
+    let old = intern(old);
+    let new = intern(new);
+
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut forward = V::new(max_d);
+    let mut backward = V::new(max_d);
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    conquer(
+        &old,
+        0..old.len(),
+        &new,
+        0..new.len(),
+        &mut forward,
+        &mut backward,
+        &mut edits,
+    );
+
+    let mut old_changed = vec![false; old.len()];
+    let mut new_changed = vec![false; new.len()];
+    for edit in edits {
+        match edit {
+            Edit::Equal { .. } => {}
+            Edit::Delete { old } => old_changed[old] = true,
+            Edit::Insert { new } => new_changed[new] = true,
+        }
+    }
+    compact(&old, &mut old_changed);
+    compact(&new, &mut new_changed);
+
+    rebuild_edits(&old_changed, &new_changed)
+}
+
+/// Slide every group of changed lines as far down as possible, like git does, so ambiguous
+/// changes (e.g. a block inserted between two identical lines) always end up in the same place.
+fn compact(lines: &[usize], changed: &mut [bool]) {
+    let mut start = 0;
+    while start < lines.len() {
+        if !changed[start] {
+            start += 1;
+            continue;
+        }
+
+        let mut end = start;
+        while end < lines.len() && changed[end] {
+            end += 1;
+        }
+
+        while end < lines.len() && lines[start] == lines[end] {
+            changed[start] = false;
+            changed[end] = true;
+            start += 1;
+            end += 1;
+            // The group may now touch the next one, in which case they're merged.
+            while end < lines.len() && changed[end] {
+                end += 1;
+            }
+        }
+
+        start = end;
+    }
+}
+
+/// Turn per-line change markers back into an edit script. Deletions are emitted before
+/// insertions within a run of changes.
+fn rebuild_edits(old_changed: &[bool], new_changed: &[bool]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old_changed.len().max(new_changed.len()));
+    let (mut old, mut new) = (0, 0);
+    while old < old_changed.len() || new < new_changed.len() {
+        if old < old_changed.len() && old_changed[old] {
+            edits.push(Edit::Delete { old });
+            old += 1;
+        } else if new < new_changed.len() && new_changed[new] {
+            edits.push(Edit::Insert { new });
+            new += 1;
+        } else {
+            edits.push(Edit::Equal { old, new });
+            old += 1;
+            new += 1;
+        }
+    }
+    edits
+}
+
+/// Count `(insertions, deletions)` in an edit script.
+pub fn count_changes(edits: &[Edit]) -> (usize, usize) {
+    edits
+        .iter()
+        .fold((0, 0), |(insertions, deletions), edit| match edit {
+            Edit::Equal { .. } => (insertions, deletions),
+            Edit::Delete { .. } => (insertions, deletions + 1),
+            Edit::Insert { .. } => (insertions + 1, deletions),
+        })
+}
+
+/// Group an edit script into hunks with `context` lines of context around each change.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal { .. }))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut groups: Vec<(usize, usize)> = vec![];
+    for index in changes {
+        match groups.last_mut() {
+            Some((_, last)) if index - *last - 1 <= 2 * context => *last = index,
+            _ => groups.push((index, index)),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(first, last)| {
+            let range = first.saturating_sub(context)..(last + 1 + context).min(edits.len());
+            let (old_start, new_start) = position_at(edits, range.start);
+            let (old_len, new_len) =
+                edits[range.clone()]
+                    .iter()
+                    .fold((0, 0), |(old, new), edit| match edit {
+                        Edit::Equal { .. } => (old + 1, new + 1),
+                        Edit::Delete { .. } => (old + 1, new),
+                        Edit::Insert { .. } => (old, new + 1),
+                    });
+
+            Hunk {
+                edits: range,
+                old_start,
+                old_len,
+                new_start,
+                new_len,
+            }
+        })
+        .collect()
+}
+
+/// Write the unified diff of `old` and `new`, hunk headers included.
+pub fn write_unified(
+    out: &mut impl Write,
+    old: &[u8],
+    new: &[u8],
+    context: usize,
+) -> std::io::Result<()> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let edits = diff_lines(&old_lines, &new_lines);
+
+    for hunk in hunks(&edits, context) {
+        let header = format!(
+            "@@ -{} +{} @@",
+            hunk_range(hunk.old_start, hunk.old_len),
+            hunk_range(hunk.new_start, hunk.new_len)
+        );
+        write!(out, "{}", header.cyan())?;
+        match function_line(&old_lines, hunk.old_start) {
+            Some(function) => {
+                out.write_all(b" ")?;
+                out.write_all(function)?;
+                out.write_all(b"\n")?;
+            }
+            None => out.write_all(b"\n")?,
+        }
+
+        for edit in &edits[hunk.edits] {
+            let (prefix, line) = match *edit {
+                Edit::Equal { old, .. } => (b' ', old_lines[old]),
+                Edit::Delete { old } => (b'-', old_lines[old]),
+                Edit::Insert { new } => (b'+', new_lines[new]),
+            };
+            write_line(out, prefix, line)?;
+            if !line.ends_with(b"\n") {
+                out.write_all(b"\n\\ No newline at end of file\n")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a line of a hunk, in red or green if it's removed or added and colors are enabled.
+fn write_line(out: &mut impl Write, prefix: u8, line: &[u8]) -> std::io::Result<()> {
+    let color = match prefix {
+        b'-' => Color::Red,
+        b'+' => Color::Green,
+        _ => Color::White,
+    };
+    if color == Color::White || !colored::control::SHOULD_COLORIZE.should_colorize() {
+        out.write_all(&[prefix])?;
+        return out.write_all(line);
+    }
+
+    let content = line.strip_suffix(b"\n").unwrap_or(line);
+    let colored_line = format!("{}{}", prefix as char, String::from_utf8_lossy(content));
+    write!(out, "{}", colored_line.color(color))?;
+    match content.len() == line.len() {
+        true => Ok(()),
+        false => out.write_all(b"\n"),
+    }
+}
+
+/// Write an extended header line, in bold if colors are enabled.
+fn write_meta(out: &mut impl Write, line: &str) -> std::io::Result<()> {
+    writeln!(out, "{}", line.bold())
+}
+
+/// Write the full patch of a single file: the `diff --git` header, the extended headers and the
+/// hunks.
+pub fn write_file_patch(
+    out: &mut impl Write,
+    change: &FileChange,
+    old: &[u8],
+    new: &[u8],
+) -> std::io::Result<()> {
+    if change.status == Status::TypeChanged {
+        // Git shows a type change as the deletion of the old entry followed by an addition.
+        let deletion = FileChange::new(change.old.clone(), None);
+        let addition = FileChange::new(None, change.new.clone());
+        write_file_patch(out, &deletion, old, b"")?;
+        return write_file_patch(out, &addition, b"", new);
+    }
+
+    let old_path = change.old.as_ref().map(|side| side.path.as_str());
+    let new_path = change.new.as_ref().map(|side| side.path.as_str());
+    let a_path = format!("a/{}", old_path.or(new_path).unwrap_or_default());
+    let b_path = format!("b/{}", new_path.or(old_path).unwrap_or_default());
+    write_meta(out, &format!("diff --git {} {}", a_path, b_path))?;
+
+    match (&change.old, &change.new) {
+        (None, Some(new)) => write_meta(out, &format!("new file mode {}", new.mode.permissions()))?,
+        (Some(old), None) => write_meta(
+            out,
+            &format!("deleted file mode {}", old.mode.permissions()),
+        )?,
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            write_meta(out, &format!("old mode {}", old.mode.permissions()))?;
+            write_meta(out, &format!("new mode {}", new.mode.permissions()))?;
+        }
+        _ => {}
+    }
+
+    if let (Status::Renamed(score) | Status::Copied(score), Some(old), Some(new)) =
+        (change.status, old_path, new_path)
+    {
+        let kind = if change.status.letter() == 'R' {
+            "rename"
+        } else {
+            "copy"
+        };
+        write_meta(out, &format!("similarity index {}%", score))?;
+        write_meta(out, &format!("{} from {}", kind, old))?;
+        write_meta(out, &format!("{} to {}", kind, new))?;
+    }
+
+    let old_hash = change.old.as_ref().map_or(NULL_HASH, |side| &side.hash);
+    let new_hash = change.new.as_ref().map_or(NULL_HASH, |side| &side.hash);
+    if old_hash != new_hash {
+        let index = format!("index {}..{}", abbrev(old_hash), abbrev(new_hash));
+        match (&change.old, &change.new) {
+            (Some(old), Some(new)) if old.mode == new.mode => {
+                write_meta(out, &format!("{} {}", index, new.mode.permissions()))?
+            }
+            _ => write_meta(out, &index)?,
+        }
+    }
+
+    if old_hash == new_hash {
+        return Ok(());
+    }
+
+    let a_name = if change.old.is_some() {
+        a_path
+    } else {
+        "/dev/null".to_string()
+    };
+    let b_name = if change.new.is_some() {
+        b_path
+    } else {
+        "/dev/null".to_string()
+    };
+
+    if is_binary(old) || is_binary(new) {
+        return writeln!(out, "Binary files {} and {} differ", a_name, b_name);
+    }
+
+    if old.is_empty() && new.is_empty() {
+        return Ok(());
+    }
+
+    write_meta(out, &format!("--- {}", a_name))?;
+    write_meta(out, &format!("+++ {}", b_name))?;
+    write_unified(out, old, new, CONTEXT_LINES)
+}
+
+/// The abbreviated form of `hash` used in patch headers.
+pub fn abbrev(hash: &str) -> &str {
+    &hash[..hash.len().min(7)]
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Find the line git would show after the hunk header: the closest line above the hunk starting
+/// with a letter, `_` or `$`.
+fn function_line<'a>(lines: &[&'a [u8]], hunk_start: usize) -> Option<&'a [u8]> {
+    lines[..hunk_start.min(lines.len())]
+        .iter()
+        .rev()
+        .find(|line| {
+            line.first()
+                .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_' || *c == b'$')
+        })
+        .map(|line| {
+            let line = &line[..line.len().min(80)];
+            let end = line
+                .iter()
+                .rposition(|c| !c.is_ascii_whitespace())
+                .map_or(0, |index| index + 1);
+            &line[..end]
+        })
+}
+
+fn position_at(edits: &[Edit], index: usize) -> (usize, usize) {
+    match edits.get(index) {
+        Some(Edit::Equal { old, new }) => (*old, *new),
+        Some(Edit::Delete { old }) => (*old, new_position(edits, index)),
+        Some(Edit::Insert { new }) => (old_position(edits, index), *new),
+        None => (0, 0),
+    }
+}
+
+fn old_position(edits: &[Edit], index: usize) -> usize {
+    edits[..index]
+        .iter()
+        .rev()
+        .find_map(|edit| match edit {
+            Edit::Equal { old, .. } | Edit::Delete { old } => Some(old + 1),
+            Edit::Insert { .. } => None,
+        })
+        .unwrap_or(0)
+}
+
+fn new_position(edits: &[Edit], index: usize) -> usize {
+    edits[..index]
+        .iter()
+        .rev()
+        .find_map(|edit| match edit {
+            Edit::Equal { new, .. } | Edit::Insert { new } => Some(new + 1),
+            Edit::Delete { .. } => None,
+        })
+        .unwrap_or(0)
+}
+
+/// A Myers V array indexed by diagonal, which may be negative.
+struct V {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl V {
+    fn new(max_d: usize) -> Self {
+        Self {
+            offset: max_d as isize,
+            v: vec![0; 2 * max_d + 2],
+        }
+    }
+}
+
+impl std::ops::Index<isize> for V {
+    type Output = usize;
+
+    fn index(&self, index: isize) -> &Self::Output {
+        &self.v[(index + self.offset) as usize]
+    }
+}
+
+impl std::ops::IndexMut<isize> for V {
+    fn index_mut(&mut self, index: isize) -> &mut Self::Output {
+        &mut self.v[(index + self.offset) as usize]
+    }
+}
+
+fn common_prefix_len(old: &[usize], new: &[usize]) -> usize {
+    old.iter().zip(new).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix_len(old: &[usize], new: &[usize]) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// Find a point on an optimal edit path through the middle of the edit graph, searching from
+/// both ends at once. This keeps the memory usage linear in the size of the inputs.
+fn find_middle_snake(
+    old: &[usize],
+    old_range: Range<usize>,
+    new: &[usize],
+    new_range: Range<usize>,
+    forward: &mut V,
+    backward: &mut V,
+) -> Option<(usize, usize)> {
+    let n = old_range.len();
+    let m = new_range.len();
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    forward[1] = 0;
+    backward[1] = 0;
+
+    let max_d = ((n + m).div_ceil(2) + 1) as isize;
+    for d in 0..max_d {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[k - 1] < forward[k + 1]) {
+                forward[k + 1]
+            } else {
+                forward[k - 1] + 1
+            };
+            let y = (x as isize - k) as usize;
+            let (x0, y0) = (x, y);
+            if x < n && y < m {
+                x += common_prefix_len(
+                    &old[old_range.start + x..old_range.end],
+                    &new[new_range.start + y..new_range.end],
+                );
+            }
+            forward[k] = x;
+
+            if odd && (k - delta).abs() < d && forward[k] + backward[-(k - delta)] >= n {
+                return Some((x0 + old_range.start, y0 + new_range.start));
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[k - 1] < backward[k + 1]) {
+                backward[k + 1]
+            } else {
+                backward[k - 1] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let advance = common_suffix_len(
+                    &old[old_range.start..old_range.start + n - x],
+                    &new[new_range.start..new_range.start + m - y],
+                );
+                x += advance;
+                y += advance;
+            }
+            backward[k] = x;
+
+            if !odd && (k - delta).abs() <= d && backward[k] + forward[-(k - delta)] >= n {
+                return Some((n - x + old_range.start, m - y + new_range.start));
+            }
+        }
+    }
+
+    None
+}
+
+fn conquer(
+    old: &[usize],
+    mut old_range: Range<usize>,
+    new: &[usize],
+    mut new_range: Range<usize>,
+    forward: &mut V,
+    backward: &mut V,
+    edits: &mut Vec<Edit>,
+) {
+    let prefix = common_prefix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    for i in 0..prefix {
+        edits.push(Edit::Equal {
+            old: old_range.start + i,
+            new: new_range.start + i,
+        });
+    }
+    old_range.start += prefix;
+    new_range.start += prefix;
+
+    let suffix = common_suffix_len(&old[old_range.clone()], &new[new_range.clone()]);
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if old_range.is_empty() {
+        edits.extend(new_range.clone().map(|new| Edit::Insert { new }));
+    } else if new_range.is_empty() {
+        edits.extend(old_range.clone().map(|old| Edit::Delete { old }));
+    } else if let Some((x, y)) = find_middle_snake(
+        old,
+        old_range.clone(),
+        new,
+        new_range.clone(),
+        forward,
+        backward,
+    ) {
+        conquer(
+            old,
+            old_range.start..x,
+            new,
+            new_range.start..y,
+            forward,
+            backward,
+            edits,
+        );
+        conquer(
+            old,
+            x..old_range.end,
+            new,
+            y..new_range.end,
+            forward,
+            backward,
+            edits,
+        );
+    } else {
+        edits.extend(old_range.clone().map(|old| Edit::Delete { old }));
+        edits.extend(new_range.clone().map(|new| Edit::Insert { new }));
+    }
+
+    for i in 0..suffix {
+        edits.push(Edit::Equal {
+            old: old_range.end + i,
+            new: new_range.end + i,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_changes, diff_lines, hunks, split_lines, write_unified, Edit};
+
+    fn lcs_len(a: &[&[u8]], b: &[&[u8]]) -> usize {
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                table[i + 1][j + 1] = if a[i] == b[j] {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[a.len()][b.len()]
+    }
+
+    #[test]
+    fn diff_lines_should_produce_a_minimal_script_that_rebuilds_the_new_file() {
+        let cases: [(&[u8], &[u8]); 5] = [
+            (b"a\nb\nc\na\nb\nb\na\n", b"c\nb\na\nb\na\nc\n"),
+            (b"", b"x\ny\n"),
+            (b"x\ny\n", b""),
+            (b"same\n", b"same\n"),
+            (b"1\n2\n3\n4\n5\n6\n7\n8\n", b"1\n3\n4\nfoo\n5\n7\n8\nbar\n"),
+        ];
+
+        for (old, new) in cases {
+            let old_lines = split_lines(old);
+            let new_lines = split_lines(new);
+            let edits = diff_lines(&old_lines, &new_lines);
+
+            let rebuilt: Vec<&[u8]> = edits
+                .iter()
+                .filter_map(|edit| match edit {
+                    Edit::Equal { new, .. } | Edit::Insert { new } => Some(new_lines[*new]),
+                    Edit::Delete { .. } => None,
+                })
+                .collect();
+            assert_eq!(rebuilt, new_lines);
+
+            let (insertions, deletions) = count_changes(&edits);
+            let common = lcs_len(&old_lines, &new_lines);
+            assert_eq!(insertions, new_lines.len() - common);
+            assert_eq!(deletions, old_lines.len() - common);
+        }
+    }
+
+    #[test]
+    fn hunks_should_merge_changes_separated_by_less_than_twice_the_context() {
+        let old = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n17\n18\n";
+        let new = b"1\nX\n3\n4\n5\n6\n7\nY\n9\n10\n11\n12\n13\n14\n15\n16\nZ\n18\n";
+        let old_lines = split_lines(old);
+        let new_lines = split_lines(new);
+        let edits = diff_lines(&old_lines, &new_lines);
+
+        let hunks = hunks(&edits, 3);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old_start, hunks[0].old_len), (0, 11));
+        assert_eq!((hunks[1].old_start, hunks[1].old_len), (13, 5));
+    }
+
+    #[test]
+    fn write_unified_should_match_git_output() {
+        let old = b"fn main() {\n    println!(\"a\");\n}\n";
+        let new = b"fn main() {\n    println!(\"b\");\n}";
+        let mut out = vec![];
+        write_unified(&mut out, old, new, 3).unwrap();
+
+        let expected = "@@ -1,3 +1,3 @@\n fn main() {\n-    println!(\"a\");\n-}\n+    println!(\"b\");\n+}\n\\ No newline at end of file\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
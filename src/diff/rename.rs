@@ -0,0 +1,369 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use crate::{error::DiffError, git_object::mode::Type};
+
+use super::{patch::is_binary, DiffSide, FileChange, Status};
+
+/// Similarity scores are expressed as a fraction of `MAX_SCORE`, like git does.
+pub const MAX_SCORE: u64 = 60000;
+pub const DEFAULT_RENAME_SCORE: u64 = 30000;
+pub const DEFAULT_RENAME_LIMIT: usize = 1000;
+
+/// Modulus of the chunk hashes used to estimate similarity.
+const HASH_BASE: u32 = 107927;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyDetection {
+    Off,
+    /// Copies may come from files modified in the same change set.
+    Modified,
+    /// Every file of the pre-image is considered as a copy source.
+    Harder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenameOptions {
+    /// Minimum similarity, out of `MAX_SCORE`, for a pair of files to be considered a rename.
+    pub minimum_score: u64,
+    pub copies: CopyDetection,
+    /// Inexact detection is skipped if there are more than `rename_limit²` candidate pairs. Zero
+    /// means no limit, `None` means `DEFAULT_RENAME_LIMIT`.
+    pub rename_limit: Option<usize>,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        Self {
+            minimum_score: DEFAULT_RENAME_SCORE,
+            copies: CopyDetection::Off,
+            rename_limit: None,
+        }
+    }
+}
+
+/// Parse the optional argument of `-M`/`-C`. `50%` is 50%, while a bare number is read as a
+/// decimal fraction: `5` is 50% and `05` is 5%.
+pub fn parse_score(score: &str) -> Option<u64> {
+    if score.is_empty() {
+        return Some(DEFAULT_RENAME_SCORE);
+    }
+
+    if let Some(percent) = score.strip_suffix('%') {
+        let percent: u64 = percent.parse().ok()?;
+        return (percent <= 100).then_some(percent * MAX_SCORE / 100);
+    }
+
+    if !score.bytes().all(|c| c.is_ascii_digit()) || score.len() > 9 {
+        return None;
+    }
+    let value: u64 = score.parse().ok()?;
+    let scale = 10_u64.pow(score.len() as u32);
+    Some((value * MAX_SCORE / scale).min(MAX_SCORE))
+}
+
+pub struct RenameDetection {
+    pub changes: Vec<FileChange>,
+    /// Set when inexact detection was skipped because of the rename limit, to the limit that
+    /// would have been needed.
+    pub needed_rename_limit: Option<usize>,
+}
+
+struct Source {
+    side: DiffSide,
+    /// Index of the deletion this source comes from, if the file doesn't exist anymore.
+    deletion: Option<usize>,
+    /// Number of destinations using this source. Sources that still exist start at one.
+    used: usize,
+}
+
+struct Match {
+    source: usize,
+    score: u64,
+}
+
+/// Pair deleted and added files with identical or similar content into renames and copies.
+///
+/// `unmodified` holds the unchanged files of the pre-image, only used as copy sources with
+/// [`CopyDetection::Harder`]. `load` returns the content of a side.
+pub fn detect_renames(
+    changes: Vec<FileChange>,
+    unmodified: Vec<DiffSide>,
+    options: &RenameOptions,
+    mut load: impl FnMut(&DiffSide) -> Result<Vec<u8>, DiffError>,
+) -> Result<RenameDetection, DiffError> {
+    let copies = options.copies != CopyDetection::Off;
+
+    let mut sources = vec![];
+    let mut destinations = vec![];
+    for (index, change) in changes.iter().enumerate() {
+        match (&change.old, &change.new, change.status) {
+            (Some(old), None, _) if !old.mode.is_tree() => sources.push(Source {
+                side: old.clone(),
+                deletion: Some(index),
+                used: 0,
+            }),
+            (None, Some(new), _) if !new.mode.is_tree() => destinations.push(index),
+            (Some(old), Some(_), Status::Modified) if copies => sources.push(Source {
+                side: old.clone(),
+                deletion: None,
+                used: 1,
+            }),
+            _ => {}
+        }
+    }
+    if options.copies == CopyDetection::Harder {
+        sources.extend(unmodified.into_iter().map(|side| Source {
+            side,
+            deletion: None,
+            used: 1,
+        }));
+    }
+
+    let mut matches: HashMap<usize, Match> = HashMap::new();
+    let mut needed_rename_limit = None;
+    if sources.is_empty() || destinations.is_empty() {
+        return Ok(RenameDetection {
+            changes,
+            needed_rename_limit,
+        });
+    }
+
+    // Exact renames first: they are cheap and always win over similar content.
+    for &destination in &destinations {
+        let new = changes[destination].new.as_ref().unwrap();
+        let basename = |side: &DiffSide| side.path.rsplit('/').next().map(str::to_string);
+        let candidates = sources.iter().enumerate().filter(|(_, source)| {
+            source.side.hash == new.hash
+                && source.side.mode.type_ == new.mode.type_
+                && (copies || source.used == 0)
+        });
+        let best = candidates
+            .clone()
+            .find(|(_, source)| basename(&source.side) == basename(new))
+            .or_else(|| candidates.clone().next());
+
+        if let Some((source, _)) = best {
+            sources[source].used += 1;
+            matches.insert(
+                destination,
+                Match {
+                    source,
+                    score: MAX_SCORE,
+                },
+            );
+        }
+    }
+
+    let remaining: Vec<usize> = destinations
+        .iter()
+        .copied()
+        .filter(|destination| !matches.contains_key(destination))
+        .collect();
+    let limit = options.rename_limit.unwrap_or(DEFAULT_RENAME_LIMIT);
+    if !remaining.is_empty() && limit != 0 && remaining.len() * sources.len() > limit * limit {
+        needed_rename_limit = Some(remaining.len().max(sources.len()));
+    } else if !remaining.is_empty() {
+        let mut source_data = HashMap::new();
+        let mut candidates = vec![];
+        for &destination in &remaining {
+            let new = changes[destination].new.as_ref().unwrap();
+            if new.mode.type_ != Type::RegularFile {
+                continue;
+            }
+            let new_data = load(new)?;
+            let new_fingerprint = Fingerprint::new(&new_data);
+
+            for (index, source) in sources.iter().enumerate() {
+                if source.side.mode.type_ != Type::RegularFile {
+                    continue;
+                }
+                let (source_size, source_fingerprint) = match source_data.entry(index) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let data = load(&source.side)?;
+                        entry.insert((data.len(), Fingerprint::new(&data)))
+                    }
+                };
+
+                let score = similarity(
+                    *source_size,
+                    source_fingerprint,
+                    new_data.len(),
+                    &new_fingerprint,
+                    options.minimum_score,
+                );
+                if score >= options.minimum_score {
+                    candidates.push((score, destination, index));
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        // Renames take priority, copies are only recorded once every rename is found.
+        for allow_copies in [false, true] {
+            if allow_copies && !copies {
+                break;
+            }
+            for &(score, destination, source) in &candidates {
+                if matches.contains_key(&destination) {
+                    continue;
+                }
+                if !allow_copies && sources[source].used > 0 {
+                    continue;
+                }
+                sources[source].used += 1;
+                matches.insert(destination, Match { source, score });
+            }
+        }
+    }
+
+    // Deletions whose file was renamed are replaced by the rename, which keeps the position
+    // of the destination.
+    let renamed: Vec<usize> = sources
+        .iter()
+        .filter(|source| source.used > 0)
+        .filter_map(|source| source.deletion)
+        .collect();
+
+    let mut result = Vec::with_capacity(changes.len());
+    for (index, change) in changes.into_iter().enumerate() {
+        if renamed.contains(&index) {
+            continue;
+        }
+
+        match matches.get(&index) {
+            Some(found) => {
+                let source = &mut sources[found.source];
+                source.used -= 1;
+                let percent = (found.score * 100 / MAX_SCORE) as u8;
+                let status = if source.used == 0 && source.deletion.is_some() {
+                    Status::Renamed(percent)
+                } else {
+                    Status::Copied(percent)
+                };
+
+                result.push(FileChange {
+                    status,
+                    old: Some(source.side.clone()),
+                    new: change.new,
+                });
+            }
+            None => result.push(change),
+        }
+    }
+
+    Ok(RenameDetection {
+        changes: result,
+        needed_rename_limit,
+    })
+}
+
+/// Counts of bytes per chunk hash, used to estimate how much content two files share.
+pub struct Fingerprint {
+    spans: HashMap<u32, usize>,
+}
+
+impl Fingerprint {
+    /// Split `data` in chunks ending at a newline (or 64 bytes long) and count the bytes of each
+    /// chunk by hash. CRs of CRLF sequences are ignored in text files.
+    pub fn new(data: &[u8]) -> Self {
+        let is_text = !is_binary(data);
+        let mut spans = HashMap::new();
+        let (mut accum1, mut accum2, mut length) = (0_u32, 0_u32, 0_usize);
+
+        let mut add_span = |accum1: u32, accum2: u32, length: usize| {
+            let hash = accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASH_BASE;
+            *spans.entry(hash).or_insert(0) += length;
+        };
+
+        for (index, &c) in data.iter().enumerate() {
+            if is_text && c == b'\r' && data.get(index + 1) == Some(&b'\n') {
+                continue;
+            }
+
+            let old_1 = accum1;
+            accum1 = (accum1 << 7) ^ (accum2 >> 25);
+            accum2 = (accum2 << 7) ^ (old_1 >> 25);
+            accum1 = accum1.wrapping_add(c as u32);
+            length += 1;
+            if length < 64 && c != b'\n' {
+                continue;
+            }
+
+            add_span(accum1, accum2, length);
+            (accum1, accum2, length) = (0, 0, 0);
+        }
+        if length > 0 {
+            add_span(accum1, accum2, length);
+        }
+
+        Self { spans }
+    }
+
+    /// The number of bytes of `self` found in `other`.
+    pub fn copied_to(&self, other: &Fingerprint) -> usize {
+        self.spans
+            .iter()
+            .map(|(hash, count)| (*count).min(other.spans.get(hash).copied().unwrap_or(0)))
+            .sum()
+    }
+}
+
+/// Estimate how similar two files are, out of `MAX_SCORE`. Returns 0 early if the sizes alone
+/// make reaching `minimum_score` impossible.
+pub fn similarity(
+    source_size: usize,
+    source: &Fingerprint,
+    destination_size: usize,
+    destination: &Fingerprint,
+    minimum_score: u64,
+) -> u64 {
+    let max_size = source_size.max(destination_size) as u64;
+    let delta_size = max_size - source_size.min(destination_size) as u64;
+    if destination_size == 0 || max_size * (MAX_SCORE - minimum_score) < delta_size * MAX_SCORE {
+        return 0;
+    }
+
+    source.copied_to(destination) as u64 * MAX_SCORE / max_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_score, similarity, Fingerprint, MAX_SCORE};
+
+    #[test]
+    fn parse_score_should_follow_git_conventions() {
+        assert_eq!(parse_score(""), Some(MAX_SCORE / 2));
+        assert_eq!(parse_score("75%"), Some(MAX_SCORE * 3 / 4));
+        assert_eq!(parse_score("5"), Some(MAX_SCORE / 2));
+        assert_eq!(parse_score("05"), Some(MAX_SCORE / 20));
+        assert_eq!(parse_score("120%"), None);
+        assert_eq!(parse_score("abc"), None);
+    }
+
+    #[test]
+    fn similarity_should_be_max_for_identical_content_ignoring_crlf() {
+        let lf = b"one\ntwo\nthree\n";
+        let crlf = b"one\r\ntwo\r\nthree\r\n";
+
+        let score = similarity(
+            lf.len(),
+            &Fingerprint::new(lf),
+            lf.len(),
+            &Fingerprint::new(lf),
+            0,
+        );
+        assert_eq!(score, MAX_SCORE);
+
+        // The CRs aren't hashed but still count in the size, like git.
+        let score = similarity(
+            lf.len(),
+            &Fingerprint::new(lf),
+            crlf.len(),
+            &Fingerprint::new(crlf),
+            0,
+        );
+        assert_eq!(score, lf.len() as u64 * MAX_SCORE / crlf.len() as u64);
+    }
+}
@@ -0,0 +1,304 @@
+use std::io::Write;
+
+use colored::Colorize;
+
+use super::{
+    patch::{count_changes, diff_lines, is_binary, split_lines},
+    FileChange,
+};
+
+/// Default width of the `--stat` output when the terminal width is unknown.
+pub const DEFAULT_STAT_WIDTH: usize = 80;
+
+/// Line counts of one changed file, as shown by `--stat` and `--numstat`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    /// The path, or `old => new` for renames and copies.
+    pub name: String,
+    pub insertions: usize,
+    pub deletions: usize,
+    /// Sizes of the old and new content, if either is binary.
+    pub binary: Option<(usize, usize)>,
+}
+
+impl FileStat {
+    pub fn new(change: &FileChange, old: &[u8], new: &[u8]) -> Self {
+        let name = match (&change.old, change.is_rename_or_copy()) {
+            (Some(side), true) => rename_name(&side.path, change.path()),
+            _ => change.path().to_string(),
+        };
+        let same_content = match (&change.old, &change.new) {
+            (Some(old), Some(new)) => old.hash == new.hash,
+            _ => false,
+        };
+
+        if is_binary(old) || is_binary(new) {
+            let sizes = match same_content {
+                true => (0, 0),
+                false => (old.len(), new.len()),
+            };
+            return Self {
+                name,
+                insertions: 0,
+                deletions: 0,
+                binary: Some(sizes),
+            };
+        }
+
+        let (insertions, deletions) = match same_content {
+            true => (0, 0),
+            false => count_changes(&diff_lines(&split_lines(old), &split_lines(new))),
+        };
+        Self {
+            name,
+            insertions,
+            deletions,
+            binary: None,
+        }
+    }
+}
+
+/// Show a rename compactly, factoring out the leading and trailing directories both paths have
+/// in common: `src/{old.rs => new.rs}`.
+pub fn rename_name(old: &str, new: &str) -> String {
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+
+    let mut prefix = 0;
+    for (index, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = index + 1;
+        }
+    }
+
+    // Walk back from the end, allowed to see the slash ending the common prefix.
+    let mut suffix = 0;
+    let floor = prefix.saturating_sub(1) as isize;
+    let (mut i, mut j) = (a.len() as isize, b.len() as isize);
+    let at = |s: &[u8], index: isize| s.get(index as usize).copied().unwrap_or(0);
+    while i >= floor && j >= floor && at(a, i) == at(b, j) {
+        if at(a, i) == b'/' {
+            suffix = a.len() - i as usize;
+        }
+        i -= 1;
+        j -= 1;
+    }
+
+    let a_middle = a.len().saturating_sub(prefix + suffix);
+    let b_middle = b.len().saturating_sub(prefix + suffix);
+    let middle = format!(
+        "{} => {}",
+        &old[prefix..prefix + a_middle],
+        &new[prefix..prefix + b_middle]
+    );
+    match prefix + suffix {
+        0 => middle,
+        _ => format!(
+            "{}{{{}}}{}",
+            &old[..prefix],
+            middle,
+            &old[old.len() - suffix..]
+        ),
+    }
+}
+
+fn decimal_width(number: usize) -> usize {
+    number.to_string().len()
+}
+
+fn scale_linear(value: usize, width: usize, max_change: usize) -> usize {
+    match value {
+        0 => 0,
+        _ => 1 + value * (width - 1) / max_change,
+    }
+}
+
+/// Write the `--stat` histogram followed by the summary line, fitting in `width` columns.
+pub fn write_stat(out: &mut impl Write, stats: &[FileStat], width: usize) -> std::io::Result<()> {
+    let max_len = stats.iter().map(|stat| stat.name.chars().count()).max();
+    let Some(max_len) = max_len else {
+        return Ok(());
+    };
+
+    let (mut max_change, mut number_width, mut bin_width) = (0, 0, 0);
+    for stat in stats {
+        match stat.binary {
+            Some((old_size, new_size)) => {
+                // "Bin XXX -> YYY bytes"
+                bin_width = bin_width.max(14 + decimal_width(old_size) + decimal_width(new_size));
+                number_width = 3;
+            }
+            None => max_change = max_change.max(stat.insertions + stat.deletions),
+        }
+    }
+
+    number_width = number_width.max(decimal_width(max_change));
+    let width = width.max(16 + 6 + number_width);
+    let mut graph_width = match max_change + 4 > bin_width {
+        true => max_change,
+        false => bin_width - 4,
+    };
+    let mut name_width = max_len;
+
+    if name_width + number_width + 6 + graph_width > width {
+        let graph_limit = (width * 3 / 8).saturating_sub(number_width + 6);
+        if graph_width > graph_limit {
+            graph_width = graph_limit.max(6);
+        }
+        let room = width.saturating_sub(number_width + 6 + graph_width);
+        if name_width > room {
+            name_width = room;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    for stat in stats {
+        let mut name: &str = &stat.name;
+        let mut prefix = "";
+        let mut length = name_width;
+        let name_len = name.chars().count();
+        if name_width < name_len {
+            prefix = "...";
+            length = length.saturating_sub(3);
+            let skip = name_len - length.min(name_len);
+            let start = name.char_indices().nth(skip).map_or(name.len(), |(i, _)| i);
+            name = &name[start..];
+            if let Some(slash) = name.find('/') {
+                name = &name[slash..];
+            }
+        }
+        let padding = length.saturating_sub(name.chars().count());
+        write!(out, " {}{}{} | ", prefix, name, " ".repeat(padding))?;
+
+        if let Some((old_size, new_size)) = stat.binary {
+            write!(out, "{:>width$}", "Bin", width = number_width)?;
+            if old_size != 0 || new_size != 0 {
+                let deleted = old_size.to_string();
+                let added = new_size.to_string();
+                write!(out, " {} -> {} bytes", deleted.red(), added.green())?;
+            }
+            writeln!(out)?;
+            continue;
+        }
+
+        let total = stat.insertions + stat.deletions;
+        let (mut add, mut del) = (stat.insertions, stat.deletions);
+        if graph_width <= max_change {
+            let mut scaled = scale_linear(total, graph_width, max_change);
+            if scaled < 2 && add > 0 && del > 0 {
+                scaled = 2;
+            }
+            if add < del {
+                add = scale_linear(add, graph_width, max_change);
+                del = scaled - add;
+            } else {
+                del = scale_linear(del, graph_width, max_change);
+                add = scaled - del;
+            }
+        }
+
+        write!(out, "{:>width$}", total, width = number_width)?;
+        if total > 0 {
+            write!(out, " ")?;
+        }
+        if add > 0 {
+            write!(out, "{}", "+".repeat(add).green())?;
+        }
+        if del > 0 {
+            write!(out, "{}", "-".repeat(del).red())?;
+        }
+        writeln!(out)?;
+    }
+
+    write_shortstat(out, stats)
+}
+
+/// Write one `insertions<TAB>deletions<TAB>path` line per file, with `-` counts for binary
+/// files.
+pub fn write_numstat(out: &mut impl Write, stats: &[FileStat]) -> std::io::Result<()> {
+    for stat in stats {
+        match stat.binary {
+            Some(_) => writeln!(out, "-\t-\t{}", stat.name)?,
+            None => writeln!(
+                out,
+                "{}\t{}\t{}",
+                stat.insertions, stat.deletions, stat.name
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// Write the summary line: ` 2 files changed, 3 insertions(+), 1 deletion(-)`.
+pub fn write_shortstat(out: &mut impl Write, stats: &[FileStat]) -> std::io::Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+
+    let files = stats.len();
+    let insertions: usize = stats.iter().map(|stat| stat.insertions).sum();
+    let deletions: usize = stats.iter().map(|stat| stat.deletions).sum();
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+
+    write!(out, " {} file{} changed", files, plural(files))?;
+    if insertions > 0 || deletions == 0 {
+        write!(out, ", {} insertion{}(+)", insertions, plural(insertions))?;
+    }
+    if deletions > 0 || insertions == 0 {
+        write!(out, ", {} deletion{}(-)", deletions, plural(deletions))?;
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rename_name, write_stat, FileStat};
+
+    #[test]
+    fn rename_name_should_factor_out_common_directories() {
+        assert_eq!("src/{a.rs => b.rs}", rename_name("src/a.rs", "src/b.rs"));
+        assert_eq!(
+            "{old => new}/lib.rs",
+            rename_name("old/lib.rs", "new/lib.rs")
+        );
+        assert_eq!("a/{ => b}/c", rename_name("a/c", "a/b/c"));
+        assert_eq!("one => two", rename_name("one", "two"));
+    }
+
+    #[test]
+    fn write_stat_should_scale_the_graph_to_the_width() {
+        let stats = [
+            FileStat {
+                name: "small".to_string(),
+                insertions: 1,
+                deletions: 1,
+                binary: None,
+            },
+            FileStat {
+                name: "big".to_string(),
+                insertions: 200,
+                deletions: 0,
+                binary: None,
+            },
+            FileStat {
+                name: "image.png".to_string(),
+                insertions: 0,
+                deletions: 0,
+                binary: Some((10, 20)),
+            },
+        ];
+
+        let mut out = vec![];
+        write_stat(&mut out, &stats, 80).unwrap();
+
+        let expected = format!(
+            " small     |   2 +-\n big       | 200 {}\n image.png | Bin 10 -> 20 bytes\n \
+             3 files changed, 201 insertions(+), 1 deletion(-)\n",
+            "+".repeat(62)
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,144 @@
+use std::{cmp::Ordering, iter::Peekable, vec::IntoIter};
+
+use crate::{
+    error::DiffError,
+    git_object::{compare_leaves, leaf::Leaf, Tree},
+    repository::GitRepository,
+    GitObject,
+};
+
+use super::{DiffSide, FileChange};
+
+/// Walks two trees in parallel and yields the entries that differ between them.
+///
+/// Sub-trees with the same hash on both sides are skipped without being read. Unless
+/// [`TreeDiff::recursive`] is set, changed sub-trees are reported as a single change instead of
+/// being descended into.
+pub struct TreeDiff<'a> {
+    repo: &'a GitRepository,
+    recursive: bool,
+    frames: Vec<Frame>,
+}
+
+struct Frame {
+    base: String,
+    old: Peekable<IntoIter<Leaf>>,
+    new: Peekable<IntoIter<Leaf>>,
+}
+
+impl<'a> TreeDiff<'a> {
+    /// Compare `old` with `new`. A missing tree is treated as an empty one.
+    pub fn new(repo: &'a GitRepository, old: Option<Tree>, new: Option<Tree>) -> Self {
+        let frame = Frame::new(String::new(), old, new);
+        Self {
+            repo,
+            recursive: false,
+            frames: vec![frame],
+        }
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    fn read_tree(&self, leaf: Option<&Leaf>) -> Result<Option<Tree>, DiffError> {
+        leaf.map(|leaf| match self.repo.read_object(&leaf.hash)? {
+            GitObject::Tree(tree) => Ok(tree),
+            _ => Err(DiffError::NotATree(leaf.hash.clone())),
+        })
+        .transpose()
+    }
+
+    fn compare(
+        &mut self,
+        base: String,
+        old: Option<Leaf>,
+        new: Option<Leaf>,
+    ) -> Result<Option<FileChange>, DiffError> {
+        if let (Some(old), Some(new)) = (&old, &new) {
+            if old.hash == new.hash && old.mode == new.mode {
+                return Ok(None);
+            }
+        }
+
+        let path = match old.as_ref().or(new.as_ref()) {
+            Some(leaf) => join_path(&base, &leaf.path),
+            None => return Ok(None),
+        };
+
+        let is_tree = |leaf: &&Leaf| leaf.mode.is_tree();
+        let old_tree = old.as_ref().filter(is_tree);
+        let new_tree = new.as_ref().filter(is_tree);
+        if self.recursive && (old_tree.is_some() || new_tree.is_some()) {
+            let old_tree = self.read_tree(old_tree)?;
+            let new_tree = self.read_tree(new_tree)?;
+            self.frames.push(Frame::new(path, old_tree, new_tree));
+            return Ok(None);
+        }
+
+        let to_side = |leaf: Leaf| DiffSide {
+            path: path.clone(),
+            mode: leaf.mode,
+            hash: leaf.hash,
+        };
+        Ok(Some(FileChange::new(old.map(to_side), new.map(to_side))))
+    }
+}
+
+impl<'a> Iterator for TreeDiff<'a> {
+    type Item = Result<FileChange, DiffError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = self.frames.last_mut()?;
+            let ordering = match (frame.old.peek(), frame.new.peek()) {
+                (None, None) => {
+                    self.frames.pop();
+                    continue;
+                }
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(old), Some(new)) => compare_leaves(old, new),
+            };
+
+            let (old, new) = match ordering {
+                Ordering::Less => (frame.old.next(), None),
+                Ordering::Greater => (None, frame.new.next()),
+                Ordering::Equal => (frame.old.next(), frame.new.next()),
+            };
+            let base = frame.base.clone();
+
+            match self.compare(base, old, new) {
+                Ok(Some(change)) => return Some(Ok(change)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl Frame {
+    fn new(base: String, old: Option<Tree>, new: Option<Tree>) -> Self {
+        let leaves = |tree: Option<Tree>| {
+            tree.map(|tree| tree.leaves)
+                .unwrap_or_default()
+                .into_iter()
+                .peekable()
+        };
+
+        Self {
+            base,
+            old: leaves(old),
+            new: leaves(new),
+        }
+    }
+}
+
+fn join_path(base: &str, name: &str) -> String {
+    if base.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    convert,
+    error::DiffError,
+    git_object::{mode::Type, SerializedGitObject, Type as ObjectType},
+    index::{Index, IndexEntry},
+    parallel,
+    pathspec::Pathspec,
+    repository::GitRepository,
+};
+
+use super::DiffSide;
+
+impl From<&IndexEntry> for DiffSide {
+    fn from(entry: &IndexEntry) -> Self {
+        Self {
+            path: entry.path.clone(),
+            mode: entry.mode.clone(),
+            hash: entry.hash.clone(),
+        }
+    }
+}
+
+/// Tracked entries of `index` matching `pathspec`. Conflicted paths are left out.
+pub fn index_files(index: &Index, pathspec: &Pathspec) -> Vec<DiffSide> {
+    index
+        .entries()
+        .iter()
+        .filter(|entry| entry.stage == 0 && pathspec.matches(&entry.path))
+        .map(DiffSide::from)
+        .collect()
+}
+
+/// The work tree version of every tracked file matching `pathspec`, hashed as it would be
+/// stored. Files missing from the work tree are left out, while submodules, entries marked
+/// skip-worktree and files found unchanged from their stat data are taken from the index.
+pub fn work_tree_files(
+    repo: &GitRepository,
+    index: &Index,
+    pathspec: &Pathspec,
+) -> Result<Vec<DiffSide>, DiffError> {
+    let entries: Vec<&IndexEntry> = index
+        .entries()
+        .iter()
+        .filter(|entry| entry.stage == 0 && pathspec.matches(&entry.path))
+        .collect();
+    let hash = |entry: &&IndexEntry| {
+        if entry.skip_worktree || entry.mode.type_ == Type::Submodule {
+            return Ok(Some(DiffSide::from(*entry)));
+        }
+
+        // Files whose stat data shows no change aren't read.
+        match std::fs::symlink_metadata(repo.work_tree_path(&entry.path)) {
+            Ok(metadata) if repo.is_stat_clean(index, entry, &metadata) => {
+                return Ok(Some(DiffSide::from(*entry)));
+            }
+            _ => {}
+        }
+        let file = repo.read_work_tree_file(&entry.path, Some((&entry.mode, &entry.hash)))?;
+        Ok::<_, DiffError>(file.map(|(mode, data)| DiffSide {
+            path: entry.path.clone(),
+            mode,
+            hash: SerializedGitObject::hash_of(ObjectType::Blob, &data),
+        }))
+    };
+    let workers = repo.workers(entries.len());
+    let files = convert::with_ordered_warnings(|| parallel::try_map(workers, &entries, hash))?;
+    Ok(files.into_iter().flatten().collect())
+}
@@ -6,38 +6,129 @@ use std::{
 #[derive(Debug, Clone)]
 pub struct DirectoryManager {
     pub work_tree: PathBuf,
+    /// The git directory of the work tree: `.git`, or `.git/worktrees/<name>` of the main work
+    /// tree for a linked work tree, which holds its `HEAD` and index.
     pub dot_git_path: PathBuf,
+    /// The git directory shared by every work tree, holding the objects, the refs and the
+    /// configuration. It's `dot_git_path` unless the work tree is a linked one.
+    pub common_dir: PathBuf,
     pub config_file: PathBuf,
     pub description_file: PathBuf,
     pub head_file: PathBuf,
+    pub index_file: PathBuf,
+    pub logs_path: PathBuf,
     pub branches_path: PathBuf,
     pub objects_path: PathBuf,
     pub refs_path: PathBuf,
     pub refs_tags_path: PathBuf,
     pub refs_heads_path: PathBuf,
+    /// The refs packed together in a single file, shared by every work tree.
+    pub packed_refs_file: PathBuf,
 }
 
+/// Refs below these prefixes belong to a work tree rather than being shared.
+const PER_WORK_TREE_REFS: [&str; 3] = ["refs/worktree/", "refs/bisect/", "refs/rewritten/"];
+
 impl DirectoryManager {
     pub fn new<T: Into<PathBuf>>(base_path: T) -> Self {
         let base_path: PathBuf = base_path.into();
         let dot_git_path = base_path.join(".git");
+        Self::with_git_dirs(base_path, dot_git_path.clone(), dot_git_path)
+    }
 
+    pub(crate) fn with_git_dirs(
+        work_tree: PathBuf,
+        dot_git_path: PathBuf,
+        common_dir: PathBuf,
+    ) -> Self {
         Self {
-            work_tree: base_path,
-            config_file: dot_git_path.join("config"),
-            description_file: dot_git_path.join("description"),
+            work_tree,
+            config_file: common_dir.join("config"),
+            description_file: common_dir.join("description"),
             head_file: dot_git_path.join("HEAD"),
-            branches_path: dot_git_path.join("branches"),
-            objects_path: dot_git_path.join("objects"),
-            refs_path: dot_git_path.join("refs"),
-            refs_tags_path: dot_git_path.join("refs").join("tags"),
-            refs_heads_path: dot_git_path.join("refs").join("heads"),
+            index_file: dot_git_path.join("index"),
+            logs_path: common_dir.join("logs"),
+            branches_path: common_dir.join("branches"),
+            objects_path: common_dir.join("objects"),
+            refs_path: common_dir.join("refs"),
+            refs_tags_path: common_dir.join("refs").join("tags"),
+            refs_heads_path: common_dir.join("refs").join("heads"),
+            packed_refs_file: common_dir.join("packed-refs"),
             dot_git_path,
+            common_dir,
+        }
+    }
+
+    /// The directories of the work tree at `base_path`, whose `.git` is either a directory or,
+    /// for a linked work tree, a file pointing to its git directory.
+    pub fn open<T: Into<PathBuf>>(base_path: T) -> Result<Self, std::io::Error> {
+        let base_path: PathBuf = base_path.into();
+        let dot_git = base_path.join(".git");
+        if !dot_git.is_file() {
+            return Ok(Self::new(base_path));
         }
+
+        let git_dir = Self::read_git_file(&dot_git)?;
+        let git_dir = base_path.join(git_dir);
+        // `commondir` is relative to the git directory.
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(common_dir) => git_dir.join(common_dir.trim_end()).canonicalize()?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => git_dir.clone(),
+            Err(error) => return Err(error),
+        };
+        Ok(Self::with_git_dirs(base_path, git_dir, common_dir))
+    }
+
+    /// The git directory a `.git` file points to with its `gitdir: <path>` line.
+    fn read_git_file(path: &Path) -> Result<PathBuf, std::io::Error> {
+        let content = fs::read_to_string(path)?;
+        content
+            .trim_end()
+            .strip_prefix("gitdir: ")
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid gitfile format: {}", path.display()),
+                )
+            })
     }
 
     pub fn is_toplevel_directory(path: &Path) -> bool {
-        path.exists() && path.join(".git").is_dir() && path.join(".git/config").is_file()
+        let dot_git = path.join(".git");
+        path.exists()
+            && ((dot_git.is_dir() && dot_git.join("config").is_file())
+                || (dot_git.is_file() && Self::read_git_file(&dot_git).is_ok()))
+    }
+
+    /// Whether this is a linked work tree rather than the main one.
+    pub fn is_linked(&self) -> bool {
+        self.dot_git_path != self.common_dir
+    }
+
+    pub(crate) fn is_per_work_tree_ref(name: &str) -> bool {
+        !name.starts_with("refs/")
+            || PER_WORK_TREE_REFS
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+    }
+
+    /// Where the ref `name`, such as `HEAD` or `refs/heads/master`, is stored. `HEAD`, pseudo
+    /// refs such as `ORIG_HEAD` and a few others belong to the work tree, the refs below
+    /// `refs/` are shared otherwise.
+    pub fn ref_path(&self, name: &str) -> PathBuf {
+        match Self::is_per_work_tree_ref(name) {
+            true => self.dot_git_path.join(name),
+            false => self.common_dir.join(name),
+        }
+    }
+
+    /// Where the reflog of the ref `name` is stored, following [`DirectoryManager::ref_path`].
+    pub fn reflog_path(&self, name: &str) -> PathBuf {
+        match Self::is_per_work_tree_ref(name) {
+            true => self.dot_git_path.join("logs").join(name),
+            false => self.logs_path.join(name),
+        }
     }
 
     pub fn is_dot_git_empty(&self) -> Result<bool, std::io::Error> {
@@ -71,7 +162,7 @@ impl DirectoryManager {
 
 #[cfg(test)]
 mod tests {
-    const PROJECT_DIR: &'static str = "~/home/projects/test";
+    const PROJECT_DIR: &str = "~/home/projects/test";
     use std::path::{Path, PathBuf};
 
     use crate::DirectoryManager;
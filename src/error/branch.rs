@@ -0,0 +1,41 @@
+use thiserror::Error;
+
+use super::{repository::ResolveRefError, ObjectParseError, WorktreeError};
+
+#[derive(Debug, Error)]
+pub enum BranchError {
+    #[error("'{0}' is not a valid branch name")]
+    InvalidName(String),
+
+    #[error("branch '{0}' not found")]
+    NotFound(String),
+
+    #[error("a branch named '{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("cannot delete branch '{0}' used by worktree at '{1}'")]
+    CheckedOut(String, String),
+
+    #[error("cannot force update the branch '{0}' used by worktree at '{1}'")]
+    ForceUpdateCheckedOut(String, String),
+
+    #[error(
+        "the branch '{0}' is not fully merged\nIf you are sure you want to delete it, run 'rit branch -D {0}'"
+    )]
+    NotFullyMerged(String),
+
+    #[error("the requested upstream branch '{0}' does not exist")]
+    UpstreamNotFound(String),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    ResolveRefError(#[from] ResolveRefError),
+
+    #[error(transparent)]
+    WorktreeError(#[from] WorktreeError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
@@ -0,0 +1,64 @@
+use thiserror::Error;
+
+use super::{repository::ResolveRefError, DiffError, IndexError, ObjectParseError};
+
+fn path_list(paths: &[String]) -> String {
+    paths.iter().map(|path| format!("\t{}\n", path)).collect()
+}
+
+#[derive(Debug, Error)]
+pub enum CheckoutError {
+    #[error(
+        "Your local changes to the following files would be overwritten by checkout:\n{}Please commit your changes or stash them before you switch branches.",
+        path_list(.0)
+    )]
+    LocalChanges(Vec<String>),
+
+    #[error(
+        "The following untracked working tree files would be overwritten by checkout:\n{}Please move or remove them before you switch branches.",
+        path_list(.0)
+    )]
+    UntrackedOverwritten(Vec<String>),
+
+    #[error(
+        "The following untracked working tree files would be removed by checkout:\n{}Please move or remove them before you switch branches.",
+        path_list(.0)
+    )]
+    UntrackedRemoved(Vec<String>),
+
+    #[error(
+        "Your local changes to the following files would be overwritten by merge:\n{}Please commit your changes or stash them before you merge.\nAborting",
+        path_list(.0)
+    )]
+    MergeLocalChanges(Vec<String>),
+
+    #[error(
+        "The following untracked working tree files would be overwritten by merge:\n{}Please move or remove them before you merge.\nAborting",
+        path_list(.0)
+    )]
+    MergeUntrackedOverwritten(Vec<String>),
+
+    #[error("You need to resolve your current index first")]
+    UnmergedIndex,
+
+    #[error("{0} is not a blob object")]
+    NotABlob(String),
+
+    #[error(transparent)]
+    DiffError(#[from] DiffError),
+
+    #[error(transparent)]
+    IndexError(#[from] IndexError),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    ResolveRefError(#[from] ResolveRefError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
@@ -0,0 +1,29 @@
+use thiserror::Error;
+
+use super::{ObjectCreateError, ObjectParseError};
+
+#[derive(Debug, Error)]
+pub enum CommitError {
+    #[error(
+        "{0} identity unknown\n\n*** Please tell me who you are.\n\nRun\n\n  git config user.email \"you@example.com\"\n  git config user.name \"Your Name\"\n\nto set your account's default identity."
+    )]
+    IdentityUnknown(String),
+
+    #[error("Invalid date format: {0}")]
+    InvalidDate(String),
+
+    #[error("{0}: needs merge")]
+    Unmerged(String),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    ObjectCreateError(#[from] ObjectCreateError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    #[error("{0} would be replaced by {1} in {2}")]
+    LineEndingsReplaced(String, String, String),
+
+    #[error("{0}: {1} filter '{2}' failed")]
+    FilterFailed(String, String, String),
+}
@@ -0,0 +1,21 @@
+use thiserror::Error;
+
+use super::ObjectParseError;
+
+#[derive(Debug, Error)]
+pub enum DiffError {
+    #[error("{0} is not a tree object")]
+    NotATree(String),
+
+    #[error("{0} is not a blob object")]
+    NotABlob(String),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
@@ -2,6 +2,8 @@ use std::num::ParseIntError;
 
 use thiserror::Error;
 
+use crate::git_object::Type;
+
 #[derive(Debug, Error)]
 pub enum ObjectParseError {
     #[error("Object type is not valid")]
@@ -13,6 +15,9 @@ pub enum ObjectParseError {
     #[error("Header size differs from the actual read bytes")]
     MismatchedObjectSize,
 
+    #[error("Object {0} cannot be peeled to a {1}")]
+    CannotPeel(String, Type),
+
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 
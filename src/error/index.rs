@@ -0,0 +1,30 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error("Index file has an invalid signature")]
+    InvalidSignature,
+
+    #[error("Index file version {0} is not supported")]
+    UnsupportedVersion(u32),
+
+    #[error("Index file is corrupt: {0}")]
+    Corrupt(String),
+
+    #[error("Index file checksum mismatch")]
+    ChecksumMismatch,
+
+    #[error("Index uses extension {0} which is not supported")]
+    UnsupportedExtension(String),
+
+    #[error(
+        "Unable to lock the index: {0} exists. Another rit or git process seems to be running"
+    )]
+    Locked(String),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
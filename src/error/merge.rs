@@ -0,0 +1,18 @@
+use thiserror::Error;
+
+use super::{DiffError, ObjectCreateError};
+
+#[derive(Debug, Error)]
+pub enum MergeError {
+    #[error(transparent)]
+    DiffError(#[from] DiffError),
+
+    #[error(transparent)]
+    ObjectCreateError(#[from] ObjectCreateError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
@@ -1,9 +1,35 @@
+pub mod branch;
+pub mod checkout;
 pub mod cli;
+pub mod commit;
+pub mod convert;
+pub mod diff;
 pub mod git_config;
 pub mod git_object;
+pub mod index;
+pub mod merge;
+pub mod pathspec;
+pub mod ref_format;
+pub mod reftable;
 pub mod repository;
+pub mod revision;
+pub mod stash;
+pub mod worktree;
 
+pub use branch::BranchError;
+pub use checkout::CheckoutError;
 pub use cli::ParseArgumentsError;
+pub use commit::CommitError;
+pub use convert::ConvertError;
+pub use diff::DiffError;
 pub use git_config::ConfigParseError;
 pub use git_object::*;
+pub use index::IndexError;
+pub use merge::MergeError;
+pub use pathspec::PathspecError;
+pub use ref_format::RefFormatError;
+pub use reftable::ReftableError;
 pub use repository::CreateRepoError;
+pub use revision::RevisionError;
+pub use stash::StashError;
+pub use worktree::WorktreeError;
@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PathspecError {
+    #[error("'{0}' is outside repository")]
+    OutsideRepository(String),
+
+    #[error("Invalid pathspec magic in '{0}'")]
+    InvalidMagic(String),
+}
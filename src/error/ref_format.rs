@@ -0,0 +1,30 @@
+use thiserror::Error;
+
+use super::{repository::ResolveRefError, ObjectParseError, RevisionError};
+
+#[derive(Debug, Error)]
+pub enum RefFormatError {
+    #[error("unknown field name: {0}")]
+    UnknownAtom(String),
+
+    #[error("malformed format string {0}")]
+    MalformedFormat(String),
+
+    #[error("unrecognized %({0}) argument: {1}")]
+    InvalidModifier(String, String),
+
+    #[error("unknown date format {0}")]
+    InvalidDateFormat(String),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    ResolveRefError(#[from] ResolveRefError),
+
+    #[error(transparent)]
+    RevisionError(#[from] RevisionError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
@@ -0,0 +1,31 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ReftableError {
+    #[error("{0} is not a reftable")]
+    InvalidSignature(String),
+
+    #[error("reftable version {0} is not supported")]
+    UnsupportedVersion(u8),
+
+    #[error("reftable is corrupt: {0}")]
+    Corrupt(String),
+
+    #[error("reftable footer checksum mismatch in {0}")]
+    ChecksumMismatch(String),
+
+    #[error("invalid object name '{0}'")]
+    InvalidObjectName(String),
+
+    #[error("record of '{0}' doesn't fit in a reftable block of {1} bytes")]
+    RecordTooLarge(String, u32),
+
+    #[error(
+        "unable to lock the reftable stack: {0} exists. Another rit or git process seems to be \
+         running"
+    )]
+    Locked(String),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use super::ConfigParseError;
+use super::{ConfigParseError, ReftableError};
 
 #[derive(Debug, Error)]
 pub enum CreateRepoError {
@@ -28,6 +28,64 @@ pub enum ResolveRefError {
     #[error("Relative path {0} is not a file")]
     RelativePathIsNotAFile(String),
 
+    #[error("unexpected line in packed-refs: {0}")]
+    InvalidPackedRefs(String),
+
+    #[error(
+        "cannot lock ref '{0}': Unable to create '{1}': File exists.\n\n\
+         Another rit or git process seems to be running in this repository. If it crashed \
+         earlier, remove the file manually to continue."
+    )]
+    Locked(String, String),
+
+    #[error("cannot lock ref '{0}': is at {1} but expected {2}")]
+    OldValueMismatch(String, String, String),
+
+    #[error("cannot lock ref '{0}': reference already exists")]
+    AlreadyExists(String),
+
+    #[error("cannot lock ref '{0}': unable to resolve reference '{0}'")]
+    Missing(String),
+
+    #[error("cannot lock ref '{1}': '{0}' exists; cannot create '{1}'")]
+    NameConflict(String, String),
+
+    #[error("multiple updates for ref '{0}' not allowed")]
+    DuplicateUpdate(String),
+
+    #[error("cannot update ref '{0}': trying to write ref '{0}' with nonexistent object {1}")]
+    MissingObject(String, String),
+
+    #[error("reflog entry {0} not found")]
+    MissingReflogEntry(String),
+
+    #[error("your current branch '{0}' does not have any commits yet")]
+    UnbornBranch(String),
+
+    #[error("invalid HEAD: '{0}' is neither a symbolic ref nor a commit")]
+    InvalidHead(String),
+
+    #[error("ref {0} is not a symbolic ref")]
+    NotASymbolicRef(String),
+
+    #[error("refusing to point {0} outside of refs/")]
+    SymbolicRefOutsideRefs(String),
+
+    #[error("refusing to set '{0}' to invalid ref '{1}'")]
+    InvalidSymbolicRefTarget(String, String),
+
+    #[error("refusing to update ref with bad name '{0}'")]
+    BadName(String),
+
+    #[error("'{0}' is not a valid tag name")]
+    InvalidTagName(String),
+
+    #[error("invalid expiry date '{0}'")]
+    InvalidExpiryDate(String),
+
+    #[error(transparent)]
+    ReftableError(#[from] ReftableError),
+
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 
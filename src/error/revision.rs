@@ -0,0 +1,67 @@
+use thiserror::Error;
+
+use crate::git_object::Type;
+
+use super::{repository::ResolveRefError, IndexError, ObjectParseError};
+
+/// One line per object a short hash may stand for, with its type.
+fn list_candidates(candidates: &[(String, Type)]) -> String {
+    candidates
+        .iter()
+        .map(|(hash, type_)| format!("\n  {} {}", hash, type_))
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum RevisionError {
+    #[error("invalid revision '{0}'")]
+    Invalid(String),
+
+    #[error("unknown revision '{0}'")]
+    Unknown(String),
+
+    #[error(
+        "short object ID {0} is ambiguous, the candidates are:{}",
+        list_candidates(.1)
+    )]
+    Ambiguous(String, Vec<(String, Type)>),
+
+    #[error("commit {0} has no parent {1}")]
+    NoParent(String, usize),
+
+    #[error("log for '{0}' only has {1} entries")]
+    NoReflogEntry(String, usize),
+
+    #[error("no upstream configured for branch '{0}'")]
+    NoUpstream(String),
+
+    #[error("HEAD is not on a branch")]
+    NoCurrentBranch,
+
+    #[error("no branch was checked out {0} checkouts ago")]
+    NoPreviousCheckout(usize),
+
+    #[error("path '{0}' does not exist in '{1}'")]
+    PathNotInTree(String, String),
+
+    #[error("path '{0}' is not in the index at stage {1}")]
+    PathNotInIndex(String, u8),
+
+    #[error("no commit message matches '{0}'")]
+    NoMatchingCommit(String),
+
+    #[error("invalid regular expression '{0}'")]
+    InvalidPattern(String),
+
+    #[error(transparent)]
+    IndexError(#[from] IndexError),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    ResolveRefError(#[from] ResolveRefError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
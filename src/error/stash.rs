@@ -0,0 +1,60 @@
+use thiserror::Error;
+
+use super::{
+    repository::ResolveRefError, CheckoutError, CommitError, DiffError, IndexError, MergeError,
+    ObjectParseError,
+};
+
+#[derive(Debug, Error)]
+pub enum StashError {
+    #[error("You do not have the initial commit yet")]
+    NoInitialCommit,
+
+    #[error("No stash entries found.")]
+    NoEntries,
+
+    #[error("{0} is not a valid reference")]
+    InvalidReference(String),
+
+    #[error("'{0}' is not a stash reference")]
+    NotAStashReference(String),
+
+    #[error("'{0}' is not a stash-like commit")]
+    NotStashLike(String),
+
+    #[error("Cannot apply a stash in the middle of a merge")]
+    UnmergedIndex,
+
+    #[error("Conflicts in index. Try without --index.")]
+    IndexConflicts,
+
+    #[error("{0} already exists, no checkout\ncould not restore untracked files from stash")]
+    UntrackedExists(String),
+
+    #[error(transparent)]
+    CheckoutError(#[from] CheckoutError),
+
+    #[error(transparent)]
+    CommitError(#[from] CommitError),
+
+    #[error(transparent)]
+    DiffError(#[from] DiffError),
+
+    #[error(transparent)]
+    IndexError(#[from] IndexError),
+
+    #[error(transparent)]
+    MergeError(#[from] MergeError),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    ResolveRefError(#[from] ResolveRefError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
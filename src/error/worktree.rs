@@ -0,0 +1,68 @@
+use thiserror::Error;
+
+use super::{
+    repository::ResolveRefError, CheckoutError, CreateRepoError, DiffError, IndexError,
+    ObjectParseError,
+};
+
+/// `, <label>: <reason>` if a lock has a reason.
+fn lock_reason(label: &str, reason: &Option<String>) -> String {
+    match reason {
+        Some(reason) if !reason.is_empty() => format!(", {}: {}", label, reason),
+        _ => String::new(),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum WorktreeError {
+    #[error("'{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("'{0}' is already checked out at '{1}'")]
+    AlreadyCheckedOut(String, String),
+
+    #[error("'{0}' is not a working tree")]
+    NotAWorktree(String),
+
+    #[error("'{0}' is a main working tree")]
+    MainWorktree(String),
+
+    #[error("The main working tree cannot be locked or unlocked")]
+    MainWorktreeLock,
+
+    #[error("'{0}' is already locked{}", lock_reason("reason", .1))]
+    AlreadyLocked(String, Option<String>),
+
+    #[error("'{0}' is not locked")]
+    NotLocked(String),
+
+    #[error(
+        "cannot remove a locked working tree{}\nuse 'remove -f -f' to override or unlock first",
+        lock_reason("lock reason", .0)
+    )]
+    Locked(Option<String>),
+
+    #[error("'{0}' contains modified or untracked files, use --force to delete it")]
+    LocalChanges(String),
+
+    #[error(transparent)]
+    CheckoutError(#[from] CheckoutError),
+
+    #[error(transparent)]
+    CreateRepoError(#[from] CreateRepoError),
+
+    #[error(transparent)]
+    DiffError(#[from] DiffError),
+
+    #[error(transparent)]
+    IndexError(#[from] IndexError),
+
+    #[error(transparent)]
+    ObjectParseError(#[from] ObjectParseError),
+
+    #[error(transparent)]
+    ResolveRefError(#[from] ResolveRefError),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
@@ -1,9 +1,40 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    io::{BufWriter, IsTerminal, Read, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
 
 use crate::{
+    attributes,
+    diff::{
+        self,
+        stat::{self, FileStat},
+        CopyDetection, DiffSide, FileChange, OutputFormat, RenameDetection, RenameOptions,
+        TreeDiff,
+    },
+    error::{repository::ResolveRefError, IndexError, StashError, WorktreeError},
     git_object::{self},
-    repository::GitRepository,
-    TagSubCommand,
+    index::Index,
+    parallel,
+    pathspec::{self, Pathspec},
+    ref_format::{take_count, RefFilter, RefFormat, SortKey},
+    ref_name::{self, RefNameOptions},
+    repository::{
+        branch,
+        clean::{CleanAction, CleanOptions, IgnoredFiles},
+        ref_backend::RefStorage,
+        ref_transaction::RefTransaction,
+        reflog,
+        refs::{self, Ref},
+        stash::Stash,
+        worktree::Worktree,
+        GitRepository,
+    },
+    revision::{ExpectedType, Revision},
+    sparse::SparsePatterns,
+    wildmatch, BranchSubCommand, ColorWhen, ReflogSubCommand, ResetMode, SparseCheckoutSubCommand,
+    StashSubCommand, TagSubCommand, WorktreeSubCommand,
 };
 use anyhow::{Context, Result};
 use colored::Colorize;
@@ -16,10 +47,15 @@ fn find_repo_in_current_directory() -> Result<GitRepository, anyhow::Error> {
 pub fn cmd_tag(command: TagSubCommand) -> Result<()> {
     let repo = find_repo_in_current_directory()?;
     match command {
-        TagSubCommand::ListTags => {
-            let tags = repo.list_refs_in(&PathBuf::from("tags"))?;
-            for tag in tags {
-                println!("{}", tag);
+        TagSubCommand::ListTags {
+            format,
+            sort,
+            filter,
+        } => {
+            let format = RefFormat::parse(format.as_deref().unwrap_or("%(refname:strip=2)"))?;
+            let tags = repo.filter_refs(repo.list_refs_in(&PathBuf::from("tags"))?, &filter)?;
+            for tag in repo.sort_refs(tags, &sort)? {
+                println!("{}", repo.format_ref(&tag, &format)?);
             }
         }
         TagSubCommand::CreateTagObject { name, object } => repo.create_tag_object(name, object)?,
@@ -31,6 +67,26 @@ pub fn cmd_tag(command: TagSubCommand) -> Result<()> {
     Ok(())
 }
 
+pub fn cmd_for_each_ref(
+    format: Option<String>,
+    sort: Vec<SortKey>,
+    count: Option<usize>,
+    filter: RefFilter,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    let format = match format {
+        Some(format) => RefFormat::parse(&format)?,
+        None => RefFormat::default(),
+    };
+    let refs = repo.filter_refs(repo.list_refs()?, &filter)?;
+    let refs = repo.sort_refs(refs, &sort)?;
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for ref_item in take_count(&refs, count) {
+        writeln!(out, "{}", repo.format_ref(ref_item, &format)?)?;
+    }
+    Ok(())
+}
+
 pub fn cmd_show_ref() -> Result<()> {
     let repo = find_repo_in_current_directory()?;
     let refs = repo.list_refs()?;
@@ -41,6 +97,202 @@ pub fn cmd_show_ref() -> Result<()> {
     Ok(())
 }
 
+/// The `<new>` or `<old>` value of a ref update: the null hash for a ref that doesn't exist,
+/// which an empty value also means, or the object named.
+fn ref_update_value(repo: &GitRepository, value: &str) -> Result<String> {
+    match value {
+        "" | diff::NULL_HASH => Ok(diff::NULL_HASH.to_string()),
+        _ if value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(value.to_lowercase())
+        }
+        _ => repo
+            .find_object(value)
+            .with_context(|| format!("{}: not a valid SHA1", value)),
+    }
+}
+
+/// Add the `update`, `create`, `delete` or `verify` command of `update-ref`, whose arguments
+/// are `args`, to `transaction`.
+fn add_ref_update<'a>(
+    repo: &GitRepository,
+    transaction: &mut RefTransaction,
+    command: &str,
+    mut args: impl Iterator<Item = &'a str>,
+) -> Result<()> {
+    if !matches!(command, "update" | "create" | "delete" | "verify") {
+        anyhow::bail!("unknown command: {}", command);
+    }
+    let name = args
+        .next()
+        .filter(|name| !name.is_empty())
+        .with_context(|| format!("{}: missing <ref>", command))?;
+    let mut value = |what: &str| -> Result<Option<String>> {
+        match args.next() {
+            Some(value) => Ok(Some(ref_update_value(repo, value)?)),
+            None if what.is_empty() => Ok(None),
+            None => anyhow::bail!("{} {}: missing <{}>", command, name, what),
+        }
+    };
+    match command {
+        "update" => {
+            let new = value("newvalue")?.unwrap_or_default();
+            let old = value("")?;
+            match new.as_str() {
+                diff::NULL_HASH => transaction.delete(name, old.as_deref()),
+                _ => transaction.update(name, &new, old.as_deref()),
+            };
+        }
+        "create" => {
+            let new = value("newvalue")?.unwrap_or_default();
+            transaction.create(name, &new);
+        }
+        "delete" => {
+            let old = value("")?;
+            transaction.delete(name, old.as_deref());
+        }
+        "verify" => {
+            let old = value("")?;
+            transaction.verify(name, old.as_deref());
+        }
+        _ => unreachable!(),
+    }
+
+    let extra: Vec<&str> = args.collect();
+    if !extra.is_empty() {
+        anyhow::bail!("{} {}: extra input: {}", command, name, extra.join(" "));
+    }
+    Ok(())
+}
+
+pub fn cmd_symbolic_ref(
+    quiet: bool,
+    short: bool,
+    message: Option<String>,
+    name: String,
+    target: Option<String>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    if let Some(target) = target {
+        repo.set_symbolic_ref(&name, &target, message.as_deref().unwrap_or_default())?;
+        return Ok(());
+    }
+    match repo.read_symbolic_ref(&name) {
+        Ok(target) if short => println!("{}", refs::shorten_ref_name(&target)),
+        Ok(target) => println!("{}", target),
+        Err(ResolveRefError::NotASymbolicRef(_)) if quiet => std::process::exit(1),
+        Err(error) => return Err(error.into()),
+    }
+    Ok(())
+}
+
+pub fn cmd_rev_parse(
+    verify: bool,
+    quiet: bool,
+    short: Option<usize>,
+    abbrev_ref: bool,
+    symbolic_full_name: bool,
+    revisions: Vec<String>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    if verify || short.is_some() {
+        let resolved = match revisions.as_slice() {
+            [revision] => repo.resolve_revision(revision).is_ok(),
+            _ => false,
+        };
+        match resolved {
+            true => {}
+            false if quiet => std::process::exit(1),
+            false => Err(anyhow::anyhow!("Needed a single revision"))?,
+        }
+    }
+
+    for revision in &revisions {
+        let hash = repo.resolve_revision(revision)?;
+        if abbrev_ref || symbolic_full_name {
+            match repo.revision_ref_name(revision)? {
+                Some(name) if abbrev_ref => println!("{}", refs::shorten_ref_name(&name)),
+                Some(name) => println!("{}", name),
+                None => {}
+            }
+        } else if let Some(length) = short {
+            println!("{}", repo.abbreviate_hash(&hash, length)?);
+        } else {
+            println!("{}", hash);
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_update_ref(
+    no_deref: bool,
+    stdin: bool,
+    delete: bool,
+    message: Option<String>,
+    args: Vec<String>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    let mut transaction = match no_deref {
+        true => RefTransaction::no_deref(),
+        false => RefTransaction::new(),
+    };
+    if let Some(message) = &message {
+        transaction.set_message(message);
+    }
+
+    if stdin {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        for line in input.lines().filter(|line| !line.is_empty()) {
+            let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+            add_ref_update(&repo, &mut transaction, command, args.split(' '))?;
+        }
+    } else {
+        let command = match delete {
+            true => "delete",
+            false => "update",
+        };
+        if delete && args.len() > 2 {
+            anyhow::bail!("usage: rit update-ref -d <ref> [<old>]");
+        }
+        add_ref_update(
+            &repo,
+            &mut transaction,
+            command,
+            args.iter().map(String::as_str),
+        )?;
+    }
+    repo.commit_ref_transaction(&transaction)?;
+    Ok(())
+}
+
+pub fn cmd_check_ref_format(branch: bool, options: RefNameOptions, name: String) -> Result<()> {
+    if branch {
+        let name = match Revision::parse(&name) {
+            Ok(Revision::PreviousCheckout(n)) => {
+                find_repo_in_current_directory()?.previous_checkout(n)?
+            }
+            _ => name,
+        };
+        if !ref_name::is_valid_branch_name(&name) {
+            anyhow::bail!("'{}' is not a valid branch name", name);
+        }
+        println!("{}", name);
+        return Ok(());
+    }
+    match ref_name::check_ref_format(&name, options) {
+        Some(name) if options.normalize => println!("{}", name),
+        Some(_) => {}
+        None => std::process::exit(1),
+    }
+    Ok(())
+}
+
+pub fn cmd_pack_refs(all: bool, prune: bool) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    repo.pack_refs(all, prune)?;
+    Ok(())
+}
+
 pub fn cmd_checkout(commit: String, path: PathBuf) -> Result<()> {
     let repo = find_repo_in_current_directory()?;
 
@@ -71,41 +323,385 @@ pub fn cmd_checkout(commit: String, path: PathBuf) -> Result<()> {
 
     // checkout the tree
     if let git_object::GitObject::Tree(tree) = object {
-        tree_checkout(&repo, tree, path)?;
+        tree_checkout(&repo, tree, path, "")?;
     }
 
     Ok(())
 }
 
+/// Write the files of `tree` to `base_path`, on several threads if `checkout.workers` allows
+/// it. `tree_path` is the path of `tree` in the tree being checked out, which decides the
+/// attributes of its files.
 pub fn tree_checkout(
     repo: &GitRepository,
     tree: git_object::Tree,
     base_path: PathBuf,
+    tree_path: &str,
 ) -> Result<()> {
-    for leaf in tree.iter() {
-        match leaf.get_type() {
-            git_object::mode::Type::Tree => {
-                std::fs::create_dir(base_path.join(&leaf.path))?;
-                let object = repo.read_object(&leaf.hash)?;
-                if let git_object::GitObject::Tree(tree) = object {
-                    tree_checkout(&repo, tree, base_path.join(&leaf.path))?;
-                } else {
-                    return Err(anyhow::anyhow!("Invalid tree object"));
-                }
+    let files = diff::tree_files(repo, Some(tree), &Pathspec::default())?;
+    for side in &files {
+        if let Some(parent) = base_path.join(&side.path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    let write = |side: &DiffSide| -> Result<()> {
+        let path = base_path.join(&side.path);
+        if side.mode.type_ == git_object::mode::Type::Submodule {
+            std::fs::create_dir_all(path)?;
+            return Ok(());
+        }
+        let git_object::GitObject::Blob(blob) = repo.read_object(&side.hash)? else {
+            return Err(anyhow::anyhow!("Invalid blob object"));
+        };
+        if side.mode.type_ == git_object::mode::Type::SymbolicLink {
+            std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(&blob.blob), path)?;
+            return Ok(());
+        }
+        let leaf_path = match tree_path {
+            "" => side.path.clone(),
+            _ => format!("{}/{}", tree_path, side.path),
+        };
+        let data = repo
+            .conversion(&leaf_path)
+            .to_work_tree(&leaf_path, blob.blob)?;
+        std::fs::write(path, data)?;
+        Ok(())
+    };
+    parallel::try_map(repo.workers(files.len()), &files, write)?;
+    Ok(())
+}
+
+/// The first line of the message of `commit`.
+fn commit_subject(commit: &git_object::Commit) -> &str {
+    commit
+        .get_value("message")
+        .and_then(|message| message.lines().next())
+        .unwrap_or_default()
+}
+
+/// The abbreviated hash and subject of a commit, as shown when moving `HEAD`.
+fn describe_commit(hash: &str, commit: &git_object::Commit) -> String {
+    format!("{} {}", &hash[..7], commit_subject(commit))
+}
+
+/// Switch to the branch `name`, updating the index and the work tree. With `detach`, or if
+/// `detach_on_commit` is set and `name` isn't a branch, `HEAD` is detached at the commit `name`
+/// points to instead.
+pub fn cmd_switch(name: String, detach: bool, detach_on_commit: bool) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+
+    let branch = format!("refs/heads/{}", name);
+    let branch_hash = repo.resolve_ref(&branch).ok().filter(|_| !detach);
+    if branch_hash.is_none() && !detach && !detach_on_commit {
+        return Err(anyhow::anyhow!(
+            "a branch is expected, got '{}'\nIf you want to detach HEAD at the commit, try again with the --detach option.",
+            name
+        ));
+    }
+    if branch_hash.is_some() {
+        if let Some(worktree) = repo.worktree_with_branch(&branch)? {
+            return Err(WorktreeError::AlreadyCheckedOut(
+                name,
+                worktree.path.display().to_string(),
+            )
+            .into());
+        }
+    }
+    let (target, commit) = repo.read_commit(branch_hash.as_deref().unwrap_or(&name))?;
+
+    let old_branch = repo.head_branch()?;
+    let old_head = repo.head_commit()?;
+    let old_tree = old_head
+        .as_ref()
+        .map(|hash| repo.read_tree(hash))
+        .transpose()?;
+
+    let mut index = Index::load(&repo.directory_manager.index_file)?;
+    repo.switch_tree(&mut index, old_tree, Some(repo.read_tree(&target)?))?;
+    index.write(&repo.directory_manager.index_file)?;
+
+    let from = match (&old_branch, &old_head) {
+        (Some(old_branch), _) => old_branch.trim_start_matches("refs/heads/").to_string(),
+        (None, Some(old_head)) => old_head.clone(),
+        (None, None) => "HEAD".to_string(),
+    };
+    let message = format!("checkout: moving from {} to {}", from, name);
+    match branch_hash {
+        Some(_) => repo.set_head_to_branch(&branch, &message)?,
+        None => repo.detach_head(&target, &message)?,
+    }
+
+    if let (None, Some(old_head)) = (&old_branch, &old_head) {
+        if *old_head != target {
+            let (_, old_commit) = repo.read_commit(old_head)?;
+            eprintln!(
+                "Previous HEAD position was {}",
+                describe_commit(old_head, &old_commit)
+            );
+        }
+    }
+
+    // Local changes carried over to the new HEAD.
+    let everything = Pathspec::default();
+    let local_changes = diff::diff_files(
+        diff::tree_files(&repo, Some(repo.read_tree(&target)?), &everything)?,
+        diff::work_tree_files(&repo, &index, &everything)?,
+    );
+    for change in local_changes {
+        println!("{}", change.name_status());
+    }
+
+    match branch_hash {
+        Some(_) if old_branch.as_ref() == Some(&branch) => eprintln!("Already on '{}'", name),
+        Some(_) => eprintln!("Switched to branch '{}'", name),
+        None => eprintln!("HEAD is now at {}", describe_commit(&target, &commit)),
+    }
+
+    Ok(())
+}
+
+/// How `branch` shows each branch of its list.
+enum BranchStyle {
+    /// The name of the branch, marked if checked out.
+    Name,
+    /// The name, commit and tracking information of the branch.
+    Verbose,
+    /// A line of `for-each-ref` format.
+    Format(RefFormat),
+}
+
+/// A line of the branch list.
+struct BranchRow {
+    name: String,
+    /// The name of the ref of the branch, such as `refs/heads/master`.
+    full_name: String,
+    /// `*` for the current branch, `+` for a branch checked out in another work tree.
+    marker: char,
+    hash: String,
+}
+
+impl BranchRow {
+    fn to_ref(&self) -> Ref {
+        Ref {
+            hash: self.hash.clone(),
+            path: PathBuf::from(&self.full_name),
+        }
+    }
+}
+
+/// How a detached `HEAD` at `head` shows in the branch list: detached at what it was checked
+/// out as according to its reflog if it hasn't moved since, or detached from it.
+fn describe_detached_head(repo: &GitRepository, head: &str) -> Result<String> {
+    let checkout = repo
+        .read_reflog("HEAD")?
+        .into_iter()
+        .rev()
+        .find_map(|entry| {
+            let (_, to) = entry
+                .message
+                .strip_prefix("checkout: moving from ")?
+                .rsplit_once(" to ")?;
+            Some((to.to_string(), entry.new))
+        });
+    Ok(match checkout {
+        Some((to, new)) => {
+            let to = match to.len() == 40 && to.chars().all(|c| c.is_ascii_hexdigit()) {
+                true => to[..7].to_string(),
+                false => to,
+            };
+            let position = if new == head { "at" } else { "from" };
+            format!("(HEAD detached {} {})", position, to)
+        }
+        None => format!("(HEAD detached at {})", &head[..7]),
+    })
+}
+
+fn print_branches(
+    repo: &GitRepository,
+    all: bool,
+    style: &BranchStyle,
+    patterns: &[String],
+    contains: Option<&str>,
+    merged: Option<&str>,
+    sort: &[SortKey],
+) -> Result<()> {
+    let head_branch = repo.head_branch()?;
+    let checked_out: Vec<String> = repo
+        .worktrees()?
+        .into_iter()
+        .filter_map(|worktree| worktree.branch)
+        .collect();
+
+    let mut rows = vec![];
+    if let (None, Some(head), true) = (&head_branch, repo.head_commit()?, patterns.is_empty()) {
+        let name = describe_detached_head(repo, &head)?;
+        rows.push(BranchRow {
+            full_name: name.clone(),
+            name,
+            marker: '*',
+            hash: head,
+        });
+    }
+    for branch in repo.branches(all)? {
+        let full_name = branch.path.to_string_lossy().into_owned();
+        let name = full_name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(full_name.trim_start_matches("refs/"));
+        if !patterns.is_empty()
+            && !patterns
+                .iter()
+                .any(|pattern| wildmatch::wildmatch(pattern, name, 0))
+        {
+            continue;
+        }
+        let marker = match () {
+            _ if head_branch.as_ref() == Some(&full_name) => '*',
+            _ if checked_out.contains(&full_name) => '+',
+            _ => ' ',
+        };
+        rows.push(BranchRow {
+            name: name.to_string(),
+            full_name: full_name.clone(),
+            marker,
+            hash: branch.hash,
+        });
+    }
+
+    if let Some(commit) = contains {
+        let (commit, _) = repo.read_commit(commit)?;
+        let mut containing = vec![];
+        for row in rows {
+            if repo.is_ancestor(&commit, &row.hash)? {
+                containing.push(row);
             }
-            git_object::mode::Type::RegularFile => {
-                let object = repo.read_object(&leaf.hash)?;
-                if let git_object::GitObject::Blob(blob) = object {
-                    std::fs::write(base_path.join(&leaf.path), blob.serialize())?;
-                } else {
-                    return Err(anyhow::anyhow!("Invalid blob object"));
-                };
+        }
+        rows = containing;
+    }
+    if let Some(commit) = merged {
+        let (commit, _) = repo.read_commit(commit)?;
+        let reachable = repo.ancestors(&[commit])?;
+        rows.retain(|row| reachable.contains(&row.hash));
+    }
+
+    if !sort.is_empty() {
+        // A detached `HEAD` stays first, the branches are sorted after it.
+        let skip = usize::from(head_branch.is_none() && patterns.is_empty());
+        let skip = skip.min(rows.len());
+        let branches: Vec<Ref> = rows[skip..].iter().map(BranchRow::to_ref).collect();
+        let mut by_name: HashMap<PathBuf, BranchRow> = rows
+            .drain(skip..)
+            .map(|row| (PathBuf::from(&row.full_name), row))
+            .collect();
+        for branch in repo.sort_refs(branches, sort)? {
+            rows.extend(by_name.remove(&branch.path));
+        }
+    }
+
+    let width = rows.iter().map(|row| row.name.chars().count()).max();
+    let tracking_format = RefFormat::parse("%(upstream:track)")?;
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for row in rows {
+        match style {
+            BranchStyle::Name => {
+                writeln!(out, "{} {}", row.marker, row.name)?;
+                continue;
             }
-            git_object::mode::Type::SymbolicLink => todo!(),
-            git_object::mode::Type::Submodule => todo!(),
-        };
+            BranchStyle::Format(format) => {
+                writeln!(out, "{}", repo.format_ref(&row.to_ref(), format)?)?;
+                continue;
+            }
+            BranchStyle::Verbose => {}
+        }
+        let (_, commit) = repo.read_commit(&row.hash)?;
+        let mut tracking = repo.format_ref(&row.to_ref(), &tracking_format)?;
+        if !tracking.is_empty() {
+            tracking.push(' ');
+        }
+        writeln!(
+            out,
+            "{} {:width$} {} {}{}",
+            row.marker,
+            row.name,
+            &row.hash[..7],
+            tracking,
+            commit_subject(&commit),
+            width = width.unwrap_or_default()
+        )?;
     }
+    Ok(())
+}
+
+/// The name of the branch `HEAD` points to, or `detached` as an error if it's detached.
+fn current_branch_name(repo: &GitRepository, detached: &str) -> Result<String> {
+    repo.head_branch()?
+        .and_then(|branch| branch.strip_prefix("refs/heads/").map(str::to_string))
+        .ok_or_else(|| anyhow::anyhow!("{}", detached))
+}
 
+pub fn cmd_branch(command: BranchSubCommand) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    match command {
+        BranchSubCommand::List {
+            all,
+            verbose,
+            patterns,
+            contains,
+            merged,
+            format,
+            sort,
+        } => {
+            let style = match format {
+                Some(format) => BranchStyle::Format(RefFormat::parse(&format)?),
+                None if verbose => BranchStyle::Verbose,
+                None => BranchStyle::Name,
+            };
+            print_branches(
+                &repo,
+                all,
+                &style,
+                &patterns,
+                contains.as_deref(),
+                merged.as_deref(),
+                &sort,
+            )?
+        }
+        BranchSubCommand::Create { name, start, force } => {
+            let start = start.as_deref().unwrap_or("HEAD");
+            let (hash, _) = repo.read_commit(start)?;
+            repo.create_branch(&name, &hash, start, force)?;
+        }
+        BranchSubCommand::Delete { names, force } => {
+            for name in names {
+                let hash = repo.delete_branch(&name, force)?;
+                println!("Deleted branch {} (was {}).", name, &hash[..7]);
+            }
+        }
+        BranchSubCommand::Rename { old, new, force } => {
+            let old = match old {
+                Some(old) => old,
+                None => {
+                    current_branch_name(&repo, "cannot rename the current branch while not on any")?
+                }
+            };
+            repo.rename_branch(&old, &new, force)?;
+        }
+        BranchSubCommand::SetUpstream { upstream, branch } => {
+            let branch = match branch {
+                Some(branch) => branch,
+                None => current_branch_name(
+                    &repo,
+                    "could not set upstream of HEAD when it does not point to any branch",
+                )?,
+            };
+            let upstream_ref = repo.set_upstream(&branch, &upstream)?;
+            println!(
+                "branch '{}' set up to track '{}'.",
+                branch,
+                refs::shorten_ref_name(&upstream_ref)
+            );
+        }
+    }
     Ok(())
 }
 
@@ -144,14 +740,14 @@ pub fn cmd_log(mut commit: String, n_logs: u32) -> Result<()> {
     let repo = GitRepository::find(&current_directory)?;
 
     for _ in 0..n_logs {
-        let commit_hash = repo.find_object(&commit)?;
+        let commit_hash = repo.find_object_as(&commit, ExpectedType::Commit)?;
         let object = repo.read_object(&commit_hash)?;
         if let git_object::GitObject::Commit(c) = object {
             println!("{} {}", "commit".yellow(), commit_hash.yellow());
             println!("Author: {}", c.get_value("author").unwrap());
             println!("Tree: {}", c.get_value("tree").unwrap());
             println!();
-            println!("  {}", c.get_value("message").unwrap());
+            println!("  {}", c.get_value("message").unwrap().trim_end());
             println!();
             commit = match c.get_value("parent") {
                 Some(parent) => parent.to_string(),
@@ -169,14 +765,45 @@ pub fn cmd_cat_file(_object_type: git_object::Type, object_hash: String) -> Resu
     let repo = GitRepository::find(&current_directory)?;
 
     let object = repo.read_object(&object_hash)?;
-    print!("{}", object.serialize());
+    std::io::stdout().write_all(&object.serialize())?;
     Ok(())
 }
 
-pub fn cmd_hash_object(file_path: &Path, object_type: git_object::Type, write: bool) -> Result<()> {
+pub fn cmd_hash_object(
+    file_path: &Path,
+    object_type: git_object::Type,
+    write: bool,
+    no_filters: bool,
+) -> Result<()> {
     let current_directory = std::env::current_dir()?;
     let repo = GitRepository::find(&current_directory)?;
-    let object = GitRepository::create_object(file_path, object_type)?;
+    let work_tree = std::fs::canonicalize(&repo.directory_manager.work_tree)?;
+    let work_tree_path = std::fs::canonicalize(file_path)
+        .ok()
+        .and_then(|path| {
+            path.strip_prefix(&work_tree)
+                .ok()
+                .map(|path| path.to_string_lossy().into_owned())
+        })
+        .filter(|_| object_type == git_object::Type::Blob && !no_filters);
+
+    // Work tree files are hashed the way they would be stored.
+    let object = match work_tree_path {
+        Some(path) => {
+            let index = Index::load(&repo.directory_manager.index_file)?;
+            let data = repo
+                .conversion(&path)
+                .to_git(&path, std::fs::read(file_path)?, || {
+                    index
+                        .entry(&path)
+                        .is_some_and(|entry| repo.blob_has_cr(&entry.hash))
+                })?;
+            git_object::SerializedGitObject::try_from(git_object::GitObject::Blob(
+                git_object::Blob { blob: data },
+            ))?
+        }
+        None => GitRepository::create_object(file_path, object_type)?,
+    };
     if write {
         repo.write_object(&object)?;
     }
@@ -186,7 +813,1092 @@ pub fn cmd_hash_object(file_path: &Path, object_type: git_object::Type, write: b
     Ok(())
 }
 
-pub fn cmd_init(path: String) -> Result<()> {
-    GitRepository::create(path)?;
+pub fn cmd_diff_tree(
+    old_tree: String,
+    new_tree: String,
+    recursive: bool,
+    format: OutputFormat,
+    renames: Option<RenameOptions>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    let old = repo.read_tree(&old_tree)?;
+    let new = repo.read_tree(&new_tree)?;
+    // Plumbing output is never colored.
+    colored::control::set_override(false);
+
+    // Patches are only meaningful for files, so -p implies -r.
+    let recursive = recursive || format == OutputFormat::Patch;
+
+    let mut changes = TreeDiff::new(&repo, Some(old), Some(new))
+        .recursive(recursive)
+        .collect::<Result<Vec<FileChange>, _>>()?;
+
+    if let Some(options) = renames {
+        changes = find_renames(&repo, changes, options, || {
+            Ok(diff::list_tree_files(&repo, repo.read_tree(&old_tree)?)?)
+        })?
+        .changes;
+    }
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    write_changes(&repo, &mut out, &changes, format)
+}
+
+/// Pair up renamed and copied files. `old_files` lists every file of the pre-image, only needed
+/// to find copies of unmodified files.
+fn find_renames(
+    repo: &GitRepository,
+    changes: Vec<FileChange>,
+    mut options: RenameOptions,
+    old_files: impl FnOnce() -> Result<Vec<DiffSide>>,
+) -> Result<RenameDetection> {
+    if options.rename_limit.is_none() {
+        options.rename_limit = repo
+            .config
+            .get("diff", "renameLimit")
+            .and_then(|limit| limit.parse().ok());
+    }
+
+    let unmodified = match options.copies {
+        CopyDetection::Harder => old_files()?
+            .into_iter()
+            .filter(|side| !changes.iter().any(|change| change.path() == side.path))
+            .collect(),
+        _ => vec![],
+    };
+    Ok(diff::detect_renames(
+        changes,
+        unmodified,
+        &options,
+        |side| diff::read_side(repo, side),
+    )?)
+}
+
+fn write_changes(
+    repo: &GitRepository,
+    out: &mut impl Write,
+    changes: &[FileChange],
+    format: OutputFormat,
+) -> Result<()> {
+    let stats = match format {
+        OutputFormat::Stat | OutputFormat::NumStat | OutputFormat::ShortStat => changes
+            .iter()
+            .map(|change| {
+                let load = |side: Option<&DiffSide>| {
+                    side.map(|side| diff::read_side(repo, side))
+                        .transpose()
+                        .map(Option::unwrap_or_default)
+                };
+                Ok(FileStat::new(
+                    change,
+                    &load(change.old.as_ref())?,
+                    &load(change.new.as_ref())?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => vec![],
+    };
+
+    match format {
+        OutputFormat::Stat => {
+            let width = std::env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.parse().ok())
+                .filter(|columns| *columns > 0)
+                .unwrap_or(stat::DEFAULT_STAT_WIDTH);
+            stat::write_stat(out, &stats, width)?
+        }
+        OutputFormat::NumStat => stat::write_numstat(out, &stats)?,
+        OutputFormat::ShortStat => stat::write_shortstat(out, &stats)?,
+        _ => {
+            for change in changes {
+                match format {
+                    OutputFormat::Raw => writeln!(out, "{}", change.raw())?,
+                    OutputFormat::NameOnly => writeln!(out, "{}", change.path())?,
+                    OutputFormat::NameStatus => writeln!(out, "{}", change.name_status())?,
+                    _ => diff::write_patch(repo, out, change)?,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// One side of a `diff`.
+enum DiffSource {
+    /// A tree-ish, or the empty tree.
+    Tree(Option<String>),
+    Index,
+    WorkTree,
+}
+
+impl DiffSource {
+    fn files(
+        &self,
+        repo: &GitRepository,
+        index: &Index,
+        pathspec: &Pathspec,
+    ) -> Result<Vec<DiffSide>> {
+        Ok(match self {
+            DiffSource::Tree(name) => {
+                let tree = name.as_ref().map(|name| repo.read_tree(name)).transpose()?;
+                diff::tree_files(repo, tree, pathspec)?
+            }
+            DiffSource::Index => diff::index_files(index, pathspec),
+            DiffSource::WorkTree => diff::work_tree_files(repo, index, pathspec)?,
+        })
+    }
+}
+
+/// Split `A..B` or `A...B` into its ends, `HEAD` standing for an omitted one. The flag is set for
+/// the symmetric `...` form.
+fn split_range(argument: &str) -> Option<(String, String, bool)> {
+    let (from, to, symmetric) = match argument.split_once("...") {
+        Some((from, to)) => (from, to, true),
+        None => {
+            let (from, to) = argument.split_once("..")?;
+            (from, to, false)
+        }
+    };
+    // A bare ".." is the parent directory.
+    if from.is_empty() && to.is_empty() {
+        return None;
+    }
+    let or_head = |name: &str| match name {
+        "" => "HEAD".to_string(),
+        name => name.to_string(),
+    };
+    Some((or_head(from), or_head(to), symmetric))
+}
+
+fn is_revision(repo: &GitRepository, argument: &str) -> bool {
+    match split_range(argument) {
+        Some((from, to, _)) => repo.find_object(&from).is_ok() && repo.find_object(&to).is_ok(),
+        None => repo.find_object(argument).is_ok(),
+    }
+}
+
+/// Resolve the color setting of a porcelain command, falling back to `color.<command>` and
+/// `color.ui`, and enable or disable colors accordingly.
+fn setup_color(repo: &GitRepository, command: &str, color: Option<ColorWhen>) {
+    let configured = || {
+        let value = repo
+            .config
+            .get("color", command)
+            .or_else(|| repo.config.get("color", "ui"))?;
+        // In the configuration, true means auto.
+        match value.to_lowercase().as_str() {
+            "true" | "yes" | "on" => Some(ColorWhen::Auto),
+            value => value.parse().ok(),
+        }
+    };
+
+    let enabled = match color.or_else(configured).unwrap_or(ColorWhen::Auto) {
+        ColorWhen::Always => true,
+        ColorWhen::Never => false,
+        ColorWhen::Auto => std::io::stdout().is_terminal(),
+    };
+    colored::control::set_override(enabled);
+}
+
+/// The current directory relative to the top of the work tree.
+fn work_tree_prefix(repo: &GitRepository) -> Result<String> {
+    let current_directory = std::env::current_dir()?;
+    Ok(current_directory
+        .strip_prefix(&repo.directory_manager.work_tree)
+        .map(|prefix| prefix.to_string_lossy().into_owned())
+        .unwrap_or_default())
+}
+
+/// `path`, relative to the top of the work tree, relative to the directory `prefix` instead.
+fn relative_path(path: &str, prefix: &str) -> String {
+    if prefix.is_empty() {
+        return path.to_string();
+    }
+    let path_components: Vec<&str> = path.split('/').collect();
+    let prefix_components: Vec<&str> = prefix.split('/').collect();
+    // The last component of `path` is kept, even if it's the directory `prefix` itself.
+    let common = path_components[..path_components.len() - 1]
+        .iter()
+        .zip(&prefix_components)
+        .take_while(|(component, prefix_component)| component == prefix_component)
+        .count();
+    format!(
+        "{}{}",
+        "../".repeat(prefix_components.len() - common),
+        path_components[common..].join("/")
+    )
+}
+
+/// Check that arguments which weren't taken as revisions, and weren't separated from them by
+/// `--`, are paths of existing files or pathspecs with magic or wildcards.
+fn check_path_arguments(args: impl IntoIterator<Item = String>) -> Result<Vec<String>> {
+    let mut pathspecs = vec![];
+    for arg in args {
+        let looks_like_pathspec = arg.starts_with(':') || wildmatch::is_glob(&arg);
+        if !looks_like_pathspec && std::fs::symlink_metadata(&arg).is_err() {
+            return Err(anyhow::anyhow!(
+                "ambiguous argument '{}': unknown revision or path not in the working tree.",
+                arg
+            ));
+        }
+        pathspecs.push(arg);
+    }
+    Ok(pathspecs)
+}
+
+pub fn cmd_diff(
+    cached: bool,
+    args: Vec<String>,
+    paths: Vec<String>,
+    format: OutputFormat,
+    renames: Option<RenameOptions>,
+    no_renames: bool,
+    color: Option<ColorWhen>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    setup_color(&repo, "diff", color);
+
+    // Leading arguments are revisions, the following ones must be existing paths.
+    let mut args = args.into_iter().peekable();
+    let mut revisions = vec![];
+    while let Some(revision) = args.next_if(|arg| is_revision(&repo, arg)) {
+        revisions.push(revision);
+    }
+    let mut pathspecs = check_path_arguments(args)?;
+    pathspecs.extend(paths);
+
+    let pathspec = Pathspec::new(&work_tree_prefix(&repo)?, &pathspecs)?;
+    let mut index = Index::load(&repo.directory_manager.index_file)?;
+
+    let tree = |name: &str| DiffSource::Tree(Some(name.to_string()));
+    let (old, new) = match (cached, revisions.as_slice()) {
+        (false, []) => (DiffSource::Index, DiffSource::WorkTree),
+        // Before the first commit, the index is compared with the empty tree.
+        (true, []) => (
+            DiffSource::Tree(repo.find_object("HEAD").ok()),
+            DiffSource::Index,
+        ),
+        (true, [revision]) if split_range(revision).is_none() => {
+            (tree(revision), DiffSource::Index)
+        }
+        (false, [range]) if split_range(range).is_some() => {
+            let (from, to, symmetric) = split_range(range).unwrap();
+            if !symmetric {
+                (tree(&from), tree(&to))
+            } else {
+                let (from_hash, _) = repo.read_commit(&from)?;
+                let (to_hash, _) = repo.read_commit(&to)?;
+                let bases = repo.merge_bases(&from_hash, &to_hash)?;
+                let base = bases
+                    .first()
+                    .ok_or_else(|| anyhow::anyhow!("{}: no merge base", range))?;
+                if bases.len() > 1 {
+                    eprintln!("warning: {}: multiple merge bases, using {}", range, base);
+                }
+                (tree(base), tree(&to))
+            }
+        }
+        (false, [revision]) => (tree(revision), DiffSource::WorkTree),
+        (false, [from, to]) => (tree(from), tree(to)),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "usage: rit diff [--cached] [<commit> [<commit>]] [--] [<path>...]"
+            ))
+        }
+    };
+
+    let mut changes = match (&old, &new) {
+        (DiffSource::Tree(Some(from)), DiffSource::Tree(Some(to))) => TreeDiff::new(
+            &repo,
+            Some(repo.read_tree(from)?),
+            Some(repo.read_tree(to)?),
+        )
+        .recursive(true)
+        .filter(|change| {
+            change
+                .as_ref()
+                .map_or(true, |change| pathspec.matches(change.path()))
+        })
+        .collect::<Result<Vec<FileChange>, _>>()?,
+        _ => {
+            if matches!(new, DiffSource::WorkTree) {
+                refresh_index_if_able(&repo, &mut index)?;
+            }
+            diff::diff_files(
+                old.files(&repo, &index, &pathspec)?,
+                new.files(&repo, &index, &pathspec)?,
+            )
+        }
+    };
+
+    // Renames are detected by default, diff.renames can turn them off or enable copies.
+    let configured = || match repo.config.get("diff", "renames").map(str::to_lowercase) {
+        Some(value) if value == "copies" || value == "copy" => Some(RenameOptions {
+            copies: CopyDetection::Modified,
+            ..Default::default()
+        }),
+        Some(value) if value == "false" || value == "no" || value == "off" || value == "0" => None,
+        _ => Some(RenameOptions::default()),
+    };
+    let renames = if no_renames {
+        None
+    } else {
+        renames.or_else(configured)
+    };
+    if let Some(options) = renames {
+        let detection = find_renames(&repo, changes, options, || {
+            old.files(&repo, &index, &pathspec)
+        })?;
+        if let Some(limit) = detection.needed_rename_limit {
+            eprintln!("warning: exhaustive rename detection was skipped due to too many files.");
+            eprintln!(
+                "warning: you may want to set your diff.renameLimit variable to at least {} and retry the command.",
+                limit
+            );
+        }
+        changes = detection.changes;
+    }
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    write_changes(&repo, &mut out, &changes, format)
+}
+
+/// Record the stat data of the files found unchanged in `index`, and write it if that changed
+/// anything, unless another process holds its lock.
+fn refresh_index_if_able(repo: &GitRepository, index: &mut Index) -> Result<()> {
+    if !repo.refresh_index(index)?.changed {
+        return Ok(());
+    }
+    match index.write(&repo.directory_manager.index_file) {
+        Ok(()) | Err(IndexError::Locked(_)) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Refresh the stat data of the index, listing the files that differ from it. Unless `quiet`,
+/// exits with an error if there are some.
+pub fn cmd_update_index(refresh: bool, quiet: bool) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    if !refresh {
+        return Ok(());
+    }
+
+    let mut index = Index::load(&repo.directory_manager.index_file)?;
+    let refresh = repo.refresh_index(&mut index)?;
+    if refresh.changed {
+        index.write(&repo.directory_manager.index_file)?;
+    }
+    if !quiet {
+        for path in &refresh.needs_update {
+            println!("{}: needs update", path);
+        }
+        for path in &refresh.needs_merge {
+            println!("{}: needs merge", path);
+        }
+        if !refresh.needs_update.is_empty() || !refresh.needs_merge.is_empty() {
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// List the tracked files whose work tree version differs from the index.
+fn print_unstaged_changes(repo: &GitRepository, index: &Index) -> Result<()> {
+    let everything = Pathspec::default();
+    let changes = diff::diff_files(
+        diff::index_files(index, &everything),
+        diff::work_tree_files(repo, index, &everything)?,
+    );
+    if !changes.is_empty() {
+        println!("Unstaged changes after reset:");
+    }
+    for change in changes {
+        println!("{}", change.name_status());
+    }
+    Ok(())
+}
+
+pub fn cmd_reset(
+    mode: Option<ResetMode>,
+    quiet: bool,
+    args: Vec<String>,
+    paths: Vec<String>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+
+    // The first argument is the commit if it names one, the following ones are paths.
+    let mut args = args.into_iter().peekable();
+    let commit = args.next_if(|arg| split_range(arg).is_none() && is_revision(&repo, arg));
+    let mut pathspecs = check_path_arguments(args)?;
+    pathspecs.extend(paths);
+
+    let old_head = repo.head_commit()?;
+    let mut index = Index::load(&repo.directory_manager.index_file)?;
+
+    if !pathspecs.is_empty() {
+        match mode {
+            None => {}
+            Some(ResetMode::Mixed) => eprintln!(
+                "warning: --mixed with paths is deprecated; use 'rit reset -- <paths>' instead."
+            ),
+            Some(mode) => return Err(anyhow::anyhow!("Cannot do {} reset with paths.", mode)),
+        }
+        let tree = match commit.as_ref().or(old_head.as_ref()) {
+            Some(name) => Some(repo.read_tree(name)?),
+            None => None,
+        };
+        let pathspec = Pathspec::new(&work_tree_prefix(&repo)?, &pathspecs)?;
+        repo.reset_index(&mut index, tree, &pathspec)?;
+        repo.refresh_index(&mut index)?;
+        index.write(&repo.directory_manager.index_file)?;
+        if !quiet {
+            print_unstaged_changes(&repo, &index)?;
+        }
+        return Ok(());
+    }
+
+    // Before the first commit, the index and the work tree are reset to the empty tree.
+    let target = match commit.as_ref().or(old_head.as_ref()) {
+        Some(name) => Some(repo.read_commit(name)?),
+        None => None,
+    };
+    let tree = match &target {
+        Some((hash, _)) => Some(repo.read_tree(hash)?),
+        None => None,
+    };
+
+    let mode = mode.unwrap_or(ResetMode::Mixed);
+    match mode {
+        ResetMode::Soft => {}
+        ResetMode::Mixed => {
+            repo.reset_index(&mut index, tree, &Pathspec::default())?;
+            repo.refresh_index(&mut index)?;
+        }
+        ResetMode::Hard => repo.reset_work_tree(&mut index, tree)?,
+        ResetMode::Keep => {
+            let old_tree = old_head
+                .as_ref()
+                .map(|hash| repo.read_tree(hash))
+                .transpose()?;
+            repo.switch_tree(&mut index, old_tree, tree.clone())?;
+            repo.reset_index(&mut index, tree, &Pathspec::default())?;
+        }
+    }
+    if mode != ResetMode::Soft {
+        index.write(&repo.directory_manager.index_file)?;
+    }
+
+    // ORIG_HEAD records where HEAD was, to undo the reset.
+    if let Some(old_head) = &old_head {
+        repo.update_ref("ORIG_HEAD", old_head)?;
+    }
+    if let Some((hash, _)) = &target {
+        let message = format!("reset: moving to {}", commit.as_deref().unwrap_or("HEAD"));
+        repo.update_head(hash, &message)?;
+    }
+
+    match (mode, &target) {
+        _ if quiet => {}
+        (ResetMode::Mixed, _) => print_unstaged_changes(&repo, &index)?,
+        (ResetMode::Hard, Some((hash, commit))) => {
+            println!("HEAD is now at {}", describe_commit(hash, commit))
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Restore the work tree files, or with `staged` the index entries, matching `paths`. Files
+/// are restored from `source`, or by default from the index for the work tree and from `HEAD`
+/// for the index.
+pub fn cmd_restore(
+    source: Option<String>,
+    staged: bool,
+    worktree: bool,
+    paths: Vec<String>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    let worktree = worktree || !staged;
+    let prefix = work_tree_prefix(&repo)?;
+    let pathspec = Pathspec::new(&prefix, &paths)?;
+    let mut index = Index::load(&repo.directory_manager.index_file)?;
+
+    // The work tree is restored from the index by default, the index from HEAD, which is the
+    // empty tree before the first commit.
+    let from_index = source.is_none() && !staged;
+    let tree_name = match source {
+        Some(name) => Some(name),
+        None if staged => repo.head_commit()?,
+        None => None,
+    };
+    let tree = tree_name.map(|name| repo.read_tree(&name)).transpose()?;
+    let files = if from_index {
+        if let Some(entry) = index
+            .entries()
+            .iter()
+            .find(|entry| entry.stage != 0 && pathspec.matches(&entry.path))
+        {
+            return Err(anyhow::anyhow!("path '{}' is unmerged", entry.path));
+        }
+        diff::index_files(&index, &pathspec)
+    } else {
+        diff::tree_files(&repo, tree.clone(), &pathspec)?
+    };
+
+    for path in &paths {
+        let single = Pathspec::new(&prefix, std::slice::from_ref(path))?;
+        let known = files.iter().any(|side| single.matches(&side.path))
+            || index
+                .entries()
+                .iter()
+                .any(|entry| single.matches(&entry.path));
+        if !known {
+            return Err(anyhow::anyhow!(
+                "pathspec '{}' did not match any file(s) known to rit",
+                path
+            ));
+        }
+    }
+
+    if worktree {
+        repo.restore_work_tree(&mut index, files, &pathspec)?;
+    }
+    if staged {
+        repo.reset_index(&mut index, tree, &pathspec)?;
+    }
+    index.write(&repo.directory_manager.index_file)?;
+    Ok(())
+}
+
+/// Apply `stash`, printing the messages of the merge. Returns whether it merged cleanly.
+fn apply_stash(repo: &GitRepository, stash: &Stash, restore_index: bool) -> Result<bool> {
+    let mut index = Index::load(&repo.directory_manager.index_file)?;
+    let merge = repo.apply_stash(&mut index, stash, restore_index)?;
+    index.write(&repo.directory_manager.index_file)?;
+    for message in &merge.messages {
+        println!("{}", message);
+    }
+    if !merge.is_clean() && restore_index {
+        println!("Index was not unstashed.");
+    }
+    Ok(merge.is_clean())
+}
+
+/// The position of `stash` in the stash list, which `drop` and `pop` need.
+fn stash_position(stash: &Stash) -> Result<usize> {
+    stash
+        .position
+        .ok_or_else(|| StashError::NotAStashReference(stash.name.clone()).into())
+}
+
+pub fn cmd_stash(command: StashSubCommand) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    match command {
+        StashSubCommand::Push {
+            message,
+            include_untracked,
+        } => {
+            let mut index = Index::load(&repo.directory_manager.index_file)?;
+            match repo.save_stash(&mut index, message.as_deref(), include_untracked)? {
+                Some(message) => {
+                    index.write(&repo.directory_manager.index_file)?;
+                    println!("Saved working directory and index state {}", message);
+                }
+                None => println!("No local changes to save"),
+            }
+        }
+        StashSubCommand::Apply { stash, index } => {
+            let stash = repo.read_stash(stash.as_deref())?;
+            if !apply_stash(&repo, &stash, index)? {
+                return Err(anyhow::anyhow!("Conflicts while applying {}", stash.name));
+            }
+        }
+        StashSubCommand::Pop { stash, index } => {
+            let stash = repo.read_stash(stash.as_deref())?;
+            let position = stash_position(&stash)?;
+            if !apply_stash(&repo, &stash, index)? {
+                return Err(anyhow::anyhow!(
+                    "The stash entry is kept in case you need it again."
+                ));
+            }
+            let hash = repo.drop_stash(position)?;
+            println!("Dropped {} ({})", stash.name, hash);
+        }
+        StashSubCommand::List => {
+            for (position, entry) in repo.stash_list()?.iter().enumerate() {
+                println!("stash@{{{}}}: {}", position, entry.message);
+            }
+        }
+        StashSubCommand::Show { stash, patch } => {
+            let stash = repo.read_stash(stash.as_deref())?;
+            let changes = TreeDiff::new(
+                &repo,
+                Some(repo.read_tree(&stash.base)?),
+                Some(repo.read_tree(&stash.work_tree)?),
+            )
+            .recursive(true)
+            .collect::<Result<Vec<_>, _>>()?;
+            let changes =
+                find_renames(&repo, changes, RenameOptions::default(), || Ok(vec![]))?.changes;
+
+            let format = match patch {
+                true => OutputFormat::Patch,
+                false => OutputFormat::Stat,
+            };
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            write_changes(&repo, &mut out, &changes, format)?;
+        }
+        StashSubCommand::Drop { stash } => {
+            let stash = repo.read_stash(stash.as_deref())?;
+            let hash = repo.drop_stash(stash_position(&stash)?)?;
+            println!("Dropped {} ({})", stash.name, hash);
+        }
+        StashSubCommand::Clear => repo.clear_stash()?,
+    }
+    Ok(())
+}
+
+/// The ref whose reflog `name` designates: `name` itself, or the first of `refs/<name>`,
+/// `refs/tags/<name>`, `refs/heads/<name>` and `refs/remotes/<name>` that exists or has a reflog.
+fn reflog_ref_name(repo: &GitRepository, name: &str) -> Result<String> {
+    let candidates = [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+    ];
+    for candidate in candidates {
+        if repo.resolve_ref(&candidate).is_ok() || !repo.read_reflog(&candidate)?.is_empty() {
+            return Ok(candidate);
+        }
+    }
+    Err(anyhow::anyhow!("reflog could not be found: '{}'", name))
+}
+
+/// Split a reflog entry such as `HEAD@{2}` into its ref and position.
+fn parse_reflog_entry(entry: &str) -> Option<(&str, usize)> {
+    let (name, position) = entry.strip_suffix('}')?.rsplit_once("@{")?;
+    Some((name, position.parse().ok()?))
+}
+
+pub fn cmd_reflog(command: ReflogSubCommand) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    match command {
+        ReflogSubCommand::Show { reference } => {
+            let name = reference.as_deref().unwrap_or("HEAD");
+            let entries = repo.read_reflog(&reflog_ref_name(&repo, name)?)?;
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            for (position, entry) in entries.iter().rev().enumerate() {
+                writeln!(
+                    out,
+                    "{} {}@{{{}}}: {}",
+                    &entry.new[..7],
+                    name,
+                    position,
+                    entry.message
+                )?;
+            }
+        }
+        ReflogSubCommand::Expire {
+            expire,
+            expire_unreachable,
+            all,
+            dry_run,
+            refs,
+        } => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs() as i64;
+            let mut expiry = repo.reflog_expiry(now)?;
+            let parse = |date: &str| {
+                reflog::parse_expiry_date(date, now)
+                    .ok_or_else(|| anyhow::anyhow!("invalid expiry date '{}'", date))
+            };
+            if let Some(expire) = &expire {
+                expiry.expire = parse(expire)?;
+            }
+            if let Some(expire_unreachable) = &expire_unreachable {
+                expiry.expire_unreachable = parse(expire_unreachable)?;
+            }
+
+            let names = match all {
+                true => repo.reflog_names()?,
+                false if refs.is_empty() => anyhow::bail!("no reflog specified to expire"),
+                false => refs
+                    .iter()
+                    .map(|name| reflog_ref_name(&repo, name))
+                    .collect::<Result<_>>()?,
+            };
+            for name in names {
+                for entry in repo.expire_reflog(&name, &expiry, dry_run)? {
+                    if dry_run {
+                        println!("would prune {}", entry.message);
+                    }
+                }
+            }
+        }
+        ReflogSubCommand::Delete { entries } => {
+            let mut parsed = entries
+                .iter()
+                .map(|entry| {
+                    let (name, position) = parse_reflog_entry(entry)
+                        .ok_or_else(|| anyhow::anyhow!("not a reflog: {}", entry))?;
+                    Ok((reflog_ref_name(&repo, name)?, position))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // Removing the oldest entries first keeps the positions of the others.
+            parsed.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            parsed.dedup();
+            for (name, position) in parsed {
+                repo.delete_reflog_entry(&name, position)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_init(path: String, ref_storage: RefStorage) -> Result<()> {
+    GitRepository::create_with(path, ref_storage)?;
+    Ok(())
+}
+
+pub fn cmd_clean(
+    dry_run: bool,
+    force: u8,
+    directories: bool,
+    ignored: bool,
+    ignored_only: bool,
+    paths: Vec<String>,
+) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    if ignored && ignored_only {
+        return Err(anyhow::anyhow!("-x and -X cannot be used together"));
+    }
+    if !dry_run && force == 0 {
+        let reason = match repo.config.get_bool("clean", "requireForce") {
+            Some(false) => None,
+            Some(true) => Some("set to true"),
+            None => Some("defaults to true"),
+        };
+        if let Some(reason) = reason {
+            return Err(anyhow::anyhow!(
+                "clean.requireForce {} and neither -n nor -f given; refusing to clean",
+                reason
+            ));
+        }
+    }
+
+    // Only the current directory is cleaned by default.
+    let prefix = work_tree_prefix(&repo)?;
+    let paths = match paths.is_empty() && !prefix.is_empty() {
+        true => vec![".".to_string()],
+        false => paths,
+    };
+    let pathspec = Pathspec::new(&prefix, &paths)?;
+    let index = Index::load(&repo.directory_manager.index_file)?;
+    let options = CleanOptions {
+        dry_run,
+        directories,
+        ignored: match (ignored, ignored_only) {
+            (true, _) => IgnoredFiles::Remove,
+            (_, true) => IgnoredFiles::Only,
+            _ => IgnoredFiles::Keep,
+        },
+        nested_repositories: force > 1,
+    };
+
+    let (removing, skipping) = match dry_run {
+        true => ("Would remove", "Would skip repository"),
+        false => ("Removing", "Skipping repository"),
+    };
+    for action in repo.clean(&index, &pathspec, options)? {
+        match action {
+            CleanAction::Removed(path) => {
+                println!("{} {}", removing, relative_path(&path, &prefix))
+            }
+            CleanAction::SkippedRepository(path) => {
+                println!("{} {}", skipping, relative_path(&path, &prefix))
+            }
+            CleanAction::Failed(path, error) => eprintln!(
+                "warning: failed to remove {}: {}",
+                relative_path(&path, &prefix),
+                error
+            ),
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_check_attr(all: bool, args: Vec<String>, paths: Vec<String>) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    let prefix = work_tree_prefix(&repo)?;
+
+    // Without `--`, the first argument is the attribute and the others are paths.
+    let (names, paths) = match (all, paths.is_empty()) {
+        (true, _) => (vec![], args.into_iter().chain(paths).collect()),
+        (false, false) => (args, paths),
+        (false, true) => {
+            let mut args = args.into_iter();
+            (args.next().into_iter().collect(), args.collect())
+        }
+    };
+    if !all && names.is_empty() {
+        return Err(anyhow::anyhow!("No attribute specified"));
+    }
+    if paths.is_empty() {
+        return Err(anyhow::anyhow!("No file specified"));
+    }
+    if let Some(name) = names.iter().find(|name| !attributes::is_valid_name(name)) {
+        return Err(anyhow::anyhow!("{}: not a valid attribute name", name));
+    }
+
+    for path in paths {
+        let full_path = pathspec::normalize(&prefix, &path)
+            .ok_or_else(|| anyhow::anyhow!("'{}' is outside repository", path))?;
+        let rules = repo.attributes(&full_path);
+        match all {
+            true => {
+                for (name, state) in rules.all(&full_path) {
+                    println!("{}: {}: {}", path, name, state);
+                }
+            }
+            false => {
+                for name in &names {
+                    println!("{}: {}: {}", path, name, rules.get(&full_path, name));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The patterns given to `sparse-checkout set` or `add`, followed by those of the standard input
+/// with `stdin`. In cone mode they are directories, relative to the current directory.
+fn sparse_checkout_patterns(
+    prefix: &str,
+    cone: bool,
+    mut patterns: Vec<String>,
+    stdin: bool,
+) -> Result<Vec<String>> {
+    if stdin {
+        for line in std::io::stdin().lines() {
+            let line = line?;
+            if !line.is_empty() {
+                patterns.push(line);
+            }
+        }
+    }
+    if !cone {
+        return Ok(patterns);
+    }
+    patterns
+        .iter()
+        .map(|pattern| {
+            pathspec::normalize(prefix, pattern)
+                .ok_or_else(|| anyhow::anyhow!("'{}' is outside repository", pattern))
+        })
+        .collect()
+}
+
+/// Make the index and the work tree follow `patterns`, warning about the files left in place.
+fn apply_sparse_patterns(repo: &GitRepository, patterns: Option<&SparsePatterns>) -> Result<()> {
+    let mut index = Index::load(&repo.directory_manager.index_file)?;
+    let left = repo.apply_sparse_patterns(&mut index, patterns)?;
+    index.write(&repo.directory_manager.index_file)?;
+    if !left.is_empty() {
+        eprintln!(
+            "warning: The following paths are not up to date and were left despite sparse patterns:"
+        );
+        for path in &left {
+            eprintln!("\t{}", path);
+        }
+        eprintln!(
+            "\nAfter fixing the above paths, you may want to run `rit sparse-checkout reapply`."
+        );
+    }
+    Ok(())
+}
+
+pub fn cmd_sparse_checkout(command: SparseCheckoutSubCommand) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+    let prefix = work_tree_prefix(&repo)?;
+    let current = repo.sparse_patterns()?;
+    // Cone mode is the default, unless sparse checkout is already on without it.
+    let is_cone = |cone: Option<bool>| {
+        cone.unwrap_or_else(|| current.as_ref().is_none_or(SparsePatterns::is_cone))
+    };
+
+    let patterns = match command {
+        SparseCheckoutSubCommand::Init { cone } => match (is_cone(cone), current.clone()) {
+            // The patterns already there are kept.
+            (cone, Some(current)) if current.is_cone() == cone => current,
+            (true, _) => SparsePatterns::cone([]),
+            (false, _) => SparsePatterns::full(vec!["/*".to_string(), "!/*/".to_string()]),
+        },
+        SparseCheckoutSubCommand::Set {
+            cone,
+            patterns,
+            stdin,
+        } => {
+            let cone = is_cone(cone);
+            let patterns = sparse_checkout_patterns(&prefix, cone, patterns, stdin)?;
+            match cone {
+                true => SparsePatterns::cone(patterns),
+                false => SparsePatterns::full(patterns),
+            }
+        }
+        SparseCheckoutSubCommand::Add { patterns, stdin } => {
+            let current = current.ok_or_else(|| anyhow::anyhow!("no sparse-checkout to add to"))?;
+            let patterns = sparse_checkout_patterns(&prefix, current.is_cone(), patterns, stdin)?;
+            current.with(patterns)
+        }
+        SparseCheckoutSubCommand::List => {
+            let current = current.ok_or_else(|| anyhow::anyhow!("this worktree is not sparse"))?;
+            for pattern in current.list() {
+                println!("{}", pattern);
+            }
+            return Ok(());
+        }
+        SparseCheckoutSubCommand::Reapply => {
+            let current = current.ok_or_else(|| {
+                anyhow::anyhow!("must be in a sparse-checkout to reapply sparsity patterns")
+            })?;
+            return apply_sparse_patterns(&repo, Some(&current));
+        }
+        SparseCheckoutSubCommand::Disable => {
+            apply_sparse_patterns(&repo, None)?;
+            repo.write_sparse_patterns(None)?;
+            return Ok(());
+        }
+    };
+
+    repo.write_sparse_patterns(Some(&patterns))?;
+    apply_sparse_patterns(&repo, Some(&patterns))
+}
+
+fn print_worktrees(worktrees: &[Worktree], porcelain: bool) {
+    let width = worktrees
+        .iter()
+        .map(|worktree| worktree.path.display().to_string().len())
+        .max()
+        .unwrap_or_default()
+        + 1;
+    for worktree in worktrees {
+        let path = worktree.path.display().to_string();
+        let head = worktree.head.as_deref().unwrap_or(diff::NULL_HASH);
+        if porcelain {
+            println!("worktree {}\nHEAD {}", path, head);
+            match &worktree.branch {
+                Some(branch) => println!("branch {}", branch),
+                None => println!("detached"),
+            }
+            match worktree.locked.as_deref() {
+                Some("") => println!("locked"),
+                Some(reason) => println!("locked {}", reason),
+                None => {}
+            }
+            if let Some(reason) = &worktree.prunable {
+                println!("prunable {}", reason);
+            }
+            println!();
+            continue;
+        }
+
+        let branch = match &worktree.branch {
+            Some(branch) => format!("[{}]", branch.trim_start_matches("refs/heads/")),
+            None => "(detached HEAD)".to_string(),
+        };
+        let mut line = format!("{:width$} {} {}", path, &head[..7], branch);
+        if worktree.locked.is_some() {
+            line.push_str(" locked");
+        }
+        if worktree.prunable.is_some() {
+            line.push_str(" prunable");
+        }
+        println!("{}", line);
+    }
+}
+
+pub fn cmd_worktree(command: WorktreeSubCommand) -> Result<()> {
+    let repo = find_repo_in_current_directory()?;
+
+    match command {
+        WorktreeSubCommand::Add {
+            path,
+            commit,
+            branch,
+            detach,
+        } => {
+            let is_branch = |name: &str| repo.resolve_ref(&format!("refs/heads/{}", name)).is_ok();
+            // Without a commit-ish, the branch named after the work tree is checked out, and
+            // created from HEAD if missing.
+            let (new_branch, branch, target) = match (branch, commit) {
+                (Some(branch), commit) => (true, Some(branch), commit),
+                (None, Some(commit)) if !detach && is_branch(&commit) => {
+                    (false, Some(commit.clone()), Some(commit))
+                }
+                (None, commit) if detach || commit.is_some() => (false, None, commit),
+                (None, _) => {
+                    let name = Path::new(&path)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    match is_branch(&name) {
+                        true => (false, Some(name.clone()), Some(name)),
+                        false => (true, Some(name), None),
+                    }
+                }
+            };
+            if new_branch && branch.as_deref().is_some_and(is_branch) {
+                return Err(anyhow::anyhow!(
+                    "a branch named '{}' already exists",
+                    branch.unwrap_or_default()
+                ));
+            }
+            let (hash, commit) = repo.read_commit(target.as_deref().unwrap_or("HEAD"))?;
+
+            match (&branch, new_branch) {
+                (Some(branch), true) => eprintln!("Preparing worktree (new branch '{}')", branch),
+                (Some(branch), false) => {
+                    eprintln!("Preparing worktree (checking out '{}')", branch)
+                }
+                (None, _) => eprintln!("Preparing worktree (detached HEAD {})", &hash[..7]),
+            }
+            let branch_ref = branch.as_deref().map(branch::branch_ref);
+            repo.add_worktree(Path::new(&path), branch_ref.as_deref(), &hash)?;
+            if let (Some(branch), true) = (&branch, new_branch) {
+                let start = target.as_deref().unwrap_or("HEAD");
+                repo.create_branch(branch, &hash, start, false)?;
+            }
+            println!("HEAD is now at {}", describe_commit(&hash, &commit));
+        }
+        WorktreeSubCommand::List { porcelain } => print_worktrees(&repo.worktrees()?, porcelain),
+        WorktreeSubCommand::Remove { worktree, force } => {
+            let worktree = repo.find_worktree(Path::new(&worktree))?;
+            repo.remove_worktree(&worktree, force)?;
+        }
+        WorktreeSubCommand::Lock { worktree, reason } => {
+            let worktree = repo.find_worktree(Path::new(&worktree))?;
+            repo.lock_worktree(&worktree, reason.as_deref())?;
+        }
+        WorktreeSubCommand::Unlock { worktree } => {
+            let worktree = repo.find_worktree(Path::new(&worktree))?;
+            repo.unlock_worktree(&worktree)?;
+        }
+        WorktreeSubCommand::Prune { dry_run, verbose } => {
+            for worktree in repo.prune_worktrees(dry_run)? {
+                if verbose {
+                    println!(
+                        "Removing worktrees/{}: {}",
+                        worktree.name.unwrap_or_default(),
+                        worktree.prunable.unwrap_or_default()
+                    );
+                }
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,369 @@
+//! Content filter drivers, set on paths with the `filter=<driver>` attribute.
+//!
+//! A driver is configured with `filter.<driver>.clean` and `filter.<driver>.smudge`, commands
+//! run once per file with the content on their standard input, or with
+//! `filter.<driver>.process`, a long-running command talking git's filter protocol over
+//! pkt-lines. Clean filters turn work tree files into blobs, smudge filters do the opposite. A
+//! failing filter leaves the content as is, unless `filter.<driver>.required` is set.
+
+use std::{
+    collections::HashMap,
+    io::{BufReader, Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::Mutex,
+};
+
+use crate::{error::ConvertError, git_config::GitConfig};
+
+/// The largest payload of a pkt-line.
+const MAX_PACKET_DATA: usize = 65516;
+
+/// Which way a filter converts content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the work tree to a blob.
+    Clean,
+    /// From a blob to the work tree.
+    Smudge,
+}
+
+impl Direction {
+    fn capability(self) -> &'static str {
+        match self {
+            Self::Clean => "clean",
+            Self::Smudge => "smudge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterDriver {
+    pub name: String,
+    pub clean: Option<String>,
+    pub smudge: Option<String>,
+    pub process: Option<String>,
+    pub required: bool,
+    /// Where commands run: the top of the work tree.
+    pub work_tree: PathBuf,
+}
+
+impl FilterDriver {
+    /// The driver `name` as configured, or `None` if nothing is configured for it.
+    pub fn from_config(config: &GitConfig, name: &str, work_tree: &Path) -> Option<Self> {
+        let section = format!("filter \"{}\"", name);
+        let driver = Self {
+            name: name.to_string(),
+            clean: config.get_string(&section, "clean"),
+            smudge: config.get_string(&section, "smudge"),
+            process: config.get_string(&section, "process"),
+            required: config.get_bool(&section, "required").unwrap_or(false),
+            work_tree: work_tree.to_path_buf(),
+        };
+        let configured =
+            driver.clean.is_some() || driver.smudge.is_some() || driver.process.is_some();
+        configured.then_some(driver)
+    }
+
+    /// Filter the content of the file at `path`. A filter that fails or isn't configured for
+    /// `direction` leaves the content as is, which is an error if the driver is required.
+    pub fn apply(
+        &self,
+        direction: Direction,
+        path: &str,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, ConvertError> {
+        // A process filter takes precedence over the single file commands.
+        let filtered = match (&self.process, direction, &self.clean, &self.smudge) {
+            (Some(process), ..) => {
+                run_process_filter(process, &self.work_tree, direction, path, &data)
+            }
+            (None, Direction::Clean, Some(command), _)
+            | (None, Direction::Smudge, _, Some(command)) => {
+                run_filter(command, &self.work_tree, path, &data).map(Some)
+            }
+            _ => Ok(None),
+        };
+
+        match filtered {
+            Ok(Some(filtered)) => Ok(filtered),
+            result => {
+                if let Err(error) = result {
+                    eprintln!("error: {}", error);
+                }
+                match self.required {
+                    true => Err(ConvertError::FilterFailed(
+                        path.to_string(),
+                        direction.capability().to_string(),
+                        self.name.clone(),
+                    )),
+                    false => Ok(data),
+                }
+            }
+        }
+    }
+}
+
+/// Quote `path` for the shell.
+fn shell_quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', "'\\''"))
+}
+
+fn spawn_shell(command: &str, work_tree: &Path) -> Result<Child, Error> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(work_tree)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("cannot fork to run external filter '{}'", command),
+            )
+        })
+}
+
+/// Run a single file filter `command`, where `%f` stands for the quoted path of the file.
+fn run_filter(command: &str, work_tree: &Path, path: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let command = command.replace("%f", &shell_quote(path));
+    let mut child = spawn_shell(&command, work_tree)?;
+
+    // The content is written from another thread, as the filter may not read all of it before
+    // writing its output.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let filtered = std::thread::scope(|scope| {
+        scope.spawn(move || {
+            // The filter may exit without reading its input, which its status tells about.
+            let _ = stdin.write_all(data);
+        });
+        let mut filtered = vec![];
+        child
+            .stdout
+            .take()
+            .expect("stdout is piped")
+            .read_to_end(&mut filtered)
+            .map(|_| filtered)
+    })?;
+
+    let status = child.wait()?;
+    match status.success() {
+        true => Ok(filtered),
+        false => Err(Error::other(format!(
+            "external filter '{}' failed {}",
+            command,
+            status.code().unwrap_or(-1)
+        ))),
+    }
+}
+
+fn write_packet(out: &mut impl Write, data: &[u8]) -> Result<(), Error> {
+    write!(out, "{:04x}", data.len() + 4)?;
+    out.write_all(data)
+}
+
+fn write_text_packet(out: &mut impl Write, text: &str) -> Result<(), Error> {
+    write_packet(out, format!("{}\n", text).as_bytes())
+}
+
+fn write_flush(out: &mut impl Write) -> Result<(), Error> {
+    out.write_all(b"0000")
+}
+
+/// Read a pkt-line. Returns `None` for a flush packet.
+fn read_packet(input: &mut impl Read) -> Result<Option<Vec<u8>>, Error> {
+    let mut header = [0; 4];
+    input.read_exact(&mut header)?;
+    let length = std::str::from_utf8(&header)
+        .ok()
+        .and_then(|header| usize::from_str_radix(header, 16).ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid pkt-line header"))?;
+    match length {
+        0 => Ok(None),
+        1..=4 => Err(Error::new(
+            ErrorKind::InvalidData,
+            "invalid pkt-line length",
+        )),
+        _ => {
+            let mut data = vec![0; length - 4];
+            input.read_exact(&mut data)?;
+            Ok(Some(data))
+        }
+    }
+}
+
+/// Read text packets up to a flush packet, without their trailing newlines.
+fn read_text_list(input: &mut impl Read) -> Result<Vec<String>, Error> {
+    let mut lines = vec![];
+    while let Some(packet) = read_packet(input)? {
+        let line = String::from_utf8_lossy(&packet);
+        lines.push(line.strip_suffix('\n').unwrap_or(&line).to_string());
+    }
+    Ok(lines)
+}
+
+/// The `status=` of a list, if any.
+fn status(list: &[String]) -> Option<&str> {
+    list.iter()
+        .rev()
+        .find_map(|line| line.strip_prefix("status="))
+}
+
+/// A running process filter.
+struct FilterProcess {
+    /// The process exits once its standard input is closed, when rit exits.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    capabilities: Vec<String>,
+}
+
+impl FilterProcess {
+    /// Start `command` and negotiate version 2 of the protocol and the capabilities.
+    fn start(command: &str, work_tree: &Path) -> Result<Self, Error> {
+        let mut child = spawn_shell(command, work_tree)?;
+        let mut process = Self {
+            stdin: child.stdin.take().expect("stdin is piped"),
+            stdout: BufReader::new(child.stdout.take().expect("stdout is piped")),
+            _child: child,
+            capabilities: vec![],
+        };
+
+        write_text_packet(&mut process.stdin, "git-filter-client")?;
+        write_text_packet(&mut process.stdin, "version=2")?;
+        write_flush(&mut process.stdin)?;
+        let welcome = read_text_list(&mut process.stdout)?;
+        if welcome.first().map(String::as_str) != Some("git-filter-server")
+            || !welcome.iter().any(|line| line == "version=2")
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "external filter '{}' does not support filter protocol version 2",
+                    command
+                ),
+            ));
+        }
+
+        write_text_packet(&mut process.stdin, "capability=clean")?;
+        write_text_packet(&mut process.stdin, "capability=smudge")?;
+        write_flush(&mut process.stdin)?;
+        process.capabilities = read_text_list(&mut process.stdout)?
+            .into_iter()
+            .filter_map(|line| line.strip_prefix("capability=").map(str::to_string))
+            .collect();
+        Ok(process)
+    }
+
+    /// Filter the content of `path`. Returns `None` if the filter refused to.
+    fn filter(
+        &mut self,
+        direction: Direction,
+        path: &str,
+        data: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        write_text_packet(
+            &mut self.stdin,
+            &format!("command={}", direction.capability()),
+        )?;
+        write_text_packet(&mut self.stdin, &format!("pathname={}", path))?;
+        write_flush(&mut self.stdin)?;
+        for chunk in data.chunks(MAX_PACKET_DATA) {
+            write_packet(&mut self.stdin, chunk)?;
+        }
+        write_flush(&mut self.stdin)?;
+        self.stdin.flush()?;
+
+        let response = read_text_list(&mut self.stdout)?;
+        match status(&response) {
+            Some("success") => {}
+            Some("abort") => {
+                // The filter gives up on this kind of conversion for the rest of the session.
+                self.capabilities
+                    .retain(|capability| capability != direction.capability());
+                return Ok(None);
+            }
+            _ => return Ok(None),
+        }
+
+        let mut filtered = vec![];
+        while let Some(packet) = read_packet(&mut self.stdout)? {
+            filtered.extend(packet);
+        }
+        // A final status list, empty to keep the first one.
+        let trailer = read_text_list(&mut self.stdout)?;
+        match status(&trailer) {
+            None | Some("success") => Ok(Some(filtered)),
+            Some("abort") => {
+                self.capabilities
+                    .retain(|capability| capability != direction.capability());
+                Ok(None)
+            }
+            Some(_) => Ok(None),
+        }
+    }
+}
+
+/// The process filters started so far, by command. They run until rit exits.
+static PROCESSES: Mutex<Option<HashMap<String, FilterProcess>>> = Mutex::new(None);
+
+/// Filter the content of `path` with the process filter `command`, starting it if needed.
+/// Returns `None` if the filter doesn't handle `direction` or refused to filter the content.
+fn run_process_filter(
+    command: &str,
+    work_tree: &Path,
+    direction: Direction,
+    path: &str,
+    data: &[u8],
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut processes = PROCESSES.lock().unwrap_or_else(|error| error.into_inner());
+    let processes = processes.get_or_insert_with(HashMap::new);
+    if !processes.contains_key(command) {
+        processes.insert(
+            command.to_string(),
+            FilterProcess::start(command, work_tree)?,
+        );
+    }
+    let process = processes.get_mut(command).expect("inserted above");
+    if !process
+        .capabilities
+        .iter()
+        .any(|capability| capability == direction.capability())
+    {
+        return Ok(None);
+    }
+
+    match process.filter(direction, path, data) {
+        Ok(filtered) => Ok(filtered),
+        Err(error) => {
+            // The process is unusable after a protocol error.
+            processes.remove(command);
+            Err(Error::new(
+                error.kind(),
+                format!("external filter '{}' failed: {}", command, error),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_packet, read_text_list, write_flush, write_packet, write_text_packet};
+
+    #[test]
+    fn pkt_lines_should_round_trip() {
+        let mut out = vec![];
+        write_text_packet(&mut out, "version=2").unwrap();
+        write_packet(&mut out, b"data").unwrap();
+        write_flush(&mut out).unwrap();
+
+        assert_eq!(out, b"000eversion=2\n0008data0000");
+        let mut input = out.as_slice();
+        assert_eq!(
+            read_text_list(&mut input).unwrap(),
+            vec!["version=2", "data"]
+        );
+        assert!(read_packet(&mut input).is_err());
+    }
+}
@@ -7,6 +7,15 @@ use crate::error::ConfigParseError;
 
 type Config = HashMap<String, HashMap<String, Option<String>>>;
 
+/// The `extensions.*` keys understood in a repository of format version 1, with their values.
+const KNOWN_EXTENSIONS: [(&str, &[&str]); 1] = [("refstorage", &["files", "reftable"])];
+
+/// The name of the section a `[section]` header line opens, `None` for other lines.
+fn section_name(line: &str) -> Option<&str> {
+    let header = line.trim().strip_prefix('[')?;
+    Some(header.split(']').next().unwrap_or_default().trim())
+}
+
 #[derive(Debug)]
 pub struct GitConfig {
     config: Config,
@@ -48,8 +57,136 @@ impl GitConfig {
         }
     }
 
+    /// Look up `section.key`. Section and key names are case-insensitive.
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.config
+            .get(&section.to_lowercase())?
+            .get(&key.to_lowercase())?
+            .as_deref()
+    }
+
+    /// Look up `section.key` like [`GitConfig::get`], with git's double quotes and escape
+    /// sequences resolved. Subsections are looked up as `section "subsection"`.
+    pub fn get_string(&self, section: &str, key: &str) -> Option<String> {
+        let value = self.get(section, key)?;
+        let mut string = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {}
+                '\\' => match chars.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('b') => {
+                        string.pop();
+                    }
+                    Some(c) => string.push(c),
+                    None => {}
+                },
+                c => string.push(c),
+            }
+        }
+        Some(string)
+    }
+
+    /// Look up a boolean `section.key`, with git's spellings of true and false. `None` if the
+    /// key is missing or isn't a boolean.
+    pub fn get_bool(&self, section: &str, key: &str) -> Option<bool> {
+        let value = self
+            .config
+            .get(&section.to_lowercase())?
+            .get(&key.to_lowercase())?;
+        match value.as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("true" | "yes" | "on" | "1") => Some(true),
+            Some("false" | "no" | "off" | "0" | "") => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Set `section.key` to `value` in the configuration file at `path`, keeping the rest of
+    /// the file as is. The key is replaced in the section already holding it, or added to the
+    /// last `[section]` of the file, or to a new one.
+    pub fn write_value(
+        path: &Path,
+        section: &str,
+        key: &str,
+        value: &str,
+    ) -> Result<(), std::io::Error> {
+        let content = std::fs::read_to_string(path)?;
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        let line = format!("\t{} = {}", key, value);
+
+        let mut in_section = false;
+        let mut section_end = None;
+        for (i, current) in lines.iter().enumerate() {
+            if let Some(name) = section_name(current) {
+                in_section = name.eq_ignore_ascii_case(section);
+                if in_section {
+                    section_end = Some(i + 1);
+                }
+                continue;
+            }
+            if !in_section {
+                continue;
+            }
+            section_end = Some(i + 1);
+            let name = current.trim().split('=').next().unwrap_or_default().trim();
+            if name.eq_ignore_ascii_case(key) {
+                lines[i] = line;
+                return std::fs::write(path, lines.join("\n") + "\n");
+            }
+        }
+
+        match section_end {
+            Some(end) => lines.insert(end, line),
+            None => {
+                lines.push(format!("[{}]", section));
+                lines.push(line);
+            }
+        }
+        std::fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /// Rename every `[old]` section of the configuration file at `path` to `[new]`, or remove
+    /// them with their keys if `new` is `None`. The rest of the file is kept as is.
+    pub fn rename_section(path: &Path, old: &str, new: Option<&str>) -> Result<(), std::io::Error> {
+        let content = std::fs::read_to_string(path)?;
+        let mut lines = vec![];
+        let mut in_section = false;
+        for line in content.lines() {
+            if let Some(name) = section_name(line) {
+                in_section = name.eq_ignore_ascii_case(old);
+                match (in_section, new) {
+                    (true, Some(new)) => lines.push(format!("[{}]", new)),
+                    (true, None) => {}
+                    (false, _) => lines.push(line.to_string()),
+                }
+                continue;
+            }
+            if !in_section || new.is_some() {
+                lines.push(line.to_string());
+            }
+        }
+        std::fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /// Whether the repository format is understood: version 0, or version 1 if every
+    /// `extensions.*` key is known and has a known value.
     pub fn is_repository_format_version_valid(&self) -> Result<bool, ConfigParseError> {
-        Ok(self.repository_format_version()? == 0)
+        Ok(match self.repository_format_version()? {
+            0 => true,
+            1 => self.config.get("extensions").is_none_or(|extensions| {
+                extensions.iter().all(|(key, value)| {
+                    KNOWN_EXTENSIONS.iter().any(|(known, values)| {
+                        key == known
+                            && value
+                                .as_deref()
+                                .is_some_and(|value| values.contains(&value))
+                    })
+                })
+            }),
+            _ => false,
+        })
     }
 
     pub fn default_str() -> &'static str {
@@ -97,6 +234,79 @@ mod tests {
         assert!(config.is_repository_format_version_valid().unwrap());
     }
 
+    #[test]
+    fn version_1_should_only_be_valid_with_known_extensions() {
+        let config = |extensions: &str| -> GitConfig {
+            format!("[core]\n\trepositoryformatversion = 1\n{}", extensions)
+                .parse()
+                .unwrap()
+        };
+
+        assert!(config("").is_repository_format_version_valid().unwrap());
+        assert!(config("[extensions]\n\trefStorage = reftable\n")
+            .is_repository_format_version_valid()
+            .unwrap());
+        assert!(!config("[extensions]\n\trefStorage = other\n")
+            .is_repository_format_version_valid()
+            .unwrap());
+        assert!(!config("[extensions]\n\tobjectFormat = sha256\n")
+            .is_repository_format_version_valid()
+            .unwrap());
+    }
+
+    #[test]
+    fn get_string_should_resolve_quotes_and_escapes() {
+        let config: GitConfig = "[filter \"crypt\"]\n\tclean = \"crypt -e\" \\\"%f\\\"\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.get_string("filter \"crypt\"", "clean").unwrap(),
+            "crypt -e \"%f\""
+        );
+        assert_eq!(config.get_string("filter \"crypt\"", "smudge"), None);
+    }
+
+    #[test]
+    fn get_bool_should_accept_git_boolean_spellings() {
+        let config_string = r#"
+        [core]
+            filemode = no
+            bare = On
+        [diff]
+            renames = copies
+        "#;
+
+        let config: GitConfig = config_string.parse().unwrap();
+
+        assert_eq!(config.get_bool("core", "fileMode"), Some(false));
+        assert_eq!(config.get_bool("core", "bare"), Some(true));
+        assert_eq!(config.get_bool("diff", "renames"), None);
+        assert_eq!(config.get_bool("diff", "missing"), None);
+    }
+
+    #[test]
+    fn rename_section_should_rename_or_remove_the_section_and_its_keys() {
+        let path = std::env::temp_dir().join(format!("rit-config-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "[core]\n\tbare = false\n[branch \"old\"]\n\tremote = origin\n[user]\n\tname = A\n",
+        )
+        .unwrap();
+
+        GitConfig::rename_section(&path, "branch \"old\"", Some("branch \"new\"")).unwrap();
+        let renamed = std::fs::read_to_string(&path).unwrap();
+        GitConfig::rename_section(&path, "branch \"new\"", None).unwrap();
+        let removed = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            renamed,
+            "[core]\n\tbare = false\n[branch \"new\"]\n\tremote = origin\n[user]\n\tname = A\n"
+        );
+        assert_eq!(removed, "[core]\n\tbare = false\n[user]\n\tname = A\n");
+    }
+
     #[test]
     fn if_config_string_doesnt_have_version_repository_format_version_function_should_return_error()
     {
@@ -2,11 +2,11 @@ use crate::error::ObjectParseError;
 
 #[derive(Debug)]
 pub struct Blob {
-    pub blob: String,
+    pub blob: Vec<u8>,
 }
 
 impl Blob {
-    pub fn serialize(&self) -> String {
+    pub fn serialize(&self) -> Vec<u8> {
         // TODO: Make it memory-friendly
         self.blob.clone()
     }
@@ -15,8 +15,8 @@ impl Blob {
         buf_reader: &mut impl std::io::BufRead,
         object_header: super::Header,
     ) -> Result<Self, crate::error::ObjectParseError> {
-        let mut blob = String::new();
-        let length = buf_reader.read_to_string(&mut blob)?;
+        let mut blob = Vec::new();
+        let length = buf_reader.read_to_end(&mut blob)?;
         if length != object_header.object_size {
             return Err(ObjectParseError::MismatchedObjectSize);
         }
@@ -21,73 +21,113 @@ impl Commit {
         })
     }
 
+    /// The first value of `key`.
     pub fn get_value(&self, key: &str) -> Option<&String> {
-        self.kvl.map.get(key)
+        self.kvl.get(key)
+    }
+
+    /// The timestamp of the `committer` line, 0 if it can't be parsed.
+    pub fn committer_time(&self) -> i64 {
+        self.get_value("committer")
+            .and_then(|committer| committer.rsplit(' ').nth(1))
+            .and_then(|time| time.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// The parents of the commit, first parent first.
+    pub fn parents(&self) -> Vec<&String> {
+        self.kvl.get_all("parent").collect()
     }
 }
 
+/// Header lines of a commit or a tag, in order, followed by an optional message stored under the
+/// `message` key. Keys like `parent` may appear several times.
 #[derive(Debug)]
 pub struct KeyValueList {
-    map: BTreeMap<String, String>,
+    entries: Vec<(String, String)>,
 }
 
 impl KeyValueList {
     pub fn new(map: BTreeMap<String, String>) -> Self {
-        KeyValueList { map }
-    }
-
-    pub fn parse(buf_reader: impl BufRead) -> Result<Self, KvlParseError> {
-        let mut map: BTreeMap<String, String> = BTreeMap::new();
-
-        let mut the_rest_is_message = false;
-        let mut message = String::new();
-
-        let mut prev_key = String::new();
-
-        for line in buf_reader.lines() {
-            let line = line.map_err(|_| KvlParseError::FailedToGetNextLine)?;
-            if line.is_empty() {
-                the_rest_is_message = true;
-            } else if the_rest_is_message {
-                message.push_str(line.as_str());
-            } else if let Some(char) = line.chars().next() {
-                if char == ' ' {
-                    // Continuation of previous value
-                    map.get_mut(&prev_key)
-                        .unwrap()
-                        .push_str(format!("\n{}", &line[1..]).as_str());
-                } else {
-                    // New key value
-                    let index = line.find(' ').ok_or(KvlParseError::KeyDelimiterNotFound)?;
-                    let key = &line[0..index];
-                    let value = &line[index + 1..];
-                    map.insert(key.to_string(), value.to_string());
-                    prev_key = key.to_string();
-                }
+        KeyValueList {
+            entries: map.into_iter().collect(),
+        }
+    }
+
+    pub fn from_entries(entries: Vec<(String, String)>) -> Self {
+        KeyValueList { entries }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    pub fn parse(mut buf_reader: impl BufRead) -> Result<Self, KvlParseError> {
+        let mut content = String::new();
+        buf_reader
+            .read_to_string(&mut content)
+            .map_err(|_| KvlParseError::FailedToGetNextLine)?;
+
+        // The message is everything after the first empty line, kept verbatim.
+        let (headers, message) = match content.split_once("\n\n") {
+            Some((headers, message)) => (headers, Some(message)),
+            None if content.starts_with('\n') => ("", Some(&content[1..])),
+            None => (content.strip_suffix('\n').unwrap_or(&content), None),
+        };
+
+        let mut entries: Vec<(String, String)> = vec![];
+        for line in headers.split('\n').filter(|line| !line.is_empty()) {
+            if let Some(continuation) = line.strip_prefix(' ') {
+                // Continuation of previous value
+                let (_, value) = entries
+                    .last_mut()
+                    .ok_or(KvlParseError::KeyDelimiterNotFound)?;
+                value.push('\n');
+                value.push_str(continuation);
+            } else {
+                // New key value
+                let (key, value) = line
+                    .split_once(' ')
+                    .ok_or(KvlParseError::KeyDelimiterNotFound)?;
+                entries.push((key.to_string(), value.to_string()));
             }
         }
 
-        if the_rest_is_message {
-            map.insert("message".to_string(), message);
+        if let Some(message) = message {
+            entries.push(("message".to_string(), message.to_string()));
         }
 
-        Ok(Self { map })
+        Ok(Self { entries })
     }
 
     pub fn serialize(&self) -> String {
         let mut buffer = String::new();
         let mut message_value = None;
-        for (key, value) in &self.map {
+        for (key, value) in &self.entries {
             if key == "message" {
                 message_value = Some(value);
                 continue;
             }
             if value.contains('\n') {
                 let multi_line_value = value
-                    .lines()
+                    .split('\n')
                     .take(1)
                     .map(|v| format!("{}\n", v))
-                    .chain(value.lines().skip(1).map(|line| format!(" {}\n", line)))
+                    .chain(value.split('\n').skip(1).map(|line| format!(" {}\n", line)))
                     .collect::<String>();
                 buffer.push_str(format!("{} {}", key, &multi_line_value).as_str());
             } else {
@@ -102,18 +142,18 @@ impl KeyValueList {
     }
 }
 
-impl AsRef<BTreeMap<String, String>> for KeyValueList {
-    fn as_ref(&self) -> &BTreeMap<String, String> {
-        &self.map
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use std::{collections::BTreeMap, io::BufReader};
 
     use super::KeyValueList;
 
+    fn sorted(entries: &[(String, String)]) -> Vec<(String, String)> {
+        let mut entries = entries.to_vec();
+        entries.sort();
+        entries
+    }
+
     #[test]
     pub fn kvl_parse_should_parse_single_line_key_values_correctly() {
         let raw = r#"tree 29ff16c9c14e2652b22f8b78bb08a5a07930c147
@@ -181,7 +221,39 @@ Create first draft"#;
 
         let kvl = KeyValueList::parse(BufReader::new(raw.as_bytes())).unwrap();
 
-        assert_eq!(parsed_key_values, *kvl.as_ref());
+        assert_eq!(
+            parsed_key_values.into_iter().collect::<Vec<_>>(),
+            sorted(kvl.entries())
+        );
+        assert_eq!(raw, kvl.serialize());
+    }
+
+    #[test]
+    fn kvl_parse_should_keep_repeated_keys_in_order() {
+        let raw = "tree 29ff16c9c14e2652b22f8b78bb08a5a07930c147
+parent 206941306e8a8af65b66eaaaea388a7ae24d49a0
+parent 4b825dc642cb6eb9a060e54bf8d69288fbe4904f
+author A U Thor <author@example.com> 1527025023 +0200
+
+Merge branch 'topic'
+
+Second paragraph.
+";
+
+        let kvl = KeyValueList::parse(BufReader::new(raw.as_bytes())).unwrap();
+
+        assert_eq!(
+            vec![
+                "206941306e8a8af65b66eaaaea388a7ae24d49a0",
+                "4b825dc642cb6eb9a060e54bf8d69288fbe4904f"
+            ],
+            kvl.get_all("parent").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "Merge branch 'topic'\n\nSecond paragraph.\n",
+            kvl.get("message").unwrap()
+        );
+        assert_eq!(raw, kvl.serialize());
     }
 
     #[test]
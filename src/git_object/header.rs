@@ -4,7 +4,7 @@ use anyhow::Context;
 
 use crate::error::ObjectParseError;
 
-#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Debug, Clone, Copy)]
 pub enum Type {
     Commit,
     Tree,
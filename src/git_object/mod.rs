@@ -3,6 +3,7 @@ pub mod commit;
 pub mod compressed;
 pub mod header;
 pub mod serialized;
+pub mod signature;
 pub mod tag;
 pub mod tree;
 
@@ -11,6 +12,7 @@ pub use commit::*;
 pub use compressed::*;
 pub use header::*;
 pub use serialized::*;
+pub use signature::*;
 pub use tag::*;
 pub use tree::*;
 
@@ -25,15 +27,24 @@ pub enum GitObject {
 }
 
 impl GitObject {
-    pub fn serialize(&self) -> String {
+    pub fn serialize(&self) -> Vec<u8> {
         match self {
-            GitObject::Commit(commit) => commit.serialize(),
+            GitObject::Commit(commit) => commit.serialize().into_bytes(),
             GitObject::Blob(blob) => blob.serialize(),
-            GitObject::Tag(tag) => tag.serialize(),
+            GitObject::Tag(tag) => tag.serialize().into_bytes(),
             GitObject::Tree(tree) => tree.serialize(),
         }
     }
 
+    pub fn get_type(&self) -> Type {
+        match self {
+            GitObject::Commit(_) => Type::Commit,
+            GitObject::Blob(_) => Type::Blob,
+            GitObject::Tag(_) => Type::Tag,
+            GitObject::Tree(_) => Type::Tree,
+        }
+    }
+
     fn deserialize(
         buf_reader: &mut impl std::io::BufRead,
         object_header: Header,
@@ -3,10 +3,10 @@ use std::io::{BufWriter, Write};
 
 use crate::{
     error::{ObjectCreateError, ObjectParseError},
-    git_object, GitObject,
+    GitObject,
 };
 
-use super::Header;
+use super::{Header, Type};
 
 pub struct SerializedGitObject {
     raw: Vec<u8>,
@@ -26,28 +26,32 @@ impl SerializedGitObject {
             raw,
         }
     }
+
+    /// The hash `data` would get as an object of `object_type`, without building the object.
+    pub fn hash_of(object_type: Type, data: &[u8]) -> String {
+        let mut sha = sha1_smol::Sha1::new();
+        sha.update(Header::new(object_type, data.len()).to_string().as_bytes());
+        sha.update(data);
+        sha.hexdigest()
+    }
 }
 
 impl TryFrom<GitObject> for SerializedGitObject {
     type Error = ObjectCreateError;
 
     fn try_from(value: GitObject) -> Result<Self, Self::Error> {
-        let (serialized_object, object_type) = match value {
-            GitObject::Commit(commit) => (commit.serialize(), git_object::Type::Commit),
-            GitObject::Blob(blob) => (blob.serialize(), git_object::Type::Blob),
-            GitObject::Tag(tag) => (tag.serialize(), git_object::Type::Tag),
-            GitObject::Tree(tree) => (tree.serialize(), git_object::Type::Tree),
-        };
+        let object_type = value.get_type();
+        let serialized_object = value.serialize();
 
         let buffer = Vec::<u8>::new();
         let mut buf_writer = BufWriter::new(buffer);
 
         write!(
             buf_writer,
-            "{}{}",
-            Header::new(object_type, serialized_object.len()),
-            serialized_object
+            "{}",
+            Header::new(object_type, serialized_object.len())
         )?;
+        buf_writer.write_all(&serialized_object)?;
 
         buf_writer.flush()?;
         let buffer = buf_writer
@@ -0,0 +1,195 @@
+use std::{fmt::Display, str::FromStr};
+
+/// The identity and time stamp of an `author`, `committer` or `tagger` line, such as
+/// `A U Thor <author@example.com> 1700000000 +0100`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the epoch.
+    pub time: i64,
+    /// Offset from UTC, like `+0100`.
+    pub offset: String,
+}
+
+impl Signature {
+    pub fn parse(line: &str) -> Option<Self> {
+        let (name, rest) = line.split_once('<')?;
+        let (email, rest) = rest.split_once('>')?;
+        let mut date = rest.split_whitespace();
+        Some(Self {
+            name: name.trim_end().to_string(),
+            email: email.to_string(),
+            time: date.next()?.parse().ok()?,
+            offset: date.next().unwrap_or("+0000").to_string(),
+        })
+    }
+}
+
+/// How a date is shown, such as the `iso` of `%(committerdate:iso)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateFormat {
+    /// `Mon Jan 2 15:04:05 2006 -0700`
+    #[default]
+    Default,
+    /// `2006-01-02 15:04:05 -0700`
+    Iso,
+    /// `2006-01-02T15:04:05-07:00`
+    IsoStrict,
+    /// `Mon, 2 Jan 2006 15:04:05 -0700`
+    Rfc2822,
+    /// `2006-01-02`
+    Short,
+    /// `1136239445`
+    Unix,
+    /// `1136239445 -0700`
+    Raw,
+}
+
+impl FromStr for DateFormat {
+    type Err = ();
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "default" => Ok(DateFormat::Default),
+            "iso" | "iso8601" => Ok(DateFormat::Iso),
+            "iso-strict" | "iso8601-strict" => Ok(DateFormat::IsoStrict),
+            "rfc" | "rfc2822" => Ok(DateFormat::Rfc2822),
+            "short" => Ok(DateFormat::Short),
+            "unix" => Ok(DateFormat::Unix),
+            "raw" => Ok(DateFormat::Raw),
+            _ => Err(()),
+        }
+    }
+}
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// The year, month and day of the `days`-th day since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, usize, i64) {
+    // Howard Hinnant's algorithm, counting in eras of 400 years starting on March 1st.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as usize, day)
+}
+
+impl Signature {
+    /// The offset from UTC in seconds.
+    pub(crate) fn offset_seconds(&self) -> i64 {
+        let sign = if self.offset.starts_with('-') { -1 } else { 1 };
+        let digits = self.offset.trim_start_matches(['+', '-']);
+        let hours: i64 = digits.get(..2).and_then(|h| h.parse().ok()).unwrap_or(0);
+        let minutes: i64 = digits.get(2..4).and_then(|m| m.parse().ok()).unwrap_or(0);
+        sign * (hours * 3600 + minutes * 60)
+    }
+
+    /// The date of the signature in `format`, in its own time zone.
+    pub fn format_date(&self, format: DateFormat) -> String {
+        let local = self.time + self.offset_seconds();
+        let days = local.div_euclid(86400);
+        let seconds = local.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        let time = format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+        let weekday = WEEKDAYS[days.rem_euclid(7) as usize];
+        let month_name = MONTHS[month - 1];
+        match format {
+            DateFormat::Default => format!(
+                "{} {} {} {} {} {}",
+                weekday, month_name, day, time, year, self.offset
+            ),
+            DateFormat::Iso => format!("{}-{:02}-{:02} {} {}", year, month, day, time, self.offset),
+            DateFormat::IsoStrict => {
+                let (sign, digits) = self.offset.split_at(1.min(self.offset.len()));
+                format!(
+                    "{}-{:02}-{:02}T{}{}{}:{}",
+                    year,
+                    month,
+                    day,
+                    time,
+                    sign,
+                    digits.get(..2).unwrap_or("00"),
+                    digits.get(2..4).unwrap_or("00")
+                )
+            }
+            DateFormat::Rfc2822 => format!(
+                "{}, {} {} {} {} {}",
+                weekday, day, month_name, year, time, self.offset
+            ),
+            DateFormat::Short => format!("{}-{:02}-{:02}", year, month, day),
+            DateFormat::Unix => self.time.to_string(),
+            DateFormat::Raw => format!("{} {}", self.time, self.offset),
+        }
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name, self.email, self.time, self.offset
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DateFormat, Signature};
+
+    #[test]
+    fn signature_should_round_trip() {
+        let line = "A U Thor <author@example.com> 1700000000 -0130";
+
+        let signature = Signature::parse(line).unwrap();
+
+        assert_eq!(signature.name, "A U Thor");
+        assert_eq!(signature.email, "author@example.com");
+        assert_eq!(signature.time, 1700000000);
+        assert_eq!(signature.offset, "-0130");
+        assert_eq!(signature.to_string(), line);
+    }
+
+    #[test]
+    fn format_date_should_use_the_signature_time_zone() {
+        let signature = Signature::parse("A U Thor <author@example.com> 1136239445 -0700").unwrap();
+
+        assert_eq!(
+            signature.format_date(DateFormat::Default),
+            "Mon Jan 2 15:04:05 2006 -0700"
+        );
+        assert_eq!(
+            signature.format_date(DateFormat::Iso),
+            "2006-01-02 15:04:05 -0700"
+        );
+        assert_eq!(
+            signature.format_date(DateFormat::IsoStrict),
+            "2006-01-02T15:04:05-07:00"
+        );
+        assert_eq!(
+            signature.format_date(DateFormat::Rfc2822),
+            "Mon, 2 Jan 2006 15:04:05 -0700"
+        );
+        assert_eq!(signature.format_date(DateFormat::Short), "2006-01-02");
+        assert_eq!(signature.format_date(DateFormat::Raw), "1136239445 -0700");
+    }
+}
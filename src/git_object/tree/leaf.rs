@@ -6,7 +6,7 @@ use crate::error::TreeLeafParseError;
 
 use super::mode::{Mode, Type};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Leaf {
     pub mode: Mode,
     pub path: String,
@@ -47,6 +47,12 @@ impl Leaf {
     pub fn get_type(&self) -> Type {
         self.mode.type_
     }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, hex::FromHexError> {
+        let mut buffer = format!("{} {}\x00", self.mode.serialize(), self.path).into_bytes();
+        buffer.extend(hex::decode(&self.hash)?);
+        Ok(buffer)
+    }
 }
 
 impl Display for Leaf {
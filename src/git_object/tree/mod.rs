@@ -1,18 +1,30 @@
 pub mod leaf;
 pub mod mode;
 
-use std::{fmt::Display, ops::Deref};
+use std::{cmp::Ordering, fmt::Display, ops::Deref};
 
 use self::leaf::Leaf;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Tree {
     pub leaves: Vec<Leaf>,
 }
 
 impl Tree {
-    pub fn serialize(&self) -> String {
-        todo!()
+    /// Build a tree out of `leaves`, sorting them the way git expects them to be stored.
+    pub fn new(mut leaves: Vec<Leaf>) -> Self {
+        leaves.sort_by(compare_leaves);
+        Self { leaves }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.leaves
+            .iter()
+            .flat_map(|leaf| {
+                leaf.serialize()
+                    .expect("Tree leaves must contain valid hashes")
+            })
+            .collect()
     }
 
     pub fn deserialize(
@@ -20,12 +32,9 @@ impl Tree {
         _object_header: super::Header,
     ) -> Result<Self, crate::error::ObjectParseError> {
         let mut leaves = vec![];
-        loop {
-            match Leaf::parse(&mut buf_reader) {
-                Ok(leaf) => leaves.push(leaf),
-                // TODO: Fix this
-                Err(_) => break,
-            }
+        // TODO: Fix this
+        while let Ok(leaf) = Leaf::parse(&mut buf_reader) {
+            leaves.push(leaf);
         }
 
         Ok(Self { leaves })
@@ -49,3 +58,15 @@ impl Deref for Tree {
         &self.leaves
     }
 }
+
+/// Compare two tree entries by name the way git does: sub-trees are compared as if their name
+/// ended with a `/`.
+pub fn compare_leaves(a: &Leaf, b: &Leaf) -> Ordering {
+    compare_entry_names(&a.path, a.mode.is_tree(), &b.path, b.mode.is_tree())
+}
+
+pub fn compare_entry_names(a: &str, a_is_tree: bool, b: &str, b_is_tree: bool) -> Ordering {
+    let a = a.bytes().chain(a_is_tree.then_some(b'/'));
+    let b = b.bytes().chain(b_is_tree.then_some(b'/'));
+    a.cmp(b)
+}
@@ -1,8 +1,8 @@
-use std::{fmt::Display, str::FromStr, usize};
+use std::{fmt::Display, str::FromStr};
 
 use crate::error::TreeLeafParseError;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
     Tree = 4,
     RegularFile = 10,
@@ -37,7 +37,7 @@ impl Display for Type {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Mode {
     pub type_: Type,
     file_permissions: String,
@@ -58,6 +58,33 @@ impl Mode {
             type_,
         })
     }
+
+    /// Build a mode from its numeric value, as stored in the index.
+    pub fn from_bits(bits: u32) -> Result<Self, TreeLeafParseError> {
+        Self::new(format!("{:06o}", bits))
+    }
+
+    pub fn bits(&self) -> u32 {
+        u32::from_str_radix(&self.file_permissions, 8).unwrap_or_default()
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.type_ == Type::RegularFile && self.bits() & 0o111 != 0
+    }
+
+    /// The six digit octal mode, e.g. `100644` or `040000`.
+    pub fn permissions(&self) -> &str {
+        &self.file_permissions
+    }
+
+    /// The mode as it's written inside a tree object, i.e. without leading zeros.
+    pub fn serialize(&self) -> &str {
+        self.file_permissions.trim_start_matches('0')
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.type_ == Type::Tree
+    }
 }
 
 impl Display for Mode {
@@ -0,0 +1,149 @@
+//! Ignore rules, read from `.gitignore` files, `.git/info/exclude` and `core.excludesFile`.
+//!
+//! A pattern without a slash matches the name of a file or directory at any depth below the
+//! directory of its `.gitignore`, otherwise it matches the path relative to that directory. A
+//! trailing slash only matches directories and a leading `!` re-includes what a previous pattern
+//! excluded. The last matching pattern decides, patterns of deeper `.gitignore` files coming last.
+
+use crate::wildmatch::{wildmatch, WM_PATHNAME};
+
+/// A pattern of an ignore or attributes file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Pattern {
+    pattern: String,
+    /// Directory of the `.gitignore` file, relative to the top of the work tree, with a trailing
+    /// slash unless empty.
+    base: String,
+    pub(crate) negated: bool,
+    directory_only: bool,
+    /// Match the pattern against the last component of the path only.
+    basename_only: bool,
+}
+
+impl Pattern {
+    pub(crate) fn parse(base: &str, line: &str) -> Option<Self> {
+        // Trailing spaces are ignored unless escaped.
+        let mut line = line.trim_end_matches('\r');
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, mut pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line),
+        };
+        let directory_only = pattern.ends_with('/');
+        pattern = pattern.trim_end_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        let basename_only = !pattern.contains('/');
+
+        Some(Self {
+            pattern: pattern.strip_prefix('/').unwrap_or(pattern).to_string(),
+            base: base.to_string(),
+            negated,
+            directory_only,
+            basename_only,
+        })
+    }
+
+    pub(crate) fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let Some(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        match self.basename_only {
+            true => {
+                let name = relative.rsplit('/').next().unwrap_or(relative);
+                wildmatch(&self.pattern, name, WM_PATHNAME)
+            }
+            false => wildmatch(&self.pattern, relative, WM_PATHNAME),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IgnoreRules {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the patterns of an ignore file found in the directory `base`, relative to the top of
+    /// the work tree. They take precedence over the patterns already added.
+    pub fn add_patterns(&mut self, base: &str, content: &str) {
+        let base = match base {
+            "" => String::new(),
+            base => format!("{}/", base.trim_end_matches('/')),
+        };
+        self.patterns.extend(
+            content
+                .lines()
+                .filter_map(|line| Pattern::parse(&base, line)),
+        );
+    }
+
+    /// The number of patterns, to drop the ones added afterwards with [`IgnoreRules::truncate`].
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.patterns.truncate(len);
+    }
+
+    /// Whether `path`, relative to the top of the work tree, is ignored. This only looks at
+    /// `path` itself, not at its leading directories.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        self.decision(path, is_dir).unwrap_or(false)
+    }
+
+    /// Like [`IgnoreRules::is_ignored`], `None` if no pattern matches `path`.
+    pub fn decision(&self, path: &str, is_dir: bool) -> Option<bool> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IgnoreRules;
+
+    #[test]
+    fn ignore_rules_should_follow_gitignore_semantics() {
+        let mut rules = IgnoreRules::new();
+        rules.add_patterns(
+            "",
+            "# comment\n*.log\n!keep.log\n/target\nbuild/\ndocs/*.html\n",
+        );
+        rules.add_patterns("sub", "local\n");
+
+        assert!(rules.is_ignored("a.log", false));
+        assert!(rules.is_ignored("deep/b.log", false));
+        assert!(!rules.is_ignored("keep.log", false));
+        assert!(rules.is_ignored("target", true));
+        assert!(!rules.is_ignored("sub/target", true));
+        assert!(rules.is_ignored("sub/build", true));
+        assert!(!rules.is_ignored("build", false));
+        assert!(rules.is_ignored("docs/index.html", false));
+        assert!(!rules.is_ignored("docs/api/index.html", false));
+        assert!(rules.is_ignored("sub/x/local", false));
+        assert!(!rules.is_ignored("local", false));
+    }
+}
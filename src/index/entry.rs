@@ -0,0 +1,115 @@
+use std::{fs::Metadata, os::unix::fs::MetadataExt};
+
+use crate::git_object::mode::Mode;
+
+const EMPTY_BLOB_HASH: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+/// File system information cached in the index, used to tell whether a file changed without
+/// hashing it. Values are truncated to 32 bits like git does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatData {
+    pub ctime_seconds: u32,
+    pub ctime_nanoseconds: u32,
+    pub mtime_seconds: u32,
+    pub mtime_nanoseconds: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+impl From<&Metadata> for StatData {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            ctime_seconds: metadata.ctime() as u32,
+            ctime_nanoseconds: metadata.ctime_nsec() as u32,
+            mtime_seconds: metadata.mtime() as u32,
+            mtime_nanoseconds: metadata.mtime_nsec() as u32,
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+        }
+    }
+}
+
+impl StatData {
+    /// Whether `current`, the stat data of the file now, is the same as the one recorded,
+    /// comparing the fields selected by `check`.
+    pub fn matches(&self, current: &StatData, check: StatCheck) -> bool {
+        if self.mtime_seconds != current.mtime_seconds || self.size != current.size {
+            return false;
+        }
+        if check.minimal {
+            return true;
+        }
+        self.mtime_nanoseconds == current.mtime_nanoseconds
+            && (!check.ctime
+                || (self.ctime_seconds == current.ctime_seconds
+                    && self.ctime_nanoseconds == current.ctime_nanoseconds))
+            && self.dev == current.dev
+            && self.ino == current.ino
+            && self.uid == current.uid
+            && self.gid == current.gid
+    }
+}
+
+/// The fields of [`StatData`] compared to tell whether a file changed, set by `core.trustCtime`
+/// and `core.checkStat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatCheck {
+    /// Compare the inode change time, which some tools update without changing the file.
+    pub ctime: bool,
+    /// Only compare the modification time in seconds and the size.
+    pub minimal: bool,
+}
+
+impl Default for StatCheck {
+    fn default() -> Self {
+        Self {
+            ctime: true,
+            minimal: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub stat: StatData,
+    pub mode: Mode,
+    pub hash: String,
+    /// Merge stage, 0 unless the path is conflicted.
+    pub stage: u8,
+    pub assume_valid: bool,
+    pub skip_worktree: bool,
+    pub intent_to_add: bool,
+    pub path: String,
+}
+
+impl IndexEntry {
+    pub fn new(path: String, mode: Mode, hash: String, stat: StatData) -> Self {
+        Self {
+            stat,
+            mode,
+            hash,
+            stage: 0,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            path,
+        }
+    }
+
+    /// Whether the size was zeroed because the entry was racily clean, in which case the stat
+    /// data never matches the file.
+    pub fn is_smudged(&self) -> bool {
+        self.stat.size == 0 && self.hash != EMPTY_BLOB_HASH
+    }
+
+    /// Whether the entry needs the extended flags only available from index version 3.
+    pub fn has_extended_flags(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+}
@@ -0,0 +1,448 @@
+pub mod entry;
+
+pub use entry::{IndexEntry, StatCheck, StatData};
+
+use std::{
+    cmp::Ordering,
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::IndexError, git_object::mode::Mode};
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const CHECKSUM_SIZE: usize = 20;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// The staging area, stored in `.git/index`.
+///
+/// Versions 2 to 4 are read. The index is written back as version 2, or 3 when an entry uses
+/// extended flags. Optional extensions such as the cached tree are dropped when writing.
+///
+/// An entry whose file was modified no earlier than the index file was written is racily clean:
+/// the file may have changed again within the same timestamp after its stat data was recorded,
+/// so its stat data can't be trusted. Such entries are written with a size of 0, which no longer
+/// matches the file, so that they are compared by content until refreshed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    entries: Vec<IndexEntry>,
+    /// Modification time of the index file when it was read, in seconds and nanoseconds.
+    timestamp: Option<(u32, u32)>,
+}
+
+impl Index {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the index file at `path`. A missing file is an empty index.
+    pub fn load(path: &Path) -> Result<Self, IndexError> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Self::new()),
+            Err(error) => return Err(error.into()),
+        };
+        let metadata = std::fs::metadata(path)?;
+        let mut index = Self::parse(&data)?;
+        index.timestamp = Some((metadata.mtime() as u32, metadata.mtime_nsec() as u32));
+        Ok(index)
+    }
+
+    /// Whether the stat data `stat` of an entry is racily clean, which is always the case for
+    /// an index that wasn't read from a file.
+    pub fn is_racy(&self, stat: &StatData) -> bool {
+        self.timestamp
+            .is_none_or(|timestamp| (stat.mtime_seconds, stat.mtime_nanoseconds) >= timestamp)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, IndexError> {
+        if data.len() < 12 + CHECKSUM_SIZE {
+            return Err(IndexError::Corrupt("file is too short".to_string()));
+        }
+        let (content, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if checksum.iter().any(|&byte| byte != 0)
+            && sha1_smol::Sha1::from(content).digest().bytes() != checksum
+        {
+            return Err(IndexError::ChecksumMismatch);
+        }
+
+        let mut reader = Reader { data: content };
+        if reader.take(4)? != SIGNATURE {
+            return Err(IndexError::InvalidSignature);
+        }
+        let version = reader.u32()?;
+        if !(2..=4).contains(&version) {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+
+        let count = reader.u32()?;
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut previous_path: Vec<u8> = vec![];
+        for _ in 0..count {
+            let start = reader.data.len();
+            let mut stat = StatData {
+                ctime_seconds: reader.u32()?,
+                ctime_nanoseconds: reader.u32()?,
+                mtime_seconds: reader.u32()?,
+                mtime_nanoseconds: reader.u32()?,
+                dev: reader.u32()?,
+                ino: reader.u32()?,
+                ..Default::default()
+            };
+            let mode = reader.u32()?;
+            (stat.uid, stat.gid, stat.size) = (reader.u32()?, reader.u32()?, reader.u32()?);
+            let hash = hex::encode(reader.take(20)?);
+            let flags = reader.u16()?;
+            let extended_flags = match flags & FLAG_EXTENDED != 0 {
+                true if version >= 3 => reader.u16()?,
+                true => return Err(IndexError::Corrupt("unexpected extended flags".to_string())),
+                false => 0,
+            };
+
+            let path = if version == 4 {
+                // Paths are prefix-compressed against the previous entry.
+                let strip = reader.varint()?;
+                let keep = previous_path
+                    .len()
+                    .checked_sub(strip)
+                    .ok_or_else(|| IndexError::Corrupt("invalid path prefix".to_string()))?;
+                let mut path = previous_path[..keep].to_vec();
+                path.extend_from_slice(reader.until_nul()?);
+                path
+            } else {
+                let path = reader.until_nul()?.to_vec();
+                // Entries are padded with NULs to a multiple of eight bytes.
+                let length = start - reader.data.len();
+                reader.take((8 - length % 8) % 8)?;
+                path
+            };
+
+            entries.push(IndexEntry {
+                stat,
+                mode: Mode::from_bits(mode)
+                    .map_err(|_| IndexError::Corrupt(format!("invalid mode {:o}", mode)))?,
+                hash,
+                stage: ((flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT) as u8,
+                assume_valid: flags & FLAG_ASSUME_VALID != 0,
+                skip_worktree: extended_flags & EXTENDED_FLAG_SKIP_WORKTREE != 0,
+                intent_to_add: extended_flags & EXTENDED_FLAG_INTENT_TO_ADD != 0,
+                path: String::from_utf8(path.clone())
+                    .map_err(|_| IndexError::Corrupt("path is not valid UTF-8".to_string()))?,
+            });
+            previous_path = path;
+        }
+
+        while !reader.data.is_empty() {
+            let signature = reader.take(4)?;
+            let size = reader.u32()? as usize;
+            reader.take(size)?;
+            // Extensions starting with an uppercase letter are optional and can be ignored.
+            if !signature[0].is_ascii_uppercase() {
+                return Err(IndexError::UnsupportedExtension(
+                    String::from_utf8_lossy(signature).to_string(),
+                ));
+            }
+        }
+
+        Ok(Self {
+            entries,
+            timestamp: None,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.serialize_smudging(|_| false)
+    }
+
+    /// Serialize the index, with a size of 0 for the entries whose stat data `smudge` selects.
+    fn serialize_smudging(&self, smudge: impl Fn(&StatData) -> bool) -> Vec<u8> {
+        let version: u32 = match self.entries.iter().any(IndexEntry::has_extended_flags) {
+            true => 3,
+            false => 2,
+        };
+
+        let mut buffer = SIGNATURE.to_vec();
+        buffer.extend(version.to_be_bytes());
+        buffer.extend((self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            let start = buffer.len();
+            let stat = &entry.stat;
+            for value in [
+                stat.ctime_seconds,
+                stat.ctime_nanoseconds,
+                stat.mtime_seconds,
+                stat.mtime_nanoseconds,
+                stat.dev,
+                stat.ino,
+                entry.mode.bits(),
+                stat.uid,
+                stat.gid,
+                match smudge(stat) {
+                    true => 0,
+                    false => stat.size,
+                },
+            ] {
+                buffer.extend(value.to_be_bytes());
+            }
+            buffer.extend(hex::decode(&entry.hash).unwrap_or_else(|_| vec![0; 20]));
+
+            let mut flags = (entry.path.len().min(FLAG_NAME_MASK as usize) as u16)
+                | ((entry.stage as u16) << FLAG_STAGE_SHIFT) & FLAG_STAGE_MASK;
+            if entry.assume_valid {
+                flags |= FLAG_ASSUME_VALID;
+            }
+            if entry.has_extended_flags() {
+                flags |= FLAG_EXTENDED;
+            }
+            buffer.extend(flags.to_be_bytes());
+            if entry.has_extended_flags() {
+                let mut extended_flags = 0_u16;
+                if entry.skip_worktree {
+                    extended_flags |= EXTENDED_FLAG_SKIP_WORKTREE;
+                }
+                if entry.intent_to_add {
+                    extended_flags |= EXTENDED_FLAG_INTENT_TO_ADD;
+                }
+                buffer.extend(extended_flags.to_be_bytes());
+            }
+
+            buffer.extend(entry.path.as_bytes());
+            let length = buffer.len() - start;
+            buffer.resize(buffer.len() + 8 - length % 8, 0);
+        }
+
+        let checksum = sha1_smol::Sha1::from(&buffer).digest().bytes();
+        buffer.extend(checksum);
+        buffer
+    }
+
+    /// Write the index to `path`, going through `<path>.lock` so readers never see a partially
+    /// written file.
+    pub fn write(&self, path: &Path) -> Result<(), IndexError> {
+        let mut lock_path = PathBuf::from(path).into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let mut lock = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(lock) => lock,
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                return Err(IndexError::Locked(lock_path.display().to_string()))
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        // Entries racily clean for the index read, or for the one being written, are smudged.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as u32;
+        let data = self.serialize_smudging(|stat| {
+            stat.mtime_seconds >= now || (self.timestamp.is_some() && self.is_racy(stat))
+        });
+        let result = lock
+            .write_all(&data)
+            .and_then(|_| lock.sync_all())
+            .and_then(|_| std::fs::rename(&lock_path, path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&lock_path);
+        }
+        Ok(result?)
+    }
+
+    /// Every entry, sorted by path then stage.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut IndexEntry> {
+        self.entries.iter_mut()
+    }
+
+    /// The stage 0 entry of `path`.
+    pub fn entry(&self, path: &str) -> Option<&IndexEntry> {
+        self.position(path, 0)
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    pub fn entry_mut(&mut self, path: &str) -> Option<&mut IndexEntry> {
+        self.position(path, 0)
+            .ok()
+            .map(|index| &mut self.entries[index])
+    }
+
+    /// Whether `path` is tracked, at any stage.
+    pub fn contains(&self, path: &str) -> bool {
+        let index = self.position(path, 0).unwrap_or_else(|index| index);
+        self.entries
+            .get(index)
+            .is_some_and(|entry| entry.path == path)
+    }
+
+    /// Whether some entry is below the directory `path`.
+    pub fn contains_directory(&self, path: &str) -> bool {
+        let prefix = format!("{}/", path);
+        let index = self
+            .entries
+            .partition_point(|entry| entry.path.as_bytes() < prefix.as_bytes());
+        self.entries
+            .get(index)
+            .is_some_and(|entry| entry.path.starts_with(&prefix))
+    }
+
+    /// Add `entry`, replacing any entry of the same path, conflicted or not.
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.remove(&entry.path);
+        let index = self
+            .position(&entry.path, entry.stage)
+            .unwrap_or_else(|index| index);
+        self.entries.insert(index, entry);
+    }
+
+    /// Record a conflict on `path`, replacing its entries with `stages`: the common ancestor at
+    /// stage 1, our version at stage 2 and theirs at stage 3, each of them optional.
+    pub fn add_conflict(&mut self, path: &str, stages: Vec<IndexEntry>) {
+        self.remove(path);
+        for entry in stages {
+            let index = self
+                .position(&entry.path, entry.stage)
+                .unwrap_or_else(|index| index);
+            self.entries.insert(index, entry);
+        }
+    }
+
+    /// Remove every stage of `path`. Returns whether something was removed.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let length = self.entries.len();
+        self.entries.retain(|entry| entry.path != path);
+        length != self.entries.len()
+    }
+
+    pub fn retain(&mut self, keep: impl FnMut(&IndexEntry) -> bool) {
+        self.entries.retain(keep);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, path: &str, stage: u8) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|entry| match entry.path.as_bytes().cmp(path.as_bytes()) {
+                Ordering::Equal => entry.stage.cmp(&stage),
+                ordering => ordering,
+            })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], IndexError> {
+        if self.data.len() < length {
+            return Err(IndexError::Corrupt("unexpected end of file".to_string()));
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, IndexError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, IndexError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Git's offset varint: each continuation adds one before shifting.
+    fn varint(&mut self) -> Result<usize, IndexError> {
+        let mut byte = self.take(1)?[0];
+        let mut value = (byte & 0x7f) as usize;
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+        }
+        Ok(value)
+    }
+
+    fn until_nul(&mut self) -> Result<&'a [u8], IndexError> {
+        let end = self
+            .data
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| IndexError::Corrupt("unterminated path".to_string()))?;
+        let taken = self.take(end + 1)?;
+        Ok(&taken[..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Index, IndexEntry, StatData};
+    use crate::git_object::mode::Mode;
+
+    fn entry(path: &str) -> IndexEntry {
+        IndexEntry::new(
+            path.to_string(),
+            Mode::new("100644".to_string()).unwrap(),
+            "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".to_string(),
+            StatData {
+                mtime_seconds: 1700000000,
+                size: 0,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn add_should_keep_entries_sorted_by_path_bytes() {
+        let mut index = Index::new();
+        index.add(entry("b"));
+        index.add(entry("a/b"));
+        index.add(entry("a.txt"));
+        index.add(entry("a/b"));
+
+        let paths: Vec<_> = index.entries().iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(vec!["a.txt", "a/b", "b"], paths);
+    }
+
+    #[test]
+    fn serialized_index_should_parse_back() {
+        let mut index = Index::new();
+        index.add(entry("file"));
+        let mut sparse = entry("dir/excluded");
+        sparse.skip_worktree = true;
+        index.add(sparse);
+
+        let data = index.serialize();
+        assert_eq!(&data[4..8], &3_u32.to_be_bytes());
+        assert_eq!(index, Index::parse(&data).unwrap());
+    }
+
+    #[test]
+    fn corrupted_index_should_fail_the_checksum() {
+        let mut index = Index::new();
+        index.add(entry("file"));
+        let mut data = index.serialize();
+        data[20] ^= 1;
+
+        assert!(Index::parse(&data).is_err());
+    }
+}
@@ -1,10 +1,25 @@
+pub mod attributes;
 pub mod cli;
+pub mod convert;
+pub mod diff;
 pub mod directory_manager;
 pub mod error;
 pub mod executer;
+pub mod filter;
 pub mod git_config;
 pub mod git_object;
+pub mod ignore;
+pub mod index;
+pub mod merge;
+pub mod parallel;
+pub mod pathspec;
+pub mod ref_format;
+pub mod ref_name;
+pub mod reftable;
 pub mod repository;
+pub mod revision;
+pub mod sparse;
+pub mod wildmatch;
 
 pub use cli::*;
 pub use directory_manager::DirectoryManager;
@@ -3,8 +3,11 @@ use std::path::PathBuf;
 use anyhow::Result;
 use rit::{
     executer::{
-        cmd_cat_file, cmd_checkout, cmd_hash_object, cmd_init, cmd_log, cmd_ls_tree, cmd_show_ref,
-        cmd_tag,
+        cmd_branch, cmd_cat_file, cmd_check_attr, cmd_check_ref_format, cmd_checkout, cmd_clean,
+        cmd_diff, cmd_diff_tree, cmd_for_each_ref, cmd_hash_object, cmd_init, cmd_log, cmd_ls_tree,
+        cmd_pack_refs, cmd_reflog, cmd_reset, cmd_restore, cmd_rev_parse, cmd_show_ref,
+        cmd_sparse_checkout, cmd_stash, cmd_switch, cmd_symbolic_ref, cmd_tag, cmd_update_index,
+        cmd_update_ref, cmd_worktree,
     },
     parse_args, Command,
 };
@@ -12,7 +15,7 @@ use rit::{
 fn main() -> Result<()> {
     let command = parse_args()?;
     match command {
-        Command::Init { path } => cmd_init(path),
+        Command::Init { path, ref_storage } => cmd_init(path, ref_storage),
         Command::CatFile {
             object_type,
             object_hash,
@@ -21,11 +24,106 @@ fn main() -> Result<()> {
             object_type,
             file_path,
             write,
-        } => cmd_hash_object(&file_path, object_type, write),
+            no_filters,
+        } => cmd_hash_object(&file_path, object_type, write, no_filters),
         Command::Log { commit, n_logs } => cmd_log(commit, n_logs),
         Command::LsTree { recursive, tree } => cmd_ls_tree(&tree, recursive, PathBuf::new()),
-        Command::Checkout { commit, path } => cmd_checkout(commit, PathBuf::from(path)),
+        Command::Checkout {
+            commit,
+            path: Some(path),
+            ..
+        } => cmd_checkout(commit, PathBuf::from(path)),
+        Command::Checkout {
+            commit,
+            path: None,
+            detach,
+        } => cmd_switch(commit, detach, true),
+        Command::Switch { branch, detach } => cmd_switch(branch, detach, false),
+        Command::Branch { command } => cmd_branch(command),
         Command::ShowRef => cmd_show_ref(),
+        Command::SymbolicRef {
+            quiet,
+            short,
+            message,
+            name,
+            target,
+        } => cmd_symbolic_ref(quiet, short, message, name, target),
+        Command::ForEachRef {
+            format,
+            sort,
+            count,
+            filter,
+        } => cmd_for_each_ref(format, sort, count, filter),
+        Command::RevParse {
+            verify,
+            quiet,
+            short,
+            abbrev_ref,
+            symbolic_full_name,
+            revisions,
+        } => cmd_rev_parse(
+            verify,
+            quiet,
+            short,
+            abbrev_ref,
+            symbolic_full_name,
+            revisions,
+        ),
+        Command::UpdateRef {
+            no_deref,
+            stdin,
+            delete,
+            message,
+            args,
+        } => cmd_update_ref(no_deref, stdin, delete, message, args),
+        Command::CheckRefFormat {
+            branch,
+            options,
+            name,
+        } => cmd_check_ref_format(branch, options, name),
+        Command::PackRefs { all, prune } => cmd_pack_refs(all, prune),
         Command::Tag { command } => cmd_tag(command),
+        Command::DiffTree {
+            old_tree,
+            new_tree,
+            recursive,
+            format,
+            renames,
+        } => cmd_diff_tree(old_tree, new_tree, recursive, format, renames),
+        Command::Diff {
+            cached,
+            args,
+            paths,
+            format,
+            renames,
+            no_renames,
+            color,
+        } => cmd_diff(cached, args, paths, format, renames, no_renames, color),
+        Command::Restore {
+            source,
+            staged,
+            worktree,
+            paths,
+        } => cmd_restore(source, staged, worktree, paths),
+        Command::Reset {
+            mode,
+            quiet,
+            args,
+            paths,
+        } => cmd_reset(mode, quiet, args, paths),
+        Command::Stash { command } => cmd_stash(command),
+        Command::Reflog { command } => cmd_reflog(command),
+        Command::Clean {
+            dry_run,
+            force,
+            directories,
+            ignored,
+            ignored_only,
+            paths,
+        } => cmd_clean(dry_run, force, directories, ignored, ignored_only, paths),
+        Command::SparseCheckout { command } => cmd_sparse_checkout(command),
+        Command::Worktree { command } => cmd_worktree(command),
+        Command::UpdateIndex { refresh, quiet } => cmd_update_index(refresh, quiet),
+        Command::CheckAttr { all, args, paths } => cmd_check_attr(all, args, paths),
     }
 }
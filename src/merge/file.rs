@@ -0,0 +1,256 @@
+use std::ops::Range;
+
+use crate::diff::patch::{diff_lines, is_binary, split_lines, Edit};
+
+/// A run of base lines replaced by a run of lines of one side.
+#[derive(Debug, Clone)]
+struct Change {
+    base: Range<usize>,
+    side: Range<usize>,
+}
+
+/// Group the edits turning the base into one side into changes.
+fn changes(edits: &[Edit]) -> Vec<Change> {
+    let mut changes: Vec<Change> = vec![];
+    let (mut base, mut side) = (0, 0);
+    let mut in_change = false;
+    for edit in edits {
+        match edit {
+            Edit::Equal { .. } => {
+                base += 1;
+                side += 1;
+                in_change = false;
+                continue;
+            }
+            Edit::Delete { .. } => base += 1,
+            Edit::Insert { .. } => side += 1,
+        }
+        match changes.last_mut() {
+            Some(change) if in_change => {
+                change.base.end = base;
+                change.side.end = side;
+            }
+            _ => {
+                let (base_start, side_start) = match edit {
+                    Edit::Delete { .. } => (base - 1, side),
+                    _ => (base, side - 1),
+                };
+                changes.push(Change {
+                    base: base_start..base,
+                    side: side_start..side,
+                });
+                in_change = true;
+            }
+        }
+    }
+    changes
+}
+
+/// The lines of one side replacing the base lines `base`, given the changes of that side
+/// falling within `base`. Outside of its changes, a side has the lines of the base.
+fn side_range(changes: &[Change], base: &Range<usize>, offset: isize) -> Range<usize> {
+    match (changes.first(), changes.last()) {
+        (Some(first), Some(last)) => {
+            first.side.start - (first.base.start - base.start)
+                ..last.side.end + (base.end - last.base.end)
+        }
+        _ => {
+            let shift = |line: usize| (line as isize + offset) as usize;
+            shift(base.start)..shift(base.end)
+        }
+    }
+}
+
+/// A part of the merged content.
+enum Chunk<'a> {
+    /// Lines both sides agree on.
+    Common(&'a [&'a [u8]]),
+    /// Lines changed by one side only, or the same way by both.
+    Resolved(&'a [&'a [u8]]),
+    Conflict(Vec<&'a [u8]>, Vec<&'a [u8]>),
+}
+
+/// Merge conflicts separated by a few common lines into a single conflict, holding the common
+/// lines on both sides, which is easier to resolve.
+fn join_close_conflicts(chunks: Vec<Chunk>) -> Vec<Chunk> {
+    let mut joined: Vec<Chunk> = vec![];
+    let mut chunks = chunks.into_iter().peekable();
+    while let Some(chunk) = chunks.next() {
+        if let (Chunk::Common(lines), Some(Chunk::Conflict(ours, theirs))) =
+            (&chunk, joined.last_mut())
+        {
+            if lines.len() <= 3 && matches!(chunks.peek(), Some(Chunk::Conflict(..))) {
+                let Some(Chunk::Conflict(next_ours, next_theirs)) = chunks.next() else {
+                    unreachable!()
+                };
+                ours.extend_from_slice(lines);
+                ours.extend(next_ours);
+                theirs.extend_from_slice(lines);
+                theirs.extend(next_theirs);
+                continue;
+            }
+        }
+        joined.push(chunk);
+    }
+    joined
+}
+
+fn push_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend_from_slice(line);
+    }
+}
+
+/// Write a conflict marker, ending the previous line first if it has no terminator.
+fn push_marker(out: &mut Vec<u8>, marker: &str) {
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    out.extend_from_slice(marker.as_bytes());
+    out.push(b'\n');
+}
+
+/// Merge the changes made to `base` by `ours` and by `theirs`, line by line. Returns the merged
+/// content and whether it has conflicts.
+///
+/// Changes of both sides touching the same or adjacent lines conflict, unless they are the same.
+/// Only the lines that differ between both sides are left in conflict, between `<<<<<<<`,
+/// `=======` and `>>>>>>>` markers followed by the labels, and conflicts separated by up to three
+/// lines are joined. Binary files can't be merged and conflict as a whole, keeping our version.
+pub fn merge_content(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    ours_label: &str,
+    theirs_label: &str,
+) -> (Vec<u8>, bool) {
+    if ours == theirs || base == theirs {
+        return (ours.to_vec(), false);
+    }
+    if base == ours {
+        return (theirs.to_vec(), false);
+    }
+    if is_binary(base) || is_binary(ours) || is_binary(theirs) {
+        return (ours.to_vec(), true);
+    }
+
+    let base_lines = split_lines(base);
+    let our_lines = split_lines(ours);
+    let their_lines = split_lines(theirs);
+    let our_changes = changes(&diff_lines(&base_lines, &our_lines));
+    let their_changes = changes(&diff_lines(&base_lines, &their_lines));
+
+    let mut chunks = vec![];
+    let (mut next_ours, mut next_theirs) = (0, 0);
+    // Offsets between the lines of each side and the base, outside of changes.
+    let (mut our_offset, mut their_offset) = (0isize, 0isize);
+    let mut base_position = 0;
+    while next_ours < our_changes.len() || next_theirs < their_changes.len() {
+        // Start a group with the first change, then absorb every change touching it.
+        let first = match (our_changes.get(next_ours), their_changes.get(next_theirs)) {
+            (Some(ours), Some(theirs)) if theirs.base.start < ours.base.start => theirs,
+            (Some(ours), _) => ours,
+            (None, Some(theirs)) => theirs,
+            (None, None) => unreachable!(),
+        };
+        let mut group = first.base.clone();
+        let (start_ours, start_theirs) = (next_ours, next_theirs);
+        loop {
+            let touches = |change: Option<&Change>| {
+                change.is_some_and(|change| change.base.start <= group.end)
+            };
+            if touches(our_changes.get(next_ours)) {
+                group.end = group.end.max(our_changes[next_ours].base.end);
+                next_ours += 1;
+            } else if touches(their_changes.get(next_theirs)) {
+                group.end = group.end.max(their_changes[next_theirs].base.end);
+                next_theirs += 1;
+            } else {
+                break;
+            }
+        }
+
+        chunks.push(Chunk::Common(&base_lines[base_position..group.start]));
+        base_position = group.end;
+
+        let ours_in_group = &our_changes[start_ours..next_ours];
+        let theirs_in_group = &their_changes[start_theirs..next_theirs];
+        let our_range = side_range(ours_in_group, &group, our_offset);
+        let their_range = side_range(theirs_in_group, &group, their_offset);
+        our_offset = our_range.end as isize - group.end as isize;
+        their_offset = their_range.end as isize - group.end as isize;
+        let our_side = &our_lines[our_range];
+        let their_side = &their_lines[their_range];
+
+        if theirs_in_group.is_empty() || our_side == their_side {
+            chunks.push(Chunk::Resolved(our_side));
+        } else if ours_in_group.is_empty() {
+            chunks.push(Chunk::Resolved(their_side));
+        } else if our_side.is_empty() || their_side.is_empty() {
+            chunks.push(Chunk::Conflict(our_side.to_vec(), their_side.to_vec()));
+        } else {
+            // Only the lines that differ between both sides are left in conflict.
+            let mut position = 0;
+            for change in changes(&diff_lines(our_side, their_side)) {
+                chunks.push(Chunk::Common(&our_side[position..change.base.start]));
+                chunks.push(Chunk::Conflict(
+                    our_side[change.base.clone()].to_vec(),
+                    their_side[change.side].to_vec(),
+                ));
+                position = change.base.end;
+            }
+            chunks.push(Chunk::Common(&our_side[position..]));
+        }
+    }
+    chunks.push(Chunk::Common(&base_lines[base_position..]));
+    chunks.retain(|chunk| !matches!(chunk, Chunk::Common(lines) if lines.is_empty()));
+
+    let mut out = vec![];
+    let mut conflict = false;
+    for chunk in join_close_conflicts(chunks) {
+        match chunk {
+            Chunk::Common(lines) | Chunk::Resolved(lines) => push_lines(&mut out, lines),
+            Chunk::Conflict(ours, theirs) => {
+                conflict = true;
+                push_marker(&mut out, &format!("<<<<<<< {}", ours_label));
+                push_lines(&mut out, &ours);
+                push_marker(&mut out, "=======");
+                push_lines(&mut out, &theirs);
+                push_marker(&mut out, &format!(">>>>>>> {}", theirs_label));
+            }
+        }
+    }
+    (out, conflict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_content;
+
+    #[test]
+    fn merge_content_should_combine_changes_to_different_lines() {
+        let base = b"1\n2\n3\n4\n5\n6\n7\n";
+        let ours = b"one\n2\n3\n4\n5\n6\n7\n";
+        let theirs = b"1\n2\n3\n4\n5\n6\nseven\neight\n";
+
+        let (merged, conflict) = merge_content(base, ours, theirs, "ours", "theirs");
+
+        assert!(!conflict);
+        assert_eq!(merged, b"one\n2\n3\n4\n5\n6\nseven\neight\n");
+    }
+
+    #[test]
+    fn merge_content_should_mark_conflicting_changes() {
+        let base = b"1\n2\n3\n";
+        let ours = b"1\ntwo\n3\n";
+        let theirs = b"1\nTWO\n3";
+
+        let (merged, conflict) = merge_content(base, ours, theirs, "ours", "theirs");
+
+        assert!(conflict);
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "1\n<<<<<<< ours\ntwo\n3\n=======\nTWO\n3\n>>>>>>> theirs\n"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Three-way merges of files and of lists of files, such as trees or the index.
+
+pub mod file;
+
+pub use file::merge_content;
+
+use std::collections::BTreeMap;
+
+use crate::{
+    diff::{self, patch::is_binary, DiffSide},
+    error::MergeError,
+    git_object::mode::Type,
+    repository::GitRepository,
+};
+
+/// A path both sides changed in incompatible ways.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: String,
+    pub base: Option<DiffSide>,
+    pub ours: Option<DiffSide>,
+    pub theirs: Option<DiffSide>,
+    /// The file to leave in the work tree, stored as a blob: the content with conflict markers,
+    /// or the version of the side that didn't delete the path.
+    pub work_tree: DiffSide,
+}
+
+/// The result of merging two lists of files with their common ancestor.
+#[derive(Debug, Default)]
+pub struct TreeMerge {
+    /// The files merged cleanly, sorted by path. Conflicted paths are left out.
+    pub files: Vec<DiffSide>,
+    pub conflicts: Vec<Conflict>,
+    /// Progress and conflict messages, in the order of the paths.
+    pub messages: Vec<String>,
+}
+
+impl TreeMerge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+fn is_same(one: Option<&DiffSide>, other: Option<&DiffSide>) -> bool {
+    match (one, other) {
+        (Some(one), Some(other)) => one.hash == other.hash && one.mode == other.mode,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Merge the changes made to the files of `base` by `ours` and by `theirs`. The three lists
+/// must be sorted by path. `labels` name our side and theirs in conflict markers and messages.
+///
+/// Merged contents and the contents left in the work tree for conflicts are written to the
+/// object database.
+pub fn merge_files(
+    repo: &GitRepository,
+    base: Vec<DiffSide>,
+    ours: Vec<DiffSide>,
+    theirs: Vec<DiffSide>,
+    labels: (&str, &str),
+) -> Result<TreeMerge, MergeError> {
+    let mut paths: BTreeMap<String, [Option<DiffSide>; 3]> = BTreeMap::new();
+    for (stage, files) in [base, ours, theirs].into_iter().enumerate() {
+        for side in files {
+            let path = side.path.clone();
+            paths.entry(path).or_default()[stage] = Some(side);
+        }
+    }
+
+    let mut merge = TreeMerge::default();
+    for (path, [base, ours, theirs]) in paths {
+        if is_same(ours.as_ref(), theirs.as_ref()) || is_same(base.as_ref(), theirs.as_ref()) {
+            merge.files.extend(ours);
+            continue;
+        }
+        if is_same(base.as_ref(), ours.as_ref()) {
+            merge.files.extend(theirs);
+            continue;
+        }
+
+        let (our_side, their_side) = match (&ours, &theirs) {
+            (Some(our_side), Some(their_side)) => (our_side, their_side),
+            // One side deleted the file the other one modified, which is kept in the work tree.
+            (Some(modified), None) | (None, Some(modified)) => {
+                let (deleted_in, modified_in) = match ours {
+                    Some(_) => (labels.1, labels.0),
+                    None => (labels.0, labels.1),
+                };
+                merge.messages.push(format!(
+                    "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  Version {} of {} left in tree.",
+                    path, deleted_in, modified_in, modified_in, path
+                ));
+                let work_tree = modified.clone();
+                merge.conflicts.push(Conflict {
+                    path,
+                    base,
+                    ours,
+                    theirs,
+                    work_tree,
+                });
+                continue;
+            }
+            (None, None) => unreachable!(),
+        };
+
+        let mergeable = |side: &DiffSide| {
+            matches!(side.mode.type_, Type::RegularFile)
+                && side.mode.type_ == our_side.mode.type_
+                && side.mode.type_ == their_side.mode.type_
+        };
+        let kind = match &base {
+            Some(_) => "content",
+            None => "add/add",
+        };
+        if !mergeable(our_side) || base.as_ref().is_some_and(|base| !mergeable(base)) {
+            merge
+                .messages
+                .push(format!("CONFLICT ({}): Merge conflict in {}", kind, path));
+            let work_tree = our_side.clone();
+            merge.conflicts.push(Conflict {
+                path,
+                base,
+                ours,
+                theirs,
+                work_tree,
+            });
+            continue;
+        }
+
+        // The executable bit is merged on its own, keeping ours if both changed it.
+        let mode = match &base {
+            Some(base) if base.mode == our_side.mode => their_side.mode.clone(),
+            _ => our_side.mode.clone(),
+        };
+        let base_content = match &base {
+            Some(base) => diff::read_side(repo, base)?,
+            None => vec![],
+        };
+        let our_content = diff::read_side(repo, our_side)?;
+        let their_content = diff::read_side(repo, their_side)?;
+
+        merge.messages.push(format!("Auto-merging {}", path));
+        let (content, conflict) = merge_content(
+            &base_content,
+            &our_content,
+            &their_content,
+            labels.0,
+            labels.1,
+        );
+        let merged = DiffSide {
+            path: path.clone(),
+            mode,
+            hash: repo.write_blob(content)?,
+        };
+        if !conflict {
+            merge.files.push(merged);
+            continue;
+        }
+
+        if is_binary(&base_content) || is_binary(&our_content) || is_binary(&their_content) {
+            merge.messages.push(format!(
+                "warning: Cannot merge binary files: {} ({} vs. {})",
+                path, labels.0, labels.1
+            ));
+        }
+        merge
+            .messages
+            .push(format!("CONFLICT ({}): Merge conflict in {}", kind, path));
+        merge.conflicts.push(Conflict {
+            path,
+            base,
+            ours,
+            theirs,
+            work_tree: merged,
+        });
+    }
+
+    Ok(merge)
+}
@@ -0,0 +1,81 @@
+//! Work on many files spread over threads, such as writing them to the work tree or hashing
+//! them. Results come back in the order of the input whatever the number of threads, so the
+//! output of a command doesn't depend on it.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// Apply `f` to every item of `items` on up to `workers` threads, returning the results in the
+/// order of `items`.
+///
+/// If `f` fails, the error of the first item it failed for is returned, like a loop stopping at
+/// the first error would. Items after that one may have been processed or not.
+pub fn try_map<T, R, E, F>(workers: usize, items: &[T], f: F) -> Result<Vec<R>, E>
+where
+    T: Sync,
+    R: Send,
+    E: Send,
+    F: Fn(&T) -> Result<R, E> + Sync,
+{
+    let workers = workers.min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    // Items are claimed in order, so once one fails, every item before it has been processed.
+    let next = AtomicUsize::new(0);
+    let first_failure = AtomicUsize::new(usize::MAX);
+    let mut results: Vec<Option<Result<R, E>>> = items.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= items.len() || i > first_failure.load(Ordering::Relaxed) {
+                            return done;
+                        }
+                        let result = f(&items[i]);
+                        if result.is_err() {
+                            first_failure.fetch_min(i, Ordering::Relaxed);
+                        }
+                        done.push((i, result));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            match worker.join() {
+                Ok(done) => {
+                    for (i, result) in done {
+                        results[i] = Some(result);
+                    }
+                }
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+    });
+    results.into_iter().map_while(|result| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::try_map;
+
+    #[test]
+    fn try_map_should_keep_the_order_and_return_the_first_error() {
+        let items: Vec<usize> = (0..1000).collect();
+
+        let doubled = try_map(8, &items, |i| Ok::<_, String>(i * 2)).unwrap();
+        assert_eq!(doubled, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+
+        let failed = try_map(8, &items, |&i| match i % 300 {
+            299 => Err(i),
+            _ => Ok(i),
+        });
+        assert_eq!(failed, Err(299));
+    }
+}
@@ -0,0 +1,203 @@
+//! Pathspecs limit a command to some paths of the repository.
+//!
+//! A pathspec matches a path if it names the path itself or one of its leading directories, or
+//! if it's a glob matching the whole path (`*` also matches `/`). `:(exclude)`, `:!` and `:^`
+//! exclude matches, `:/` and `:(top)` make the pathspec relative to the top of the work tree
+//! instead of the current directory, and `:(literal)` disables globbing.
+
+use crate::{
+    error::PathspecError,
+    wildmatch::{is_glob, wildmatch},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    /// Path relative to the top of the work tree, without trailing slash.
+    path: String,
+    glob: bool,
+    exclude: bool,
+}
+
+impl Item {
+    fn matches(&self, path: &str) -> bool {
+        if self.path.is_empty() {
+            return true;
+        }
+        if let Some(rest) = path.strip_prefix(&self.path) {
+            if rest.is_empty() || rest.starts_with('/') {
+                return true;
+            }
+        }
+        self.glob && wildmatch(&self.path, path, 0)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pathspec {
+    items: Vec<Item>,
+}
+
+impl Pathspec {
+    /// Parse `specs`, relative to `prefix`: the current directory relative to the top of the
+    /// work tree, empty at the top.
+    pub fn new(prefix: &str, specs: &[String]) -> Result<Self, PathspecError> {
+        let items = specs
+            .iter()
+            .map(|spec| Self::parse_item(prefix, spec))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { items })
+    }
+
+    fn parse_item(prefix: &str, spec: &str) -> Result<Item, PathspecError> {
+        let (mut top, mut exclude, mut literal) = (false, false, false);
+        let mut path = spec;
+        if let Some(rest) = spec.strip_prefix(":(") {
+            let (magic, rest) = rest
+                .split_once(')')
+                .ok_or_else(|| PathspecError::InvalidMagic(spec.to_string()))?;
+            for word in magic.split(',').filter(|word| !word.is_empty()) {
+                match word {
+                    "top" => top = true,
+                    "exclude" => exclude = true,
+                    "literal" => literal = true,
+                    "glob" => {}
+                    _ => return Err(PathspecError::InvalidMagic(spec.to_string())),
+                }
+            }
+            path = rest;
+        } else if let Some(rest) = spec.strip_prefix(':') {
+            let magic_length = rest
+                .find(|c| !matches!(c, '/' | '!' | '^'))
+                .unwrap_or(rest.len());
+            for c in rest[..magic_length].chars() {
+                match c {
+                    '/' => top = true,
+                    _ => exclude = true,
+                }
+            }
+            path = rest[magic_length..]
+                .strip_prefix(':')
+                .unwrap_or(&rest[magic_length..]);
+        }
+
+        let base = if top { "" } else { prefix };
+        let path = normalize(base, path)
+            .ok_or_else(|| PathspecError::OutsideRepository(spec.to_string()))?;
+
+        Ok(Item {
+            glob: !literal && is_glob(&path),
+            path,
+            exclude,
+        })
+    }
+
+    /// Whether no path is given, in which case every path matches.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Whether `path` matches a pathspec naming it, or a glob matching it, rather than one
+    /// naming a leading directory.
+    pub fn matches_exactly(&self, path: &str) -> bool {
+        self.matches(path)
+            && self.items.iter().any(|item| {
+                !item.exclude && (item.path == path || item.glob && wildmatch(&item.path, path, 0))
+            })
+    }
+
+    /// Whether paths below the directory `directory` may match, even if `directory` doesn't.
+    pub fn may_match_below(&self, directory: &str) -> bool {
+        let directory = format!("{}/", directory);
+        let mut includes = self.items.iter().filter(|item| !item.exclude).peekable();
+        includes.peek().is_none()
+            || includes.any(|item| {
+                let literal = match item.glob {
+                    true => &item.path[..item.path.find(['*', '?', '[']).unwrap_or(0)],
+                    false => &item.path,
+                };
+                literal.starts_with(&directory) || directory.starts_with(literal)
+            })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let mut includes = self.items.iter().filter(|item| !item.exclude).peekable();
+        let included = includes.peek().is_none() || includes.any(|item| item.matches(path));
+
+        included
+            && !self
+                .items
+                .iter()
+                .filter(|item| item.exclude)
+                .any(|item| item.matches(path))
+    }
+}
+
+/// Join `path` to the directory `base` and resolve `.` and `..` components. Returns `None` if the
+/// result is outside of the work tree.
+pub fn normalize(base: &str, path: &str) -> Option<String> {
+    let mut components: Vec<&str> = vec![];
+    for component in base.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            _ => components.push(component),
+        }
+    }
+    Some(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pathspec;
+
+    fn pathspec(prefix: &str, specs: &[&str]) -> Pathspec {
+        let specs: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+        Pathspec::new(prefix, &specs).unwrap()
+    }
+
+    #[test]
+    fn pathspec_should_match_files_and_leading_directories() {
+        let spec = pathspec("", &["src", "README.md"]);
+
+        assert!(spec.matches("src/lib.rs"));
+        assert!(spec.matches("README.md"));
+        assert!(!spec.matches("srcs/lib.rs"));
+        assert!(!spec.matches("docs/README.md"));
+    }
+
+    #[test]
+    fn pathspec_should_be_relative_to_the_prefix() {
+        let spec = pathspec("src/diff", &["../lib.rs", "*.rs", ":/Cargo.toml"]);
+
+        assert!(spec.matches("src/lib.rs"));
+        assert!(spec.matches("src/diff/patch.rs"));
+        assert!(spec.matches("src/diff/nested/deep.rs"));
+        assert!(spec.matches("Cargo.toml"));
+        assert!(!spec.matches("src/main.rs"));
+        assert!(Pathspec::new("", &["../outside".to_string()]).is_err());
+    }
+
+    #[test]
+    fn excluded_pathspecs_should_remove_matches() {
+        let spec = pathspec("", &[":!*.lock", ":(exclude)target"]);
+
+        assert!(spec.matches("src/lib.rs"));
+        assert!(!spec.matches("Cargo.lock"));
+        assert!(!spec.matches("target/debug/rit"));
+    }
+
+    #[test]
+    fn pathspec_should_tell_exact_matches_and_matches_below_directories() {
+        let spec = pathspec("", &["src", "docs/api/index.md", "tests/*.rs"]);
+
+        assert!(spec.matches_exactly("src"));
+        assert!(!spec.matches_exactly("src/lib.rs"));
+        assert!(spec.matches_exactly("tests/clean.rs"));
+        assert!(spec.may_match_below("docs"));
+        assert!(spec.may_match_below("docs/api"));
+        assert!(spec.may_match_below("tests"));
+        assert!(!spec.may_match_below("target"));
+    }
+}
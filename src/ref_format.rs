@@ -0,0 +1,484 @@
+//! The format language of `for-each-ref`, which the ref listings of `branch` and `tag` share.
+//!
+//! A format is text with `%(<atom>)` placeholders, such as `%(refname:short)` or
+//! `%(committerdate:iso)`, `%%` for a `%` and `%<xx>` for the character of hexadecimal code
+//! `xx`. A `*` before an atom, such as `%(*objectname)`, reads it from the object an annotated
+//! tag points to. Refs are sorted by atoms too, `-` in front of a sort key reversing the order
+//! and `version:` comparing the numbers in the values as numbers.
+
+use std::{cmp::Ordering, fmt::Display};
+
+use crate::{
+    error::RefFormatError,
+    git_object::DateFormat,
+    repository::refs,
+    wildmatch::{is_glob, wildmatch, WM_PATHNAME},
+};
+
+/// How a ref name is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameStyle {
+    Full,
+    /// Without `refs/heads/` or a similar prefix.
+    Short,
+    /// Without the first `n` components, or with only the last `-n` if negative.
+    LStrip(isize),
+    /// Without the last `n` components, or with only the first `-n` if negative.
+    RStrip(isize),
+}
+
+impl NameStyle {
+    fn parse(modifier: Option<&str>) -> Option<Self> {
+        let Some(modifier) = modifier else {
+            return Some(NameStyle::Full);
+        };
+        if modifier == "short" {
+            return Some(NameStyle::Short);
+        }
+        if let Some(n) = modifier
+            .strip_prefix("lstrip=")
+            .or_else(|| modifier.strip_prefix("strip="))
+        {
+            return n.parse().ok().map(NameStyle::LStrip);
+        }
+        modifier
+            .strip_prefix("rstrip=")
+            .and_then(|n| n.parse().ok())
+            .map(NameStyle::RStrip)
+    }
+
+    pub fn apply(self, name: &str) -> String {
+        let components: Vec<&str> = name.split('/').collect();
+        let count = components.len() as isize;
+        let (start, end) = match self {
+            NameStyle::Full => return name.to_string(),
+            NameStyle::Short => return refs::shorten_ref_name(name).to_string(),
+            NameStyle::LStrip(n) if n >= 0 => (n.min(count), count),
+            NameStyle::LStrip(n) => ((count + n).max(0), count),
+            NameStyle::RStrip(n) if n >= 0 => (0, (count - n).max(0)),
+            NameStyle::RStrip(n) => (0, (-n).min(count)),
+        };
+        components[start as usize..end as usize].join("/")
+    }
+}
+
+/// Whose identity a person atom reads: `creator` is the tagger of a tag and the committer of
+/// other objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Person {
+    Author,
+    Committer,
+    Tagger,
+    Creator,
+}
+
+/// What a person atom shows of the identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersonPart {
+    /// The whole line, such as `A U Thor <author@example.com> 1700000000 +0100`.
+    Full,
+    Name,
+    /// The email, between angle brackets unless trimmed.
+    Email {
+        trim: bool,
+    },
+    Date(DateFormat),
+}
+
+/// What `%(upstream)` shows of the upstream of a branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamStyle {
+    Name(NameStyle),
+    /// `[ahead <n>, behind <m>]`, or `[gone]` if the upstream doesn't exist.
+    Track,
+    /// `>`, `<`, `<>` or `=`.
+    TrackShort,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Atom {
+    RefName(NameStyle),
+    /// The hash of the object, abbreviated to at least that many digits if given.
+    ObjectName(Option<usize>),
+    ObjectType,
+    ObjectSize,
+    /// The tree of a commit.
+    Tree,
+    /// The parents of a commit, separated by spaces.
+    Parent,
+    NumParent,
+    /// The object a tag points to.
+    Object,
+    /// The type of the object a tag points to.
+    Type,
+    /// The name of a tag.
+    Tag,
+    Subject,
+    Body,
+    Contents,
+    Person(Person, PersonPart),
+    Upstream(UpstreamStyle),
+    /// `*` for the branch `HEAD` points to, a space otherwise.
+    Head,
+    /// The atom of the object an annotated tag points to, once every tag is followed.
+    Deref(Box<Atom>),
+}
+
+impl Atom {
+    /// Parse the text between `%(` and `)`, such as `refname:short`.
+    pub fn parse(text: &str) -> Result<Self, RefFormatError> {
+        if let Some(inner) = text.strip_prefix('*') {
+            return Ok(Atom::Deref(Box::new(Self::parse(inner)?)));
+        }
+        let (name, modifier) = match text.split_once(':') {
+            Some((name, modifier)) => (name, Some(modifier)),
+            None => (text, None),
+        };
+        let invalid = || {
+            RefFormatError::InvalidModifier(text.to_string(), modifier.unwrap_or("").to_string())
+        };
+        let plain = |atom: Atom| match modifier {
+            None => Ok(atom),
+            Some(_) => Err(invalid()),
+        };
+
+        match name {
+            "refname" => NameStyle::parse(modifier)
+                .map(Atom::RefName)
+                .ok_or_else(invalid),
+            "objectname" => match modifier {
+                None => Ok(Atom::ObjectName(None)),
+                Some("short") => Ok(Atom::ObjectName(Some(7))),
+                Some(modifier) => modifier
+                    .strip_prefix("short=")
+                    .and_then(|length| length.parse().ok())
+                    .map(|length| Atom::ObjectName(Some(length)))
+                    .ok_or_else(invalid),
+            },
+            "objecttype" => plain(Atom::ObjectType),
+            "objectsize" => plain(Atom::ObjectSize),
+            "tree" => plain(Atom::Tree),
+            "parent" => plain(Atom::Parent),
+            "numparent" => plain(Atom::NumParent),
+            "object" => plain(Atom::Object),
+            "type" => plain(Atom::Type),
+            "tag" => plain(Atom::Tag),
+            "subject" => plain(Atom::Subject),
+            "body" => plain(Atom::Body),
+            "contents" => match modifier {
+                None => Ok(Atom::Contents),
+                Some("subject") => Ok(Atom::Subject),
+                Some("body") => Ok(Atom::Body),
+                Some(_) => Err(invalid()),
+            },
+            "HEAD" => plain(Atom::Head),
+            "upstream" => match modifier {
+                Some("track") => Ok(Atom::Upstream(UpstreamStyle::Track)),
+                Some("trackshort") => Ok(Atom::Upstream(UpstreamStyle::TrackShort)),
+                _ => NameStyle::parse(modifier)
+                    .map(|style| Atom::Upstream(UpstreamStyle::Name(style)))
+                    .ok_or_else(invalid),
+            },
+            _ => {
+                let (person, part) = [
+                    ("author", Person::Author),
+                    ("committer", Person::Committer),
+                    ("tagger", Person::Tagger),
+                    ("creator", Person::Creator),
+                ]
+                .into_iter()
+                .find_map(|(prefix, person)| Some((person, name.strip_prefix(prefix)?)))
+                .ok_or_else(|| RefFormatError::UnknownAtom(name.to_string()))?;
+                let part = match (part, modifier) {
+                    ("", None) => PersonPart::Full,
+                    ("name", None) => PersonPart::Name,
+                    ("email", None) => PersonPart::Email { trim: false },
+                    ("email", Some("trim")) => PersonPart::Email { trim: true },
+                    ("date", None) => PersonPart::Date(DateFormat::Default),
+                    ("date", Some(format)) => PersonPart::Date(
+                        format
+                            .parse()
+                            .map_err(|_| RefFormatError::InvalidDateFormat(format.to_string()))?,
+                    ),
+                    ("" | "name" | "email", Some(_)) => return Err(invalid()),
+                    _ => return Err(RefFormatError::UnknownAtom(name.to_string())),
+                };
+                Ok(Atom::Person(person, part))
+            }
+        }
+    }
+}
+
+/// The value of an atom for a ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtomValue {
+    Text(String),
+    /// A number, such as a size or the time stamp of a date, shown as the text.
+    Number(i64, String),
+}
+
+impl AtomValue {
+    /// Compare values as numbers if both are, as text otherwise, the numbers in them compared
+    /// as numbers if `version`.
+    pub fn compare(&self, other: &Self, version: bool) -> Ordering {
+        match (self, other) {
+            (AtomValue::Number(one, _), AtomValue::Number(two, _)) => one.cmp(two),
+            _ if version => compare_versions(&self.to_string(), &other.to_string()),
+            _ => self.to_string().cmp(&other.to_string()),
+        }
+    }
+}
+
+impl Display for AtomValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtomValue::Text(text) | AtomValue::Number(_, text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Compare `one` and `two` as versions: runs of digits are compared as numbers, so that
+/// `v1.9` comes before `v1.10`.
+pub fn compare_versions(one: &str, two: &str) -> Ordering {
+    fn chunks(text: &str) -> Vec<&str> {
+        let mut chunks = vec![];
+        let mut start = 0;
+        for (i, c) in text.char_indices().skip(1) {
+            let previous = text[..i].chars().next_back().unwrap_or_default();
+            if c.is_ascii_digit() != previous.is_ascii_digit() {
+                chunks.push(&text[start..i]);
+                start = i;
+            }
+        }
+        if start < text.len() {
+            chunks.push(&text[start..]);
+        }
+        chunks
+    }
+
+    for (one, two) in chunks(one).into_iter().zip(chunks(two)) {
+        let both_numbers = one.starts_with(|c: char| c.is_ascii_digit())
+            && two.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = match both_numbers {
+            true => one
+                .trim_start_matches('0')
+                .len()
+                .cmp(&two.trim_start_matches('0').len())
+                .then_with(|| one.trim_start_matches('0').cmp(two.trim_start_matches('0'))),
+            false => one.cmp(two),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    one.len().cmp(&two.len())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    Atom(Atom),
+}
+
+/// A parsed format, such as `%(refname:short) %(subject)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefFormat {
+    segments: Vec<Segment>,
+}
+
+impl RefFormat {
+    pub fn parse(format: &str) -> Result<Self, RefFormatError> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut rest = format;
+        while let Some(i) = rest.find('%') {
+            literal.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix('%') {
+                literal.push('%');
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('(') {
+                let end = after
+                    .find(')')
+                    .ok_or_else(|| RefFormatError::MalformedFormat(format!("%({}", after)))?;
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Atom(Atom::parse(&after[..end])?));
+                rest = &after[end + 1..];
+            } else if let Some(byte) = rest
+                .get(..2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                literal.push(char::from(byte));
+                rest = &rest[2..];
+            } else {
+                literal.push('%');
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+impl Default for RefFormat {
+    /// `%(objectname) %(objecttype)\t%(refname)`, the format of `for-each-ref`.
+    fn default() -> Self {
+        Self {
+            segments: vec![
+                Segment::Atom(Atom::ObjectName(None)),
+                Segment::Literal(" ".to_string()),
+                Segment::Atom(Atom::ObjectType),
+                Segment::Literal("\t".to_string()),
+                Segment::Atom(Atom::RefName(NameStyle::Full)),
+            ],
+        }
+    }
+}
+
+/// A key refs are sorted by, such as `-creatordate` or `version:refname`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub atom: Atom,
+    pub reverse: bool,
+    /// Compare the numbers in the values as numbers.
+    pub version: bool,
+}
+
+impl SortKey {
+    pub fn parse(key: &str) -> Result<Self, RefFormatError> {
+        let (reverse, key) = match key.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, key),
+        };
+        let (version, key) = match key
+            .strip_prefix("version:")
+            .or_else(|| key.strip_prefix("v:"))
+        {
+            Some(key) => (true, key),
+            None => (false, key),
+        };
+        Ok(Self {
+            atom: Atom::parse(key)?,
+            reverse,
+            version,
+        })
+    }
+}
+
+/// Which refs a listing shows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefFilter {
+    /// Only the refs matching one of these, if any: a glob matched against the full name of the
+    /// ref, `*` stopping at `/`, or a prefix of the name ending at a `/`.
+    pub patterns: Vec<String>,
+    /// Only the refs pointing to this object, directly or through tags.
+    pub points_at: Option<String>,
+    /// Only the refs whose commit is reachable from this one.
+    pub merged: Option<String>,
+    /// Only the refs whose commit reaches this one.
+    pub contains: Option<String>,
+}
+
+impl RefFilter {
+    /// Whether the ref `name` matches the patterns of the filter.
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.patterns.is_empty()
+            || self.patterns.iter().any(|pattern| match is_glob(pattern) {
+                true => wildmatch(pattern, name, WM_PATHNAME),
+                false => name
+                    .strip_prefix(pattern.trim_end_matches('/'))
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/')),
+            })
+    }
+}
+
+/// The first `count` of `refs`, as `--count` limits a listing, or all of them if `count` isn't
+/// given or is 0.
+pub fn take_count<T>(refs: &[T], count: Option<usize>) -> &[T] {
+    match count {
+        Some(count) if count > 0 => &refs[..count.min(refs.len())],
+        _ => refs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{
+        compare_versions, take_count, Atom, NameStyle, Person, PersonPart, RefFilter, RefFormat,
+        Segment, SortKey,
+    };
+    use crate::git_object::DateFormat;
+
+    #[test]
+    fn parse_should_split_literals_atoms_and_escapes() {
+        let format = RefFormat::parse("%(refname:short) %%%41 %(*committerdate:iso)").unwrap();
+
+        assert_eq!(
+            format.segments(),
+            [
+                Segment::Atom(Atom::RefName(NameStyle::Short)),
+                Segment::Literal(" %A ".to_string()),
+                Segment::Atom(Atom::Deref(Box::new(Atom::Person(
+                    Person::Committer,
+                    PersonPart::Date(DateFormat::Iso)
+                )))),
+            ]
+        );
+        assert!(RefFormat::parse("%(refname").is_err());
+        assert!(RefFormat::parse("%(nothing)").is_err());
+        assert!(RefFormat::parse("%(refname:bad)").is_err());
+        assert!(RefFormat::parse("%(authordate:bad)").is_err());
+        assert_eq!(
+            SortKey::parse("-version:refname:lstrip=2").unwrap(),
+            SortKey {
+                atom: Atom::RefName(NameStyle::LStrip(2)),
+                reverse: true,
+                version: true,
+            }
+        );
+    }
+
+    #[test]
+    fn name_styles_and_versions_should_follow_git() {
+        let name = "refs/remotes/origin/main";
+
+        assert_eq!(NameStyle::Short.apply(name), "origin/main");
+        assert_eq!(NameStyle::LStrip(2).apply(name), "origin/main");
+        assert_eq!(NameStyle::LStrip(-1).apply(name), "main");
+        assert_eq!(NameStyle::RStrip(1).apply(name), "refs/remotes/origin");
+        assert_eq!(NameStyle::RStrip(-2).apply(name), "refs/remotes");
+        assert_eq!(compare_versions("v1.9", "v1.10"), Ordering::Less);
+        assert_eq!(compare_versions("v2.0", "v1.10"), Ordering::Greater);
+        assert_eq!(compare_versions("v1.0", "v1.0-rc1"), Ordering::Less);
+
+        let filter = RefFilter {
+            patterns: vec!["refs/tags/v1".to_string(), "refs/heads/*".to_string()],
+            ..RefFilter::default()
+        };
+        assert!(filter.matches_name("refs/tags/v1"));
+        assert!(filter.matches_name("refs/tags/v1/rc"));
+        assert!(!filter.matches_name("refs/tags/v1.0"));
+        assert!(filter.matches_name("refs/heads/main"));
+        assert!(!filter.matches_name("refs/heads/feature/x"));
+    }
+
+    #[test]
+    fn take_count_should_list_every_ref_for_a_count_of_zero() {
+        let refs = ["refs/heads/a", "refs/heads/b"];
+
+        assert_eq!(take_count(&refs, Some(1)), ["refs/heads/a"]);
+        assert_eq!(take_count(&refs, Some(5)), refs);
+        assert_eq!(take_count(&refs, Some(0)), refs);
+        assert_eq!(take_count(&refs, None), refs);
+    }
+}
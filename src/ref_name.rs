@@ -0,0 +1,164 @@
+//! Git's rules for ref names, checked before a ref is written so that no name can point outside
+//! of the refs or be mistaken for a revision.
+//!
+//! A ref name is made of components separated by `/`. A component can't be empty, start with
+//! `.` or end with `.lock`. The name can't contain `..`, `@{`, a control character, a space or
+//! any of `~^:?*[\`, can't end with `/` or `.`, and can't be `@`. Unless it's allowed to be a
+//! single component, such as `HEAD`, it needs at least two.
+
+/// How lenient [`check_ref_format`] is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RefNameOptions {
+    /// Accept names of a single component, such as `HEAD` or `master`.
+    pub allow_onelevel: bool,
+    /// Accept one `*` in the name, as in the refspec `refs/heads/*`.
+    pub refspec_pattern: bool,
+    /// Remove a leading `/` and collapse consecutive ones before checking the name.
+    pub normalize: bool,
+}
+
+/// Whether `component`, a part of a ref name between slashes, is valid. `stars` counts the
+/// `*` seen so far in the name.
+fn is_valid_component(component: &str, refspec_pattern: bool, stars: &mut usize) -> bool {
+    if component.is_empty() || component.starts_with('.') || component.ends_with(".lock") {
+        return false;
+    }
+    if component.contains("..") || component.contains("@{") {
+        return false;
+    }
+    for c in component.chars() {
+        match c {
+            '*' if refspec_pattern && *stars == 0 => *stars += 1,
+            ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\' => return false,
+            c if c.is_ascii_control() => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+/// Collapse consecutive slashes in `name` and remove its leading ones.
+pub fn normalize_ref_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c == '/' && (normalized.is_empty() || normalized.ends_with('/')) {
+            continue;
+        }
+        normalized.push(c);
+    }
+    normalized
+}
+
+/// The name `name` once normalized if `options` asks for it, or `None` if it isn't a valid ref
+/// name.
+pub fn check_ref_format(name: &str, options: RefNameOptions) -> Option<String> {
+    let name = match options.normalize {
+        true => normalize_ref_name(name),
+        false => name.to_string(),
+    };
+    if name.is_empty() || name == "@" || name.ends_with('.') {
+        return None;
+    }
+    let mut stars = 0;
+    let mut components = 0;
+    for component in name.split('/') {
+        if !is_valid_component(component, options.refspec_pattern, &mut stars) {
+            return None;
+        }
+        components += 1;
+    }
+    (options.allow_onelevel || components > 1).then_some(name)
+}
+
+/// Whether `name` can name a branch, `refs/heads/<name>` being a valid ref name. A name can't
+/// start with `-`, which would read as an option, or be `HEAD`.
+pub fn is_valid_branch_name(name: &str) -> bool {
+    !name.starts_with('-')
+        && name != "HEAD"
+        && check_ref_format(&format!("refs/heads/{}", name), RefNameOptions::default()).is_some()
+}
+
+/// Whether `name` can name a tag, `refs/tags/<name>` being a valid ref name that doesn't read
+/// as an option.
+pub fn is_valid_tag_name(name: &str) -> bool {
+    !name.starts_with('-')
+        && check_ref_format(&format!("refs/tags/{}", name), RefNameOptions::default()).is_some()
+}
+
+/// Whether the ref `name` can be read or deleted without touching a file outside of the refs:
+/// a name below `refs/` without `.` or `..` components, or a single component of capitals and
+/// underscores, such as `ORIG_HEAD`.
+pub fn is_safe_ref_name(name: &str) -> bool {
+    match name.strip_prefix("refs/") {
+        Some(rest) => rest
+            .split('/')
+            .all(|component| !matches!(component, "" | "." | "..")),
+        None => !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c == '_'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_ref_format, is_valid_branch_name, RefNameOptions};
+
+    #[test]
+    fn check_ref_format_should_follow_git_rules() {
+        let strict = RefNameOptions::default();
+        for valid in [
+            "refs/heads/main",
+            "refs/tags/v1.0",
+            "a/b@c",
+            "refs/heads/a.b/c",
+        ] {
+            assert_eq!(check_ref_format(valid, strict).as_deref(), Some(valid));
+        }
+        for invalid in [
+            "main",
+            "refs/tags/../../config",
+            "refs/heads/foo..bar",
+            "refs/heads/.hidden",
+            "refs/heads/main.lock",
+            "refs/heads/main.lock/a",
+            "refs/heads/a@{1}",
+            "refs/heads/a b",
+            "refs/heads/a\tb",
+            "refs/heads/a:b",
+            "refs/heads/a*",
+            "refs/heads/",
+            "refs//heads/a",
+            "/refs/heads/a",
+            "refs/heads/a.",
+            "@",
+        ] {
+            assert_eq!(check_ref_format(invalid, strict), None, "{}", invalid);
+        }
+
+        let onelevel = RefNameOptions {
+            allow_onelevel: true,
+            ..strict
+        };
+        assert_eq!(check_ref_format("HEAD", onelevel).as_deref(), Some("HEAD"));
+        let pattern = RefNameOptions {
+            refspec_pattern: true,
+            ..strict
+        };
+        assert!(check_ref_format("refs/heads/*", pattern).is_some());
+        assert!(check_ref_format("refs/*/a*", pattern).is_none());
+        let normalize = RefNameOptions {
+            normalize: true,
+            ..strict
+        };
+        assert_eq!(
+            check_ref_format("//refs///heads/a", normalize).as_deref(),
+            Some("refs/heads/a")
+        );
+    }
+
+    #[test]
+    fn is_valid_branch_name_should_reject_options_and_head() {
+        assert!(is_valid_branch_name("feature/x"));
+        assert!(!is_valid_branch_name("-x"));
+        assert!(!is_valid_branch_name("HEAD"));
+        assert!(!is_valid_branch_name("../x"));
+    }
+}
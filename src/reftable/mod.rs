@@ -0,0 +1,137 @@
+//! The reftable format, storing refs and their logs in a stack of sorted, immutable tables
+//! rather than in a file per ref.
+//!
+//! A table starts with a 24-byte header: `REFT`, the version, the block size, and the range of
+//! update indexes of its records. It ends with a footer repeating the header, followed by the
+//! position of each section and a CRC-32. In between come the ref blocks, their index, and the
+//! log blocks with theirs. A block holds records sorted by key, each one only writing the part
+//! of its key it doesn't share with the one before, except at restart points, every 16 records,
+//! whose offsets are listed at the end of the block for binary search. Log blocks are
+//! compressed with zlib, and a log is keyed by its ref and its update index, latest first.
+//!
+//! The tables of a repository are listed oldest first in `reftable/tables.list`. Every change
+//! of the refs adds a table, whose records hide those of the same key in older tables, and
+//! tables are merged as they pile up (see [`stack`]).
+
+pub mod reader;
+pub mod stack;
+pub mod writer;
+
+use crate::repository::reflog::ReflogEntry;
+
+const MAGIC: &[u8; 4] = b"REFT";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 24;
+const FOOTER_SIZE: usize = 68;
+const HASH_SIZE: usize = 20;
+
+/// How many records a block holds between two restart points.
+const RESTART_INTERVAL: usize = 16;
+
+/// Above this many blocks, a section gets an index.
+const INDEX_THRESHOLD: usize = 3;
+
+pub const DEFAULT_BLOCK_SIZE: u32 = 4096;
+
+const BLOCK_TYPE_REF: u8 = b'r';
+const BLOCK_TYPE_LOG: u8 = b'g';
+const BLOCK_TYPE_INDEX: u8 = b'i';
+
+/// What a ref record says about its ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefRecordValue {
+    /// The ref is deleted, hiding its records in older tables.
+    Deletion,
+    Hash(String),
+    /// An annotated tag and the object it peels to.
+    Peeled(String, String),
+    Symbolic(String),
+}
+
+impl RefRecordValue {
+    fn value_type(&self) -> u8 {
+        match self {
+            RefRecordValue::Deletion => 0,
+            RefRecordValue::Hash(_) => 1,
+            RefRecordValue::Peeled(..) => 2,
+            RefRecordValue::Symbolic(_) => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefRecord {
+    pub name: String,
+    pub update_index: u64,
+    pub value: RefRecordValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub name: String,
+    pub update_index: u64,
+    /// The reflog entry, or `None` for a deletion hiding the entry of the same ref and update
+    /// index in older tables.
+    pub entry: Option<ReflogEntry>,
+}
+
+impl LogRecord {
+    fn key(&self) -> Vec<u8> {
+        log_key(&self.name, self.update_index)
+    }
+}
+
+/// The key of the log of `name` at `update_index`: the name, a NUL and the update index
+/// subtracted from the largest one, so that the latest log of a ref comes first.
+fn log_key(name: &str, update_index: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(name.len() + 9);
+    key.extend(name.as_bytes());
+    key.push(0);
+    key.extend((u64::MAX - update_index).to_be_bytes());
+    key
+}
+
+/// The name and update index a log key is made of.
+fn parse_log_key(key: &[u8]) -> Option<(String, u64)> {
+    let (name, update_index) = key.split_at(key.len().checked_sub(9)?);
+    let (&0, update_index) = update_index.split_first()? else {
+        return None;
+    };
+    let reversed = u64::from_be_bytes(update_index.try_into().ok()?);
+    Some((String::from_utf8(name.to_vec()).ok()?, u64::MAX - reversed))
+}
+
+/// Append `value` to `buffer` as git's offset varint, each continuation byte standing for one
+/// more than its bits.
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    buffer.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{log_key, parse_log_key, put_varint};
+
+    #[test]
+    fn varints_and_log_keys_should_round_trip() {
+        let mut buffer = vec![];
+        put_varint(&mut buffer, 127);
+        put_varint(&mut buffer, 128);
+        put_varint(&mut buffer, 16511);
+        assert_eq!(buffer, [0x7f, 0x80, 0x00, 0xff, 0x7f]);
+
+        let key = log_key("refs/heads/main", 3);
+        assert!(key > log_key("refs/heads/main", 4));
+        assert!(key < log_key("refs/heads/main/x", 1));
+        assert_eq!(
+            parse_log_key(&key),
+            Some(("refs/heads/main".to_string(), 3))
+        );
+    }
+}
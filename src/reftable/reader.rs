@@ -0,0 +1,510 @@
+use std::{
+    fs::File,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+
+use crate::{error::ReftableError, git_object::Signature, repository::reflog::ReflogEntry};
+
+use super::{
+    log_key, parse_log_key, LogRecord, RefRecord, RefRecordValue, BLOCK_TYPE_INDEX, BLOCK_TYPE_LOG,
+    BLOCK_TYPE_REF, FOOTER_SIZE, HASH_SIZE, HEADER_SIZE, MAGIC, VERSION,
+};
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ReftableError> {
+        if self.data.len() < length {
+            return Err(ReftableError::Corrupt(
+                "unexpected end of block".to_string(),
+            ));
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u64(&mut self) -> Result<u64, ReftableError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Git's offset varint: each continuation adds one before shifting.
+    fn varint(&mut self) -> Result<u64, ReftableError> {
+        let mut byte = self.take(1)?[0];
+        let mut value = (byte & 0x7f) as u64;
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = ((value + 1) << 7) | (byte & 0x7f) as u64;
+        }
+        Ok(value)
+    }
+
+    fn hash(&mut self) -> Result<String, ReftableError> {
+        Ok(hex::encode(self.take(HASH_SIZE)?))
+    }
+
+    fn string(&mut self) -> Result<String, ReftableError> {
+        let length = self.varint()? as usize;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| ReftableError::Corrupt("string is not UTF-8".to_string()))
+    }
+}
+
+/// The value of a record, which depends on the block it's in.
+enum Value {
+    Ref(u64, RefRecordValue),
+    Log(Option<ReflogEntry>),
+    /// The position of the block an index record points to.
+    Index(u64),
+}
+
+fn read_ref_value(reader: &mut Reader, value_type: u8) -> Result<Value, ReftableError> {
+    let update_index_delta = reader.varint()?;
+    let value = match value_type {
+        0 => RefRecordValue::Deletion,
+        1 => RefRecordValue::Hash(reader.hash()?),
+        2 => RefRecordValue::Peeled(reader.hash()?, reader.hash()?),
+        3 => RefRecordValue::Symbolic(reader.string()?),
+        _ => {
+            return Err(ReftableError::Corrupt(format!(
+                "unknown ref value type {}",
+                value_type
+            )))
+        }
+    };
+    Ok(Value::Ref(update_index_delta, value))
+}
+
+/// `minutes` east of UTC, such as 90, as a signature offset, such as `+0130`.
+fn format_offset(minutes: i16) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let minutes = minutes.unsigned_abs();
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+fn read_log_value(reader: &mut Reader, value_type: u8) -> Result<Value, ReftableError> {
+    if value_type == 0 {
+        return Ok(Value::Log(None));
+    }
+    let old = reader.hash()?;
+    let new = reader.hash()?;
+    let name = reader.string()?;
+    let email = reader.string()?;
+    let time = reader.varint()? as i64;
+    let offset = i16::from_be_bytes(reader.take(2)?.try_into().unwrap());
+    let message = reader.string()?;
+    Ok(Value::Log(Some(ReflogEntry {
+        old,
+        new,
+        committer: Signature {
+            name,
+            email,
+            time,
+            offset: format_offset(offset),
+        },
+        message: message.trim_end_matches('\n').to_string(),
+    })))
+}
+
+/// Read the record at the start of `reader`, whose key shares a prefix with `key`, the key of
+/// the record before, and replace `key` with its own.
+fn read_record(
+    reader: &mut Reader,
+    key: &mut Vec<u8>,
+    block_type: u8,
+) -> Result<Value, ReftableError> {
+    let prefix = reader.varint()? as usize;
+    let suffix_and_type = reader.varint()?;
+    if prefix > key.len() {
+        return Err(ReftableError::Corrupt("invalid key prefix".to_string()));
+    }
+    key.truncate(prefix);
+    key.extend(reader.take((suffix_and_type >> 3) as usize)?);
+    let value_type = (suffix_and_type & 0x7) as u8;
+    match block_type {
+        BLOCK_TYPE_REF => read_ref_value(reader, value_type),
+        BLOCK_TYPE_LOG => read_log_value(reader, value_type),
+        _ => Ok(Value::Index(reader.varint()?)),
+    }
+}
+
+/// A block read from a table, uncompressed.
+struct Block {
+    /// The block from its start, including the file header for the first block of the table.
+    data: Vec<u8>,
+    block_type: u8,
+    records_start: usize,
+    records_end: usize,
+    restarts: Vec<usize>,
+    /// How much of the file the block takes, padding included.
+    size: u64,
+}
+
+impl Block {
+    /// The key of the record at the restart point `restart`, which doesn't share a prefix with
+    /// the record before.
+    fn restart_key(&self, restart: usize) -> Result<Vec<u8>, ReftableError> {
+        let mut reader = Reader {
+            data: &self.data[self.restarts[restart]..self.records_end],
+        };
+        let mut key = vec![];
+        read_record(&mut reader, &mut key, self.block_type)?;
+        Ok(key)
+    }
+
+    /// Where to start reading records to find the first one whose key is at least `key`: the
+    /// last restart point at or before it.
+    fn seek(&self, key: &[u8]) -> Result<usize, ReftableError> {
+        let (mut low, mut high) = (0, self.restarts.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.restart_key(middle)?.as_slice() <= key {
+                true => low = middle + 1,
+                false => high = middle,
+            }
+        }
+        Ok(match low {
+            0 => self.records_start,
+            low => self.restarts[low - 1],
+        })
+    }
+}
+
+/// A table of a reftable stack, read block by block as records are looked up.
+#[derive(Debug)]
+pub struct Table {
+    pub path: PathBuf,
+    file: File,
+    /// Where the blocks end and the footer starts.
+    blocks_end: u64,
+    /// The size blocks other than log blocks are padded to.
+    block_size: u64,
+    pub min_update_index: u64,
+    pub max_update_index: u64,
+    /// The size of the file.
+    pub size: u64,
+    has_refs: bool,
+    ref_index_position: u64,
+    log_position: Option<u64>,
+    log_index_position: u64,
+}
+
+impl Table {
+    pub fn open(path: &Path) -> Result<Self, ReftableError> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let invalid = || ReftableError::InvalidSignature(path.display().to_string());
+        if size < (HEADER_SIZE + FOOTER_SIZE) as u64 {
+            return Err(invalid());
+        }
+        let mut header = [0; HEADER_SIZE];
+        file.read_exact_at(&mut header, 0)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid());
+        }
+        if header[4] != VERSION {
+            return Err(ReftableError::UnsupportedVersion(header[4]));
+        }
+        let blocks_end = size - FOOTER_SIZE as u64;
+        let mut footer = [0; FOOTER_SIZE];
+        file.read_exact_at(&mut footer, blocks_end)?;
+        if footer[..HEADER_SIZE] != header {
+            return Err(ReftableError::Corrupt(format!(
+                "footer of {} doesn't match its header",
+                path.display()
+            )));
+        }
+        let mut crc = Crc::new();
+        crc.update(&footer[..FOOTER_SIZE - 4]);
+        if crc.sum().to_be_bytes() != footer[FOOTER_SIZE - 4..] {
+            return Err(ReftableError::ChecksumMismatch(path.display().to_string()));
+        }
+
+        let mut reader = Reader { data: &footer[8..] };
+        let min_update_index = reader.u64()?;
+        let max_update_index = reader.u64()?;
+        let ref_index_position = reader.u64()?;
+        // The object blocks and their index, which aren't read.
+        reader.u64()?;
+        reader.u64()?;
+        let log_position = reader.u64()?;
+        let log_index_position = reader.u64()?;
+
+        // The first block follows the header, and tells which section comes first.
+        let mut first_block_type = [0];
+        if blocks_end > HEADER_SIZE as u64 {
+            file.read_exact_at(&mut first_block_type, HEADER_SIZE as u64)?;
+        }
+        let log_position = match (log_position, first_block_type[0]) {
+            (0, BLOCK_TYPE_LOG) => Some(0),
+            (0, _) => None,
+            (position, _) => Some(position),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            blocks_end,
+            block_size: u32::from_be_bytes([0, header[5], header[6], header[7]]) as u64,
+            min_update_index,
+            max_update_index,
+            size,
+            has_refs: first_block_type[0] == BLOCK_TYPE_REF,
+            ref_index_position,
+            log_position,
+            log_index_position,
+        })
+    }
+
+    fn corrupt(&self, reason: &str) -> ReftableError {
+        ReftableError::Corrupt(format!("{}: {}", self.path.display(), reason))
+    }
+
+    /// Read `length` bytes from `position`, or fewer if the blocks end before.
+    fn read_at(&self, position: u64, length: usize) -> Result<Vec<u8>, ReftableError> {
+        let length = length.min(self.blocks_end.saturating_sub(position) as usize);
+        let mut buffer = vec![0; length];
+        self.file.read_exact_at(&mut buffer, position)?;
+        Ok(buffer)
+    }
+
+    /// The type of the block at `position`, `None` past the last block.
+    fn block_type_at(&self, position: u64) -> Result<Option<u8>, ReftableError> {
+        let header_offset = if position == 0 { HEADER_SIZE } else { 0 };
+        let position = position + header_offset as u64;
+        match position < self.blocks_end {
+            true => Ok(Some(self.read_at(position, 1)?[0])),
+            false => Ok(None),
+        }
+    }
+
+    /// The block of `block_type` at `position`, or `None` if there is another type of block
+    /// there, which ends a section, or none at all.
+    fn read_block(&self, position: u64, block_type: u8) -> Result<Option<Block>, ReftableError> {
+        if self.block_type_at(position)? != Some(block_type) {
+            return Ok(None);
+        }
+        let header_offset = if position == 0 { HEADER_SIZE } else { 0 };
+        let block_header = self.read_at(position + header_offset as u64, 4)?;
+        let length = u32::from_be_bytes([0, block_header[1], block_header[2], block_header[3]]);
+        let length = length as usize;
+        if length < header_offset + 6 {
+            return Err(self.corrupt("block too short"));
+        }
+
+        let (data, size) = match block_type {
+            BLOCK_TYPE_LOG => {
+                // The records are compressed, and take less than a little more than their
+                // length.
+                let start = position + (header_offset + 4) as u64;
+                let compressed = self.read_at(start, length + length / 16 + 64)?;
+                let mut data = vec![0; header_offset];
+                data.extend(&block_header);
+                data.reserve(length - data.len());
+                let mut decompress = Decompress::new(true);
+                let status =
+                    decompress.decompress_vec(&compressed, &mut data, FlushDecompress::Finish);
+                if !matches!(status, Ok(Status::StreamEnd)) || data.len() != length {
+                    return Err(self.corrupt("invalid compressed log block"));
+                }
+                (data, (header_offset + 4) as u64 + decompress.total_in())
+            }
+            _ => {
+                let data = self.read_at(position, length)?;
+                if data.len() != length {
+                    return Err(self.corrupt("truncated block"));
+                }
+                // Blocks are padded with zeros up to the block size, unless the next one
+                // starts right away.
+                let next = position + length as u64;
+                let padded = next < self.blocks_end && self.read_at(next, 1)?[0] == 0;
+                let size = match padded {
+                    true => self.block_size.max(length as u64),
+                    false => length as u64,
+                };
+                (data, size)
+            }
+        };
+
+        let count = u16::from_be_bytes([data[length - 2], data[length - 1]]) as usize;
+        let records_end = (length - 2)
+            .checked_sub(3 * count)
+            .filter(|&end| end >= header_offset + 4)
+            .ok_or_else(|| self.corrupt("invalid restart points"))?;
+        let restarts = data[records_end..length - 2]
+            .chunks(3)
+            .map(|restart| u32::from_be_bytes([0, restart[0], restart[1], restart[2]]) as usize)
+            .collect::<Vec<_>>();
+        if restarts
+            .iter()
+            .any(|&restart| restart < header_offset + 4 || restart >= records_end)
+        {
+            return Err(self.corrupt("invalid restart points"));
+        }
+        Ok(Some(Block {
+            data,
+            block_type,
+            records_start: header_offset + 4,
+            records_end,
+            restarts,
+            size,
+        }))
+    }
+
+    /// Visit the records of the blocks of `block_type` from the one at `position`, starting at
+    /// the first whose key is at least `from`, until `visit` returns false or the section
+    /// ends.
+    fn scan(
+        &self,
+        block_type: u8,
+        position: u64,
+        from: &[u8],
+        visit: &mut dyn FnMut(&[u8], Value) -> bool,
+    ) -> Result<(), ReftableError> {
+        let mut position = position;
+        while let Some(block) = self.read_block(position, block_type)? {
+            let start = block.seek(from)?;
+            let mut reader = Reader {
+                data: &block.data[start..block.records_end],
+            };
+            let mut key = vec![];
+            while !reader.data.is_empty() {
+                let value = read_record(&mut reader, &mut key, block_type)?;
+                if key.as_slice() >= from && !visit(&key, value) {
+                    return Ok(());
+                }
+            }
+            position += block.size;
+        }
+        Ok(())
+    }
+
+    /// The position of the block of a section to start reading from to find `key`, going down
+    /// the index of the section if it has one. `None` if every key of the section comes before.
+    fn seek(
+        &self,
+        position: u64,
+        index_position: u64,
+        key: &[u8],
+    ) -> Result<Option<u64>, ReftableError> {
+        if index_position == 0 {
+            return Ok(Some(position));
+        }
+        let mut position = index_position;
+        loop {
+            let mut found = None;
+            self.scan(BLOCK_TYPE_INDEX, position, key, &mut |_, value| {
+                if let Value::Index(block) = value {
+                    found = Some(block);
+                }
+                false
+            })?;
+            match found {
+                Some(block) if self.block_type_at(block)? == Some(BLOCK_TYPE_INDEX) => {
+                    position = block
+                }
+                found => return Ok(found),
+            }
+        }
+    }
+
+    /// Visit the ref records from the first whose name is at least `from`.
+    fn scan_refs(
+        &self,
+        from: &str,
+        visit: &mut dyn FnMut(RefRecord) -> bool,
+    ) -> Result<(), ReftableError> {
+        if !self.has_refs {
+            return Ok(());
+        }
+        let Some(position) = self.seek(0, self.ref_index_position, from.as_bytes())? else {
+            return Ok(());
+        };
+        let mut invalid = false;
+        self.scan(
+            BLOCK_TYPE_REF,
+            position,
+            from.as_bytes(),
+            &mut |key, value| {
+                let (Value::Ref(delta, value), Ok(name)) = (value, std::str::from_utf8(key)) else {
+                    invalid = true;
+                    return false;
+                };
+                visit(RefRecord {
+                    name: name.to_string(),
+                    update_index: self.min_update_index + delta,
+                    value,
+                })
+            },
+        )?;
+        match invalid {
+            true => Err(self.corrupt("invalid ref record")),
+            false => Ok(()),
+        }
+    }
+
+    /// The record of the ref `name`, if the table has one.
+    pub fn ref_record(&self, name: &str) -> Result<Option<RefRecord>, ReftableError> {
+        let mut found = None;
+        self.scan_refs(name, &mut |record| {
+            if record.name == name {
+                found = Some(record);
+            }
+            false
+        })?;
+        Ok(found)
+    }
+
+    /// The records of the refs whose name starts with `prefix`, sorted by name.
+    pub fn ref_records(&self, prefix: &str) -> Result<Vec<RefRecord>, ReftableError> {
+        let mut records = vec![];
+        self.scan_refs(prefix, &mut |record| {
+            let matches = record.name.starts_with(prefix);
+            if matches {
+                records.push(record);
+            }
+            matches
+        })?;
+        Ok(records)
+    }
+
+    /// The log records of the ref `name`, or of every ref, sorted by name and latest first.
+    pub fn log_records(&self, name: Option<&str>) -> Result<Vec<LogRecord>, ReftableError> {
+        let Some(log_position) = self.log_position else {
+            return Ok(vec![]);
+        };
+        let from = match name {
+            Some(name) => log_key(name, u64::MAX),
+            None => vec![],
+        };
+        let Some(position) = self.seek(log_position, self.log_index_position, &from)? else {
+            return Ok(vec![]);
+        };
+        let mut records = vec![];
+        let mut invalid = false;
+        self.scan(BLOCK_TYPE_LOG, position, &from, &mut |key, value| {
+            let (Value::Log(entry), Some((ref_name, update_index))) = (value, parse_log_key(key))
+            else {
+                invalid = true;
+                return false;
+            };
+            if name.is_some_and(|name| name != ref_name) {
+                return false;
+            }
+            records.push(LogRecord {
+                name: ref_name,
+                update_index,
+                entry,
+            });
+            true
+        })?;
+        match invalid {
+            true => Err(self.corrupt("invalid log record")),
+            false => Ok(records),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! The tables of a reftable directory, read together as one, and their updates.
+//!
+//! Each update of the refs writes a new table with its records and adds it to the end of
+//! `tables.list` under the `tables.list.lock` lock. To keep the number of tables small, the
+//! newest ones are then merged as long as the table before them isn't more than twice as large
+//! as they are together, so that the sizes of the tables decrease geometrically from the oldest
+//! to the newest.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::error::ReftableError;
+
+use super::{
+    reader::Table, writer::write_table, LogRecord, RefRecord, RefRecordValue, DEFAULT_BLOCK_SIZE,
+};
+
+/// How many times the tables are read again when a concurrent compaction removes one of them.
+const OPEN_ATTEMPTS: usize = 3;
+
+/// The tables of a reftable directory, oldest first.
+#[derive(Debug)]
+pub struct Stack {
+    directory: PathBuf,
+    tables: Vec<Table>,
+}
+
+fn list_path(directory: &Path) -> PathBuf {
+    directory.join("tables.list")
+}
+
+/// The names of the tables listed in `tables.list`, none if it doesn't exist.
+fn read_table_names(directory: &Path) -> Result<Vec<String>, ReftableError> {
+    match fs::read_to_string(list_path(directory)) {
+        Ok(content) => Ok(content.lines().map(|name| name.to_string()).collect()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(error) => Err(error.into()),
+    }
+}
+
+impl Stack {
+    /// The tables of `directory`, such as `.git/reftable`, which may not exist yet.
+    pub fn open(directory: &Path) -> Result<Self, ReftableError> {
+        let mut attempt = 1;
+        loop {
+            let tables = read_table_names(directory)?
+                .iter()
+                .map(|name| Table::open(&directory.join(name)))
+                .collect::<Result<Vec<_>, _>>();
+            match tables {
+                Ok(tables) => {
+                    return Ok(Self {
+                        directory: directory.to_path_buf(),
+                        tables,
+                    })
+                }
+                Err(ReftableError::IoError(error))
+                    if error.kind() == ErrorKind::NotFound && attempt < OPEN_ATTEMPTS =>
+                {
+                    attempt += 1
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Take the lock of the tables of `directory`, creating it if needed, and read them.
+    pub fn lock(directory: &Path) -> Result<LockedStack, ReftableError> {
+        fs::create_dir_all(directory)?;
+        let lock_path = directory.join("tables.list.lock");
+        let lock = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path);
+        match lock {
+            Ok(_) => {}
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                return Err(ReftableError::Locked(lock_path.display().to_string()))
+            }
+            Err(error) => return Err(error.into()),
+        }
+        let mut locked = LockedStack {
+            stack: Self {
+                directory: directory.to_path_buf(),
+                tables: vec![],
+            },
+            lock_path,
+            committed: false,
+        };
+        locked.stack = Self::open(directory)?;
+        Ok(locked)
+    }
+
+    /// The update index of the next update: one more than the last one.
+    pub fn next_update_index(&self) -> u64 {
+        self.tables
+            .last()
+            .map_or(1, |table| table.max_update_index + 1)
+    }
+
+    /// The value of the ref `name` in the newest table that has it, `None` if it doesn't exist
+    /// or was deleted.
+    pub fn read_ref(&self, name: &str) -> Result<Option<RefRecordValue>, ReftableError> {
+        for table in self.tables.iter().rev() {
+            if let Some(record) = table.ref_record(name)? {
+                return Ok(match record.value {
+                    RefRecordValue::Deletion => None,
+                    value => Some(value),
+                });
+            }
+        }
+        Ok(None)
+    }
+
+    /// The refs whose name starts with `prefix`, sorted by name, newer records hiding older
+    /// ones and deleted refs left out.
+    pub fn ref_records(&self, prefix: &str) -> Result<Vec<RefRecord>, ReftableError> {
+        let mut records = BTreeMap::new();
+        for table in &self.tables {
+            for record in table.ref_records(prefix)? {
+                records.insert(record.name.clone(), record);
+            }
+        }
+        Ok(records
+            .into_values()
+            .filter(|record| record.value != RefRecordValue::Deletion)
+            .collect())
+    }
+
+    /// The log entries of the ref `name`, or of every ref, sorted by name and latest first,
+    /// deleted ones left out.
+    pub fn log_records(&self, name: Option<&str>) -> Result<Vec<LogRecord>, ReftableError> {
+        let mut records = BTreeMap::new();
+        for table in &self.tables {
+            for record in table.log_records(name)? {
+                records.insert(record.key(), record);
+            }
+        }
+        Ok(records
+            .into_values()
+            .filter(|record| record.entry.is_some())
+            .collect())
+    }
+}
+
+/// The tables of a reftable directory, locked for an update.
+#[derive(Debug)]
+pub struct LockedStack {
+    stack: Stack,
+    lock_path: PathBuf,
+    /// Whether the lock was renamed to `tables.list`, and isn't ours to remove anymore.
+    committed: bool,
+}
+
+impl LockedStack {
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    /// Write a table of `refs` and `logs`, whose update indexes range from `min_update_index`
+    /// to `max_update_index`. Returns its name.
+    fn write_table(
+        &self,
+        min_update_index: u64,
+        max_update_index: u64,
+        refs: &[RefRecord],
+        logs: &[LogRecord],
+    ) -> Result<String, ReftableError> {
+        let table = write_table(
+            DEFAULT_BLOCK_SIZE,
+            min_update_index,
+            max_update_index,
+            refs,
+            logs,
+        )?;
+        let suffix = uuid::Uuid::new_v4().as_u128() as u32;
+        let name = format!(
+            "0x{:012x}-0x{:012x}-{:08x}.ref",
+            min_update_index, max_update_index, suffix
+        );
+        let path = self.stack.directory.join(&name);
+        let temporary = self.stack.directory.join(format!("{}.lock", name));
+        let written = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temporary)
+            .and_then(|mut file| file.write_all(&table))
+            .and_then(|_| fs::rename(&temporary, &path));
+        if let Err(error) = written {
+            let _ = fs::remove_file(&temporary);
+            return Err(error.into());
+        }
+        Ok(name)
+    }
+
+    /// Merge the tables from `start` to the newest one into a single table. Deleted refs and
+    /// log entries are only dropped when the oldest table is merged, as they hide records of
+    /// older tables otherwise. Returns the name of the new table.
+    fn merge(&self, start: usize) -> Result<String, ReftableError> {
+        let tables = &self.stack.tables[start..];
+        let mut refs = BTreeMap::new();
+        let mut logs = BTreeMap::new();
+        for table in tables {
+            for record in table.ref_records("")? {
+                refs.insert(record.name.clone(), record);
+            }
+            for record in table.log_records(None)? {
+                logs.insert(record.key(), record);
+            }
+        }
+        let refs: Vec<RefRecord> = refs
+            .into_values()
+            .filter(|record| start > 0 || record.value != RefRecordValue::Deletion)
+            .collect();
+        let logs: Vec<LogRecord> = logs
+            .into_values()
+            .filter(|record| start > 0 || record.entry.is_some())
+            .collect();
+        self.write_table(
+            tables[0].min_update_index,
+            tables[tables.len() - 1].max_update_index,
+            &refs,
+            &logs,
+        )
+    }
+
+    /// Replace `tables.list` with `names`, releasing the lock, and delete the tables that
+    /// aren't listed anymore.
+    fn commit(mut self, names: &[String]) -> Result<(), ReftableError> {
+        let content: String = names.iter().map(|name| format!("{}\n", name)).collect();
+        fs::write(&self.lock_path, content)?;
+        fs::rename(&self.lock_path, list_path(&self.stack.directory))?;
+        self.committed = true;
+        for table in &self.stack.tables {
+            let name = table.path.file_name().map(|name| name.to_string_lossy());
+            if name.is_some_and(|name| !names.iter().any(|listed| *listed == name)) {
+                let _ = fs::remove_file(&table.path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Where to start merging the newest tables, `table_sizes` ordered from the oldest, so that
+    /// none is more than half the size of the one before it.
+    fn compaction_start(table_sizes: &[u64]) -> usize {
+        let Some(&last) = table_sizes.last() else {
+            return 0;
+        };
+        let mut start = table_sizes.len() - 1;
+        let mut total = last;
+        while start > 0 && table_sizes[start - 1] <= 2 * total {
+            start -= 1;
+            total += table_sizes[start];
+        }
+        start
+    }
+
+    /// Add a table of `refs` and `logs` to the stack and release the lock, merging the newest
+    /// tables if they piled up. The table covers the update indexes from
+    /// [`Stack::next_update_index`] to the highest one of the records, while log records may
+    /// have older ones to hide existing entries.
+    pub fn add(mut self, refs: &[RefRecord], logs: &[LogRecord]) -> Result<(), ReftableError> {
+        if refs.is_empty() && logs.is_empty() {
+            return Ok(());
+        }
+        let min_update_index = self.stack.next_update_index();
+        let max_update_index = refs
+            .iter()
+            .map(|record| record.update_index)
+            .chain(logs.iter().map(|record| record.update_index))
+            .fold(min_update_index, u64::max);
+        let name = self.write_table(min_update_index, max_update_index, refs, logs)?;
+        let table = Table::open(&self.stack.directory.join(&name));
+        let table = match table {
+            Ok(table) => table,
+            Err(error) => {
+                let _ = fs::remove_file(self.stack.directory.join(&name));
+                return Err(error);
+            }
+        };
+        self.stack.tables.push(table);
+
+        let sizes: Vec<u64> = self.stack.tables.iter().map(|table| table.size).collect();
+        let start = Self::compaction_start(&sizes);
+        self.commit_merged(start)
+    }
+
+    /// Merge every table into one, which drops deleted refs and log entries, and release the
+    /// lock.
+    pub fn compact_all(self) -> Result<(), ReftableError> {
+        self.commit_merged(0)
+    }
+
+    /// Merge the tables from `start` if there are several, and list the tables.
+    fn commit_merged(self, start: usize) -> Result<(), ReftableError> {
+        let mut names: Vec<String> = self
+            .stack
+            .tables
+            .iter()
+            .filter_map(|table| Some(table.path.file_name()?.to_string_lossy().into_owned()))
+            .collect();
+        if self.stack.tables.len() - start > 1 {
+            let merged = self.merge(start)?;
+            names.truncate(start);
+            names.push(merged);
+        }
+        self.commit(&names)
+    }
+}
+
+impl Drop for LockedStack {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LockedStack;
+
+    #[test]
+    fn compaction_should_keep_table_sizes_geometric() {
+        assert_eq!(LockedStack::compaction_start(&[]), 0);
+        assert_eq!(LockedStack::compaction_start(&[1000]), 0);
+        assert_eq!(LockedStack::compaction_start(&[1000, 100]), 1);
+        assert_eq!(LockedStack::compaction_start(&[1000, 100, 100]), 1);
+        assert_eq!(LockedStack::compaction_start(&[1000, 300, 200]), 0);
+        assert_eq!(LockedStack::compaction_start(&[100, 100, 100, 100]), 0);
+    }
+}
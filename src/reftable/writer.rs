@@ -0,0 +1,306 @@
+use std::io::Write;
+
+use flate2::{write::ZlibEncoder, Compression, Crc};
+
+use crate::error::ReftableError;
+
+use super::{
+    put_varint, LogRecord, RefRecord, RefRecordValue, BLOCK_TYPE_INDEX, BLOCK_TYPE_LOG,
+    BLOCK_TYPE_REF, HASH_SIZE, HEADER_SIZE, INDEX_THRESHOLD, MAGIC, RESTART_INTERVAL, VERSION,
+};
+
+/// A record ready to be written: its key, the type of its value and the value itself.
+struct EncodedRecord {
+    key: Vec<u8>,
+    value_type: u8,
+    value: Vec<u8>,
+}
+
+/// The block being filled with records.
+struct BlockWriter {
+    block_type: u8,
+    /// The block, from its start. The first block of a table starts with the file header,
+    /// which the first `header_offset` bytes leave room for.
+    buffer: Vec<u8>,
+    header_offset: usize,
+    block_size: usize,
+    restarts: Vec<usize>,
+    last_key: Vec<u8>,
+    count: usize,
+}
+
+impl BlockWriter {
+    fn new(block_type: u8, header_offset: usize, block_size: usize) -> Self {
+        let mut buffer = vec![0; header_offset];
+        buffer.extend([block_type, 0, 0, 0]);
+        Self {
+            block_type,
+            buffer,
+            header_offset,
+            block_size,
+            restarts: vec![],
+            last_key: vec![],
+            count: 0,
+        }
+    }
+
+    /// Add `record` unless the block is too full for it. A log record too large for a block
+    /// gets a block of its own.
+    fn add(&mut self, record: &EncodedRecord) -> bool {
+        let restart = self.count.is_multiple_of(RESTART_INTERVAL);
+        let prefix = match restart {
+            true => 0,
+            false => self
+                .last_key
+                .iter()
+                .zip(&record.key)
+                .take_while(|(a, b)| a == b)
+                .count(),
+        };
+        let mut encoded = vec![];
+        put_varint(&mut encoded, prefix as u64);
+        let suffix = &record.key[prefix..];
+        put_varint(
+            &mut encoded,
+            ((suffix.len() as u64) << 3) | record.value_type as u64,
+        );
+        encoded.extend(suffix);
+        encoded.extend(&record.value);
+
+        let restarts = self.restarts.len() + usize::from(restart);
+        let size = self.buffer.len() + encoded.len() + 3 * restarts + 2;
+        let fits =
+            size <= self.block_size || (self.block_type == BLOCK_TYPE_LOG && self.count == 0);
+        if !fits || size >= 1 << 24 {
+            return false;
+        }
+        if restart {
+            self.restarts.push(self.buffer.len());
+        }
+        self.buffer.extend(encoded);
+        self.last_key = record.key.clone();
+        self.count += 1;
+        true
+    }
+
+    /// The block as written to the table: with its restart points, padded to the block size
+    /// unless it's a log block, which is compressed instead.
+    fn finish(mut self) -> Result<Vec<u8>, ReftableError> {
+        for restart in &self.restarts {
+            self.buffer.extend(&(*restart as u32).to_be_bytes()[1..]);
+        }
+        self.buffer
+            .extend((self.restarts.len() as u16).to_be_bytes());
+        let length = (self.buffer.len() as u32).to_be_bytes();
+        let header = self.header_offset;
+        self.buffer[header + 1..header + 4].copy_from_slice(&length[1..]);
+
+        if self.block_type == BLOCK_TYPE_LOG {
+            let mut encoder =
+                ZlibEncoder::new(self.buffer[..header + 4].to_vec(), Compression::default());
+            encoder.write_all(&self.buffer[header + 4..])?;
+            return Ok(encoder.finish()?);
+        }
+        self.buffer.resize(self.block_size, 0);
+        Ok(self.buffer)
+    }
+}
+
+/// A table being written in memory.
+struct TableWriter {
+    buffer: Vec<u8>,
+    block_size: usize,
+}
+
+impl TableWriter {
+    /// A block of `block_type`, making room for the file header if it's the first one.
+    fn new_block(&self, block_type: u8) -> BlockWriter {
+        let header_offset = match self.buffer.is_empty() {
+            true => HEADER_SIZE,
+            false => 0,
+        };
+        BlockWriter::new(block_type, header_offset, self.block_size)
+    }
+
+    fn flush(
+        &mut self,
+        block: BlockWriter,
+        blocks: &mut Vec<(Vec<u8>, u64)>,
+    ) -> Result<(), ReftableError> {
+        blocks.push((block.last_key.clone(), self.buffer.len() as u64));
+        self.buffer.extend(block.finish()?);
+        Ok(())
+    }
+
+    /// Write `records` in blocks of `block_type`. Returns the last key and the position of
+    /// each block.
+    fn write_blocks(
+        &mut self,
+        block_type: u8,
+        records: Vec<EncodedRecord>,
+    ) -> Result<Vec<(Vec<u8>, u64)>, ReftableError> {
+        let too_large = |record: &EncodedRecord, block_size: usize| {
+            let name = String::from_utf8_lossy(&record.key).into_owned();
+            ReftableError::RecordTooLarge(name, block_size as u32)
+        };
+        let mut blocks = vec![];
+        let mut block = self.new_block(block_type);
+        for record in records {
+            if block.add(&record) {
+                continue;
+            }
+            if block.count == 0 {
+                return Err(too_large(&record, self.block_size));
+            }
+            self.flush(block, &mut blocks)?;
+            block = self.new_block(block_type);
+            if !block.add(&record) {
+                return Err(too_large(&record, self.block_size));
+            }
+        }
+        if block.count > 0 {
+            self.flush(block, &mut blocks)?;
+        }
+        Ok(blocks)
+    }
+
+    /// Write a section of `block_type` with `records`, followed by its index if it has many
+    /// blocks. Returns the position of the section, `None` if it's empty, and of its index, 0
+    /// if it has none.
+    fn write_section(
+        &mut self,
+        block_type: u8,
+        records: Vec<EncodedRecord>,
+    ) -> Result<(Option<u64>, u64), ReftableError> {
+        let start = self.buffer.len() as u64;
+        let mut index = self.write_blocks(block_type, records)?;
+        if index.is_empty() {
+            return Ok((None, 0));
+        }
+        // Index blocks are indexed in turn until the top level is small enough to scan.
+        let mut index_position = 0;
+        while index.len() > INDEX_THRESHOLD {
+            index_position = self.buffer.len() as u64;
+            let records = index.into_iter().map(|(key, position)| {
+                let mut value = vec![];
+                put_varint(&mut value, position);
+                EncodedRecord {
+                    key,
+                    value_type: 0,
+                    value,
+                }
+            });
+            index = self.write_blocks(BLOCK_TYPE_INDEX, records.collect())?;
+        }
+        Ok((Some(start), index_position))
+    }
+}
+
+/// The 20 bytes of the object name `hash`.
+fn hash_bytes(hash: &str) -> Result<Vec<u8>, ReftableError> {
+    match hex::decode(hash) {
+        Ok(bytes) if bytes.len() == HASH_SIZE => Ok(bytes),
+        _ => Err(ReftableError::InvalidObjectName(hash.to_string())),
+    }
+}
+
+fn encode_ref(record: &RefRecord, min_update_index: u64) -> Result<EncodedRecord, ReftableError> {
+    let mut value = vec![];
+    put_varint(&mut value, record.update_index - min_update_index);
+    match &record.value {
+        RefRecordValue::Deletion => {}
+        RefRecordValue::Hash(hash) => value.extend(hash_bytes(hash)?),
+        RefRecordValue::Peeled(hash, peeled) => {
+            value.extend(hash_bytes(hash)?);
+            value.extend(hash_bytes(peeled)?);
+        }
+        RefRecordValue::Symbolic(target) => {
+            put_varint(&mut value, target.len() as u64);
+            value.extend(target.as_bytes());
+        }
+    }
+    Ok(EncodedRecord {
+        key: record.name.as_bytes().to_vec(),
+        value_type: record.value.value_type(),
+        value,
+    })
+}
+
+fn put_string(buffer: &mut Vec<u8>, string: &str) {
+    put_varint(buffer, string.len() as u64);
+    buffer.extend(string.as_bytes());
+}
+
+fn encode_log(record: &LogRecord) -> Result<EncodedRecord, ReftableError> {
+    let mut value = vec![];
+    if let Some(entry) = &record.entry {
+        value.extend(hash_bytes(&entry.old)?);
+        value.extend(hash_bytes(&entry.new)?);
+        put_string(&mut value, &entry.committer.name);
+        put_string(&mut value, &entry.committer.email);
+        put_varint(&mut value, entry.committer.time.max(0) as u64);
+        value.extend(((entry.committer.offset_seconds() / 60) as i16).to_be_bytes());
+        // Messages are single lines, stored with their newline.
+        put_string(&mut value, &format!("{}\n", entry.message));
+    }
+    Ok(EncodedRecord {
+        key: record.key(),
+        value_type: u8::from(record.entry.is_some()),
+        value,
+    })
+}
+
+/// A table holding `refs` and `logs`, which can't have two records of the same key, for the
+/// update indexes from `min_update_index` to `max_update_index`.
+pub fn write_table(
+    block_size: u32,
+    min_update_index: u64,
+    max_update_index: u64,
+    refs: &[RefRecord],
+    logs: &[LogRecord],
+) -> Result<Vec<u8>, ReftableError> {
+    let mut refs: Vec<&RefRecord> = refs.iter().collect();
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut logs: Vec<&LogRecord> = logs.iter().collect();
+    logs.sort_by_key(|log| log.key());
+
+    let mut header = MAGIC.to_vec();
+    header.push(VERSION);
+    header.extend(&block_size.to_be_bytes()[1..]);
+    header.extend(min_update_index.to_be_bytes());
+    header.extend(max_update_index.to_be_bytes());
+
+    let mut writer = TableWriter {
+        buffer: vec![],
+        block_size: block_size as usize,
+    };
+    let refs = refs
+        .into_iter()
+        .map(|record| encode_ref(record, min_update_index))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (_, ref_index_position) = writer.write_section(BLOCK_TYPE_REF, refs)?;
+    let logs = logs
+        .into_iter()
+        .map(encode_log)
+        .collect::<Result<Vec<_>, _>>()?;
+    let (log_position, log_index_position) = writer.write_section(BLOCK_TYPE_LOG, logs)?;
+
+    let mut table = writer.buffer;
+    match table.is_empty() {
+        true => table.extend(&header),
+        false => table[..HEADER_SIZE].copy_from_slice(&header),
+    }
+    let mut footer = header;
+    footer.extend(ref_index_position.to_be_bytes());
+    // No object blocks: their position, shifted by 5 bits to hold the length of the object
+    // names, and their index.
+    footer.extend(0_u64.to_be_bytes());
+    footer.extend(0_u64.to_be_bytes());
+    footer.extend(log_position.unwrap_or(0).to_be_bytes());
+    footer.extend(log_index_position.to_be_bytes());
+    let mut crc = Crc::new();
+    crc.update(&footer);
+    footer.extend(crc.sum().to_be_bytes());
+    table.extend(footer);
+    Ok(table)
+}
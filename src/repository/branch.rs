@@ -0,0 +1,218 @@
+use crate::{diff::NULL_HASH, error::BranchError, git_config::GitConfig, ref_name};
+
+use super::{ref_backend::RefStorage, ref_transaction::RefTransaction, refs::Ref, GitRepository};
+
+/// The ref of the branch `name`, such as `refs/heads/master`.
+pub fn branch_ref(name: &str) -> String {
+    format!("refs/heads/{}", name)
+}
+
+/// The configuration section of the branch `name`, holding its upstream.
+fn config_section(name: &str) -> String {
+    format!("branch \"{}\"", name)
+}
+
+// Branch methods
+impl GitRepository {
+    /// The local branches sorted by name, followed by the remote-tracking ones if `remotes`.
+    pub fn branches(&self, remotes: bool) -> Result<Vec<Ref>, BranchError> {
+        let mut branches = self.list_refs_in("heads".as_ref())?;
+        if remotes {
+            branches.extend(self.list_refs_in("remotes".as_ref())?);
+        }
+        Ok(branches)
+    }
+
+    /// The path of the work tree that has the branch `branch`, such as `refs/heads/master`,
+    /// checked out, if any.
+    fn worktree_using_branch(&self, branch: &str) -> Result<Option<String>, BranchError> {
+        Ok(self
+            .worktrees()?
+            .into_iter()
+            .find(|worktree| worktree.branch.as_deref() == Some(branch))
+            .map(|worktree| worktree.path.display().to_string()))
+    }
+
+    /// Create the branch `name` at the commit `start`, named `start_name` in the reflog. With
+    /// `force`, an existing branch is moved there unless a work tree has it checked out.
+    pub fn create_branch(
+        &self,
+        name: &str,
+        start: &str,
+        start_name: &str,
+        force: bool,
+    ) -> Result<(), BranchError> {
+        if !ref_name::is_valid_branch_name(name) {
+            return Err(BranchError::InvalidName(name.to_string()));
+        }
+        let branch = branch_ref(name);
+        let mut transaction = RefTransaction::new();
+        match self.current_ref_value(&branch)? {
+            Some(_) if !force => return Err(BranchError::AlreadyExists(name.to_string())),
+            Some(old) => {
+                if let Some(path) = self.worktree_using_branch(&branch)? {
+                    return Err(BranchError::ForceUpdateCheckedOut(name.to_string(), path));
+                }
+                let message = format!("branch: Reset to {}", start_name);
+                transaction
+                    .set_message(&message)
+                    .update(&branch, start, Some(&old))
+            }
+            None => {
+                let message = format!("branch: Created from {}", start_name);
+                transaction.set_message(&message).create(&branch, start)
+            }
+        };
+        Ok(self.commit_ref_transaction(&transaction)?)
+    }
+
+    /// Delete the branch `name`, its reflog and its configuration. Unless `force`, the branch
+    /// must be merged into its upstream, or into `HEAD` if it has none. Returns the commit the
+    /// branch pointed to.
+    pub fn delete_branch(&self, name: &str, force: bool) -> Result<String, BranchError> {
+        let branch = branch_ref(name);
+        let hash = self
+            .current_ref_value(&branch)?
+            .ok_or_else(|| BranchError::NotFound(name.to_string()))?;
+        if let Some(path) = self.worktree_using_branch(&branch)? {
+            return Err(BranchError::CheckedOut(name.to_string(), path));
+        }
+        if !force {
+            let upstream = match self.upstream(name) {
+                Some(upstream) => self.current_ref_value(&upstream)?,
+                None => None,
+            };
+            let merged = match upstream.or(self.head_commit()?) {
+                Some(into) => self.is_ancestor(&hash, &into)?,
+                None => false,
+            };
+            if !merged {
+                return Err(BranchError::NotFullyMerged(name.to_string()));
+            }
+        }
+
+        let mut transaction = RefTransaction::new();
+        transaction.delete(&branch, Some(&hash));
+        self.commit_ref_transaction(&transaction)?;
+        let config_file = &self.directory_manager.config_file;
+        GitConfig::rename_section(config_file, &config_section(name), None)?;
+        Ok(hash)
+    }
+
+    /// Rename the branch `old` to `new`, with its reflog and configuration, and make the work
+    /// trees on `old` follow it. With `force`, an existing branch `new` is replaced.
+    pub fn rename_branch(&self, old: &str, new: &str, force: bool) -> Result<(), BranchError> {
+        if !ref_name::is_valid_branch_name(new) {
+            return Err(BranchError::InvalidName(new.to_string()));
+        }
+        let (old_ref, new_ref) = (branch_ref(old), branch_ref(new));
+        let hash = self
+            .current_ref_value(&old_ref)?
+            .ok_or_else(|| BranchError::NotFound(old.to_string()))?;
+        let replaced = self.current_ref_value(&new_ref)?;
+        if replaced.is_some() && !force {
+            return Err(BranchError::AlreadyExists(new.to_string()));
+        }
+        if old == new {
+            return Ok(());
+        }
+        if let (Some(_), Some(path)) = (&replaced, self.worktree_using_branch(&new_ref)?) {
+            return Err(BranchError::ForceUpdateCheckedOut(new.to_string(), path));
+        }
+
+        let mut entries = self.read_reflog(&old_ref)?;
+        let message = format!("Branch: renamed {} to {}", old_ref, new_ref);
+        let mut transaction = RefTransaction::new();
+        transaction
+            .set_message(&message)
+            .delete(&old_ref, Some(&hash));
+        // A loose ref can't be a file and a directory at once, so renaming `a` to `a/b`, or
+        // back, deletes the old ref before creating the new one, restoring it if that fails.
+        let nested = [(&old_ref, &new_ref), (&new_ref, &old_ref)]
+            .iter()
+            .any(|(outer, inner)| inner.starts_with(&format!("{}/", outer)));
+        let in_two_steps = nested && self.ref_storage() == RefStorage::Files;
+        if in_two_steps {
+            self.commit_ref_transaction(&transaction)?;
+            transaction = RefTransaction::new();
+            transaction.set_message(&message);
+        }
+        transaction.update(
+            &new_ref,
+            &hash,
+            Some(replaced.as_deref().unwrap_or(NULL_HASH)),
+        );
+        if let Err(error) = self.commit_ref_transaction(&transaction) {
+            if in_two_steps {
+                let mut restore = RefTransaction::new();
+                restore.create(&old_ref, &hash);
+                self.commit_ref_transaction(&restore)?;
+                self.write_reflog(&old_ref, &entries)?;
+            }
+            return Err(error.into());
+        }
+        // The history of the branch moves along, the rename at its end.
+        if let Some(mut renamed) = self.read_reflog(&new_ref)?.pop() {
+            renamed.old = hash.clone();
+            entries.push(renamed);
+        }
+        if !entries.is_empty() {
+            self.write_reflog(&new_ref, &entries)?;
+        }
+
+        for worktree in self.worktrees()? {
+            if worktree.branch.as_deref() == Some(old_ref.as_str()) {
+                self.set_worktree_head(&worktree, &new_ref)?;
+            }
+        }
+        let config_file = &self.directory_manager.config_file;
+        GitConfig::rename_section(config_file, &config_section(new), None)?;
+        GitConfig::rename_section(
+            config_file,
+            &config_section(old),
+            Some(&config_section(new)),
+        )?;
+        Ok(())
+    }
+
+    /// The ref the branch `name` tracks, from `branch.<name>.remote` and `branch.<name>.merge`:
+    /// a remote-tracking branch, or a local branch for the remote `.`.
+    pub fn upstream(&self, name: &str) -> Option<String> {
+        let section = config_section(name);
+        let remote = self.config.get_string(&section, "remote")?;
+        let merge = self.config.get_string(&section, "merge")?;
+        match remote.as_str() {
+            "." => Some(merge),
+            remote => Some(format!(
+                "refs/remotes/{}/{}",
+                remote,
+                merge.strip_prefix("refs/heads/")?
+            )),
+        }
+    }
+
+    /// Make the branch `name` track `upstream`, a remote-tracking branch such as `origin/main`
+    /// or a local branch. The repository's configuration loaded in memory isn't updated.
+    /// Returns the ref of the upstream.
+    pub fn set_upstream(&self, name: &str, upstream: &str) -> Result<String, BranchError> {
+        if self.current_ref_value(&branch_ref(name))?.is_none() {
+            return Err(BranchError::NotFound(name.to_string()));
+        }
+        let remote_ref = format!("refs/remotes/{}", upstream);
+        let (remote, merge, upstream_ref) = match upstream.split_once('/') {
+            Some((remote, branch)) if self.current_ref_value(&remote_ref)?.is_some() => {
+                (remote, branch_ref(branch), remote_ref)
+            }
+            _ if self.current_ref_value(&branch_ref(upstream))?.is_some() => {
+                (".", branch_ref(upstream), branch_ref(upstream))
+            }
+            _ => return Err(BranchError::UpstreamNotFound(upstream.to_string())),
+        };
+
+        let config_file = &self.directory_manager.config_file;
+        let section = config_section(name);
+        GitConfig::write_value(config_file, &section, "remote", remote)?;
+        GitConfig::write_value(config_file, &section, "merge", &merge)?;
+        Ok(upstream_ref)
+    }
+}
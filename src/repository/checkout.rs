@@ -0,0 +1,528 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::ErrorKind,
+    path::Path,
+};
+
+use crate::{
+    diff::{self, DiffSide, TreeDiff},
+    error::CheckoutError,
+    git_object::{mode::Type, Tree},
+    index::{Index, IndexEntry, StatData},
+    merge::TreeMerge,
+    parallel,
+    pathspec::Pathspec,
+    sparse::SparsePatterns,
+    GitObject,
+};
+
+use super::GitRepository;
+
+/// Paths that can't be updated by a checkout, grouped by reason.
+#[derive(Debug, Default)]
+struct Conflicts {
+    local_changes: BTreeSet<String>,
+    untracked_overwritten: BTreeSet<String>,
+    untracked_removed: BTreeSet<String>,
+}
+
+impl Conflicts {
+    /// The error of a merge, which reports untracked files in the way as overwritten.
+    fn into_merge_error(self) -> Option<CheckoutError> {
+        let mut untracked = self.untracked_overwritten;
+        untracked.extend(self.untracked_removed);
+        let list = |paths: BTreeSet<String>| paths.into_iter().collect();
+        if !self.local_changes.is_empty() {
+            Some(CheckoutError::MergeLocalChanges(list(self.local_changes)))
+        } else if !untracked.is_empty() {
+            Some(CheckoutError::MergeUntrackedOverwritten(list(untracked)))
+        } else {
+            None
+        }
+    }
+
+    fn into_error(self) -> Option<CheckoutError> {
+        let list = |paths: BTreeSet<String>| paths.into_iter().collect();
+        if !self.local_changes.is_empty() {
+            Some(CheckoutError::LocalChanges(list(self.local_changes)))
+        } else if !self.untracked_overwritten.is_empty() {
+            Some(CheckoutError::UntrackedOverwritten(list(
+                self.untracked_overwritten,
+            )))
+        } else if !self.untracked_removed.is_empty() {
+            Some(CheckoutError::UntrackedRemoved(list(
+                self.untracked_removed,
+            )))
+        } else {
+            None
+        }
+    }
+}
+
+fn is_same(entry: Option<&IndexEntry>, side: Option<&DiffSide>) -> bool {
+    match (entry, side) {
+        (Some(entry), Some(side)) => entry.hash == side.hash && entry.mode == side.mode,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Whether the file at `path` is in the work tree with the sparse checkout patterns `sparse`.
+fn is_checked_out(sparse: Option<&SparsePatterns>, path: &str) -> bool {
+    sparse.is_none_or(|sparse| sparse.includes(path))
+}
+
+// Checkout methods
+impl GitRepository {
+    /// Move the index and the work tree from the tree `from`, normally the one of `HEAD`, to the
+    /// tree `to`.
+    ///
+    /// Only the paths that differ between the two trees are touched, so local changes to other
+    /// files are kept. A path whose index entry already matches `to` is left alone as well. If a
+    /// file with local changes or an untracked file would be overwritten or removed, nothing is
+    /// changed and an error lists the offending paths. Files outside of the sparse checkout
+    /// patterns are only recorded in the index.
+    pub fn switch_tree(
+        &self,
+        index: &mut Index,
+        from: Option<Tree>,
+        to: Option<Tree>,
+    ) -> Result<(), CheckoutError> {
+        if index.entries().iter().any(|entry| entry.stage != 0) {
+            return Err(CheckoutError::UnmergedIndex);
+        }
+
+        let changes = TreeDiff::new(self, from, to)
+            .recursive(true)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sparse = self.sparse_patterns()?;
+        let mut removals = BTreeSet::new();
+        let mut writes = BTreeMap::new();
+        let mut conflicts = Conflicts::default();
+        for change in changes {
+            let path = change.path().to_string();
+            let entry = index.entry(&path);
+            if is_same(entry, change.new.as_ref()) {
+                continue;
+            }
+            if !is_same(entry, change.old.as_ref()) {
+                conflicts.local_changes.insert(path);
+                continue;
+            }
+
+            if let Some(entry) = entry {
+                if !entry.skip_worktree && !self.is_work_tree_file_up_to_date(index, entry)? {
+                    conflicts.local_changes.insert(path);
+                    continue;
+                }
+            }
+            match change.new {
+                Some(side) => {
+                    writes.insert(path, side);
+                }
+                None => {
+                    removals.insert(path);
+                }
+            }
+        }
+
+        for (path, side) in &writes {
+            if is_checked_out(sparse.as_ref(), path) {
+                self.check_write(index, &removals, path, side, &mut conflicts)?;
+            }
+        }
+        if let Some(error) = conflicts.into_error() {
+            return Err(error);
+        }
+
+        for path in &removals {
+            let skip_worktree = index.entry(path).is_some_and(|entry| entry.skip_worktree);
+            if !skip_worktree {
+                self.remove_work_tree_file(path)?;
+            }
+            index.remove(path);
+        }
+        self.checkout_files(index, writes.into_values().collect(), sparse.as_ref())?;
+
+        Ok(())
+    }
+
+    /// Check that writing `side` at `path` won't overwrite an untracked file, or remove one
+    /// from a directory in the way.
+    fn check_write(
+        &self,
+        index: &Index,
+        removals: &BTreeSet<String>,
+        path: &str,
+        side: &DiffSide,
+        conflicts: &mut Conflicts,
+    ) -> Result<(), CheckoutError> {
+        // Tracked files in the way must be removed by the checkout, or they have local changes.
+        let is_tracked = |path: &str, conflicts: &mut Conflicts| {
+            if removals.contains(path) {
+                return true;
+            }
+            if index.entry(path).is_some() {
+                conflicts.local_changes.insert(path.to_string());
+                return true;
+            }
+            false
+        };
+
+        // A leading directory may be a file that isn't tracked.
+        let mut leading = path;
+        while let Some((parent, _)) = leading.rsplit_once('/') {
+            leading = parent;
+            let metadata = std::fs::symlink_metadata(self.work_tree_path(leading));
+            if metadata.is_ok_and(|metadata| !metadata.is_dir()) && !is_tracked(leading, conflicts)
+            {
+                conflicts.untracked_overwritten.insert(leading.to_string());
+            }
+        }
+
+        let full_path = self.work_tree_path(path);
+        let metadata = match std::fs::symlink_metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(error)
+                if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) =>
+            {
+                return Ok(())
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        if metadata.is_dir() {
+            if side.mode.type_ == Type::Submodule {
+                return Ok(());
+            }
+            let mut files = vec![];
+            self.list_directory_files(&full_path, path, &mut files)?;
+            for file in files {
+                if !is_tracked(&file, conflicts) {
+                    conflicts.untracked_removed.insert(file);
+                }
+            }
+        } else if index.entry(path).is_none() {
+            // An untracked file is only overwritten if it differs from the new version.
+            if !self.work_tree_file_matches(path, &side.mode, &side.hash)? {
+                conflicts.untracked_overwritten.insert(path.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Every file below the directory `full_path`, as paths relative to the top of the work tree.
+    fn list_directory_files(
+        &self,
+        full_path: &Path,
+        path: &str,
+        files: &mut Vec<String>,
+    ) -> Result<(), std::io::Error> {
+        for entry in std::fs::read_dir(full_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let entry_path = format!("{}/{}", path, name);
+            // A nested repository is reported as a whole.
+            if entry.file_type()?.is_dir() && name != ".git" {
+                self.list_directory_files(&entry.path(), &entry_path, files)?;
+            } else {
+                files.push(entry_path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the blobs of `sides` to the work tree and record them in the index. Files outside
+    /// of the sparse checkout patterns `sparse` are recorded with the skip-worktree bit instead,
+    /// and their previous version removed from the work tree.
+    fn checkout_files(
+        &self,
+        index: &mut Index,
+        sides: Vec<DiffSide>,
+        sparse: Option<&SparsePatterns>,
+    ) -> Result<(), CheckoutError> {
+        let (sides, excluded): (Vec<DiffSide>, Vec<DiffSide>) = sides
+            .into_iter()
+            .partition(|side| is_checked_out(sparse, &side.path));
+        for side in excluded {
+            if index
+                .entry(&side.path)
+                .is_some_and(|entry| !entry.skip_worktree)
+            {
+                self.remove_work_tree_file(&side.path)?;
+            }
+            let mut entry = IndexEntry::new(side.path, side.mode, side.hash, StatData::default());
+            entry.skip_worktree = true;
+            index.add(entry);
+        }
+
+        let stats = self.write_sides(&sides)?;
+        for (side, stat) in sides.into_iter().zip(stats) {
+            index.add(IndexEntry::new(side.path, side.mode, side.hash, stat));
+        }
+        Ok(())
+    }
+
+    /// Write the blob of `side` to the work tree, removing files and directories in the way.
+    /// Returns the file system information to record in the index.
+    pub(crate) fn write_side(&self, side: &DiffSide) -> Result<StatData, CheckoutError> {
+        self.clear_way(side)?;
+        self.write_side_file(side)
+    }
+
+    /// Like [`GitRepository::write_side`] for many files, written on several threads if
+    /// `checkout.workers` allows it. The file system information is returned in the order of
+    /// `sides`.
+    pub(crate) fn write_sides(&self, sides: &[DiffSide]) -> Result<Vec<StatData>, CheckoutError> {
+        // What's in the way goes first, so that the files can be written in any order.
+        for side in sides {
+            self.clear_way(side)?;
+        }
+        parallel::try_map(self.workers(sides.len()), sides, |side| {
+            self.write_side_file(side)
+        })
+    }
+
+    /// Remove the files in place of the leading directories of `side`, and the directory in
+    /// place of its file unless it's a submodule.
+    fn clear_way(&self, side: &DiffSide) -> Result<(), CheckoutError> {
+        let mut leading = side.path.as_str();
+        while let Some((parent, _)) = leading.rsplit_once('/') {
+            leading = parent;
+            let full_path = self.work_tree_path(leading);
+            if std::fs::symlink_metadata(&full_path).is_ok_and(|metadata| !metadata.is_dir()) {
+                std::fs::remove_file(full_path)?;
+            }
+        }
+
+        let full_path = self.work_tree_path(&side.path);
+        if side.mode.type_ != Type::Submodule
+            && std::fs::symlink_metadata(&full_path).is_ok_and(|metadata| metadata.is_dir())
+        {
+            std::fs::remove_dir_all(&full_path)?;
+        }
+        Ok(())
+    }
+
+    /// Write the file of `side`, once nothing is in the way.
+    fn write_side_file(&self, side: &DiffSide) -> Result<StatData, CheckoutError> {
+        if side.mode.type_ == Type::Submodule {
+            self.write_work_tree_file(&side.path, &side.mode, &[])?;
+            return Ok(StatData::default());
+        }
+        let data = match self.read_object(&side.hash)? {
+            GitObject::Blob(blob) => blob.blob,
+            _ => return Err(CheckoutError::NotABlob(side.hash.clone())),
+        };
+        let metadata = self.write_work_tree_file(&side.path, &side.mode, &data)?;
+        Ok(StatData::from(&metadata))
+    }
+
+    /// Make the work tree files matching `pathspec` match `files`, which come from the index or
+    /// a tree. Tracked files matching `pathspec` that aren't in `files` are removed. Index
+    /// entries are left alone, except for the file system information of the ones whose file
+    /// now matches them.
+    pub fn restore_work_tree(
+        &self,
+        index: &mut Index,
+        files: Vec<DiffSide>,
+        pathspec: &Pathspec,
+    ) -> Result<(), CheckoutError> {
+        let paths: BTreeSet<&str> = files.iter().map(|side| side.path.as_str()).collect();
+        for entry in index.entries() {
+            if !entry.skip_worktree
+                && pathspec.matches(&entry.path)
+                && !paths.contains(entry.path.as_str())
+            {
+                self.remove_work_tree_file(&entry.path)?;
+            }
+        }
+
+        let files: Vec<DiffSide> = files
+            .into_iter()
+            .filter(|side| {
+                !index
+                    .entry(&side.path)
+                    .is_some_and(|entry| entry.skip_worktree)
+            })
+            .collect();
+        let matches = parallel::try_map(self.workers(files.len()), &files, |side| {
+            self.work_tree_file_matches(&side.path, &side.mode, &side.hash)
+        })?;
+        let writes: Vec<DiffSide> = files
+            .into_iter()
+            .zip(matches)
+            .filter_map(|(side, matches)| (!matches).then_some(side))
+            .collect();
+
+        let stats = self.write_sides(&writes)?;
+        for (side, stat) in writes.iter().zip(stats) {
+            if let Some(entry) = index.entry_mut(&side.path) {
+                if entry.hash == side.hash && entry.mode == side.mode {
+                    entry.stat = stat;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the entries of `index` matching `pathspec` with the files of `tree`. Entries
+    /// that don't change keep their cached file system information.
+    pub fn reset_index(
+        &self,
+        index: &mut Index,
+        tree: Option<Tree>,
+        pathspec: &Pathspec,
+    ) -> Result<(), CheckoutError> {
+        let old = index.clone();
+        index.retain(|entry| !pathspec.matches(&entry.path));
+        for side in diff::tree_files(self, tree, pathspec)? {
+            let entry = match old.entry(&side.path) {
+                Some(entry) if is_same(Some(entry), Some(&side)) => entry.clone(),
+                _ => IndexEntry::new(side.path, side.mode, side.hash, StatData::default()),
+            };
+            index.add(entry);
+        }
+        Ok(())
+    }
+
+    /// Make the index and the work tree match `tree`, discarding every local change to tracked
+    /// files. Files that are tracked in `index` but not in `tree` are removed, untracked files
+    /// are left alone unless they are in the way.
+    pub fn reset_work_tree(
+        &self,
+        index: &mut Index,
+        tree: Option<Tree>,
+    ) -> Result<(), CheckoutError> {
+        let files = diff::tree_files(self, tree, &Pathspec::default())?;
+        let paths: BTreeSet<&str> = files.iter().map(|side| side.path.as_str()).collect();
+        let sparse = self.sparse_patterns()?;
+
+        for entry in index.entries() {
+            if !entry.skip_worktree && !paths.contains(entry.path.as_str()) {
+                self.remove_work_tree_file(&entry.path)?;
+            }
+        }
+
+        // Entries whose file is already there are kept as they are.
+        let old = std::mem::take(index);
+        let kept = parallel::try_map(self.workers(files.len()), &files, |side| {
+            Ok::<_, CheckoutError>(match old.entry(&side.path) {
+                Some(entry)
+                    if is_same(Some(entry), Some(side))
+                        && (entry.skip_worktree
+                            || self.is_work_tree_file_present(&old, entry)?) =>
+                {
+                    Some(entry.clone())
+                }
+                _ => None,
+            })
+        })?;
+        let mut checkouts = vec![];
+        for (side, kept) in files.into_iter().zip(kept) {
+            match kept {
+                Some(entry) => index.add(entry),
+                None => checkouts.push(side),
+            }
+        }
+        self.checkout_files(index, checkouts, sparse.as_ref())
+    }
+
+    /// Move the index and the work tree from the files of `index` to the result of `merge`.
+    /// Conflicted paths get their stages recorded in the index and the content with conflict
+    /// markers written to the work tree.
+    ///
+    /// Like [`GitRepository::switch_tree`], nothing is changed if a file with local changes or
+    /// an untracked file is in the way.
+    pub fn checkout_merge(
+        &self,
+        index: &mut Index,
+        merge: &TreeMerge,
+    ) -> Result<(), CheckoutError> {
+        if index.entries().iter().any(|entry| entry.stage != 0) {
+            return Err(CheckoutError::UnmergedIndex);
+        }
+
+        let mut removals: BTreeSet<String> = index
+            .entries()
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+        let mut writes = BTreeMap::new();
+        for side in &merge.files {
+            removals.remove(&side.path);
+            if !is_same(index.entry(&side.path), Some(side)) {
+                writes.insert(side.path.clone(), side);
+            }
+        }
+        for conflict in &merge.conflicts {
+            removals.remove(&conflict.path);
+            writes.insert(conflict.path.clone(), &conflict.work_tree);
+        }
+
+        let sparse = self.sparse_patterns()?;
+        let mut conflicts = Conflicts::default();
+        for path in writes.keys().chain(&removals) {
+            if let Some(entry) = index.entry(path) {
+                if !entry.skip_worktree && !self.is_work_tree_file_up_to_date(index, entry)? {
+                    conflicts.local_changes.insert(path.clone());
+                }
+            }
+        }
+        for (path, side) in &writes {
+            // Conflicted files are written even outside of the sparse checkout.
+            let conflicted = merge
+                .conflicts
+                .iter()
+                .any(|conflict| conflict.path == *path);
+            if conflicted || is_checked_out(sparse.as_ref(), path) {
+                self.check_write(index, &removals, path, side, &mut conflicts)?;
+            }
+        }
+        if let Some(error) = conflicts.into_merge_error() {
+            return Err(error);
+        }
+
+        for path in &removals {
+            let skip_worktree = index.entry(path).is_some_and(|entry| entry.skip_worktree);
+            if !skip_worktree {
+                self.remove_work_tree_file(path)?;
+            }
+            index.remove(path);
+        }
+        let checkouts = merge
+            .files
+            .iter()
+            .filter(|side| writes.contains_key(&side.path))
+            .cloned()
+            .collect();
+        self.checkout_files(index, checkouts, sparse.as_ref())?;
+        for conflict in &merge.conflicts {
+            self.write_side(&conflict.work_tree)?;
+            let stages = [&conflict.base, &conflict.ours, &conflict.theirs]
+                .into_iter()
+                .zip(1..)
+                .filter_map(|(side, stage)| {
+                    let side = side.as_ref()?;
+                    let mut entry = IndexEntry::new(
+                        side.path.clone(),
+                        side.mode.clone(),
+                        side.hash.clone(),
+                        StatData::default(),
+                    );
+                    entry.stage = stage;
+                    Some(entry)
+                })
+                .collect();
+            index.add_conflict(&conflict.path, stages);
+        }
+        Ok(())
+    }
+
+    /// Write `files` to the work tree without recording them in the index.
+    pub fn write_work_tree_files(&self, files: &[DiffSide]) -> Result<(), CheckoutError> {
+        self.write_sides(files)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    fs::DirEntry,
+    io::{Error, ErrorKind},
+};
+
+use crate::{ignore::IgnoreRules, index::Index, pathspec::Pathspec};
+
+use super::GitRepository;
+
+/// How ignored files are treated by [`GitRepository::clean`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IgnoredFiles {
+    /// Keep them.
+    #[default]
+    Keep,
+    /// Remove them along with the untracked files, the ignore rules aren't used (`-x`).
+    Remove,
+    /// Remove them only (`-X`).
+    Only,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CleanOptions {
+    /// Report what would be removed without removing anything.
+    pub dry_run: bool,
+    /// Remove untracked directories as a whole, instead of only the files outside of them.
+    pub directories: bool,
+    pub ignored: IgnoredFiles,
+    /// Remove nested repositories as well.
+    pub nested_repositories: bool,
+}
+
+/// What [`GitRepository::clean`] did, or would do, to a path. Directory paths have a trailing
+/// slash.
+#[derive(Debug)]
+pub enum CleanAction {
+    Removed(String),
+    /// A nested repository, kept along with the directories holding it. Its path has no
+    /// trailing slash.
+    SkippedRepository(String),
+    Failed(String, Error),
+}
+
+/// What an untracked directory holds.
+#[derive(Debug, Default)]
+struct DirectoryContent {
+    untracked: bool,
+    ignored: bool,
+}
+
+fn child_path(directory: &str, entry: &DirEntry) -> String {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    match directory {
+        "" => name,
+        _ => format!("{}/{}", directory, name),
+    }
+}
+
+/// The entries of a directory, sorted by name, without `.git`.
+fn read_directory(path: &std::path::Path) -> Result<Vec<DirEntry>, Error> {
+    let mut entries = std::fs::read_dir(path)?
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .map_or(true, |entry| entry.file_name() != ".git")
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+/// Walks the work tree collecting the paths to clean.
+struct CleanWalk<'a> {
+    repo: &'a GitRepository,
+    index: &'a Index,
+    pathspec: &'a Pathspec,
+    options: CleanOptions,
+    rules: IgnoreRules,
+    /// Paths to remove, with whether they are directories.
+    paths: Vec<(String, bool)>,
+}
+
+impl CleanWalk<'_> {
+    fn is_ignored(&self, path: &str, is_dir: bool, in_ignored: bool) -> bool {
+        self.options.ignored != IgnoredFiles::Remove
+            && (in_ignored || self.rules.is_ignored(path, is_dir))
+    }
+
+    fn is_nested_repository(&self, path: &str) -> bool {
+        self.repo.work_tree_path(path).join(".git").exists()
+    }
+
+    fn push_rules(&mut self, directory: &str) {
+        if self.options.ignored == IgnoredFiles::Remove {
+            return;
+        }
+        let gitignore = self.repo.work_tree_path(directory).join(".gitignore");
+        if let Ok(content) = std::fs::read_to_string(gitignore) {
+            self.rules.add_patterns(directory, &content);
+        }
+    }
+
+    /// Keep `path` for removal if the options select it.
+    fn select(&mut self, path: String, is_dir: bool, ignored: bool) {
+        let selected = match self.options.ignored {
+            IgnoredFiles::Keep => !ignored,
+            IgnoredFiles::Remove => true,
+            IgnoredFiles::Only => ignored,
+        };
+        // A directory named by a pathspec is removed even without `-d`.
+        let directory_selected = self.options.directories || self.pathspec.matches_exactly(&path);
+        if selected && (!is_dir || directory_selected) && self.pathspec.matches(&path) {
+            self.paths.push((path, is_dir));
+        }
+    }
+
+    fn walk(&mut self, directory: &str, in_ignored: bool) -> Result<(), Error> {
+        let rules_len = self.rules.len();
+        self.push_rules(directory);
+
+        for entry in read_directory(&self.repo.work_tree_path(directory))? {
+            let path = child_path(directory, &entry);
+            if !entry.file_type()?.is_dir() {
+                if !self.index.contains(&path) {
+                    let ignored = self.is_ignored(&path, false, in_ignored);
+                    self.select(path, false, ignored);
+                }
+                continue;
+            }
+
+            if self.index.contains_directory(&path) {
+                self.walk(&path, in_ignored)?;
+                continue;
+            }
+            if !self.options.nested_repositories && self.is_nested_repository(&path) {
+                continue;
+            }
+            if !self.pathspec.matches(&path) {
+                // A pathspec naming paths inside the directory looks at them one by one.
+                if self.pathspec.may_match_below(&path) {
+                    let ignored = self.is_ignored(&path, true, in_ignored);
+                    self.walk(&path, ignored)?;
+                }
+                continue;
+            }
+            if self.is_ignored(&path, true, in_ignored) {
+                self.select(path, true, true);
+                continue;
+            }
+
+            let content = self.directory_content(&path)?;
+            // Removing an untracked directory holding ignored files as a whole would lose them,
+            // or keep the untracked ones when only ignored files are removed.
+            let mixed_matters = match self.options.ignored {
+                IgnoredFiles::Keep => {
+                    self.options.directories || self.pathspec.matches_exactly(&path)
+                }
+                IgnoredFiles::Remove => false,
+                IgnoredFiles::Only => true,
+            };
+            match (content.untracked, content.ignored) {
+                (true, true) if mixed_matters => self.walk(&path, false)?,
+                (false, true) => self.select(path, true, true),
+                _ => self.select(path, true, false),
+            }
+        }
+
+        self.rules.truncate(rules_len);
+        Ok(())
+    }
+
+    /// What the untracked directory `directory` holds. Empty directories count as untracked
+    /// content, nested repositories as nothing.
+    fn directory_content(&mut self, directory: &str) -> Result<DirectoryContent, Error> {
+        let rules_len = self.rules.len();
+        self.push_rules(directory);
+
+        let mut content = DirectoryContent::default();
+        for entry in read_directory(&self.repo.work_tree_path(directory))? {
+            let path = child_path(directory, &entry);
+            if !entry.file_type()?.is_dir() {
+                match self.is_ignored(&path, false, false) {
+                    true => content.ignored = true,
+                    false => content.untracked = true,
+                }
+            } else if self.is_nested_repository(&path) {
+                continue;
+            } else if self.is_ignored(&path, true, false) {
+                content.ignored = true;
+            } else {
+                let inner = self.directory_content(&path)?;
+                content.untracked |= inner.untracked || !inner.ignored;
+                content.ignored |= inner.ignored;
+            }
+        }
+
+        self.rules.truncate(rules_len);
+        Ok(content)
+    }
+}
+
+// Clean methods
+impl GitRepository {
+    /// The paths of the work tree that [`GitRepository::clean`] removes, sorted, with whether
+    /// they are directories.
+    pub fn clean_paths(
+        &self,
+        index: &Index,
+        pathspec: &Pathspec,
+        options: CleanOptions,
+    ) -> Result<Vec<(String, bool)>, Error> {
+        let mut walk = CleanWalk {
+            repo: self,
+            index,
+            pathspec,
+            options,
+            rules: match options.ignored {
+                IgnoredFiles::Remove => IgnoreRules::new(),
+                _ => self.ignore_rules(),
+            },
+            paths: vec![],
+        };
+        walk.walk("", false)?;
+        walk.paths.sort();
+        Ok(walk.paths)
+    }
+
+    /// Remove the files of the work tree matching `pathspec` that aren't tracked in `index`:
+    /// untracked files, and depending on `options` untracked directories and ignored files.
+    /// Symbolic links are removed, never followed, and nested repositories are kept unless
+    /// asked otherwise.
+    ///
+    /// Returns what was removed, or would be with `dry_run`, in order.
+    pub fn clean(
+        &self,
+        index: &Index,
+        pathspec: &Pathspec,
+        options: CleanOptions,
+    ) -> Result<Vec<CleanAction>, Error> {
+        let mut actions = vec![];
+        for (path, is_dir) in self.clean_paths(index, pathspec, options)? {
+            if !is_dir {
+                actions.push(self.clean_file(path, options));
+            } else if self.clean_directory(&path, options, &mut actions) {
+                actions.push(CleanAction::Removed(format!("{}/", path)));
+            }
+        }
+        Ok(actions)
+    }
+
+    fn clean_file(&self, path: String, options: CleanOptions) -> CleanAction {
+        if options.dry_run {
+            return CleanAction::Removed(path);
+        }
+        match std::fs::remove_file(self.work_tree_path(&path)) {
+            Err(error) if error.kind() != ErrorKind::NotFound => CleanAction::Failed(path, error),
+            _ => CleanAction::Removed(path),
+        }
+    }
+
+    /// Remove the directory `path` with its content. Returns whether it's gone, otherwise what
+    /// was removed inside it is reported one by one.
+    fn clean_directory(
+        &self,
+        path: &str,
+        options: CleanOptions,
+        actions: &mut Vec<CleanAction>,
+    ) -> bool {
+        let full_path = self.work_tree_path(path);
+        if !options.nested_repositories && full_path.join(".git").exists() {
+            actions.push(CleanAction::SkippedRepository(path.to_string()));
+            return false;
+        }
+
+        let entries = match std::fs::read_dir(&full_path) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, _>>(),
+            Err(error) => Err(error),
+        };
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(error) => {
+                actions.push(CleanAction::Failed(format!("{}/", path), error));
+                return false;
+            }
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let mut gone = true;
+        let mut removed = vec![];
+        for entry in entries {
+            let child = child_path(path, &entry);
+            // Symbolic links to directories are removed as files.
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if is_dir {
+                match self.clean_directory(&child, options, actions) {
+                    true => removed.push(CleanAction::Removed(format!("{}/", child))),
+                    false => gone = false,
+                }
+            } else {
+                match self.clean_file(child, options) {
+                    action @ CleanAction::Removed(_) => removed.push(action),
+                    action => {
+                        actions.push(action);
+                        gone = false;
+                    }
+                }
+            }
+        }
+
+        if gone && !options.dry_run {
+            if let Err(error) = std::fs::remove_dir(&full_path) {
+                actions.push(CleanAction::Failed(format!("{}/", path), error));
+                gone = false;
+            }
+        }
+        if !gone {
+            actions.extend(removed);
+        }
+        gone
+    }
+}
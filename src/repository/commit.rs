@@ -0,0 +1,148 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    diff::DiffSide,
+    error::{CommitError, ObjectParseError},
+    git_object::{leaf::Leaf, Commit, KeyValueList, SerializedGitObject, Signature, Tree},
+    index::Index,
+    GitObject,
+};
+
+use super::GitRepository;
+
+/// Parse a date given in the environment: seconds since the epoch, optionally prefixed with `@`,
+/// followed by an optional UTC offset.
+fn parse_date(date: &str) -> Result<(i64, String), CommitError> {
+    let mut parts = date.split_whitespace();
+    let time = parts
+        .next()
+        .map(|time| time.trim_start_matches('@'))
+        .and_then(|time| time.parse().ok())
+        .ok_or_else(|| CommitError::InvalidDate(date.to_string()))?;
+    let offset = parts.next().unwrap_or("+0000").to_string();
+    Ok((time, offset))
+}
+
+// Commit methods
+impl GitRepository {
+    /// The identity of `role`, either `AUTHOR` or `COMMITTER`. It comes from the
+    /// `GIT_<role>_NAME`, `GIT_<role>_EMAIL` and `GIT_<role>_DATE` environment variables, falling
+    /// back to `user.name`, `user.email` and the current time.
+    pub fn signature(&self, role: &str) -> Result<Signature, CommitError> {
+        let lookup = |variable: &str, key: &str| {
+            std::env::var(format!("GIT_{}_{}", role, variable))
+                .ok()
+                .or_else(|| self.config.get("user", key).map(str::to_string))
+        };
+        let unknown = || {
+            let mut role = role.to_lowercase();
+            role[..1].make_ascii_uppercase();
+            CommitError::IdentityUnknown(role)
+        };
+        let name = lookup("NAME", "name").ok_or_else(unknown)?;
+        let email = lookup("EMAIL", "email").ok_or_else(unknown)?;
+
+        let (time, offset) = match std::env::var(format!("GIT_{}_DATE", role)) {
+            Ok(date) => parse_date(&date)?,
+            Err(_) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(anyhow::Error::from)?;
+                (now.as_secs() as i64, "+0000".to_string())
+            }
+        };
+
+        Ok(Signature {
+            name,
+            email,
+            time,
+            offset,
+        })
+    }
+
+    /// Write the tree objects holding `files`, which must be sorted by path. Returns the hash of
+    /// the top tree.
+    pub fn write_files_tree(&self, files: &[DiffSide]) -> Result<String, CommitError> {
+        self.write_tree_level(files, 0)
+    }
+
+    fn write_tree_level(
+        &self,
+        files: &[DiffSide],
+        prefix_len: usize,
+    ) -> Result<String, CommitError> {
+        let mut leaves = vec![];
+        let mut start = 0;
+        while start < files.len() {
+            let file = &files[start];
+            let name = &file.path[prefix_len..];
+            let leaf = match name.split_once('/') {
+                None => {
+                    start += 1;
+                    Leaf::new(
+                        file.mode.serialize().as_bytes(),
+                        name.as_bytes(),
+                        file.hash.clone(),
+                    )
+                }
+                Some((directory, _)) => {
+                    let directory_prefix = &file.path[..prefix_len + directory.len() + 1];
+                    let end = start
+                        + files[start..]
+                            .iter()
+                            .take_while(|file| file.path.starts_with(directory_prefix))
+                            .count();
+                    let hash = self.write_tree_level(&files[start..end], directory_prefix.len())?;
+                    start = end;
+                    Leaf::new(b"40000", directory.as_bytes(), hash)
+                }
+            };
+            leaves.push(leaf.map_err(ObjectParseError::from)?);
+        }
+
+        let serialized = SerializedGitObject::try_from(GitObject::Tree(Tree::new(leaves)))?;
+        self.write_object(&serialized)?;
+        Ok(serialized.hash)
+    }
+
+    /// Write the tree of the staged files, like `git write-tree`.
+    pub fn write_index_tree(&self, index: &Index) -> Result<String, CommitError> {
+        if let Some(entry) = index.entries().iter().find(|entry| entry.stage != 0) {
+            return Err(CommitError::Unmerged(entry.path.clone()));
+        }
+        let files: Vec<DiffSide> = index
+            .entries()
+            .iter()
+            .filter(|entry| !entry.intent_to_add)
+            .map(DiffSide::from)
+            .collect();
+        self.write_files_tree(&files)
+    }
+
+    /// Write a commit of `tree` on top of `parents`, authored and committed by the current
+    /// user. Returns its hash.
+    pub fn create_commit(
+        &self,
+        tree: &str,
+        parents: &[String],
+        message: &str,
+    ) -> Result<String, CommitError> {
+        let mut entries = vec![("tree".to_string(), tree.to_string())];
+        for parent in parents {
+            entries.push(("parent".to_string(), parent.clone()));
+        }
+        entries.push(("author".to_string(), self.signature("AUTHOR")?.to_string()));
+        entries.push((
+            "committer".to_string(),
+            self.signature("COMMITTER")?.to_string(),
+        ));
+        entries.push(("message".to_string(), message.to_string()));
+
+        let commit = Commit {
+            kvl: KeyValueList::from_entries(entries),
+        };
+        let serialized = SerializedGitObject::try_from(GitObject::Commit(commit))?;
+        self.write_object(&serialized)?;
+        Ok(serialized.hash)
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    error::ObjectParseError,
+    git_object::{Commit, Type},
+    GitObject,
+};
+
+use super::GitRepository;
+
+// History methods
+impl GitRepository {
+    /// Read the commit `name` points to, peeling tags.
+    pub fn read_commit(&self, name: &str) -> Result<(String, Commit), ObjectParseError> {
+        match self.peel_object(name, Type::Commit)? {
+            (hash, GitObject::Commit(commit)) => Ok((hash, commit)),
+            _ => Err(ObjectParseError::CannotPeel(name.to_string(), Type::Commit)),
+        }
+    }
+
+    /// Every commit reachable from `hashes`, themselves included.
+    pub fn ancestors(&self, hashes: &[String]) -> Result<HashSet<String>, ObjectParseError> {
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut queue: VecDeque<String> = hashes.iter().cloned().collect();
+        while let Some(hash) = queue.pop_front() {
+            if !seen.insert(hash.clone()) {
+                continue;
+            }
+            let (_, commit) = self.read_commit(&hash)?;
+            queue.extend(commit.parents().into_iter().cloned());
+        }
+        Ok(seen)
+    }
+
+    pub fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool, ObjectParseError> {
+        Ok(self
+            .ancestors(&[descendant.to_string()])?
+            .contains(ancestor))
+    }
+
+    /// How many commits `one` has that `two` doesn't, and the other way around.
+    pub fn ahead_behind(&self, one: &str, two: &str) -> Result<(usize, usize), ObjectParseError> {
+        let one = self.ancestors(&[one.to_string()])?;
+        let two = self.ancestors(&[two.to_string()])?;
+        Ok((one.difference(&two).count(), two.difference(&one).count()))
+    }
+
+    /// The best common ancestors of two commits: common ancestors that aren't ancestors of
+    /// another common ancestor. Most recent first.
+    pub fn merge_bases(&self, one: &str, two: &str) -> Result<Vec<String>, ObjectParseError> {
+        let one = self.ancestors(&[one.to_string()])?;
+        let two = self.ancestors(&[two.to_string()])?;
+        let common: Vec<String> = one.intersection(&two).cloned().collect();
+
+        let mut parents = vec![];
+        for hash in &common {
+            parents.extend(self.read_commit(hash)?.1.parents().into_iter().cloned());
+        }
+        let redundant = self.ancestors(&parents)?;
+
+        let mut bases = vec![];
+        for hash in common.into_iter().filter(|hash| !redundant.contains(hash)) {
+            let time = self.read_commit(&hash)?.1.committer_time();
+            bases.push((time, hash));
+        }
+        bases.sort_by(|a, b| b.cmp(a));
+        Ok(bases.into_iter().map(|(_, hash)| hash).collect())
+    }
+}
@@ -1,12 +1,34 @@
+pub mod branch;
+pub mod checkout;
+pub mod clean;
+pub mod commit;
+pub mod history;
+pub mod ref_backend;
+pub mod ref_format;
+pub mod ref_transaction;
+pub mod reflog;
 pub mod refs;
+pub mod revision;
+pub mod sparse;
+pub mod stash;
+pub mod work_tree;
+pub mod worktree;
 
 use crate::{
-    error::{repository::ResolveRefError, CreateRepoError, ObjectCreateError, ObjectParseError},
+    error::{
+        repository::ResolveRefError, CreateRepoError, ObjectCreateError, ObjectParseError,
+        RevisionError,
+    },
     git_config::GitConfig,
-    git_object::{Blob, CompressedGitObject, KeyValueList, SerializedGitObject, Tag, Type},
+    git_object::{Blob, CompressedGitObject, KeyValueList, SerializedGitObject, Tag, Tree, Type},
+    ref_name::{self, RefNameOptions},
+    revision::ExpectedType,
     DirectoryManager, GitObject,
 };
 
+use ref_backend::{RefStorage, RefValue, ReftableBackend};
+use ref_transaction::RefTransaction;
+use refs::Head;
 use std::{
     collections::BTreeMap,
     fs::File,
@@ -33,7 +55,9 @@ impl GitRepository {
 
     /// Load an existing repository.
     pub fn load<T: Into<PathBuf>>(base_path: T) -> Result<Self, CreateRepoError> {
-        GitRepository::try_from(DirectoryManager::new(base_path))
+        let directory_manager =
+            DirectoryManager::open(base_path).context("Failed to read the .git file")?;
+        GitRepository::try_from(directory_manager)
     }
 
     /// Try to load a git repo in `working_dir`, if it fails, recursively try parent directory.
@@ -51,6 +75,17 @@ impl GitRepository {
 
     /// Create a new repository
     pub fn create<T: Into<PathBuf>>(base_path: T) -> Result<Self, CreateRepoError> {
+        Self::create_with(base_path, RefStorage::Files)
+    }
+
+    /// Create a new repository storing its refs with `ref_storage`. A reftable repository
+    /// needs format version 1 with `extensions.refStorage`, and gets a `HEAD` file pointing to
+    /// an invalid branch so that tools unaware of reftable don't mistake it for another
+    /// repository.
+    pub fn create_with<T: Into<PathBuf>>(
+        base_path: T,
+        ref_storage: RefStorage,
+    ) -> Result<Self, CreateRepoError> {
         let directory_manager = DirectoryManager::new(base_path);
 
         if directory_manager.work_tree.exists() && !directory_manager.work_tree.is_dir() {
@@ -75,66 +110,63 @@ impl GitRepository {
         )
         .context("Failed to write to .git/description")?;
 
-        // Write initial contents of .git/HEAD
-        std::fs::write(&directory_manager.head_file, "ref: refs/heads/master\n")
-            .context("Failed to write to .git/HEAD")?;
-
         // Write initial contents of .git/config
         std::fs::write(&directory_manager.config_file, GitConfig::default_str())
             .context("Failed to write to .git/config")?;
 
-        Ok(Self::new(GitConfig::default(), directory_manager))
+        if ref_storage == RefStorage::Files {
+            // Write initial contents of .git/HEAD
+            std::fs::write(&directory_manager.head_file, "ref: refs/heads/master\n")
+                .context("Failed to write to .git/HEAD")?;
+            return Ok(Self::new(GitConfig::default(), directory_manager));
+        }
+
+        let config_file = &directory_manager.config_file;
+        GitConfig::write_value(config_file, "core", "repositoryformatversion", "1")
+            .and_then(|_| {
+                GitConfig::write_value(config_file, "extensions", "refStorage", "reftable")
+            })
+            .context("Failed to write to .git/config")?;
+        std::fs::write(&directory_manager.head_file, "ref: refs/heads/.invalid\n")
+            .context("Failed to write to .git/HEAD")?;
+        let repo = Self::new(GitConfig::load_from_file(config_file)?, directory_manager);
+        repo.ref_backend()
+            .write_symbolic_ref("HEAD", "refs/heads/master")
+            .context("Failed to write HEAD")?;
+        Ok(repo)
     }
 }
 
 // Object related methods
 impl GitRepository {
-    fn resolve_object(&self, name: &str) -> Result<Vec<String>, anyhow::Error> {
-        let mut candidates = vec![];
-
-        if name == "HEAD" {
-            let ref_entry = refs::resolve_ref(
-                &self.directory_manager.dot_git_path,
-                &self.directory_manager.dot_git_path.join("HEAD"),
-            )?;
-            return Ok(vec![ref_entry]);
-        }
-
-        let regex = regex::Regex::new("^[0-9A-Fa-f]{4,40}$").unwrap();
-        if regex.is_match(&name) {
-            let directory = &name[0..2].to_lowercase();
-            let path = self.directory_manager.objects_path.join(directory);
-            for entry in path.read_dir()? {
-                let entry = entry?.path();
-                let filename = entry
-                    .file_name()
-                    .ok_or(anyhow::anyhow!("Failed to get filename"))?
-                    .to_str()
-                    .ok_or(anyhow::anyhow!("Failed to get the filename"))?;
-                if filename.starts_with(&name[2..]) {
-                    candidates.push(format!("{}{}", directory, filename))
-                }
-            }
-        }
-
-        if let Ok(tag) = self.resolve_ref(&format!("refs/tags/{}", name)) {
-            candidates.push(tag);
-        }
+    /// Resolve the revision `name`, such as `HEAD~2`, `v1.0^{commit}` or `main:src/lib.rs`, to
+    /// the hash of the object it names.
+    pub fn find_object(&self, name: &str) -> Result<String, anyhow::Error> {
+        self.find_object_as(name, ExpectedType::Any)
+    }
 
-        if let Ok(branch) = self.resolve_ref(&format!("refs/heads/{}", name)) {
-            candidates.push(branch);
+    /// Like [`GitRepository::find_object`], picking an object of the `expected` type when a
+    /// short hash stands for several objects.
+    pub fn find_object_as(
+        &self,
+        name: &str,
+        expected: ExpectedType,
+    ) -> Result<String, anyhow::Error> {
+        match self.resolve_revision_as(name, expected) {
+            Ok(hash) => Ok(hash),
+            // Keep ref errors, such as an unborn branch, for the callers to downcast.
+            Err(RevisionError::ResolveRefError(error)) => Err(error.into()),
+            Err(error) => Err(error.into()),
         }
-
-        Ok(candidates)
     }
 
-    pub fn find_object(&self, name: &str) -> Result<String, anyhow::Error> {
-        let candidates = self.resolve_object(name)?;
-        match candidates.len() {
-            1 => Ok(candidates[0].clone()),
-            0 => Err(anyhow::anyhow!("Not a hash")),
-            _ => Err(anyhow::anyhow!("Ambiguous object name")),
-        }
+    /// Whether the object `hash` is stored in the repository.
+    pub fn has_object(&self, hash: &str) -> bool {
+        hash.len() == 40
+            && self
+                .directory_manager
+                .sha_to_file_path(hash, false)
+                .is_ok_and(|path| path.is_file())
     }
 
     pub fn read_object(&self, name: &str) -> Result<GitObject, ObjectParseError> {
@@ -148,13 +180,45 @@ impl GitRepository {
         serialized.try_into()
     }
 
+    /// Read `name` and peel it until an object of `object_type` is reached. Tags are followed to
+    /// the object they point to and commits are peeled to their tree.
+    pub fn peel_object(
+        &self,
+        name: &str,
+        object_type: Type,
+    ) -> Result<(String, GitObject), ObjectParseError> {
+        let mut hash = self.find_object_as(name, ExpectedType::from(object_type))?;
+        loop {
+            let object = self.read_object(&hash)?;
+            if object.get_type() == object_type {
+                return Ok((hash, object));
+            }
+
+            hash = match &object {
+                GitObject::Tag(tag) => tag.get_value("object"),
+                GitObject::Commit(commit) if object_type == Type::Tree => commit.get_value("tree"),
+                _ => None,
+            }
+            .ok_or_else(|| ObjectParseError::CannotPeel(name.to_string(), object_type))?
+            .clone();
+        }
+    }
+
+    /// Read the tree a tree-ish (a tree, a commit or a tag pointing to one of them) refers to.
+    pub fn read_tree(&self, name: &str) -> Result<Tree, ObjectParseError> {
+        match self.peel_object(name, Type::Tree)?.1 {
+            GitObject::Tree(tree) => Ok(tree),
+            _ => Err(ObjectParseError::CannotPeel(name.to_string(), Type::Tree)),
+        }
+    }
+
     pub fn create_object(
         file_path: &Path,
         object_type: Type,
     ) -> Result<SerializedGitObject, ObjectCreateError> {
         let mut buf_reader = BufReader::new(File::open(file_path)?);
-        let mut buffer = String::new();
-        buf_reader.read_to_string(&mut buffer)?;
+        let mut buffer = Vec::new();
+        buf_reader.read_to_end(&mut buffer)?;
 
         let object = match object_type {
             Type::Commit => todo!(),
@@ -178,17 +242,133 @@ impl GitRepository {
 
         Ok(())
     }
+
+    /// Store `data` as a blob. Returns its hash.
+    pub fn write_blob(&self, data: Vec<u8>) -> Result<String, ObjectCreateError> {
+        let serialized = SerializedGitObject::try_from(GitObject::Blob(Blob { blob: data }))?;
+        self.write_object(&serialized)?;
+        Ok(serialized.hash)
+    }
 }
 
 // Refs methods
 impl GitRepository {
     pub fn resolve_ref(&self, ref_relative_path: &str) -> Result<String, ResolveRefError> {
-        let ref_path = self.directory_manager.dot_git_path.join(ref_relative_path);
-        refs::resolve_ref(&self.directory_manager.dot_git_path, &ref_path)
+        self.ref_backend().resolve_ref(ref_relative_path)
+    }
+
+    /// Point the ref at `ref_relative_path` to `hash`, under its lock. A symbolic ref is
+    /// overwritten rather than followed.
+    pub fn update_ref(&self, ref_relative_path: &str, hash: &str) -> Result<(), ResolveRefError> {
+        let mut transaction = RefTransaction::no_deref();
+        transaction.update(ref_relative_path, hash, None);
+        self.commit_ref_transaction(&transaction)
+    }
+
+    /// Delete the ref at `ref_relative_path`, loose and packed. Deleting a missing ref is not
+    /// an error.
+    pub fn delete_ref(&self, ref_relative_path: &str) -> Result<(), ResolveRefError> {
+        let mut transaction = RefTransaction::no_deref();
+        transaction.delete(ref_relative_path, None);
+        self.commit_ref_transaction(&transaction)
+    }
+
+    /// What `HEAD` points to: a branch, possibly unborn, or a commit.
+    pub fn head(&self) -> Result<Head, ResolveRefError> {
+        match self.ref_backend().read_ref("HEAD")? {
+            Some(RefValue::Symbolic(branch)) => Ok(Head::Branch(branch)),
+            Some(RefValue::Hash(hash))
+                if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+            {
+                Ok(Head::Detached(hash))
+            }
+            Some(RefValue::Hash(content)) => Err(ResolveRefError::InvalidHead(content)),
+            None => Err(ResolveRefError::InvalidHead(String::new())),
+        }
+    }
+
+    /// The branch `HEAD` points to, such as `refs/heads/master`, or `None` if it's detached.
+    /// The branch may not exist yet.
+    pub fn head_branch(&self) -> Result<Option<String>, ResolveRefError> {
+        match self.head()? {
+            Head::Branch(branch) => Ok(Some(branch)),
+            Head::Detached(_) => Ok(None),
+        }
+    }
+
+    /// The commit `HEAD` points to, or `None` before the first commit of the current branch.
+    pub fn head_commit(&self) -> Result<Option<String>, ResolveRefError> {
+        match self.head()? {
+            Head::Branch(branch) => self.current_ref_value(&branch),
+            Head::Detached(hash) => Ok(Some(hash)),
+        }
+    }
+
+    /// The target of the symbolic ref `name`, such as `refs/heads/master` for `HEAD`.
+    pub fn read_symbolic_ref(&self, name: &str) -> Result<String, ResolveRefError> {
+        match self.ref_backend().read_ref(name)? {
+            Some(RefValue::Symbolic(target)) => Ok(target),
+            _ => Err(ResolveRefError::NotASymbolicRef(name.to_string())),
+        }
+    }
+
+    /// Make `name`, such as `HEAD`, a symbolic ref to `target`, which must be a valid ref name
+    /// below `refs/`. The move is recorded in the reflog of `name` with `message` if `target`
+    /// exists.
+    pub fn set_symbolic_ref(
+        &self,
+        name: &str,
+        target: &str,
+        message: &str,
+    ) -> Result<(), ResolveRefError> {
+        let options = RefNameOptions {
+            allow_onelevel: true,
+            ..RefNameOptions::default()
+        };
+        if !ref_name::is_safe_ref_name(name) || ref_name::check_ref_format(name, options).is_none()
+        {
+            return Err(ResolveRefError::BadName(name.to_string()));
+        }
+        if !target.starts_with("refs/") {
+            return Err(ResolveRefError::SymbolicRefOutsideRefs(name.to_string()));
+        }
+        if ref_name::check_ref_format(target, RefNameOptions::default()).is_none() {
+            return Err(ResolveRefError::InvalidSymbolicRefTarget(
+                name.to_string(),
+                target.to_string(),
+            ));
+        }
+        let old = self.current_ref_value(name)?;
+        self.ref_backend().write_symbolic_ref(name, target)?;
+        match self.current_ref_value(name)? {
+            Some(new) => self.log_ref_update(name, old.as_deref(), &new, message, false),
+            None => Ok(()),
+        }
+    }
+
+    /// Make `HEAD` point to the branch `branch`, such as `refs/heads/master`, recording the move
+    /// in the reflog of `HEAD` with `message` if the branch exists.
+    pub fn set_head_to_branch(&self, branch: &str, message: &str) -> Result<(), ResolveRefError> {
+        self.set_symbolic_ref("HEAD", branch, message)
+    }
+
+    /// Move the branch `HEAD` points to, or `HEAD` itself if it's detached, to `hash`, with
+    /// `message` in the reflogs.
+    pub fn update_head(&self, hash: &str, message: &str) -> Result<(), ResolveRefError> {
+        let mut transaction = RefTransaction::new();
+        transaction.set_message(message).update("HEAD", hash, None);
+        self.commit_ref_transaction(&transaction)
+    }
+
+    /// Detach `HEAD` at the commit `hash`, with `message` in its reflog.
+    pub fn detach_head(&self, hash: &str, message: &str) -> Result<(), ResolveRefError> {
+        let mut transaction = RefTransaction::no_deref();
+        transaction.set_message(message).update("HEAD", hash, None);
+        self.commit_ref_transaction(&transaction)
     }
 
     pub fn list_refs(&self) -> Result<Vec<refs::Ref>, ResolveRefError> {
-        self.list_refs_in_absolute(&self.directory_manager.refs_path)
+        self.ref_backend().list_refs("refs")
     }
 
     pub fn list_refs_in(&self, path: &Path) -> Result<Vec<refs::Ref>, ResolveRefError> {
@@ -196,19 +376,99 @@ impl GitRepository {
     }
 
     pub fn list_refs_in_absolute(&self, path: &Path) -> Result<Vec<refs::Ref>, ResolveRefError> {
-        let refs = refs::list_refs(&self.directory_manager.dot_git_path, path)?;
-        refs.into_iter()
-            .map(|ref_item| {
-                Ok(refs::Ref {
-                    hash: ref_item.hash,
-                    path: ref_item
-                        .path
-                        .strip_prefix(&self.directory_manager.dot_git_path)
-                        .context("Failed to strip_prefix")?
-                        .to_path_buf(),
-                })
+        let prefix = path
+            .strip_prefix(&self.directory_manager.common_dir)
+            .context("Failed to strip_prefix")?;
+        self.ref_backend().list_refs(&prefix.to_string_lossy())
+    }
+
+    /// The object the annotated tag `hash` points to once every tag is followed, or `None` if
+    /// `hash` isn't a tag.
+    pub(crate) fn peel_tag(&self, hash: &str) -> Result<Option<String>, ObjectParseError> {
+        let mut peeled = None;
+        while let GitObject::Tag(tag) = self.read_object(peeled.as_deref().unwrap_or(hash))? {
+            let object = tag
+                .get_value("object")
+                .ok_or_else(|| ObjectParseError::CannotPeel(hash.to_string(), Type::Commit))?;
+            peeled = Some(object.clone());
+        }
+        Ok(peeled)
+    }
+
+    /// Move loose refs to `.git/packed-refs`: the tags and the refs already packed, or every
+    /// ref below `refs/` with `all`. Symbolic refs and the refs of a work tree stay loose. With
+    /// `prune`, the files of the refs packed are deleted. With reftable, the tables are merged
+    /// into one instead.
+    pub fn pack_refs(&self, all: bool, prune: bool) -> Result<(), ResolveRefError> {
+        if self.ref_storage() == RefStorage::Reftable {
+            return ReftableBackend::new(&self.directory_manager).compact();
+        }
+        let directory_manager = &self.directory_manager;
+        let mut packed: BTreeMap<String, String> = refs::read_packed_refs(directory_manager)?
+            .into_iter()
+            .map(|packed| (packed.name, packed.hash))
+            .collect();
+
+        let mut packed_loose = vec![];
+        for name in refs::loose_ref_names(directory_manager, "refs")? {
+            let ref_path = directory_manager.ref_path(&name);
+            let packable = all || name.starts_with("refs/tags/") || packed.contains_key(&name);
+            if !packable
+                || DirectoryManager::is_per_work_tree_ref(&name)
+                || refs::read_symbolic_ref(&ref_path)?.is_some()
+            {
+                continue;
+            }
+            let hash = std::fs::read_to_string(&ref_path)?.trim_end().to_string();
+            packed.insert(name.clone(), hash.clone());
+            packed_loose.push((name, hash));
+        }
+
+        let packed = packed
+            .into_iter()
+            .map(|(name, hash)| {
+                let peeled = self.peel_tag(&hash).context("Failed to peel a ref")?;
+                Ok(refs::PackedRef { name, hash, peeled })
             })
-            .collect()
+            .collect::<Result<Vec<_>, ResolveRefError>>()?;
+        refs::write_packed_refs(directory_manager, &packed)?;
+
+        if prune {
+            for (name, hash) in packed_loose {
+                self.prune_loose_ref(&name, &hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the file of the loose ref `name` if it still holds `hash`, then the directories it
+    /// leaves empty.
+    fn prune_loose_ref(&self, name: &str, hash: &str) -> Result<(), ResolveRefError> {
+        let ref_path = self.directory_manager.ref_path(name);
+        match std::fs::read_to_string(&ref_path) {
+            Ok(content) if content.trim_end() == hash => std::fs::remove_file(&ref_path)?,
+            Ok(_) => return Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error.into()),
+        }
+
+        self.remove_empty_ref_directories(name);
+        Ok(())
+    }
+
+    /// Delete the directories that the removal of the loose ref `name` left empty, keeping the
+    /// ones directly below `refs/` such as `refs/heads`.
+    pub(crate) fn remove_empty_ref_directories(&self, name: &str) {
+        let mut directory = name;
+        while let Some((parent, _)) = directory.rsplit_once('/') {
+            if parent.matches('/').count() < 2 {
+                break;
+            }
+            if std::fs::remove_dir(self.directory_manager.ref_path(parent)).is_err() {
+                break;
+            }
+            directory = parent;
+        }
     }
 }
 
@@ -219,13 +479,21 @@ impl GitRepository {
         name: String,
         object: String,
     ) -> Result<(), anyhow::Error> {
+        if !ref_name::is_valid_tag_name(&name) {
+            return Err(ResolveRefError::InvalidTagName(name).into());
+        }
         let object = self.find_object(&object)?;
-        std::fs::write(self.directory_manager.refs_tags_path.join(name), object)?;
+        let mut transaction = RefTransaction::new();
+        transaction.create(&format!("refs/tags/{}", name), &object);
+        self.commit_ref_transaction(&transaction)?;
 
         Ok(())
     }
 
     pub fn create_tag_object(&self, name: String, object: String) -> Result<(), anyhow::Error> {
+        if !ref_name::is_valid_tag_name(&name) {
+            return Err(ResolveRefError::InvalidTagName(name).into());
+        }
         let object = self.find_object(&object)?;
         let kvl = BTreeMap::from([
             ("object".to_string(), object),
@@ -245,10 +513,9 @@ impl GitRepository {
         let serialized = SerializedGitObject::try_from(GitObject::Tag(tag))?;
 
         self.write_object(&serialized)?;
-        std::fs::write(
-            self.directory_manager.refs_tags_path.join(name),
-            serialized.hash,
-        )?;
+        let mut transaction = RefTransaction::new();
+        transaction.create(&format!("refs/tags/{}", name), &serialized.hash);
+        self.commit_ref_transaction(&transaction)?;
         Ok(())
     }
 }
@@ -0,0 +1,449 @@
+//! Where the refs and their logs are stored: loose files and `packed-refs`, or a reftable
+//! stack when `extensions.refStorage` is `reftable`. Both are read through [`RefBackend`].
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    error::repository::ResolveRefError,
+    reftable::{
+        stack::{LockedStack, Stack},
+        LogRecord, RefRecord, RefRecordValue,
+    },
+    DirectoryManager,
+};
+
+use super::{
+    reflog::ReflogEntry,
+    refs::{self, Ref},
+    GitRepository,
+};
+
+/// How many symbolic refs are followed before giving up on a loop.
+pub(crate) const MAX_SYMBOLIC_REF_DEPTH: usize = 5;
+
+/// What a ref holds, without following it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefValue {
+    Hash(String),
+    /// The name of the ref it points to, such as `refs/heads/master` for `HEAD`.
+    Symbolic(String),
+}
+
+/// How the refs of a repository are stored, from `extensions.refStorage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RefStorage {
+    /// A file per ref, and `packed-refs`.
+    #[default]
+    Files,
+    Reftable,
+}
+
+impl std::str::FromStr for RefStorage {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "files" => Ok(Self::Files),
+            "reftable" => Ok(Self::Reftable),
+            _ => Err(anyhow!("unknown ref storage format '{}'", s)),
+        }
+    }
+}
+
+/// Reads and writes of refs and reflogs that differ between ways of storing them. Updates of
+/// ref values go through [`GitRepository::commit_ref_transaction`].
+pub trait RefBackend {
+    /// The value of the ref `name`, `None` if it doesn't exist.
+    fn read_ref(&self, name: &str) -> Result<Option<RefValue>, ResolveRefError>;
+
+    /// The refs below `prefix`, such as `refs` or `refs/tags`, sorted by name.
+    fn read_refs(&self, prefix: &str) -> Result<Vec<(String, RefValue)>, ResolveRefError>;
+
+    /// Resolve the ref `name`, such as `HEAD` or `refs/heads/master`, following symbolic refs.
+    fn resolve_ref(&self, name: &str) -> Result<String, ResolveRefError> {
+        let mut name = name.to_string();
+        for _ in 0..MAX_SYMBOLIC_REF_DEPTH {
+            match self.read_ref(&name)? {
+                Some(RefValue::Hash(hash)) => return Ok(hash),
+                Some(RefValue::Symbolic(target)) => name = target,
+                None => break,
+            }
+        }
+        Err(ResolveRefError::RelativePathIsNotAFile(name))
+    }
+
+    /// The refs below `prefix`, resolved and sorted by name. The path of each ref is its name.
+    fn list_refs(&self, prefix: &str) -> Result<Vec<Ref>, ResolveRefError> {
+        self.read_refs(prefix)?
+            .into_iter()
+            .map(|(name, value)| {
+                let hash = match value {
+                    RefValue::Hash(hash) => hash,
+                    RefValue::Symbolic(_) => self.resolve_ref(&name)?,
+                };
+                Ok(Ref {
+                    hash,
+                    path: PathBuf::from(name),
+                })
+            })
+            .collect()
+    }
+
+    /// Make `name` a symbolic ref to `target`.
+    fn write_symbolic_ref(&self, name: &str, target: &str) -> Result<(), ResolveRefError>;
+
+    /// The reflog of `name`, oldest entry first. A ref without a log has no entries.
+    fn read_reflog(&self, name: &str) -> Result<Vec<ReflogEntry>, ResolveRefError>;
+
+    /// Whether `name` has a reflog.
+    fn has_reflog(&self, name: &str) -> Result<bool, ResolveRefError> {
+        Ok(!self.read_reflog(name)?.is_empty())
+    }
+
+    /// The refs that have a reflog, `HEAD` first and the others sorted by name.
+    fn reflog_names(&self) -> Result<Vec<String>, ResolveRefError>;
+
+    fn append_reflog(&self, name: &str, entry: &ReflogEntry) -> Result<(), ResolveRefError>;
+
+    /// Replace the reflog of `name` with `entries`.
+    fn write_reflog(&self, name: &str, entries: &[ReflogEntry]) -> Result<(), ResolveRefError>;
+
+    fn delete_reflog(&self, name: &str) -> Result<(), ResolveRefError>;
+}
+
+/// Refs stored in a file each below the git directory, or in `packed-refs`, and their logs in
+/// `logs/`.
+pub struct FilesBackend<'a> {
+    pub directory_manager: &'a DirectoryManager,
+}
+
+impl RefBackend for FilesBackend<'_> {
+    fn read_ref(&self, name: &str) -> Result<Option<RefValue>, ResolveRefError> {
+        let path = self.directory_manager.ref_path(name);
+        if path.is_file() {
+            let content = fs::read_to_string(&path)?;
+            let content = content.trim_end();
+            return Ok(Some(match content.strip_prefix("ref: ") {
+                Some(target) => RefValue::Symbolic(target.to_string()),
+                None => RefValue::Hash(content.to_string()),
+            }));
+        }
+        let packed = refs::read_packed_refs(self.directory_manager)?;
+        Ok(packed
+            .binary_search_by(|packed| packed.name.as_str().cmp(name))
+            .ok()
+            .map(|i| RefValue::Hash(packed[i].hash.clone())))
+    }
+
+    fn read_refs(&self, prefix: &str) -> Result<Vec<(String, RefValue)>, ResolveRefError> {
+        let directory_prefix = format!("{}/", prefix);
+        let mut values: BTreeMap<String, RefValue> =
+            refs::read_packed_refs(self.directory_manager)?
+                .into_iter()
+                .filter(|packed| packed.name.starts_with(&directory_prefix))
+                .map(|packed| (packed.name, RefValue::Hash(packed.hash)))
+                .collect();
+        for name in refs::loose_ref_names(self.directory_manager, prefix)? {
+            if let Some(value) = self.read_ref(&name)? {
+                values.insert(name, value);
+            }
+        }
+        Ok(values.into_iter().collect())
+    }
+
+    fn resolve_ref(&self, name: &str) -> Result<String, ResolveRefError> {
+        refs::resolve_ref(self.directory_manager, name)
+    }
+
+    fn list_refs(&self, prefix: &str) -> Result<Vec<Ref>, ResolveRefError> {
+        refs::list_refs(self.directory_manager, prefix)
+    }
+
+    fn write_symbolic_ref(&self, name: &str, target: &str) -> Result<(), ResolveRefError> {
+        Ok(refs::write_symbolic_ref(
+            &self.directory_manager.ref_path(name),
+            target,
+        )?)
+    }
+
+    fn read_reflog(&self, name: &str) -> Result<Vec<ReflogEntry>, ResolveRefError> {
+        let content = match fs::read_to_string(self.directory_manager.reflog_path(name)) {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(content.lines().filter_map(ReflogEntry::parse).collect())
+    }
+
+    fn has_reflog(&self, name: &str) -> Result<bool, ResolveRefError> {
+        Ok(self.directory_manager.reflog_path(name).is_file())
+    }
+
+    fn reflog_names(&self) -> Result<Vec<String>, ResolveRefError> {
+        let mut names = vec![];
+        if self.has_reflog("HEAD")? {
+            names.push("HEAD".to_string());
+        }
+        let logs = self.directory_manager.logs_path.join("refs");
+        let mut ref_names = vec![];
+        refs::collect_loose_ref_names(&logs, "refs", &mut ref_names)?;
+        ref_names.sort();
+        names.extend(ref_names);
+        Ok(names)
+    }
+
+    fn append_reflog(&self, name: &str, entry: &ReflogEntry) -> Result<(), ResolveRefError> {
+        let path = self.directory_manager.reflog_path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", entry)?;
+        Ok(())
+    }
+
+    fn write_reflog(&self, name: &str, entries: &[ReflogEntry]) -> Result<(), ResolveRefError> {
+        let content: String = entries.iter().map(|entry| format!("{}\n", entry)).collect();
+        Ok(fs::write(
+            self.directory_manager.reflog_path(name),
+            content,
+        )?)
+    }
+
+    fn delete_reflog(&self, name: &str) -> Result<(), ResolveRefError> {
+        let path = self.directory_manager.reflog_path(name);
+        match fs::remove_file(&path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+            _ => {}
+        }
+        // Directories left empty would keep the log of a ref named like them from being written.
+        let logs = self.directory_manager.logs_path.join("refs");
+        let mut directory = path.parent();
+        while let Some(parent) = directory.filter(|parent| parent.starts_with(&logs)) {
+            if parent == logs || fs::remove_dir(parent).is_err() {
+                break;
+            }
+            directory = parent.parent();
+        }
+        Ok(())
+    }
+}
+
+/// Refs and their logs stored in reftable stacks: `reftable/` in the common directory for the
+/// shared refs, and in the git directory of a linked work tree for its own refs, such as its
+/// `HEAD`.
+pub struct ReftableBackend {
+    shared: PathBuf,
+    work_tree: PathBuf,
+}
+
+impl ReftableBackend {
+    pub fn new(directory_manager: &DirectoryManager) -> Self {
+        Self {
+            shared: directory_manager.common_dir.join("reftable"),
+            work_tree: directory_manager.dot_git_path.join("reftable"),
+        }
+    }
+
+    /// The directory of the stack holding the ref `name`.
+    pub(crate) fn directory(&self, name: &str) -> &Path {
+        match DirectoryManager::is_per_work_tree_ref(name) {
+            true => &self.work_tree,
+            false => &self.shared,
+        }
+    }
+
+    fn stack(&self, name: &str) -> Result<Stack, ResolveRefError> {
+        Ok(Stack::open(self.directory(name))?)
+    }
+
+    fn lock(&self, name: &str) -> Result<LockedStack, ResolveRefError> {
+        Ok(Stack::lock(self.directory(name))?)
+    }
+
+    /// Merge the tables of each stack into one.
+    pub(crate) fn compact(&self) -> Result<(), ResolveRefError> {
+        Stack::lock(&self.shared)?.compact_all()?;
+        if self.work_tree != self.shared {
+            Stack::lock(&self.work_tree)?.compact_all()?;
+        }
+        Ok(())
+    }
+
+    /// The stacks to read every ref from, with whether each one holds the refs of the work tree
+    /// or the shared ones: a single stack unless this is a linked work tree.
+    fn stacks(&self) -> Result<Vec<(Stack, bool)>, ResolveRefError> {
+        let mut stacks = vec![(Stack::open(&self.shared)?, false)];
+        if self.work_tree != self.shared {
+            stacks.push((Stack::open(&self.work_tree)?, true));
+        }
+        Ok(stacks)
+    }
+
+    /// Whether the ref `name` is read from a stack holding the refs of the work tree if
+    /// `per_work_tree`, from one holding the shared refs otherwise.
+    fn belongs_to(&self, name: &str, per_work_tree: bool) -> bool {
+        self.work_tree == self.shared
+            || DirectoryManager::is_per_work_tree_ref(name) == per_work_tree
+    }
+}
+
+/// The log records replacing the log of `name` at `update_index` with `entries`, oldest first,
+/// given its current `records`: the entries at increasing update indexes, and deletions of the
+/// current records.
+fn replaced_log_records(
+    name: &str,
+    update_index: u64,
+    entries: &[ReflogEntry],
+    records: Vec<LogRecord>,
+) -> Vec<LogRecord> {
+    let deletions = records.into_iter().map(|record| LogRecord {
+        entry: None,
+        ..record
+    });
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| LogRecord {
+            name: name.to_string(),
+            update_index: update_index + i as u64,
+            entry: Some(entry.clone()),
+        })
+        .chain(deletions)
+        .collect()
+}
+
+impl RefBackend for ReftableBackend {
+    fn read_ref(&self, name: &str) -> Result<Option<RefValue>, ResolveRefError> {
+        Ok(match self.stack(name)?.read_ref(name)? {
+            Some(RefRecordValue::Hash(hash) | RefRecordValue::Peeled(hash, _)) => {
+                Some(RefValue::Hash(hash))
+            }
+            Some(RefRecordValue::Symbolic(target)) => Some(RefValue::Symbolic(target)),
+            Some(RefRecordValue::Deletion) | None => None,
+        })
+    }
+
+    fn read_refs(&self, prefix: &str) -> Result<Vec<(String, RefValue)>, ResolveRefError> {
+        let directory_prefix = format!("{}/", prefix);
+        let mut values = BTreeMap::new();
+        for (stack, per_work_tree) in self.stacks()? {
+            for record in stack.ref_records(&directory_prefix)? {
+                if !self.belongs_to(&record.name, per_work_tree) {
+                    continue;
+                }
+                let value = match record.value {
+                    RefRecordValue::Hash(hash) | RefRecordValue::Peeled(hash, _) => {
+                        RefValue::Hash(hash)
+                    }
+                    RefRecordValue::Symbolic(target) => RefValue::Symbolic(target),
+                    RefRecordValue::Deletion => continue,
+                };
+                values.insert(record.name, value);
+            }
+        }
+        Ok(values.into_iter().collect())
+    }
+
+    fn write_symbolic_ref(&self, name: &str, target: &str) -> Result<(), ResolveRefError> {
+        let locked = self.lock(name)?;
+        let record = RefRecord {
+            name: name.to_string(),
+            update_index: locked.stack().next_update_index(),
+            value: RefRecordValue::Symbolic(target.to_string()),
+        };
+        Ok(locked.add(&[record], &[])?)
+    }
+
+    fn read_reflog(&self, name: &str) -> Result<Vec<ReflogEntry>, ResolveRefError> {
+        let records = self.stack(name)?.log_records(Some(name))?;
+        Ok(records
+            .into_iter()
+            .rev()
+            .filter_map(|record| record.entry)
+            .collect())
+    }
+
+    fn reflog_names(&self) -> Result<Vec<String>, ResolveRefError> {
+        let mut has_head = false;
+        let mut names = vec![];
+        for (stack, per_work_tree) in self.stacks()? {
+            for record in stack.log_records(None)? {
+                if !self.belongs_to(&record.name, per_work_tree) {
+                    continue;
+                }
+                match record.name.as_str() {
+                    "HEAD" => has_head = true,
+                    name if name.starts_with("refs/") => names.push(record.name),
+                    _ => {}
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        if has_head {
+            names.insert(0, "HEAD".to_string());
+        }
+        Ok(names)
+    }
+
+    fn append_reflog(&self, name: &str, entry: &ReflogEntry) -> Result<(), ResolveRefError> {
+        let locked = self.lock(name)?;
+        let record = LogRecord {
+            name: name.to_string(),
+            update_index: locked.stack().next_update_index(),
+            entry: Some(entry.clone()),
+        };
+        Ok(locked.add(&[], &[record])?)
+    }
+
+    fn write_reflog(&self, name: &str, entries: &[ReflogEntry]) -> Result<(), ResolveRefError> {
+        let locked = self.lock(name)?;
+        let update_index = locked.stack().next_update_index();
+        let records = locked.stack().log_records(Some(name))?;
+        let records = replaced_log_records(name, update_index, entries, records);
+        Ok(locked.add(&[], &records)?)
+    }
+
+    fn delete_reflog(&self, name: &str) -> Result<(), ResolveRefError> {
+        self.write_reflog(name, &[])
+    }
+}
+
+// Ref backend methods
+impl GitRepository {
+    /// How the refs are stored, from `extensions.refStorage`.
+    pub fn ref_storage(&self) -> RefStorage {
+        match self.config.get("extensions", "refStorage") {
+            Some("reftable") => RefStorage::Reftable,
+            _ => RefStorage::Files,
+        }
+    }
+
+    /// The backend reading and writing the refs of the repository.
+    pub fn ref_backend(&self) -> Box<dyn RefBackend + '_> {
+        self.ref_backend_for(&self.directory_manager)
+    }
+
+    /// The backend reading and writing the refs of the work tree of `directory_manager`, which
+    /// shares its refs with the repository.
+    pub(crate) fn ref_backend_for<'a>(
+        &self,
+        directory_manager: &'a DirectoryManager,
+    ) -> Box<dyn RefBackend + 'a> {
+        match self.ref_storage() {
+            RefStorage::Files => Box::new(FilesBackend { directory_manager }),
+            RefStorage::Reftable => Box::new(ReftableBackend::new(directory_manager)),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::cell::OnceCell;
+
+use crate::{
+    error::RefFormatError,
+    git_object::{Signature, Type},
+    ref_format::{
+        Atom, AtomValue, Person, PersonPart, RefFilter, RefFormat, Segment, SortKey, UpstreamStyle,
+    },
+    GitObject,
+};
+
+use super::{refs::Ref, GitRepository};
+
+/// A ref being formatted, whose objects are read once whatever the number of atoms.
+struct LoadedRef<'a> {
+    name: String,
+    hash: &'a str,
+    object: OnceCell<GitObject>,
+    /// The object the ref points to once every tag is followed, if it's a tag.
+    peeled: OnceCell<Option<(String, GitObject)>>,
+}
+
+impl<'a> LoadedRef<'a> {
+    fn new(ref_item: &'a Ref) -> Self {
+        Self {
+            name: ref_item.path.to_string_lossy().into_owned(),
+            hash: &ref_item.hash,
+            object: OnceCell::new(),
+            peeled: OnceCell::new(),
+        }
+    }
+}
+
+/// The text of the message of a commit or a tag, and its subject: the first paragraph, its
+/// lines joined with spaces.
+fn message_parts(object: &GitObject) -> (String, String, String) {
+    let message = match object {
+        GitObject::Commit(commit) | GitObject::Tag(commit) => {
+            commit.get_value("message").cloned().unwrap_or_default()
+        }
+        _ => String::new(),
+    };
+    let (subject, body) = match message.split_once("\n\n") {
+        Some((subject, body)) => (subject, body.to_string()),
+        None => (message.trim_end_matches('\n'), String::new()),
+    };
+    let subject = subject.lines().collect::<Vec<_>>().join(" ");
+    (subject, body, message)
+}
+
+// Ref formatting methods
+impl GitRepository {
+    fn loaded_object<'r>(&self, loaded: &'r LoadedRef) -> Result<&'r GitObject, RefFormatError> {
+        if let Some(object) = loaded.object.get() {
+            return Ok(object);
+        }
+        let object = self.read_object(loaded.hash)?;
+        Ok(loaded.object.get_or_init(|| object))
+    }
+
+    fn loaded_peeled<'r>(
+        &self,
+        loaded: &'r LoadedRef,
+    ) -> Result<Option<&'r (String, GitObject)>, RefFormatError> {
+        if let Some(peeled) = loaded.peeled.get() {
+            return Ok(peeled.as_ref());
+        }
+        let peeled = match self.peel_tag(loaded.hash)? {
+            Some(hash) => {
+                let object = self.read_object(&hash)?;
+                Some((hash, object))
+            }
+            None => None,
+        };
+        Ok(loaded.peeled.get_or_init(|| peeled).as_ref())
+    }
+
+    /// The value of an atom read from the object `hash` itself.
+    fn object_atom_value(
+        &self,
+        hash: &str,
+        object: &GitObject,
+        atom: &Atom,
+    ) -> Result<AtomValue, RefFormatError> {
+        let text = |text: &str| Ok(AtomValue::Text(text.to_string()));
+        let value = |key: &str| match object {
+            GitObject::Commit(commit) | GitObject::Tag(commit) => {
+                commit.get_value(key).cloned().unwrap_or_default()
+            }
+            _ => String::new(),
+        };
+        match atom {
+            Atom::ObjectName(None) => text(hash),
+            Atom::ObjectName(Some(length)) => text(&self.abbreviate_hash(hash, *length)?),
+            Atom::ObjectType => text(&object.get_type().to_string()),
+            Atom::ObjectSize => {
+                let size = object.serialize().len() as i64;
+                Ok(AtomValue::Number(size, size.to_string()))
+            }
+            Atom::Tree => match object {
+                GitObject::Commit(_) => text(&value("tree")),
+                _ => text(""),
+            },
+            Atom::Parent | Atom::NumParent => {
+                let GitObject::Commit(commit) = object else {
+                    return text("");
+                };
+                let parents = commit.parents();
+                match atom {
+                    Atom::Parent => text(
+                        &parents
+                            .iter()
+                            .map(|parent| parent.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                    _ => Ok(AtomValue::Number(
+                        parents.len() as i64,
+                        parents.len().to_string(),
+                    )),
+                }
+            }
+            Atom::Object | Atom::Type | Atom::Tag => match object {
+                GitObject::Tag(_) => text(&value(match atom {
+                    Atom::Object => "object",
+                    Atom::Type => "type",
+                    _ => "tag",
+                })),
+                _ => text(""),
+            },
+            Atom::Subject => text(&message_parts(object).0),
+            Atom::Body => text(&message_parts(object).1),
+            Atom::Contents => text(&message_parts(object).2),
+            Atom::Person(person, part) => {
+                let key = match (person, object.get_type()) {
+                    (Person::Author, _) => "author",
+                    (Person::Committer, _) => "committer",
+                    (Person::Tagger, _) | (Person::Creator, Type::Tag) => "tagger",
+                    (Person::Creator, _) => "committer",
+                };
+                let line = value(key);
+                let Some(signature) = Signature::parse(&line) else {
+                    return match part {
+                        PersonPart::Date(_) => Ok(AtomValue::Number(0, String::new())),
+                        _ => text(""),
+                    };
+                };
+                match part {
+                    PersonPart::Full => text(&line),
+                    PersonPart::Name => text(&signature.name),
+                    PersonPart::Email { trim: true } => text(&signature.email),
+                    PersonPart::Email { trim: false } => text(&format!("<{}>", signature.email)),
+                    PersonPart::Date(format) => Ok(AtomValue::Number(
+                        signature.time,
+                        signature.format_date(*format),
+                    )),
+                }
+            }
+            Atom::RefName(_) | Atom::Upstream(_) | Atom::Head | Atom::Deref(_) => text(""),
+        }
+    }
+
+    /// `%(upstream)` of the ref `name`, empty unless it's a branch with an upstream.
+    fn upstream_atom_value(
+        &self,
+        name: &str,
+        hash: &str,
+        style: UpstreamStyle,
+    ) -> Result<String, RefFormatError> {
+        let Some(upstream) = name
+            .strip_prefix("refs/heads/")
+            .and_then(|branch| self.upstream(branch))
+        else {
+            return Ok(String::new());
+        };
+        let counts = match style {
+            UpstreamStyle::Name(style) => return Ok(style.apply(&upstream)),
+            _ => match self.current_ref_value(&upstream)? {
+                Some(upstream) => Some(self.ahead_behind(hash, &upstream)?),
+                None => None,
+            },
+        };
+        Ok(match (style, counts) {
+            (UpstreamStyle::Track, None) => "[gone]".to_string(),
+            (_, None) => String::new(),
+            (UpstreamStyle::Track, Some((0, 0))) => String::new(),
+            (UpstreamStyle::Track, Some((ahead, 0))) => format!("[ahead {}]", ahead),
+            (UpstreamStyle::Track, Some((0, behind))) => format!("[behind {}]", behind),
+            (UpstreamStyle::Track, Some((ahead, behind))) => {
+                format!("[ahead {}, behind {}]", ahead, behind)
+            }
+            (_, Some((0, 0))) => "=".to_string(),
+            (_, Some((_, 0))) => ">".to_string(),
+            (_, Some((0, _))) => "<".to_string(),
+            (_, Some(_)) => "<>".to_string(),
+        })
+    }
+
+    fn atom_value(&self, loaded: &LoadedRef, atom: &Atom) -> Result<AtomValue, RefFormatError> {
+        match atom {
+            Atom::RefName(style) => Ok(AtomValue::Text(style.apply(&loaded.name))),
+            Atom::Head => {
+                let is_head = self.head_branch()?.as_ref() == Some(&loaded.name);
+                Ok(AtomValue::Text(if is_head { "*" } else { " " }.to_string()))
+            }
+            Atom::Upstream(style) => Ok(AtomValue::Text(self.upstream_atom_value(
+                &loaded.name,
+                loaded.hash,
+                *style,
+            )?)),
+            Atom::Deref(atom) => match self.loaded_peeled(loaded)? {
+                Some((hash, object)) => self.object_atom_value(hash, object, atom),
+                None => Ok(AtomValue::Text(String::new())),
+            },
+            atom => self.object_atom_value(loaded.hash, self.loaded_object(loaded)?, atom),
+        }
+    }
+
+    /// The value of `atom` for `ref_item`.
+    pub fn ref_atom_value(&self, ref_item: &Ref, atom: &Atom) -> Result<AtomValue, RefFormatError> {
+        self.atom_value(&LoadedRef::new(ref_item), atom)
+    }
+
+    /// Show `ref_item` in `format`.
+    pub fn format_ref(&self, ref_item: &Ref, format: &RefFormat) -> Result<String, RefFormatError> {
+        let loaded = LoadedRef::new(ref_item);
+        let mut formatted = String::new();
+        for segment in format.segments() {
+            match segment {
+                Segment::Literal(literal) => formatted.push_str(literal),
+                Segment::Atom(atom) => {
+                    formatted.push_str(&self.atom_value(&loaded, atom)?.to_string())
+                }
+            }
+        }
+        Ok(formatted)
+    }
+
+    /// Sort `refs` by `keys`, the last key being the primary one as with git, and by name when
+    /// the keys are equal.
+    pub fn sort_refs(&self, refs: Vec<Ref>, keys: &[SortKey]) -> Result<Vec<Ref>, RefFormatError> {
+        let mut decorated = Vec::with_capacity(refs.len());
+        for ref_item in refs {
+            let loaded = LoadedRef::new(&ref_item);
+            let mut values = Vec::with_capacity(keys.len());
+            for key in keys.iter().rev() {
+                values.push(self.atom_value(&loaded, &key.atom)?);
+            }
+            decorated.push((values, ref_item));
+        }
+
+        decorated.sort_by(|(one_values, one), (two_values, two)| {
+            keys.iter()
+                .rev()
+                .zip(one_values.iter().zip(two_values))
+                .map(|(key, (one, two))| {
+                    let ordering = one.compare(two, key.version);
+                    if key.reverse {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| one.path.cmp(&two.path))
+        });
+        Ok(decorated
+            .into_iter()
+            .map(|(_, ref_item)| ref_item)
+            .collect())
+    }
+
+    /// The refs of `refs` that `filter` keeps. Refs that don't point to a commit, through tags
+    /// or not, are left out when filtering on commits.
+    pub fn filter_refs(
+        &self,
+        refs: Vec<Ref>,
+        filter: &RefFilter,
+    ) -> Result<Vec<Ref>, RefFormatError> {
+        let points_at = match &filter.points_at {
+            Some(object) => Some(self.resolve_revision(object)?),
+            None => None,
+        };
+        let merged = match &filter.merged {
+            Some(commit) => Some(self.ancestors(&[self.read_commit(commit)?.0])?),
+            None => None,
+        };
+        let contains = match &filter.contains {
+            Some(commit) => Some(self.read_commit(commit)?.0),
+            None => None,
+        };
+
+        let mut kept = vec![];
+        for ref_item in refs {
+            if !filter.matches_name(&ref_item.path.to_string_lossy()) {
+                continue;
+            }
+            if let Some(object) = &points_at {
+                if ref_item.hash != *object
+                    && self.peel_tag(&ref_item.hash)?.as_ref() != Some(object)
+                {
+                    continue;
+                }
+            }
+            if merged.is_some() || contains.is_some() {
+                let Ok((commit, _)) = self.read_commit(&ref_item.hash) else {
+                    continue;
+                };
+                if merged
+                    .as_ref()
+                    .is_some_and(|reachable| !reachable.contains(&commit))
+                {
+                    continue;
+                }
+                if let Some(contained) = &contains {
+                    if !self.is_ancestor(contained, &commit)? {
+                        continue;
+                    }
+                }
+            }
+            kept.push(ref_item);
+        }
+        Ok(kept)
+    }
+}
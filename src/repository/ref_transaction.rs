@@ -0,0 +1,522 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diff::NULL_HASH,
+    error::repository::ResolveRefError,
+    ref_name::{self, RefNameOptions},
+    reftable::{stack::Stack, LogRecord, RefRecord, RefRecordValue},
+};
+
+use super::{
+    ref_backend::{RefStorage, RefValue, ReftableBackend, MAX_SYMBOLIC_REF_DEPTH},
+    refs, GitRepository,
+};
+
+/// What a [`RefUpdate`] does to its ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefChange {
+    /// Point the ref to the object with this hash.
+    Update(String),
+    Delete,
+    /// Leave the ref as it is, only checking its old value.
+    Verify,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefUpdate {
+    pub name: String,
+    pub change: RefChange,
+    /// The value the ref must have for the transaction to go through, [`NULL_HASH`] if it must
+    /// not exist, `None` if any value will do.
+    pub old: Option<String>,
+}
+
+/// Updates of several refs, made all together or not at all by
+/// [`GitRepository::commit_ref_transaction`].
+#[derive(Debug, Clone, Default)]
+pub struct RefTransaction {
+    updates: Vec<RefUpdate>,
+    /// Change symbolic refs themselves rather than the refs they point to.
+    no_deref: bool,
+    /// Why the refs are changed, recorded in their reflog.
+    message: String,
+    /// Record the updates in the reflogs even if `core.logAllRefUpdates` wouldn't.
+    force_reflog: bool,
+}
+
+impl RefTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A transaction that overwrites symbolic refs rather than following them.
+    pub fn no_deref() -> Self {
+        Self {
+            no_deref: true,
+            ..Self::default()
+        }
+    }
+
+    /// Record `message` in the reflog of the refs updated.
+    pub fn set_message(&mut self, message: &str) -> &mut Self {
+        self.message = message.to_string();
+        self
+    }
+
+    /// Record the updates in the reflogs of the refs, even those that `core.logAllRefUpdates`
+    /// doesn't log.
+    pub fn force_reflog(&mut self) -> &mut Self {
+        self.force_reflog = true;
+        self
+    }
+
+    pub fn updates(&self) -> &[RefUpdate] {
+        &self.updates
+    }
+
+    fn push(&mut self, name: &str, change: RefChange, old: Option<&str>) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            change,
+            old: old.map(|old| old.to_string()),
+        });
+        self
+    }
+
+    /// Point `name` to `new`, if it's at `old` when given.
+    pub fn update(&mut self, name: &str, new: &str, old: Option<&str>) -> &mut Self {
+        self.push(name, RefChange::Update(new.to_string()), old)
+    }
+
+    /// Create `name` pointing to `new`, which fails if it exists.
+    pub fn create(&mut self, name: &str, new: &str) -> &mut Self {
+        self.push(name, RefChange::Update(new.to_string()), Some(NULL_HASH))
+    }
+
+    /// Delete `name`, if it's at `old` when given.
+    pub fn delete(&mut self, name: &str, old: Option<&str>) -> &mut Self {
+        self.push(name, RefChange::Delete, old)
+    }
+
+    /// Check that `name` is at `old`, or doesn't exist if `old` isn't given.
+    pub fn verify(&mut self, name: &str, old: Option<&str>) -> &mut Self {
+        self.push(name, RefChange::Verify, Some(old.unwrap_or(NULL_HASH)))
+    }
+}
+
+/// Remove `directory` if it holds nothing but empty directories, such as the ones a failed
+/// ref update may leave where a ref is to be written.
+fn remove_empty_directories(directory: &Path) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_empty_directories(&entry.path())?;
+        }
+    }
+    fs::remove_dir(directory)
+}
+
+/// Fail if `update` would write a ref whose name breaks git's rules, or touch a file outside
+/// of the refs. Deleting or checking a ref only needs a safe name, so that badly named refs can
+/// be removed.
+fn check_update_name(update: &RefUpdate) -> Result<(), ResolveRefError> {
+    let valid = ref_name::is_safe_ref_name(&update.name)
+        && match update.change {
+            RefChange::Update(_) => {
+                let options = RefNameOptions {
+                    allow_onelevel: true,
+                    ..RefNameOptions::default()
+                };
+                ref_name::check_ref_format(&update.name, options).is_some()
+            }
+            RefChange::Delete | RefChange::Verify => true,
+        };
+    match valid {
+        true => Ok(()),
+        false => Err(ResolveRefError::BadName(update.name.clone())),
+    }
+}
+
+/// The refs among `names`, the refs the updates of `transaction` change, that it deletes.
+fn deleted_names<'a>(transaction: &RefTransaction, names: &'a [String]) -> Vec<&'a str> {
+    transaction
+        .updates
+        .iter()
+        .zip(names)
+        .filter(|(update, _)| update.change == RefChange::Delete)
+        .map(|(_, name)| name.as_str())
+        .collect()
+}
+
+/// A ref locked by a transaction, whose new value, if any, is in its lock file.
+struct LockedRef {
+    name: String,
+    path: PathBuf,
+    lock_path: PathBuf,
+    change: RefChange,
+    /// The value of the ref when it was locked.
+    old: Option<String>,
+}
+
+// Ref transaction methods
+impl GitRepository {
+    /// The ref that changing `name` changes: the one it points to in the end if it's a
+    /// symbolic ref, unless `no_deref`.
+    fn ref_to_change(&self, name: &str, no_deref: bool) -> Result<String, ResolveRefError> {
+        let mut name = name.to_string();
+        if no_deref {
+            return Ok(name);
+        }
+        let backend = self.ref_backend();
+        for _ in 0..MAX_SYMBOLIC_REF_DEPTH {
+            match backend.read_ref(&name)? {
+                Some(RefValue::Symbolic(target)) => name = target,
+                _ => return Ok(name),
+            }
+        }
+        Err(ResolveRefError::Missing(name))
+    }
+
+    /// The value of the ref `name`, or `None` if it doesn't exist.
+    pub(crate) fn current_ref_value(&self, name: &str) -> Result<Option<String>, ResolveRefError> {
+        match self.resolve_ref(name) {
+            Ok(hash) => Ok(Some(hash)),
+            Err(ResolveRefError::RelativePathIsNotAFile(_)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Fail if creating the ref `name` would need a ref to be a directory, or a directory to
+    /// be a ref, such as `refs/heads/a` and `refs/heads/a/b`. The refs in `deleted`, which the
+    /// same transaction deletes, don't conflict.
+    fn check_ref_name_conflicts(
+        &self,
+        name: &str,
+        deleted: &[&str],
+    ) -> Result<(), ResolveRefError> {
+        if !name.starts_with("refs/") {
+            return Ok(());
+        }
+        // Only the refs above and below `name` are looked up, so that creating a ref doesn't
+        // read every ref of a large reftable stack.
+        let backend = self.ref_backend();
+        for (end, _) in name.match_indices('/').skip(1) {
+            let ancestor = &name[..end];
+            if !deleted.contains(&ancestor) && backend.read_ref(ancestor)?.is_some() {
+                return Err(ResolveRefError::NameConflict(
+                    ancestor.to_string(),
+                    name.to_string(),
+                ));
+            }
+        }
+        let descendant = backend
+            .read_refs(name)?
+            .into_iter()
+            .find(|(descendant, _)| !deleted.contains(&descendant.as_str()));
+        match descendant {
+            Some((descendant, _)) => {
+                Err(ResolveRefError::NameConflict(descendant, name.to_string()))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Take the lock of the ref `name`, check its old value and write its new one to the lock
+    /// file. The lock is released if that fails.
+    fn lock_ref(
+        &self,
+        name: String,
+        update: &RefUpdate,
+        deleted: &[&str],
+    ) -> Result<LockedRef, ResolveRefError> {
+        let path = self.directory_manager.ref_path(&name);
+        let mut lock_path = OsString::from(path.as_os_str());
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let current = self.current_ref_value(&name)?;
+        if current.is_none() && update.change != RefChange::Delete {
+            self.check_ref_name_conflicts(&name, deleted)?;
+        }
+        if path.is_dir() {
+            remove_empty_directories(&path)?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut lock = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(lock) => lock,
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                return Err(ResolveRefError::Locked(
+                    name,
+                    lock_path.display().to_string(),
+                ))
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let mut locked = LockedRef {
+            name,
+            path,
+            lock_path,
+            change: update.change.clone(),
+            old: None,
+        };
+        match self.check_locked_ref(&locked, update, &mut lock) {
+            Ok(old) => {
+                locked.old = old;
+                Ok(locked)
+            }
+            Err(error) => {
+                let _ = fs::remove_file(&locked.lock_path);
+                Err(error)
+            }
+        }
+    }
+
+    /// Check that the ref `name`, locked so that nobody else can change it, has the old value
+    /// `update` expects, and that its new value is an object. Returns its current value.
+    fn check_ref_update(
+        &self,
+        name: &str,
+        update: &RefUpdate,
+    ) -> Result<Option<String>, ResolveRefError> {
+        let current = self.current_ref_value(name)?;
+        match (update.old.as_deref(), current.clone()) {
+            (Some(NULL_HASH), Some(_)) => {
+                return Err(ResolveRefError::AlreadyExists(name.to_string()))
+            }
+            (Some(NULL_HASH), None) | (None, _) => {}
+            (Some(_), None) => return Err(ResolveRefError::Missing(name.to_string())),
+            (Some(old), Some(current)) if old != current => {
+                return Err(ResolveRefError::OldValueMismatch(
+                    name.to_string(),
+                    current,
+                    old.to_string(),
+                ))
+            }
+            (Some(_), Some(_)) => {}
+        }
+
+        if let RefChange::Update(new) = &update.change {
+            if !self.has_object(new) {
+                return Err(ResolveRefError::MissingObject(
+                    name.to_string(),
+                    new.clone(),
+                ));
+            }
+        }
+        Ok(current)
+    }
+
+    /// Check the old value of a ref now that nobody else can change it, and write its new value
+    /// to its lock file. Returns the old value.
+    fn check_locked_ref(
+        &self,
+        locked: &LockedRef,
+        update: &RefUpdate,
+        lock: &mut fs::File,
+    ) -> Result<Option<String>, ResolveRefError> {
+        let current = self.check_ref_update(&locked.name, update)?;
+        if let RefChange::Update(new) = &update.change {
+            writeln!(lock, "{}", new)?;
+        }
+        Ok(current)
+    }
+
+    /// Make the changes of the locked refs: packed refs deleted first, then the loose ones
+    /// replaced by their lock file or deleted, and finally the reflogs written.
+    fn apply_locked_refs(
+        &self,
+        locked: &[LockedRef],
+        transaction: &RefTransaction,
+    ) -> Result<(), ResolveRefError> {
+        let mut packed = refs::read_packed_refs(&self.directory_manager)?;
+        let count = packed.len();
+        packed.retain(|packed| {
+            !locked
+                .iter()
+                .any(|locked| locked.change == RefChange::Delete && locked.name == packed.name)
+        });
+        if packed.len() != count {
+            refs::write_packed_refs(&self.directory_manager, &packed)?;
+        }
+
+        for locked in locked {
+            match &locked.change {
+                RefChange::Update(_) => fs::rename(&locked.lock_path, &locked.path)?,
+                RefChange::Delete => {
+                    fs::remove_file(&locked.lock_path)?;
+                    match fs::remove_file(&locked.path) {
+                        Err(error) if error.kind() != ErrorKind::NotFound => {
+                            return Err(error.into())
+                        }
+                        _ => {}
+                    }
+                    self.remove_empty_ref_directories(&locked.name);
+                }
+                RefChange::Verify => {}
+            }
+        }
+
+        // Moving the branch `HEAD` points to moves `HEAD` as well.
+        let head_branch = match locked.iter().any(|locked| locked.name == "HEAD") {
+            true => None,
+            false => self.head_branch()?,
+        };
+        for locked in locked {
+            let old = locked.old.as_deref();
+            match &locked.change {
+                RefChange::Update(new) => {
+                    let (message, force) = (&transaction.message, transaction.force_reflog);
+                    self.log_ref_update(&locked.name, old, new, message, force)?;
+                    if head_branch.as_ref() == Some(&locked.name) {
+                        self.log_ref_update("HEAD", old, new, message, force)?;
+                    }
+                }
+                RefChange::Delete => self.delete_reflog(&locked.name)?,
+                RefChange::Verify => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Make every update of `transaction` to reftable stacks, locked while the old values are
+    /// checked. The updates of each stack go in a new table of their own, with their reflog
+    /// entries.
+    fn commit_reftable_transaction(
+        &self,
+        transaction: &RefTransaction,
+        names: &[String],
+    ) -> Result<(), ResolveRefError> {
+        let backend = ReftableBackend::new(&self.directory_manager);
+        let deleted = deleted_names(transaction, names);
+        // The stack of `HEAD` is locked as well, for its reflog.
+        let mut stacks = vec![];
+        for name in names.iter().map(String::as_str).chain(["HEAD"]) {
+            let directory = backend.directory(name);
+            if !stacks.iter().any(|(path, _)| path == directory) {
+                stacks.push((directory.to_path_buf(), Stack::lock(directory)?));
+            }
+        }
+        let stack = |name: &str| {
+            let directory = backend.directory(name);
+            let (_, locked) = stacks.iter().find(|(path, _)| path == directory).unwrap();
+            locked.stack()
+        };
+
+        // Moving the branch `HEAD` points to moves `HEAD` as well.
+        let head_branch = match names.iter().any(|name| name == "HEAD") {
+            true => None,
+            false => self.head_branch()?,
+        };
+        let (message, force) = (&transaction.message, transaction.force_reflog);
+        let mut refs = vec![];
+        let mut logs = vec![];
+        for (update, name) in transaction.updates.iter().zip(names) {
+            let old = self.check_ref_update(name, update)?;
+            let value = match &update.change {
+                RefChange::Update(new) => {
+                    if old.is_none() {
+                        self.check_ref_name_conflicts(name, &deleted)?;
+                    }
+                    let old = old.as_deref();
+                    let mut logged = vec![name.as_str()];
+                    if head_branch.as_ref() == Some(name) {
+                        logged.push("HEAD");
+                    }
+                    for logged in logged {
+                        if let Some(entry) =
+                            self.ref_update_entry(logged, old, new, message, force)?
+                        {
+                            logs.push(LogRecord {
+                                name: logged.to_string(),
+                                update_index: stack(logged).next_update_index(),
+                                entry: Some(entry),
+                            });
+                        }
+                    }
+                    RefRecordValue::Hash(new.clone())
+                }
+                RefChange::Delete => {
+                    let records = stack(name).log_records(Some(name))?;
+                    logs.extend(records.into_iter().map(|record| LogRecord {
+                        entry: None,
+                        ..record
+                    }));
+                    RefRecordValue::Deletion
+                }
+                RefChange::Verify => continue,
+            };
+            refs.push(RefRecord {
+                name: name.clone(),
+                update_index: stack(name).next_update_index(),
+                value,
+            });
+        }
+
+        for (directory, locked) in stacks {
+            let in_stack = |name: &str| backend.directory(name) == directory;
+            let refs: Vec<RefRecord> = refs
+                .iter()
+                .filter(|record| in_stack(&record.name))
+                .cloned()
+                .collect();
+            let logs: Vec<LogRecord> = logs
+                .iter()
+                .filter(|record| in_stack(&record.name))
+                .cloned()
+                .collect();
+            locked.add(&refs, &logs)?;
+        }
+        Ok(())
+    }
+
+    /// Make every update of `transaction`, or none of them if one fails. Each ref changed is
+    /// locked with a `<ref>.lock` file first, or its whole reftable stack, and its old value
+    /// checked while it's locked.
+    pub fn commit_ref_transaction(
+        &self,
+        transaction: &RefTransaction,
+    ) -> Result<(), ResolveRefError> {
+        let mut names = Vec::with_capacity(transaction.updates.len());
+        for update in &transaction.updates {
+            check_update_name(update)?;
+            let name = self.ref_to_change(&update.name, transaction.no_deref)?;
+            if names.contains(&name) {
+                return Err(ResolveRefError::DuplicateUpdate(name));
+            }
+            names.push(name);
+        }
+        if self.ref_storage() == RefStorage::Reftable {
+            return self.commit_reftable_transaction(transaction, &names);
+        }
+        let deleted = deleted_names(transaction, &names);
+        let mut locked = vec![];
+        let mut result = Ok(());
+        for (update, name) in transaction.updates.iter().zip(names.iter().cloned()) {
+            match self.lock_ref(name, update, &deleted) {
+                Ok(locked_ref) => locked.push(locked_ref),
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+        if result.is_ok() {
+            result = self.apply_locked_refs(&locked, transaction);
+        }
+        for locked in &locked {
+            let _ = fs::remove_file(&locked.lock_path);
+        }
+        result
+    }
+}
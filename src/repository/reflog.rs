@@ -0,0 +1,286 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    diff::NULL_HASH,
+    error::{repository::ResolveRefError, CommitError},
+    git_object::Signature,
+};
+
+use super::GitRepository;
+
+/// How long reflog entries are kept when `gc.reflogExpire` isn't set.
+const DEFAULT_EXPIRE: &str = "90.days.ago";
+
+/// How long reflog entries no longer reachable from their ref are kept when
+/// `gc.reflogExpireUnreachable` isn't set.
+const DEFAULT_EXPIRE_UNREACHABLE: &str = "30.days.ago";
+
+/// Parse an expiry date relative to `now`: `<n>.<unit>.ago` or `<n> <unit> ago`, with units
+/// from seconds to years, a time stamp, `never` or `now`. Returns the time before which entries
+/// expire, which for `now` and `all` is after every entry and for `never` and `false` before
+/// every entry.
+pub fn parse_expiry_date(date: &str, now: i64) -> Option<i64> {
+    match date {
+        "never" | "false" => return Some(i64::MIN),
+        "now" | "all" => return Some(i64::MAX),
+        _ => {}
+    }
+    if let Ok(time) = date.parse() {
+        return Some(time);
+    }
+
+    let words: Vec<&str> = date
+        .split(|c: char| c == '.' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let [count, unit, "ago"] = words[..] else {
+        return None;
+    };
+    let count: i64 = count.parse().ok()?;
+    let seconds = match unit.trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(now - count * seconds)
+}
+
+/// When reflog entries expire, as time stamps before which they do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReflogExpiry {
+    pub expire: i64,
+    /// When entries whose commit isn't reachable from the ref anymore expire.
+    pub expire_unreachable: i64,
+}
+
+/// One update of a ref, as recorded in its reflog, such as `.git/logs/<ref>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub old: String,
+    pub new: String,
+    pub committer: Signature,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    /// Parse a line of the form `<old> <new> <committer>\t<message>`, `None` if it's corrupt.
+    pub fn parse(line: &str) -> Option<Self> {
+        let is_hash = |hash: &str| hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit());
+        let (old, rest) = line.split_once(' ').filter(|(old, _)| is_hash(old))?;
+        let (new, rest) = rest.split_once(' ').filter(|(new, _)| is_hash(new))?;
+        let (committer, message) = rest.split_once('\t').unwrap_or((rest, ""));
+        Some(Self {
+            old: old.to_string(),
+            new: new.to_string(),
+            committer: Signature::parse(committer)?,
+            message: message.to_string(),
+        })
+    }
+}
+
+impl Display for ReflogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.old, self.new, self.committer)?;
+        if !self.message.is_empty() {
+            write!(f, "\t{}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+// Reflog methods
+impl GitRepository {
+    /// The reflog of `ref_name`, such as `refs/stash`, oldest entry first. A ref without a log
+    /// has no entries.
+    pub fn read_reflog(&self, ref_name: &str) -> Result<Vec<ReflogEntry>, ResolveRefError> {
+        self.ref_backend().read_reflog(ref_name)
+    }
+
+    pub fn append_reflog(
+        &self,
+        ref_name: &str,
+        entry: &ReflogEntry,
+    ) -> Result<(), ResolveRefError> {
+        self.ref_backend().append_reflog(ref_name, entry)
+    }
+
+    /// Replace the reflog of `ref_name` with `entries`.
+    pub fn write_reflog(
+        &self,
+        ref_name: &str,
+        entries: &[ReflogEntry],
+    ) -> Result<(), ResolveRefError> {
+        self.ref_backend().write_reflog(ref_name, entries)
+    }
+
+    /// The refs that have a reflog, `HEAD` first and the others sorted by name.
+    pub fn reflog_names(&self) -> Result<Vec<String>, ResolveRefError> {
+        self.ref_backend().reflog_names()
+    }
+
+    /// Whether updates of `ref_name` are recorded in its reflog. Every ref below `refs/` and
+    /// `HEAD` are if `core.logAllRefUpdates` is unset or `always`, only the branches,
+    /// remote-tracking branches, notes and `HEAD` if it's `true`. Otherwise, or for pseudo refs
+    /// such as `ORIG_HEAD`, only the refs that already have a reflog are.
+    fn logs_updates(&self, ref_name: &str) -> Result<bool, ResolveRefError> {
+        if ref_name != "HEAD" && !ref_name.starts_with("refs/") {
+            return Ok(false);
+        }
+        if self.ref_backend().has_reflog(ref_name)? {
+            return Ok(true);
+        }
+        Ok(match self.config.get_bool("core", "logAllRefUpdates") {
+            None => true,
+            Some(true) => {
+                ref_name == "HEAD"
+                    || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                        .iter()
+                        .any(|prefix| ref_name.starts_with(prefix))
+            }
+            Some(false) => false,
+        })
+    }
+
+    /// The identity recorded in reflog entries: the committer, or the user of the system if no
+    /// committer identity is configured, as a ref update shouldn't fail for lack of one.
+    fn reflog_signature(&self) -> Result<Signature, ResolveRefError> {
+        match self.signature("COMMITTER") {
+            Ok(signature) => Ok(signature),
+            Err(CommitError::IdentityUnknown(_)) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(anyhow::Error::from)?;
+                Ok(Signature {
+                    name: std::env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
+                    email: String::new(),
+                    time: now.as_secs() as i64,
+                    offset: "+0000".to_string(),
+                })
+            }
+            Err(error) => Err(anyhow::Error::from(error).into()),
+        }
+    }
+
+    /// The entry recording that `ref_name` moved from `old`, `None` if it didn't exist, to
+    /// `new`, or `None` unless its updates are logged or `force`.
+    pub(crate) fn ref_update_entry(
+        &self,
+        ref_name: &str,
+        old: Option<&str>,
+        new: &str,
+        message: &str,
+        force: bool,
+    ) -> Result<Option<ReflogEntry>, ResolveRefError> {
+        if !force && !self.logs_updates(ref_name)? {
+            return Ok(None);
+        }
+        Ok(Some(ReflogEntry {
+            old: old.unwrap_or(NULL_HASH).to_string(),
+            new: new.to_string(),
+            committer: self.reflog_signature()?,
+            message: message.lines().next().unwrap_or_default().to_string(),
+        }))
+    }
+
+    /// Record that `ref_name` moved from `old`, `None` if it didn't exist, to `new`, if its
+    /// updates are logged or `force`.
+    pub(crate) fn log_ref_update(
+        &self,
+        ref_name: &str,
+        old: Option<&str>,
+        new: &str,
+        message: &str,
+        force: bool,
+    ) -> Result<(), ResolveRefError> {
+        match self.ref_update_entry(ref_name, old, new, message, force)? {
+            Some(entry) => self.append_reflog(ref_name, &entry),
+            None => Ok(()),
+        }
+    }
+
+    /// When reflog entries expire according to `gc.reflogExpire` and
+    /// `gc.reflogExpireUnreachable`, 90 and 30 days ago from `now` by default.
+    pub fn reflog_expiry(&self, now: i64) -> Result<ReflogExpiry, ResolveRefError> {
+        let date = |key: &str, default: &str| {
+            let date = self.config.get("gc", key).unwrap_or(default);
+            parse_expiry_date(date, now)
+                .ok_or_else(|| ResolveRefError::InvalidExpiryDate(date.to_string()))
+        };
+        Ok(ReflogExpiry {
+            expire: date("reflogExpire", DEFAULT_EXPIRE)?,
+            expire_unreachable: date("reflogExpireUnreachable", DEFAULT_EXPIRE_UNREACHABLE)?,
+        })
+    }
+
+    /// Remove the entries of the reflog of `ref_name` that expired according to `expiry`.
+    /// Returns the entries removed, or that would be with `dry_run`.
+    pub fn expire_reflog(
+        &self,
+        ref_name: &str,
+        expiry: &ReflogExpiry,
+        dry_run: bool,
+    ) -> Result<Vec<ReflogEntry>, ResolveRefError> {
+        let tip = self.resolve_ref(ref_name).ok();
+        // The commits reachable from the ref, only computed if an entry needs them. A tip that
+        // isn't a commit only reaches itself.
+        let mut reachable: Option<HashSet<String>> = None;
+        let mut reaches = |hash: &str| {
+            reachable
+                .get_or_insert_with(|| match &tip {
+                    Some(tip) => self
+                        .ancestors(std::slice::from_ref(tip))
+                        .unwrap_or_else(|_| HashSet::from([tip.clone()])),
+                    None => HashSet::new(),
+                })
+                .contains(hash)
+        };
+
+        // An entry is unreachable if the ref moved from or to a commit it no longer reaches, the
+        // null hash of a ref's creation counting as reachable.
+        let mut unreachable = |entry: &ReflogEntry| {
+            !reaches(&entry.new) || (entry.old != NULL_HASH && !reaches(&entry.old))
+        };
+        let (mut kept, mut expired) = (vec![], vec![]);
+        for entry in self.read_reflog(ref_name)? {
+            let time = entry.committer.time;
+            match time < expiry.expire || (time < expiry.expire_unreachable && unreachable(&entry))
+            {
+                true => expired.push(entry),
+                false => kept.push(entry),
+            }
+        }
+        if !dry_run && !expired.is_empty() {
+            self.write_reflog(ref_name, &kept)?;
+        }
+        Ok(expired)
+    }
+
+    /// Remove the entry of the reflog of `ref_name` at `position`, 0 being the latest one as in
+    /// `<ref>@{0}`. Returns the entry removed.
+    pub fn delete_reflog_entry(
+        &self,
+        ref_name: &str,
+        position: usize,
+    ) -> Result<ReflogEntry, ResolveRefError> {
+        let mut entries = self.read_reflog(ref_name)?;
+        let index = entries.len().checked_sub(position + 1).ok_or_else(|| {
+            ResolveRefError::MissingReflogEntry(format!("{}@{{{}}}", ref_name, position))
+        })?;
+        let removed = entries.remove(index);
+        self.write_reflog(ref_name, &entries)?;
+        Ok(removed)
+    }
+
+    pub fn delete_reflog(&self, ref_name: &str) -> Result<(), ResolveRefError> {
+        self.ref_backend().delete_reflog(ref_name)
+    }
+}
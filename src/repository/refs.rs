@@ -1,10 +1,12 @@
 use std::{
+    collections::BTreeMap,
     fmt::Display,
     fs,
+    io::{ErrorKind, Write},
     path::{Path, PathBuf},
 };
 
-use crate::error::repository::ResolveRefError;
+use crate::{error::repository::ResolveRefError, DirectoryManager};
 
 #[derive(Debug)]
 pub struct Ref {
@@ -18,45 +20,262 @@ impl Display for Ref {
     }
 }
 
-pub fn resolve_ref(dot_git_path: &Path, ref_path: &Path) -> Result<String, ResolveRefError> {
-    if ref_path.is_file() == false {
-        return Err(ResolveRefError::RelativePathIsNotAFile(format!(
+/// What `HEAD` points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// A branch, such as `refs/heads/master`, which doesn't exist yet before its first commit.
+    Branch(String),
+    /// A commit, `HEAD` being detached.
+    Detached(String),
+}
+
+/// A ref stored in `.git/packed-refs` rather than in a file of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub hash: String,
+    /// The object an annotated tag points to once every tag is followed, from the `^` line after
+    /// the ref.
+    pub peeled: Option<String>,
+}
+
+/// The first line of the packed-refs files written: every annotated tag has its peeled line and
+/// the refs are sorted by name.
+const PACKED_REFS_HEADER: &str = "# pack-refs with: peeled fully-peeled sorted ";
+
+/// Parse the content of a packed-refs file. The refs are returned sorted by name, whether the
+/// file claims to be sorted or not.
+pub fn parse_packed_refs(content: &str) -> Result<Vec<PackedRef>, ResolveRefError> {
+    let mut refs: Vec<PackedRef> = vec![];
+    for line in content.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some(peeled) = line.strip_prefix('^') {
+            match refs.last_mut() {
+                Some(last) if last.peeled.is_none() => last.peeled = Some(peeled.to_string()),
+                _ => return Err(ResolveRefError::InvalidPackedRefs(line.to_string())),
+            }
+            continue;
+        }
+        match line.split_once(' ') {
+            Some((hash, name)) if hash.len() == 40 && !name.is_empty() => refs.push(PackedRef {
+                name: name.to_string(),
+                hash: hash.to_string(),
+                peeled: None,
+            }),
+            _ => return Err(ResolveRefError::InvalidPackedRefs(line.to_string())),
+        }
+    }
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(refs)
+}
+
+/// The refs of `.git/packed-refs`, sorted by name. There are none if the file is missing.
+pub fn read_packed_refs(
+    directory_manager: &DirectoryManager,
+) -> Result<Vec<PackedRef>, ResolveRefError> {
+    match fs::read_to_string(&directory_manager.packed_refs_file) {
+        Ok(content) => parse_packed_refs(&content),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Replace `.git/packed-refs` with `refs`, which must be sorted by name. The new content is
+/// written to `packed-refs.lock` first, which fails if another process holds it, and then
+/// renamed over the file.
+pub fn write_packed_refs(
+    directory_manager: &DirectoryManager,
+    refs: &[PackedRef],
+) -> Result<(), std::io::Error> {
+    let path = &directory_manager.packed_refs_file;
+    let lock_path = path.with_extension("lock");
+    let mut lock = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock_path)
+        .map_err(|error| match error.kind() {
+            ErrorKind::AlreadyExists => std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("Unable to create '{}': File exists.", lock_path.display()),
+            ),
+            _ => error,
+        })?;
+
+    let mut content = format!("{}\n", PACKED_REFS_HEADER);
+    for packed in refs {
+        content.push_str(&format!("{} {}\n", packed.hash, packed.name));
+        if let Some(peeled) = &packed.peeled {
+            content.push_str(&format!("^{}\n", peeled));
+        }
+    }
+    let written = lock
+        .write_all(content.as_bytes())
+        .and_then(|_| fs::rename(&lock_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&lock_path);
+    }
+    written
+}
+
+/// Resolve the ref `name`, such as `HEAD` or `refs/heads/master`, following symbolic refs. A
+/// loose ref, stored in a file of its own, wins over a packed one.
+pub fn resolve_ref(
+    directory_manager: &DirectoryManager,
+    name: &str,
+) -> Result<String, ResolveRefError> {
+    let ref_path = directory_manager.ref_path(name);
+    if ref_path.is_file() {
+        let ref_value = fs::read_to_string(&ref_path)?;
+        let ref_value = ref_value.trim_end();
+        if let Some(target) = ref_value.strip_prefix("ref: ") {
+            return resolve_ref(directory_manager, target);
+        }
+        return Ok(ref_value.to_string());
+    }
+
+    let packed = read_packed_refs(directory_manager)?;
+    match packed.binary_search_by(|packed| packed.name.as_str().cmp(name)) {
+        Ok(i) => Ok(packed[i].hash.clone()),
+        Err(_) => Err(ResolveRefError::RelativePathIsNotAFile(format!(
             "{}",
             ref_path.display()
-        )));
+        ))),
     }
+}
 
-    let ref_value = fs::read_to_string(ref_path)?;
-    let ref_value = ref_value.trim_end();
+/// Add the names of the loose refs in `directory`, whose refs are named `<prefix>/<file>`, to
+/// `names`. Lock files of refs being written are skipped.
+pub(crate) fn collect_loose_ref_names(
+    directory: &Path,
+    prefix: &str,
+    names: &mut Vec<String>,
+) -> Result<(), std::io::Error> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let name = format!("{}/{}", prefix, file_name);
+        if entry.file_type()?.is_dir() {
+            collect_loose_ref_names(&entry.path(), &name, names)?;
+        } else if !file_name.ends_with(".lock") {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
 
-    if ref_value.starts_with("ref: ") {
-        return resolve_ref(
-            dot_git_path,
-            &dot_git_path.join(&PathBuf::from(&ref_value[5..])),
-        );
-        // Skip ref:
+/// The names of the loose refs below `prefix`, such as `refs` or `refs/tags`.
+pub fn loose_ref_names(
+    directory_manager: &DirectoryManager,
+    prefix: &str,
+) -> Result<Vec<String>, std::io::Error> {
+    let mut names = vec![];
+    collect_loose_ref_names(
+        &directory_manager.common_dir.join(prefix),
+        prefix,
+        &mut names,
+    )?;
+    names.sort();
+    Ok(names)
+}
+
+/// The refs below `prefix`, such as `refs` or `refs/tags`, loose or packed, sorted by name. The
+/// path of each ref is its name.
+pub fn list_refs(
+    directory_manager: &DirectoryManager,
+    prefix: &str,
+) -> Result<Vec<Ref>, ResolveRefError> {
+    let directory_prefix = format!("{}/", prefix);
+    let mut refs: BTreeMap<String, String> = read_packed_refs(directory_manager)?
+        .into_iter()
+        .filter(|packed| packed.name.starts_with(&directory_prefix))
+        .map(|packed| (packed.name, packed.hash))
+        .collect();
+    for name in loose_ref_names(directory_manager, prefix)? {
+        let hash = resolve_ref(directory_manager, &name)?;
+        refs.insert(name, hash);
     }
 
-    Ok(ref_value.to_string())
+    Ok(refs
+        .into_iter()
+        .map(|(name, hash)| Ref {
+            hash,
+            path: PathBuf::from(name),
+        })
+        .collect())
 }
 
-pub fn list_refs(dot_git_path: &Path, path: &Path) -> Result<Vec<Ref>, ResolveRefError> {
-    let mut refs = vec![];
+/// The short name of the ref `name`, without its `refs/heads/`, `refs/tags/`, `refs/remotes/`
+/// or `refs/` prefix, such as `master` for `refs/heads/master`.
+pub fn shorten_ref_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
 
-    for entry in std::fs::read_dir(path)? {
-        let entry = entry?;
-        let entry_path = entry.path();
-        if entry_path.is_dir() {
-            let out = list_refs(dot_git_path, &entry_path)?;
-            refs.extend(out);
-        } else {
-            let hash = resolve_ref(dot_git_path, &entry_path)?;
-            refs.push(Ref {
-                hash,
-                path: entry_path,
-            });
-        }
+/// Point the ref at `ref_path` to `hash`. A symbolic ref is overwritten rather than followed.
+pub fn write_ref(ref_path: &Path, hash: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = ref_path.parent() {
+        fs::create_dir_all(parent)?;
     }
+    fs::write(ref_path, format!("{}\n", hash))
+}
 
-    Ok(refs)
+/// Make the ref at `ref_path` a symbolic ref to `target`, such as `refs/heads/master`.
+pub fn write_symbolic_ref(ref_path: &Path, target: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = ref_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(ref_path, format!("ref: {}\n", target))
+}
+
+/// The target of the symbolic ref at `ref_path`, or `None` if it holds a hash.
+pub fn read_symbolic_ref(ref_path: &Path) -> Result<Option<String>, std::io::Error> {
+    let ref_value = fs::read_to_string(ref_path)?;
+    Ok(ref_value
+        .trim_end()
+        .strip_prefix("ref: ")
+        .map(|target| target.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_packed_refs, PackedRef};
+
+    #[test]
+    fn parse_packed_refs_should_attach_peeled_lines_and_sort() {
+        let tag = "1".repeat(40);
+        let commit = "2".repeat(40);
+        let content = format!(
+            "# pack-refs with: peeled fully-peeled \n{} refs/tags/v1\n^{}\n{} refs/heads/master\n",
+            tag, commit, commit
+        );
+
+        let refs = parse_packed_refs(&content).unwrap();
+
+        assert_eq!(
+            refs,
+            vec![
+                PackedRef {
+                    name: "refs/heads/master".to_string(),
+                    hash: commit.clone(),
+                    peeled: None,
+                },
+                PackedRef {
+                    name: "refs/tags/v1".to_string(),
+                    hash: tag,
+                    peeled: Some(commit.clone()),
+                },
+            ]
+        );
+        assert!(parse_packed_refs(&format!("^{}\n", commit)).is_err());
+        assert!(parse_packed_refs("garbage\n").is_err());
+    }
 }
@@ -0,0 +1,342 @@
+use std::collections::{BinaryHeap, HashSet};
+
+use crate::{
+    error::{repository::ResolveRefError, RevisionError},
+    git_object::Type,
+    index::Index,
+    revision::{ExpectedType, PeelTarget, Revision},
+    GitObject,
+};
+
+use super::{refs::Head, GitRepository};
+
+// Revision methods
+impl GitRepository {
+    /// The full name of the ref `name` stands for, the first of these that exists, in git's
+    /// order: `$GIT_DIR/<name>`, `refs/<name>`, `refs/tags/<name>`, `refs/heads/<name>`,
+    /// `refs/remotes/<name>` and `refs/remotes/<name>/HEAD`. Only names made of capitals and
+    /// underscores, such as `ORIG_HEAD`, are looked up at the top of `.git`.
+    pub fn dwim_ref(&self, name: &str) -> Result<Option<String>, ResolveRefError> {
+        let candidates = [
+            name.to_string(),
+            format!("refs/{}", name),
+            format!("refs/tags/{}", name),
+            format!("refs/heads/{}", name),
+            format!("refs/remotes/{}", name),
+            format!("refs/remotes/{}/HEAD", name),
+        ];
+        let is_top_level =
+            name.starts_with("refs/") || name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+        for candidate in candidates.into_iter().skip(usize::from(!is_top_level)) {
+            if self.current_ref_value(&candidate)?.is_some() {
+                return Ok(Some(candidate));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Resolve `revision` to the hash of the object it names.
+    pub fn resolve_revision(&self, revision: &str) -> Result<String, RevisionError> {
+        self.resolve_revision_as(revision, ExpectedType::Any)
+    }
+
+    /// Resolve `revision` to the hash of the object it names, which should be of the `expected`
+    /// type if a short hash in it may stand for several objects.
+    pub fn resolve_revision_as(
+        &self,
+        revision: &str,
+        expected: ExpectedType,
+    ) -> Result<String, RevisionError> {
+        self.evaluate_revision(&Revision::parse(revision)?, expected)
+    }
+
+    /// The full name of the ref `revision` names, such as `refs/heads/master` for `HEAD`, or
+    /// `None` if it names an object rather than a ref.
+    pub fn revision_ref_name(&self, revision: &str) -> Result<Option<String>, RevisionError> {
+        match Revision::parse(revision)? {
+            Revision::Name(name) if name == "HEAD" => Ok(Some(self.head_branch()?.unwrap_or(name))),
+            Revision::Name(name) => Ok(self.dwim_ref(&name)?),
+            Revision::Upstream(branch) => self.upstream_of(branch.as_deref()).map(Some),
+            Revision::PreviousCheckout(n) => Ok(self.dwim_ref(&self.previous_checkout(n)?)?),
+            _ => Ok(None),
+        }
+    }
+
+    /// The hashes of the objects starting with `prefix`, of at least two hexadecimal digits.
+    fn objects_with_prefix(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let prefix = prefix.to_lowercase();
+        let directory = self.directory_manager.objects_path.join(&prefix[..2]);
+        let mut hashes = vec![];
+        if directory.is_dir() {
+            for entry in directory.read_dir()? {
+                let filename = entry?.file_name().to_string_lossy().into_owned();
+                if filename.starts_with(&prefix[2..]) {
+                    hashes.push(format!("{}{}", &prefix[..2], filename));
+                }
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    /// The shortest prefix of `hash`, at least `length` digits long, that no other object
+    /// starts with.
+    pub fn abbreviate_hash(&self, hash: &str, length: usize) -> Result<String, std::io::Error> {
+        let others: Vec<String> = self
+            .objects_with_prefix(&hash[..2])?
+            .into_iter()
+            .filter(|other| other != hash)
+            .collect();
+        let mut length = length.clamp(4, hash.len());
+        while length < hash.len()
+            && others
+                .iter()
+                .any(|other| other.starts_with(&hash[..length]))
+        {
+            length += 1;
+        }
+        Ok(hash[..length].to_string())
+    }
+
+    /// Resolve `revision`, expecting an object of the `expected` type. The revisions it's made
+    /// of are expected to be commits or trees if it follows their history or reads their tree.
+    fn evaluate_revision(
+        &self,
+        revision: &Revision,
+        expected: ExpectedType,
+    ) -> Result<String, RevisionError> {
+        let commit = ExpectedType::Commit;
+        match revision {
+            Revision::Name(name) => self.resolve_name(name, expected),
+            Revision::ReflogEntry(name, n) => self.reflog_entry(name.as_deref(), *n),
+            Revision::Upstream(branch) => {
+                let upstream = self.upstream_of(branch.as_deref())?;
+                self.current_ref_value(&upstream)?
+                    .ok_or(RevisionError::Unknown(upstream))
+            }
+            Revision::PreviousCheckout(n) => {
+                self.resolve_name(&self.previous_checkout(*n)?, expected)
+            }
+            Revision::Ancestor(base, n) => {
+                let mut hash = self.evaluate_revision(base, commit)?;
+                for _ in 0..*n {
+                    hash = self.nth_parent(&hash, 1)?;
+                }
+                Ok(hash)
+            }
+            Revision::Parent(base, n) => {
+                self.nth_parent(&self.evaluate_revision(base, commit)?, *n)
+            }
+            Revision::Peel(base, target) => match target {
+                PeelTarget::Type(type_) => {
+                    let hash = self.evaluate_revision(base, ExpectedType::from(*type_))?;
+                    Ok(self.peel_object(&hash, *type_)?.0)
+                }
+                PeelTarget::Object => {
+                    let hash = self.evaluate_revision(base, expected)?;
+                    self.read_object(&hash)?;
+                    Ok(hash)
+                }
+                PeelTarget::NonTag => {
+                    let hash = self.evaluate_revision(base, expected)?;
+                    Ok(self.peel_tag(&hash)?.unwrap_or(hash))
+                }
+            },
+            Revision::MessageSearchFrom(base, pattern) => {
+                let (hash, _) = self.read_commit(&self.evaluate_revision(base, commit)?)?;
+                self.search_commit_message(vec![hash], pattern)
+            }
+            Revision::MessageSearch(pattern) => {
+                let mut starts: Vec<String> = self
+                    .list_refs()?
+                    .into_iter()
+                    .map(|ref_item| ref_item.hash)
+                    .collect();
+                starts.extend(self.head_commit()?);
+                self.search_commit_message(starts, pattern)
+            }
+            Revision::TreePath(base, path) => {
+                let hash = self.evaluate_revision(base, ExpectedType::Tree)?;
+                self.tree_path(&hash, path)
+                    .ok_or_else(|| RevisionError::PathNotInTree(path.clone(), base.to_string()))
+            }
+            Revision::IndexPath(stage, path) => {
+                let index = Index::load(&self.directory_manager.index_file)?;
+                index
+                    .entries()
+                    .iter()
+                    .find(|entry| entry.path == *path && entry.stage == *stage)
+                    .map(|entry| entry.hash.clone())
+                    .ok_or_else(|| RevisionError::PathNotInIndex(path.clone(), *stage))
+            }
+        }
+    }
+
+    /// Resolve `name` the way git does: `HEAD`, a full hash, the first ref
+    /// [`GitRepository::dwim_ref`] finds, and only then a short hash.
+    fn resolve_name(&self, name: &str, expected: ExpectedType) -> Result<String, RevisionError> {
+        if name == "HEAD" {
+            return match self.head()? {
+                Head::Detached(hash) => Ok(hash),
+                Head::Branch(branch) => Ok(self.current_ref_value(&branch)?.ok_or_else(|| {
+                    let short = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+                    ResolveRefError::UnbornBranch(short.to_string())
+                })?),
+            };
+        }
+        if name.len() == 40 && self.has_object(name) {
+            return Ok(name.to_string());
+        }
+        if let Some(ref_name) = self.dwim_ref(name)? {
+            return self
+                .current_ref_value(&ref_name)?
+                .ok_or_else(|| RevisionError::Unknown(name.to_string()));
+        }
+        self.resolve_short_hash(name, expected)
+    }
+
+    /// The object whose hash starts with `prefix`. If several do, the one of the `expected`
+    /// type is picked, and if that's still not a single object, every candidate is listed in
+    /// the error.
+    fn resolve_short_hash(
+        &self,
+        prefix: &str,
+        expected: ExpectedType,
+    ) -> Result<String, RevisionError> {
+        let regex = regex::Regex::new("^[0-9A-Fa-f]{4,40}$").unwrap();
+        if !regex.is_match(prefix) {
+            return Err(RevisionError::Unknown(prefix.to_string()));
+        }
+        let mut candidates = self.objects_with_prefix(prefix)?;
+        if candidates.len() > 1 {
+            if let Some(type_) = expected.peeled_type() {
+                let matching: Vec<&String> = candidates
+                    .iter()
+                    .filter(|candidate| self.peel_object(candidate, type_).is_ok())
+                    .collect();
+                if let [hash] = matching[..] {
+                    return Ok(hash.clone());
+                }
+            }
+        }
+        match candidates.len() {
+            0 => Err(RevisionError::Unknown(prefix.to_string())),
+            1 => Ok(candidates.remove(0)),
+            _ => {
+                let mut typed = vec![];
+                for candidate in candidates {
+                    let type_ = self.read_object(&candidate)?.get_type();
+                    typed.push((candidate, type_));
+                }
+                Err(RevisionError::Ambiguous(prefix.to_string(), typed))
+            }
+        }
+    }
+
+    /// The value the ref `name`, the current branch by default, had `n` updates ago.
+    fn reflog_entry(&self, name: Option<&str>, n: usize) -> Result<String, RevisionError> {
+        let ref_name = match name {
+            Some(name) => self
+                .dwim_ref(name)?
+                .ok_or_else(|| RevisionError::Unknown(name.to_string()))?,
+            None => self.head_branch()?.unwrap_or_else(|| "HEAD".to_string()),
+        };
+        let entries = self.read_reflog(&ref_name)?;
+        entries
+            .len()
+            .checked_sub(n + 1)
+            .map(|i| entries[i].new.clone())
+            .ok_or(RevisionError::NoReflogEntry(ref_name, entries.len()))
+    }
+
+    /// The upstream of the branch `branch`, or of the current branch if `None` or `HEAD`.
+    fn upstream_of(&self, branch: Option<&str>) -> Result<String, RevisionError> {
+        let branch = match branch {
+            Some(branch) if branch != "HEAD" => branch.to_string(),
+            _ => self.head_branch()?.ok_or(RevisionError::NoCurrentBranch)?,
+        };
+        let branch = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+        self.upstream(branch)
+            .ok_or_else(|| RevisionError::NoUpstream(branch.to_string()))
+    }
+
+    /// What was checked out `n` checkouts ago, a branch name or a hash, from the reflog of
+    /// `HEAD`.
+    pub(crate) fn previous_checkout(&self, n: usize) -> Result<String, RevisionError> {
+        let entries = self.read_reflog("HEAD")?;
+        let mut previous = entries.iter().rev().filter_map(|entry| {
+            let moved = entry.message.strip_prefix("checkout: moving from ")?;
+            moved.split_once(" to ").map(|(from, _)| from.to_string())
+        });
+        n.checked_sub(1)
+            .and_then(|skip| previous.nth(skip))
+            .ok_or(RevisionError::NoPreviousCheckout(n))
+    }
+
+    /// The `n`-th parent of the commit `hash`, the commit itself for 0.
+    fn nth_parent(&self, hash: &str, n: usize) -> Result<String, RevisionError> {
+        let (hash, commit) = self.read_commit(hash)?;
+        match n {
+            0 => Ok(hash),
+            _ => commit
+                .parents()
+                .get(n - 1)
+                .map(|parent| parent.to_string())
+                .ok_or(RevisionError::NoParent(hash, n)),
+        }
+    }
+
+    /// The object at `path` in the tree of the tree-ish `hash`, the tree itself for an empty
+    /// path.
+    fn tree_path(&self, hash: &str, path: &str) -> Option<String> {
+        let (mut hash, _) = self.peel_object(hash, Type::Tree).ok()?;
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            let GitObject::Tree(tree) = self.read_object(&hash).ok()? else {
+                return None;
+            };
+            hash = tree
+                .iter()
+                .find(|leaf| leaf.path == component)?
+                .hash
+                .clone();
+        }
+        Some(hash)
+    }
+
+    /// The youngest commit reachable from `starts` whose message matches the regular
+    /// expression `pattern`. Objects of `starts` that aren't commits are skipped.
+    fn search_commit_message(
+        &self,
+        starts: Vec<String>,
+        pattern: &str,
+    ) -> Result<String, RevisionError> {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|_| RevisionError::InvalidPattern(pattern.to_string()))?;
+        let mut seen = HashSet::new();
+        let mut queue = BinaryHeap::new();
+        for start in starts {
+            if let Ok((hash, commit)) = self.read_commit(&start) {
+                if seen.insert(hash.clone()) {
+                    queue.push((commit.committer_time(), hash));
+                }
+            }
+        }
+
+        while let Some((_, hash)) = queue.pop() {
+            let (_, commit) = self.read_commit(&hash)?;
+            if commit
+                .get_value("message")
+                .is_some_and(|message| regex.is_match(message))
+            {
+                return Ok(hash);
+            }
+            for parent in commit.parents() {
+                if seen.insert(parent.clone()) {
+                    let (_, parent_commit) = self.read_commit(parent)?;
+                    queue.push((parent_commit.committer_time(), parent.clone()));
+                }
+            }
+        }
+        Err(RevisionError::NoMatchingCommit(pattern.to_string()))
+    }
+}
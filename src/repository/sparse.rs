@@ -0,0 +1,116 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use crate::{
+    diff::DiffSide, error::CheckoutError, git_config::GitConfig, index::Index,
+    sparse::SparsePatterns,
+};
+
+use super::GitRepository;
+
+// Sparse checkout methods
+impl GitRepository {
+    fn sparse_checkout_file(&self) -> PathBuf {
+        self.directory_manager
+            .dot_git_path
+            .join("info/sparse-checkout")
+    }
+
+    /// The sparse checkout patterns, or `None` if `core.sparseCheckout` isn't set or there is no
+    /// sparse-checkout file, in which case every file is checked out.
+    pub fn sparse_patterns(&self) -> Result<Option<SparsePatterns>, std::io::Error> {
+        if !self
+            .config
+            .get_bool("core", "sparseCheckout")
+            .unwrap_or(false)
+        {
+            return Ok(None);
+        }
+        let cone = self
+            .config
+            .get_bool("core", "sparseCheckoutCone")
+            .unwrap_or(false);
+        match std::fs::read_to_string(self.sparse_checkout_file()) {
+            Ok(content) => Ok(Some(SparsePatterns::parse(&content, cone))),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Write `patterns` to the sparse-checkout file and turn sparse checkout on, or off if
+    /// `patterns` is `None`, in the configuration. The repository's configuration loaded in
+    /// memory isn't updated.
+    pub fn write_sparse_patterns(
+        &self,
+        patterns: Option<&SparsePatterns>,
+    ) -> Result<(), std::io::Error> {
+        let config_file = &self.directory_manager.config_file;
+        if let Some(patterns) = patterns {
+            let file = self.sparse_checkout_file();
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(file, patterns.to_string())?;
+            let cone = patterns.is_cone().to_string();
+            GitConfig::write_value(config_file, "core", "sparseCheckoutCone", &cone)?;
+        }
+        let enabled = patterns.is_some().to_string();
+        GitConfig::write_value(config_file, "core", "sparseCheckout", &enabled)
+    }
+
+    /// Make the work tree match `patterns`, or hold every file if `patterns` is `None`. Entries
+    /// of `index` outside of the patterns get the skip-worktree bit and their file removed, the
+    /// others lose the bit and get their file written if it's missing.
+    ///
+    /// Files with local changes are left in place, without the bit. Returns their paths.
+    pub fn apply_sparse_patterns(
+        &self,
+        index: &mut Index,
+        patterns: Option<&SparsePatterns>,
+    ) -> Result<Vec<String>, CheckoutError> {
+        let mut not_up_to_date = vec![];
+        let mut removals = vec![];
+        let mut writes = vec![];
+        for entry in index.entries() {
+            if entry.stage != 0 {
+                continue;
+            }
+            let included = patterns.is_none_or(|patterns| patterns.includes(&entry.path));
+            match (included, entry.skip_worktree) {
+                (true, true) => writes.push(DiffSide {
+                    path: entry.path.clone(),
+                    mode: entry.mode.clone(),
+                    hash: entry.hash.clone(),
+                }),
+                (false, false) if self.is_work_tree_file_up_to_date(index, entry)? => {
+                    removals.push(entry.path.clone())
+                }
+                (false, false) => not_up_to_date.push(entry.path.clone()),
+                _ => {}
+            }
+        }
+
+        for path in removals {
+            self.remove_work_tree_file(&path)?;
+            if let Some(entry) = index.entry_mut(&path) {
+                entry.skip_worktree = true;
+            }
+        }
+        // A file already there is kept, as it may hold local changes.
+        let (kept, missing): (Vec<_>, Vec<_>) = writes
+            .into_iter()
+            .partition(|side| std::fs::symlink_metadata(self.work_tree_path(&side.path)).is_ok());
+        let stats = self.write_sides(&missing)?;
+        for side in kept {
+            if let Some(entry) = index.entry_mut(&side.path) {
+                entry.skip_worktree = false;
+            }
+        }
+        for (side, stat) in missing.into_iter().zip(stats) {
+            if let Some(entry) = index.entry_mut(&side.path) {
+                entry.skip_worktree = false;
+                entry.stat = stat;
+            }
+        }
+        Ok(not_up_to_date)
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    diff::{self, DiffSide, NULL_HASH},
+    error::StashError,
+    git_object::Commit,
+    index::Index,
+    merge::{self, TreeMerge},
+    pathspec::Pathspec,
+};
+
+use super::{ref_transaction::RefTransaction, reflog::ReflogEntry, GitRepository};
+
+pub const STASH_REF: &str = "refs/stash";
+
+/// The commits recording a stash. The work tree commit, the one `refs/stash` points to, has the
+/// commit the changes were made on top of as first parent, the index commit as second parent
+/// and the untracked files commit, if any, as third parent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stash {
+    /// How the stash was named, such as `refs/stash@{0}`.
+    pub name: String,
+    /// Position in the stash list, `None` for a stash given by commit.
+    pub position: Option<usize>,
+    pub work_tree: String,
+    pub base: String,
+    pub index: String,
+    pub untracked: Option<String>,
+}
+
+/// Parse `stash@{<n>}`, `refs/stash@{<n>}` or a bare `<n>` into `n`.
+fn parse_position(name: &str) -> Option<usize> {
+    let position = name
+        .strip_prefix("refs/")
+        .unwrap_or(name)
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(name);
+    position.parse().ok()
+}
+
+/// The abbreviated hash and subject of a commit, as shown in stash messages.
+fn describe(hash: &str, commit: &Commit) -> String {
+    let subject = commit
+        .get_value("message")
+        .and_then(|message| message.lines().next())
+        .unwrap_or_default();
+    format!("{} {}", &hash[..7], subject)
+}
+
+// Stash methods
+impl GitRepository {
+    /// The stash entries, most recent first.
+    pub fn stash_list(&self) -> Result<Vec<ReflogEntry>, StashError> {
+        let mut entries = self.read_reflog(STASH_REF)?;
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Look up the stash named `name`, `stash@{<n>}`, `<n>` or any commit made by `stash`. The
+    /// most recent stash is used by default.
+    pub fn read_stash(&self, name: Option<&str>) -> Result<Stash, StashError> {
+        let (name, position) = match name {
+            None => ("refs/stash@{0}".to_string(), Some(0)),
+            Some(name) if name.chars().all(|c| c.is_ascii_digit()) => {
+                (format!("refs/stash@{{{}}}", name), parse_position(name))
+            }
+            Some(name) => (name.to_string(), parse_position(name)),
+        };
+
+        let hash = match position {
+            Some(position) => {
+                let entries = self.stash_list()?;
+                if entries.is_empty() {
+                    return Err(StashError::NoEntries);
+                }
+                entries
+                    .get(position)
+                    .ok_or_else(|| StashError::InvalidReference(name.clone()))?
+                    .new
+                    .clone()
+            }
+            None => self
+                .find_object(&name)
+                .map_err(|_| StashError::InvalidReference(name.clone()))?,
+        };
+
+        let (hash, commit) = self.read_commit(&hash)?;
+        let parents = commit.parents();
+        if !(2..=3).contains(&parents.len()) {
+            return Err(StashError::NotStashLike(name));
+        }
+        Ok(Stash {
+            name,
+            position,
+            work_tree: hash,
+            base: parents[0].clone(),
+            index: parents[1].clone(),
+            untracked: parents.get(2).map(|hash| hash.to_string()),
+        })
+    }
+
+    /// Record the changes of the index and the work tree, and with `include_untracked` the
+    /// untracked files, as a new stash, then reset the index and the work tree to `HEAD`.
+    /// Returns the message of the stash, or `None` if there was nothing to save.
+    pub fn save_stash(
+        &self,
+        index: &mut Index,
+        message: Option<&str>,
+        include_untracked: bool,
+    ) -> Result<Option<String>, StashError> {
+        let head = self.head_commit()?.ok_or(StashError::NoInitialCommit)?;
+        let (head, head_commit) = self.read_commit(&head)?;
+        let head_tree = self.read_tree(&head)?;
+        let head_tree_hash = head_commit.get_value("tree").cloned().unwrap_or_default();
+
+        let index_tree = self.write_index_tree(index)?;
+
+        // The work tree version of tracked files, whose blobs aren't stored yet.
+        let everything = Pathspec::default();
+        let work_tree_files = diff::work_tree_files(self, index, &everything)?;
+        for side in &work_tree_files {
+            if !self.has_object(&side.hash) {
+                let data = diff::read_side(self, side)?;
+                self.write_blob(data).map_err(anyhow::Error::from)?;
+            }
+        }
+        let work_tree_tree = self.write_files_tree(&work_tree_files)?;
+
+        let untracked_paths = match include_untracked {
+            true => self.untracked_files(index)?,
+            false => vec![],
+        };
+        if index_tree == head_tree_hash
+            && work_tree_tree == head_tree_hash
+            && untracked_paths.is_empty()
+        {
+            return Ok(None);
+        }
+
+        let branch = match self.head_branch()? {
+            Some(branch) => branch.trim_start_matches("refs/heads/").to_string(),
+            None => "(no branch)".to_string(),
+        };
+        let description = describe(&head, &head_commit);
+
+        let index_commit = self.create_commit(
+            &index_tree,
+            std::slice::from_ref(&head),
+            &format!("index on {}: {}\n", branch, description),
+        )?;
+        let mut parents = vec![head.clone(), index_commit];
+
+        let mut untracked_files = vec![];
+        for path in &untracked_paths {
+            if let Some((mode, data)) = self.read_work_tree_file(path, None)? {
+                let hash = self.write_blob(data).map_err(anyhow::Error::from)?;
+                untracked_files.push(DiffSide {
+                    path: path.clone(),
+                    mode,
+                    hash,
+                });
+            }
+        }
+        if !untracked_files.is_empty() {
+            let untracked_tree = self.write_files_tree(&untracked_files)?;
+            parents.push(self.create_commit(
+                &untracked_tree,
+                &[],
+                &format!("untracked files on {}: {}\n", branch, description),
+            )?);
+        }
+
+        let message = match message {
+            Some(message) => format!("On {}: {}", branch, message),
+            None => format!("WIP on {}: {}", branch, description),
+        };
+        let stash = self.create_commit(&work_tree_tree, &parents, &format!("{}\n", message))?;
+
+        // The stash list is the reflog of the stash ref, so it's always written.
+        let mut transaction = RefTransaction::no_deref();
+        transaction
+            .set_message(&message)
+            .force_reflog()
+            .update(STASH_REF, &stash, None);
+        self.commit_ref_transaction(&transaction)?;
+
+        self.reset_work_tree(index, Some(head_tree))?;
+        for side in &untracked_files {
+            self.remove_work_tree_file(&side.path)?;
+        }
+        Ok(Some(message))
+    }
+
+    /// Apply the changes recorded by `stash` on top of the index and the work tree, merging them
+    /// with the changes made since. With `restore_index`, the staged changes are restored as
+    /// well, otherwise only files the stash added stay staged.
+    ///
+    /// Returns the merge, whose conflicts are recorded in the index and the work tree.
+    pub fn apply_stash(
+        &self,
+        index: &mut Index,
+        stash: &Stash,
+        restore_index: bool,
+    ) -> Result<TreeMerge, StashError> {
+        if index.entries().iter().any(|entry| entry.stage != 0) {
+            return Err(StashError::UnmergedIndex);
+        }
+
+        let everything = Pathspec::default();
+        let files = |name: &str| -> Result<Vec<DiffSide>, StashError> {
+            Ok(diff::tree_files(
+                self,
+                Some(self.read_tree(name)?),
+                &everything,
+            )?)
+        };
+        let base_files = files(&stash.base)?;
+        let index_files = files(&stash.index)?;
+        let current_files = diff::index_files(index, &everything);
+
+        // The staged changes are merged into the current index on their own, which must not
+        // conflict.
+        let restored_index = match restore_index {
+            true if index_files != base_files && index_files != current_files => {
+                let merge = merge::merge_files(
+                    self,
+                    base_files.clone(),
+                    current_files.clone(),
+                    index_files,
+                    ("Updated upstream", "Stashed changes"),
+                )?;
+                if !merge.is_clean() {
+                    return Err(StashError::IndexConflicts);
+                }
+                Some(merge.files)
+            }
+            _ => None,
+        };
+
+        let untracked_files = match &stash.untracked {
+            Some(untracked) => files(untracked)?,
+            None => vec![],
+        };
+        for side in &untracked_files {
+            if std::fs::symlink_metadata(self.work_tree_path(&side.path)).is_ok() {
+                return Err(StashError::UntrackedExists(side.path.clone()));
+            }
+        }
+
+        let merge = merge::merge_files(
+            self,
+            base_files,
+            current_files.clone(),
+            files(&stash.work_tree)?,
+            ("Updated upstream", "Stashed changes"),
+        )?;
+        self.checkout_merge(index, &merge)?;
+
+        if merge.is_clean() {
+            match restored_index {
+                Some(files) => {
+                    let tree = self.read_tree(&self.write_files_tree(&files)?)?;
+                    self.reset_index(index, Some(tree), &everything)?;
+                }
+                None => {
+                    // Only the files added by the stash stay staged.
+                    let current: BTreeSet<&str> = current_files
+                        .iter()
+                        .map(|side| side.path.as_str())
+                        .collect();
+                    let added: Vec<_> = index
+                        .entries()
+                        .iter()
+                        .filter(|entry| !current.contains(entry.path.as_str()))
+                        .cloned()
+                        .collect();
+                    let tree = self.read_tree(&self.write_files_tree(&current_files)?)?;
+                    self.reset_index(index, Some(tree), &everything)?;
+                    for entry in added {
+                        index.add(entry);
+                    }
+                }
+            }
+        }
+
+        self.write_work_tree_files(&untracked_files)?;
+        Ok(merge)
+    }
+
+    /// Remove the stash at `position` from the stash list. Returns the hash of the dropped stash.
+    pub fn drop_stash(&self, position: usize) -> Result<String, StashError> {
+        let mut entries = self.read_reflog(STASH_REF)?;
+        if entries.is_empty() {
+            return Err(StashError::NoEntries);
+        }
+        let index = entries
+            .len()
+            .checked_sub(position + 1)
+            .ok_or_else(|| StashError::InvalidReference(format!("stash@{{{}}}", position)))?;
+        let dropped = entries.remove(index);
+
+        // Entries keep pointing to the one before them in the list.
+        let previous = match index {
+            0 => NULL_HASH.to_string(),
+            _ => entries[index - 1].new.clone(),
+        };
+        if let Some(next) = entries.get_mut(index) {
+            next.old = previous;
+        }
+
+        match entries.last() {
+            Some(latest) => {
+                self.update_ref(STASH_REF, &latest.new)?;
+                self.write_reflog(STASH_REF, &entries)?;
+            }
+            None => self.clear_stash()?,
+        }
+        Ok(dropped.new)
+    }
+
+    /// Remove every stash.
+    pub fn clear_stash(&self) -> Result<(), StashError> {
+        self.delete_ref(STASH_REF)?;
+        self.delete_reflog(STASH_REF)?;
+        Ok(())
+    }
+}
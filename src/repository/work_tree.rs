@@ -0,0 +1,450 @@
+use std::{
+    ffi::OsStr,
+    fs::{Metadata, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{OpenOptionsExt, PermissionsExt},
+    },
+    path::PathBuf,
+};
+
+use crate::{
+    attributes::AttributeRules,
+    convert::{self, Conversion},
+    git_object::{
+        mode::{Mode, Type},
+        SerializedGitObject, Type as ObjectType,
+    },
+    ignore::IgnoreRules,
+    index::{Index, IndexEntry, StatCheck, StatData},
+    parallel, GitObject,
+};
+
+use super::GitRepository;
+
+/// What [`GitRepository::refresh_index`] found.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IndexRefresh {
+    /// Paths whose work tree file differs from the index, or is missing.
+    pub needs_update: Vec<String>,
+    /// Conflicted paths.
+    pub needs_merge: Vec<String>,
+    /// Whether the stat data of an entry was updated, making the index worth writing.
+    pub changed: bool,
+}
+
+// Working tree methods
+impl GitRepository {
+    /// The absolute path of `path`, given relative to the top of the work tree.
+    pub fn work_tree_path(&self, path: &str) -> PathBuf {
+        self.directory_manager.work_tree.join(path)
+    }
+
+    /// Whether the executable bit of files can be trusted (`core.fileMode`, true by default).
+    pub fn trust_executable_bit(&self) -> bool {
+        self.config.get_bool("core", "fileMode").unwrap_or(true)
+    }
+
+    /// Read the file at `path`, relative to the top of the work tree, the way it would be stored
+    /// as a blob, along with its mode. Symbolic links are read as their target. Returns `None`
+    /// if there is no file at `path`.
+    ///
+    /// `index_version` is the mode and blob hash recorded in the index. The executable bit of
+    /// the mode is kept when the file system one can't be trusted, and line endings aren't
+    /// normalized by `text=auto` if the blob has CRs.
+    pub fn read_work_tree_file(
+        &self,
+        path: &str,
+        index_version: Option<(&Mode, &str)>,
+    ) -> Result<Option<(Mode, Vec<u8>)>, std::io::Error> {
+        self.read_work_tree_file_with(path, index_version.map(|(mode, _)| mode), || {
+            index_version.is_some_and(|(_, hash)| self.blob_has_cr(hash))
+        })
+    }
+
+    /// Like [`GitRepository::read_work_tree_file`], with `index_has_cr` telling whether the
+    /// blob recorded in the index has CRs.
+    pub fn read_work_tree_file_with(
+        &self,
+        path: &str,
+        index_mode: Option<&Mode>,
+        index_has_cr: impl FnOnce() -> bool,
+    ) -> Result<Option<(Mode, Vec<u8>)>, std::io::Error> {
+        let full_path = self.work_tree_path(path);
+        let metadata = match std::fs::symlink_metadata(&full_path) {
+            Ok(metadata) => metadata,
+            Err(error)
+                if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) =>
+            {
+                return Ok(None)
+            }
+            Err(error) => return Err(error),
+        };
+
+        let permissions = if metadata.file_type().is_symlink() {
+            let target = std::fs::read_link(&full_path)?;
+            let mode = Mode::new("120000".to_string()).map_err(std::io::Error::other)?;
+            return Ok(Some((mode, target.as_os_str().as_bytes().to_vec())));
+        } else if !metadata.is_file() {
+            return Ok(None);
+        } else if self.trust_executable_bit() {
+            match metadata.permissions().mode() & 0o100 != 0 {
+                true => "100755",
+                false => "100644",
+            }
+        } else {
+            match index_mode {
+                Some(mode) if mode.type_ == Type::RegularFile => mode.permissions(),
+                _ => "100644",
+            }
+        };
+
+        let mode = Mode::new(permissions.to_string()).map_err(std::io::Error::other)?;
+        let data = self
+            .conversion(path)
+            .to_git(path, std::fs::read(full_path)?, index_has_cr)
+            .map_err(std::io::Error::other)?;
+        Ok(Some((mode, data)))
+    }
+
+    /// Whether the blob `hash` has CRs. Missing blobs have none.
+    pub fn blob_has_cr(&self, hash: &str) -> bool {
+        matches!(self.read_object(hash), Ok(GitObject::Blob(blob)) if blob.blob.contains(&b'\r'))
+    }
+
+    /// How many threads write or hash `count` files: `checkout.workers`, 1 by default and the
+    /// number of cores if below 1. Fewer files than `checkout.thresholdForParallelism`, 100 by
+    /// default, are handled on the current thread.
+    pub fn workers(&self, count: usize) -> usize {
+        let threshold = self
+            .config
+            .get("checkout", "thresholdForParallelism")
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(100);
+        let workers: i64 = self
+            .config
+            .get("checkout", "workers")
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(1);
+        match workers {
+            _ if count < threshold => 1,
+            workers if workers < 1 => std::thread::available_parallelism().map_or(1, usize::from),
+            workers => workers as usize,
+        }
+    }
+
+    /// The stat data compared to tell whether a file changed (`core.trustCtime` and
+    /// `core.checkStat`).
+    pub fn stat_check(&self) -> StatCheck {
+        StatCheck {
+            ctime: self.config.get_bool("core", "trustCtime").unwrap_or(true),
+            minimal: self
+                .config
+                .get("core", "checkStat")
+                .is_some_and(|value| value.eq_ignore_ascii_case("minimal")),
+        }
+    }
+
+    /// Whether the file with `metadata` is known from its stat data alone to be unchanged since
+    /// `entry` was recorded in `index`. Racily clean and smudged entries never are, their file
+    /// has to be compared by content.
+    pub fn is_stat_clean(&self, index: &Index, entry: &IndexEntry, metadata: &Metadata) -> bool {
+        if entry.assume_valid {
+            return true;
+        }
+        let same_type = match entry.mode.type_ {
+            Type::SymbolicLink => metadata.file_type().is_symlink(),
+            Type::RegularFile => {
+                let is_executable = metadata.permissions().mode() & 0o100 != 0;
+                metadata.is_file()
+                    && (!self.trust_executable_bit() || is_executable == entry.mode.is_executable())
+            }
+            _ => false,
+        };
+        same_type
+            && !entry.is_smudged()
+            && !index.is_racy(&entry.stat)
+            && entry
+                .stat
+                .matches(&StatData::from(metadata), self.stat_check())
+    }
+
+    /// Whether the work tree file of `entry` has the content and mode recorded in `index`. A
+    /// missing file is considered up to date, as there is nothing to lose by replacing it.
+    pub fn is_work_tree_file_up_to_date(
+        &self,
+        index: &Index,
+        entry: &IndexEntry,
+    ) -> Result<bool, std::io::Error> {
+        let exists = std::fs::symlink_metadata(self.work_tree_path(&entry.path)).is_ok();
+        Ok(!exists
+            || entry.mode.type_ == Type::Submodule
+            || self.is_work_tree_file_present(index, entry)?)
+    }
+
+    /// Whether the work tree file of `entry` exists with the content and mode recorded in
+    /// `index`.
+    pub fn is_work_tree_file_present(
+        &self,
+        index: &Index,
+        entry: &IndexEntry,
+    ) -> Result<bool, std::io::Error> {
+        match std::fs::symlink_metadata(self.work_tree_path(&entry.path)) {
+            Ok(metadata) if self.is_stat_clean(index, entry, &metadata) => Ok(true),
+            _ => self.work_tree_file_matches(&entry.path, &entry.mode, &entry.hash),
+        }
+    }
+
+    /// Record the stat data of the files found unchanged by content despite their stat data,
+    /// so that they are found unchanged from it next time. Entries marked skip-worktree and
+    /// submodules are left alone.
+    pub fn refresh_index(&self, index: &mut Index) -> Result<IndexRefresh, std::io::Error> {
+        /// What was found for an entry.
+        enum Found {
+            Unchanged,
+            Refreshed(StatData),
+            NeedsUpdate,
+            NeedsMerge,
+        }
+
+        let entries = index.entries();
+        let check = |entry: &IndexEntry| {
+            if entry.stage != 0 {
+                return Ok(Found::NeedsMerge);
+            }
+            if entry.skip_worktree || entry.mode.type_ == Type::Submodule {
+                return Ok(Found::Unchanged);
+            }
+
+            let metadata = match std::fs::symlink_metadata(self.work_tree_path(&entry.path)) {
+                Ok(metadata) => metadata,
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) =>
+                {
+                    return Ok(Found::NeedsUpdate)
+                }
+                Err(error) => return Err(error),
+            };
+            if self.is_stat_clean(index, entry, &metadata) {
+                return Ok(Found::Unchanged);
+            }
+            let stat = StatData::from(&metadata);
+            Ok(
+                match self.work_tree_file_matches(&entry.path, &entry.mode, &entry.hash)? {
+                    true if stat != entry.stat => Found::Refreshed(stat),
+                    true => Found::Unchanged,
+                    false => Found::NeedsUpdate,
+                },
+            )
+        };
+        let workers = self.workers(entries.len());
+        let found = convert::with_ordered_warnings(|| parallel::try_map(workers, entries, check))?;
+
+        let mut refresh = IndexRefresh::default();
+        let mut stats = vec![];
+        for (entry, found) in entries.iter().zip(found) {
+            match found {
+                Found::Unchanged => {}
+                Found::Refreshed(stat) => stats.push((entry.path.clone(), stat)),
+                Found::NeedsUpdate => refresh.needs_update.push(entry.path.clone()),
+                Found::NeedsMerge if refresh.needs_merge.last() != Some(&entry.path) => {
+                    refresh.needs_merge.push(entry.path.clone())
+                }
+                Found::NeedsMerge => {}
+            }
+        }
+
+        refresh.changed = !stats.is_empty();
+        for (path, stat) in stats {
+            if let Some(entry) = index.entry_mut(&path) {
+                entry.stat = stat;
+            }
+        }
+        Ok(refresh)
+    }
+
+    /// Whether there is a file at `path` with the given mode and blob hash.
+    pub fn work_tree_file_matches(
+        &self,
+        path: &str,
+        mode: &Mode,
+        hash: &str,
+    ) -> Result<bool, std::io::Error> {
+        if mode.type_ == Type::Submodule {
+            return Ok(self.work_tree_path(path).is_dir());
+        }
+        Ok(self
+            .read_work_tree_file(path, Some((mode, hash)))?
+            .is_some_and(|(file_mode, data)| {
+                file_mode == *mode && SerializedGitObject::hash_of(ObjectType::Blob, &data) == hash
+            }))
+    }
+
+    /// Write `data` to the work tree at `path`, replacing any file already there and creating
+    /// the missing leading directories. Symbolic links are created pointing to `data`. Returns
+    /// the metadata of the new file, to be recorded in the index.
+    pub fn write_work_tree_file(
+        &self,
+        path: &str,
+        mode: &Mode,
+        data: &[u8],
+    ) -> Result<Metadata, std::io::Error> {
+        let full_path = self.work_tree_path(path);
+        if let Some(parent) = full_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match std::fs::symlink_metadata(&full_path) {
+            Ok(metadata) if !metadata.is_dir() => std::fs::remove_file(&full_path)?,
+            _ => {}
+        }
+
+        match mode.type_ {
+            Type::SymbolicLink => std::os::unix::fs::symlink(OsStr::from_bytes(data), &full_path)?,
+            Type::Submodule => std::fs::create_dir_all(&full_path)?,
+            _ => {
+                let data = self
+                    .conversion(path)
+                    .to_work_tree(path, data.to_vec())
+                    .map_err(std::io::Error::other)?;
+                let permissions = match mode.is_executable() {
+                    true => 0o777,
+                    false => 0o666,
+                };
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(permissions)
+                    .open(&full_path)?
+                    .write_all(&data)?;
+            }
+        }
+
+        std::fs::symlink_metadata(full_path)
+    }
+
+    /// Remove the file at `path` from the work tree, along with the leading directories it
+    /// leaves empty.
+    pub fn remove_work_tree_file(&self, path: &str) -> Result<(), std::io::Error> {
+        let full_path = self.work_tree_path(path);
+        match std::fs::remove_file(&full_path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+
+        let mut directory = full_path.parent();
+        while let Some(path) = directory {
+            if path == self.directory_manager.work_tree || std::fs::remove_dir(path).is_err() {
+                break;
+            }
+            directory = path.parent();
+        }
+        Ok(())
+    }
+
+    /// The ignore rules that apply everywhere: `core.excludesFile` and `.git/info/exclude`.
+    /// Those of `.gitignore` files are added while walking the work tree.
+    pub fn ignore_rules(&self) -> IgnoreRules {
+        let mut rules = IgnoreRules::new();
+        let excludes_file = self.config.get("core", "excludesFile").map(|path| {
+            match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+                (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                _ => PathBuf::from(path),
+            }
+        });
+        let info_exclude = self.directory_manager.common_dir.join("info/exclude");
+        for path in excludes_file.into_iter().chain([info_exclude]) {
+            if let Ok(content) = std::fs::read_to_string(path) {
+                rules.add_patterns("", &content);
+            }
+        }
+        rules
+    }
+
+    /// The attributes that apply to `path`: those of `core.attributesFile`, of the
+    /// `.gitattributes` files of its leading directories, then of `.git/info/attributes`.
+    pub fn attributes(&self, path: &str) -> AttributeRules {
+        let mut rules = AttributeRules::new();
+        let attributes_file = self.config.get("core", "attributesFile").map(|path| {
+            match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+                (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                _ => PathBuf::from(path),
+            }
+        });
+        if let Some(content) = attributes_file.and_then(|path| std::fs::read_to_string(path).ok()) {
+            rules.add_rules("", &content);
+        }
+
+        let leading_directories = path.match_indices('/').map(|(end, _)| &path[..end]);
+        for base in std::iter::once("").chain(leading_directories) {
+            let gitattributes = self.work_tree_path(base).join(".gitattributes");
+            if let Ok(content) = std::fs::read_to_string(gitattributes) {
+                rules.add_rules(base, &content);
+            }
+        }
+
+        let info_attributes = self.directory_manager.common_dir.join("info/attributes");
+        if let Ok(content) = std::fs::read_to_string(info_attributes) {
+            rules.add_rules("", &content);
+        }
+        rules
+    }
+
+    /// How the file at `path` is converted between its blob and the work tree.
+    pub fn conversion(&self, path: &str) -> Conversion {
+        Conversion::new(
+            &self.config,
+            &self.attributes(path),
+            &self.directory_manager.work_tree,
+            path,
+        )
+    }
+
+    /// Every file of the work tree that is neither tracked in `index` nor ignored, sorted by
+    /// path. Nested repositories are left out.
+    pub fn untracked_files(&self, index: &Index) -> Result<Vec<String>, std::io::Error> {
+        let mut files = vec![];
+        let mut rules = self.ignore_rules();
+        self.collect_untracked(index, "", &mut rules, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    fn collect_untracked(
+        &self,
+        index: &Index,
+        directory: &str,
+        rules: &mut IgnoreRules,
+        files: &mut Vec<String>,
+    ) -> Result<(), std::io::Error> {
+        let full_path = self.work_tree_path(directory);
+        let rules_len = rules.len();
+        if let Ok(content) = std::fs::read_to_string(full_path.join(".gitignore")) {
+            rules.add_patterns(directory, &content);
+        }
+
+        for entry in std::fs::read_dir(&full_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == ".git" {
+                continue;
+            }
+            let path = match directory {
+                "" => name,
+                _ => format!("{}/{}", directory, name),
+            };
+
+            if entry.file_type()?.is_dir() {
+                let is_nested_repository = entry.path().join(".git").exists();
+                if !is_nested_repository && !rules.is_ignored(&path, true) {
+                    self.collect_untracked(index, &path, rules, files)?;
+                }
+            } else if !index.contains(&path) && !rules.is_ignored(&path, false) {
+                files.push(path);
+            }
+        }
+
+        rules.truncate(rules_len);
+        Ok(())
+    }
+}
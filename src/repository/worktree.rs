@@ -0,0 +1,364 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diff,
+    error::{repository::ResolveRefError, WorktreeError},
+    index::Index,
+    pathspec::Pathspec,
+    repository::clean::CleanOptions,
+    DirectoryManager,
+};
+
+use super::{
+    ref_backend::{RefStorage, RefValue},
+    refs, GitRepository,
+};
+
+/// A work tree of the repository: the main one, or a linked one whose administrative files are
+/// in `.git/worktrees/<name>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worktree {
+    pub path: PathBuf,
+    /// The name of a linked work tree, `None` for the main one.
+    pub name: Option<String>,
+    /// The git directory holding its `HEAD` and index.
+    pub git_dir: PathBuf,
+    /// The commit checked out, `None` before the first commit.
+    pub head: Option<String>,
+    /// The branch checked out, such as `refs/heads/master`, `None` if `HEAD` is detached.
+    pub branch: Option<String>,
+    /// Why the work tree is locked, possibly empty, `None` if it isn't.
+    pub locked: Option<String>,
+    /// Why the administrative files of the work tree can be pruned, if they can.
+    pub prunable: Option<String>,
+}
+
+impl Worktree {
+    pub fn is_main(&self) -> bool {
+        self.name.is_none()
+    }
+
+    fn display_path(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// The name of the administrative directory of a work tree at `path`: its file name, without
+/// characters that would make an invalid ref component.
+fn worktree_name(path: &Path) -> String {
+    let name: String = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect();
+    match name.trim_start_matches('.') {
+        "" => "worktree".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// Worktree methods
+impl GitRepository {
+    fn worktrees_path(&self) -> PathBuf {
+        self.directory_manager.common_dir.join("worktrees")
+    }
+
+    /// The directories of the work tree whose git directory is `git_dir`, to reach its own
+    /// refs such as its `HEAD`.
+    fn worktree_directory_manager(&self, git_dir: &Path) -> DirectoryManager {
+        DirectoryManager::with_git_dirs(
+            self.directory_manager.work_tree.clone(),
+            git_dir.to_path_buf(),
+            self.directory_manager.common_dir.clone(),
+        )
+    }
+
+    /// Read the `HEAD` of the git directory `git_dir`.
+    fn read_worktree_head(
+        &self,
+        git_dir: &Path,
+    ) -> Result<(Option<String>, Option<String>), WorktreeError> {
+        let directory_manager = self.worktree_directory_manager(git_dir);
+        let head = self.ref_backend_for(&directory_manager).read_ref("HEAD")?;
+        Ok(match head {
+            Some(RefValue::Symbolic(branch)) => (self.resolve_ref(&branch).ok(), Some(branch)),
+            Some(RefValue::Hash(hash)) => (Some(hash), None),
+            None => (None, None),
+        })
+    }
+
+    /// Point the `HEAD` of the work tree `worktree` to the branch `branch`.
+    pub(crate) fn set_worktree_head(
+        &self,
+        worktree: &Worktree,
+        branch: &str,
+    ) -> Result<(), ResolveRefError> {
+        let directory_manager = self.worktree_directory_manager(&worktree.git_dir);
+        let backend = self.ref_backend_for(&directory_manager);
+        backend.write_symbolic_ref("HEAD", branch)
+    }
+
+    fn read_worktree(&self, name: &str) -> Result<Worktree, WorktreeError> {
+        let git_dir = self.worktrees_path().join(name);
+        let (head, branch) = self.read_worktree_head(&git_dir)?;
+        let locked = match std::fs::read_to_string(git_dir.join("locked")) {
+            Ok(reason) => Some(reason.trim_end().to_string()),
+            Err(error) if error.kind() == ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+
+        // `gitdir` holds the path of the `.git` file of the work tree.
+        let (path, prunable) = match std::fs::read_to_string(git_dir.join("gitdir")) {
+            Ok(dot_git) => {
+                let dot_git = PathBuf::from(dot_git.trim_end());
+                let prunable = (!dot_git.is_file())
+                    .then(|| "gitdir file points to non-existent location".to_string());
+                let path = dot_git.parent().map(Path::to_path_buf).unwrap_or(dot_git);
+                (path, prunable)
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => (
+                git_dir.clone(),
+                Some("gitdir file does not exist".to_string()),
+            ),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Worktree {
+            path,
+            name: Some(name.to_string()),
+            git_dir,
+            head,
+            branch,
+            locked,
+            prunable,
+        })
+    }
+
+    /// Every work tree of the repository, the main one first and the linked ones sorted by path.
+    pub fn worktrees(&self) -> Result<Vec<Worktree>, WorktreeError> {
+        let common_dir = &self.directory_manager.common_dir;
+        let (head, branch) = self.read_worktree_head(common_dir)?;
+        let main = Worktree {
+            path: common_dir
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| common_dir.clone()),
+            name: None,
+            git_dir: common_dir.clone(),
+            head,
+            branch,
+            locked: None,
+            prunable: None,
+        };
+
+        let mut linked = vec![];
+        match std::fs::read_dir(self.worktrees_path()) {
+            Ok(entries) => {
+                for entry in entries {
+                    let name = entry?.file_name().to_string_lossy().into_owned();
+                    linked.push(self.read_worktree(&name)?);
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        linked.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(std::iter::once(main).chain(linked).collect())
+    }
+
+    /// The work tree at `path`, or whose linked work tree name is `path`.
+    pub fn find_worktree(&self, path: &Path) -> Result<Worktree, WorktreeError> {
+        let canonical = path.canonicalize().ok();
+        self.worktrees()?
+            .into_iter()
+            .find(|worktree| {
+                canonical.is_some() && worktree.path.canonicalize().ok() == canonical
+                    || worktree.name.as_deref() == path.to_str()
+            })
+            .ok_or_else(|| WorktreeError::NotAWorktree(path.display().to_string()))
+    }
+
+    /// The work tree other than this one that has `branch` checked out, if any.
+    pub fn worktree_with_branch(&self, branch: &str) -> Result<Option<Worktree>, WorktreeError> {
+        let own_git_dir = self.directory_manager.dot_git_path.canonicalize()?;
+        Ok(self.worktrees()?.into_iter().find(|worktree| {
+            worktree.branch.as_deref() == Some(branch)
+                && worktree.git_dir.canonicalize().ok().as_ref() != Some(&own_git_dir)
+        }))
+    }
+
+    /// Create a linked work tree at `path`, which must be missing or an empty directory, with
+    /// the commit `commit` checked out, on `branch` if given or with a detached `HEAD`.
+    pub fn add_worktree(
+        &self,
+        path: &Path,
+        branch: Option<&str>,
+        commit: &str,
+    ) -> Result<Worktree, WorktreeError> {
+        let is_empty = std::fs::read_dir(path).map_or(true, |mut entries| entries.next().is_none());
+        if !is_empty || (path.exists() && !path.is_dir()) {
+            return Err(WorktreeError::AlreadyExists(path.display().to_string()));
+        }
+        if let Some(branch) = branch {
+            let worktrees = self.worktrees()?;
+            let checked_out = worktrees
+                .iter()
+                .find(|worktree| worktree.branch.as_deref() == Some(branch));
+            if let Some(worktree) = checked_out {
+                return Err(WorktreeError::AlreadyCheckedOut(
+                    branch.trim_start_matches("refs/heads/").to_string(),
+                    worktree.display_path(),
+                ));
+            }
+        }
+
+        std::fs::create_dir_all(path)?;
+        let path = path.canonicalize()?;
+        let worktrees_path = self.worktrees_path();
+        let base_name = worktree_name(&path);
+        let mut name = base_name.clone();
+        let mut suffix = 1;
+        while worktrees_path.join(&name).exists() {
+            name = format!("{}{}", base_name, suffix);
+            suffix += 1;
+        }
+
+        let git_dir = worktrees_path.join(&name);
+        std::fs::create_dir_all(&git_dir)?;
+        let git_dir = git_dir.canonicalize()?;
+        let dot_git = path.join(".git");
+        std::fs::write(git_dir.join("gitdir"), format!("{}\n", dot_git.display()))?;
+        std::fs::write(git_dir.join("commondir"), "../..\n")?;
+        // With reftable, `HEAD` is in the stack of the work tree, written once the files are
+        // checked out, and the file only tells tools unaware of reftable that this is a git
+        // directory.
+        let reftable = self.ref_storage() == RefStorage::Reftable;
+        match reftable {
+            true => std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/.invalid\n")?,
+            false => refs::write_ref(&git_dir.join("HEAD"), commit)?,
+        }
+        std::fs::write(&dot_git, format!("gitdir: {}\n", git_dir.display()))?;
+
+        let worktree = GitRepository::load(&path)?;
+        let mut index = Index::new();
+        worktree.reset_work_tree(&mut index, Some(self.read_tree(commit)?))?;
+        index.write(&worktree.directory_manager.index_file)?;
+        match branch {
+            Some(branch) => worktree.set_head_to_branch(branch, "")?,
+            None if reftable => worktree.detach_head(commit, "")?,
+            None => {}
+        }
+        self.read_worktree(&name)
+    }
+
+    /// Whether the work tree has changes to tracked files, staged or not, or untracked files
+    /// that aren't ignored.
+    fn has_local_changes(&self, worktree: &Worktree) -> Result<bool, WorktreeError> {
+        let repo = GitRepository::load(&worktree.path)?;
+        let index = Index::load(&repo.directory_manager.index_file)?;
+        let everything = Pathspec::default();
+        let head_tree = repo
+            .head_commit()?
+            .map(|hash| repo.read_tree(&hash))
+            .transpose()?;
+        let index_files = diff::index_files(&index, &everything);
+        let staged = diff::diff_files(
+            diff::tree_files(&repo, head_tree, &everything)?,
+            index_files.clone(),
+        );
+        let unstaged = diff::diff_files(
+            index_files,
+            diff::work_tree_files(&repo, &index, &everything)?,
+        );
+        let options = CleanOptions {
+            directories: true,
+            ..Default::default()
+        };
+        let untracked = repo.clean_paths(&index, &everything, options)?;
+        Ok(!staged.is_empty() || !unstaged.is_empty() || !untracked.is_empty())
+    }
+
+    /// Delete a linked work tree and its administrative files. Unless `force` is at least 1,
+    /// work trees with local changes are kept, and unless it's 2, locked ones are.
+    pub fn remove_worktree(&self, worktree: &Worktree, force: u8) -> Result<(), WorktreeError> {
+        if worktree.is_main() {
+            return Err(WorktreeError::MainWorktree(worktree.display_path()));
+        }
+        if worktree.locked.is_some() && force < 2 {
+            return Err(WorktreeError::Locked(worktree.locked.clone()));
+        }
+        let exists = worktree.path.join(".git").is_file();
+        if exists && force == 0 && self.has_local_changes(worktree)? {
+            return Err(WorktreeError::LocalChanges(worktree.display_path()));
+        }
+
+        if exists {
+            std::fs::remove_dir_all(&worktree.path)?;
+        }
+        self.delete_worktree_files(worktree)
+    }
+
+    fn delete_worktree_files(&self, worktree: &Worktree) -> Result<(), WorktreeError> {
+        std::fs::remove_dir_all(&worktree.git_dir)?;
+        // The directory of the work trees goes once empty.
+        let _ = std::fs::remove_dir(self.worktrees_path());
+        Ok(())
+    }
+
+    /// Lock a linked work tree, so that it's neither pruned nor removed.
+    pub fn lock_worktree(
+        &self,
+        worktree: &Worktree,
+        reason: Option<&str>,
+    ) -> Result<(), WorktreeError> {
+        if worktree.is_main() {
+            return Err(WorktreeError::MainWorktreeLock);
+        }
+        if worktree.locked.is_some() {
+            return Err(WorktreeError::AlreadyLocked(
+                worktree.display_path(),
+                worktree.locked.clone(),
+            ));
+        }
+        let content = reason.map(|reason| format!("{}\n", reason));
+        std::fs::write(worktree.git_dir.join("locked"), content.unwrap_or_default())?;
+        Ok(())
+    }
+
+    pub fn unlock_worktree(&self, worktree: &Worktree) -> Result<(), WorktreeError> {
+        if worktree.is_main() {
+            return Err(WorktreeError::MainWorktreeLock);
+        }
+        if worktree.locked.is_none() {
+            return Err(WorktreeError::NotLocked(worktree.display_path()));
+        }
+        std::fs::remove_file(worktree.git_dir.join("locked"))?;
+        Ok(())
+    }
+
+    /// Delete the administrative files of the linked work trees that are gone, unless they are
+    /// locked. Returns the work trees pruned, or that would be with `dry_run`.
+    pub fn prune_worktrees(&self, dry_run: bool) -> Result<Vec<Worktree>, WorktreeError> {
+        let prunable: Vec<Worktree> = self
+            .worktrees()?
+            .into_iter()
+            .filter(|worktree| worktree.prunable.is_some() && worktree.locked.is_none())
+            .collect();
+        if !dry_run {
+            for worktree in &prunable {
+                self.delete_worktree_files(worktree)?;
+            }
+        }
+        Ok(prunable)
+    }
+}
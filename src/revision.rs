@@ -0,0 +1,330 @@
+//! Git's revision grammar, naming an object relative to refs, the reflog, the history or the
+//! index.
+//!
+//! A revision starts with a name, a ref or a hash prefix, optionally followed by `@{<n>}` for
+//! an entry of its reflog, `@{upstream}` for its upstream, and any number of `~<n>` (the n-th
+//! first-parent ancestor), `^<n>` (the n-th parent) and `^{<type>}` (peel to a type, `^{}` for
+//! tags only, `^{/<regex>}` for the youngest commit whose message matches). `@` alone is
+//! `HEAD`, `@{-<n>}` is the n-th branch checked out before the current one.
+//!
+//! `<rev>:<path>` names the blob or tree at `path` in the tree of `rev`, `:<path>` and
+//! `:<stage>:<path>` an entry of the index, and `:/<regex>` the youngest commit reachable from
+//! any ref whose message matches.
+
+use std::fmt::Display;
+
+use crate::{error::RevisionError, git_object::Type};
+
+/// What `^{...}` peels a revision to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeelTarget {
+    /// `^{<type>}`: an object of that type.
+    Type(Type),
+    /// `^{object}`: the object itself, which must exist.
+    Object,
+    /// `^{}`: the first object that isn't a tag.
+    NonTag,
+}
+
+/// The kind of object a revision should name, which decides what a short hash stands for when
+/// objects of several types start with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExpectedType {
+    #[default]
+    Any,
+    /// A commit, or a tag pointing to one.
+    Commit,
+    /// A tree, or a commit or a tag pointing to one.
+    Tree,
+}
+
+impl ExpectedType {
+    /// The type an object must peel to, if any.
+    pub fn peeled_type(self) -> Option<Type> {
+        match self {
+            ExpectedType::Any => None,
+            ExpectedType::Commit => Some(Type::Commit),
+            ExpectedType::Tree => Some(Type::Tree),
+        }
+    }
+}
+
+impl From<Type> for ExpectedType {
+    fn from(type_: Type) -> Self {
+        match type_ {
+            Type::Commit => ExpectedType::Commit,
+            Type::Tree => ExpectedType::Tree,
+            Type::Blob | Type::Tag => ExpectedType::Any,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revision {
+    /// A ref or an object name, such as `HEAD`, `main`, `refs/remotes/origin/main` or a hash
+    /// prefix.
+    Name(String),
+    /// `<ref>@{<n>}`: the value of the ref `n` updates ago, from the reflog of the current
+    /// branch if no ref is given.
+    ReflogEntry(Option<String>, usize),
+    /// `<branch>@{upstream}`: the branch the branch tracks, the current branch by default.
+    Upstream(Option<String>),
+    /// `@{-<n>}`: the n-th branch or commit checked out before the current one.
+    PreviousCheckout(usize),
+    /// `<rev>~<n>`: the n-th ancestor, following first parents.
+    Ancestor(Box<Revision>, usize),
+    /// `<rev>^<n>`: the n-th parent, the commit itself for 0.
+    Parent(Box<Revision>, usize),
+    Peel(Box<Revision>, PeelTarget),
+    /// `<rev>^{/<regex>}`: the youngest commit reachable from `rev` whose message matches.
+    MessageSearchFrom(Box<Revision>, String),
+    /// `:/<regex>`: the youngest commit reachable from any ref whose message matches.
+    MessageSearch(String),
+    /// `<rev>:<path>`: the object at `path` in the tree of `rev`, the tree itself if empty.
+    TreePath(Box<Revision>, String),
+    /// `:<stage>:<path>`: the index entry of `path` at `stage`, 0 for `:<path>`.
+    IndexPath(u8, String),
+}
+
+/// The position of the `:` separating a revision from a path, outside of `{...}`.
+fn path_separator(revision: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in revision.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split a leading number off `text`, `default` if it doesn't start with a digit.
+fn leading_number(text: &str, default: usize) -> Result<(usize, &str), RevisionError> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    match end {
+        0 => Ok((default, text)),
+        _ => Ok((
+            text[..end]
+                .parse()
+                .map_err(|_| RevisionError::Invalid(text.to_string()))?,
+            &text[end..],
+        )),
+    }
+}
+
+impl Revision {
+    pub fn parse(revision: &str) -> Result<Self, RevisionError> {
+        let invalid = || RevisionError::Invalid(revision.to_string());
+        if revision.is_empty() {
+            return Err(invalid());
+        }
+
+        if let Some(rest) = revision.strip_prefix(':') {
+            if let Some(pattern) = rest.strip_prefix('/') {
+                return Ok(Revision::MessageSearch(pattern.to_string()));
+            }
+            let stage = rest.as_bytes();
+            if stage.len() > 2 && stage[1] == b':' && (b'0'..=b'3').contains(&stage[0]) {
+                return Ok(Revision::IndexPath(stage[0] - b'0', rest[2..].to_string()));
+            }
+            return Ok(Revision::IndexPath(0, rest.to_string()));
+        }
+        if let Some(i) = path_separator(revision) {
+            let base = Self::parse(&revision[..i])?;
+            return Ok(Revision::TreePath(
+                Box::new(base),
+                revision[i + 1..].to_string(),
+            ));
+        }
+
+        // The name goes up to the first `~` or `^`, which ref names can't contain.
+        let end = revision.find(['~', '^']).unwrap_or(revision.len());
+        let (name, mut suffixes) = revision.split_at(end);
+        let mut parsed = Self::parse_name(name).ok_or_else(invalid)?;
+
+        while !suffixes.is_empty() {
+            if let Some(rest) = suffixes.strip_prefix("^{") {
+                let close = rest.find('}').ok_or_else(invalid)?;
+                let inside = &rest[..close];
+                parsed = match inside.strip_prefix('/') {
+                    Some(pattern) => {
+                        Revision::MessageSearchFrom(Box::new(parsed), pattern.to_string())
+                    }
+                    None => {
+                        let target = match inside {
+                            "" => PeelTarget::NonTag,
+                            "object" => PeelTarget::Object,
+                            type_ => PeelTarget::Type(type_.parse().map_err(|_| invalid())?),
+                        };
+                        Revision::Peel(Box::new(parsed), target)
+                    }
+                };
+                suffixes = &rest[close + 1..];
+            } else if let Some(rest) = suffixes.strip_prefix('~') {
+                let (count, rest) = leading_number(rest, 1)?;
+                parsed = Revision::Ancestor(Box::new(parsed), count);
+                suffixes = rest;
+            } else if let Some(rest) = suffixes.strip_prefix('^') {
+                let (count, rest) = leading_number(rest, 1)?;
+                parsed = Revision::Parent(Box::new(parsed), count);
+                suffixes = rest;
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Parse the name at the start of a revision, with its `@{...}` suffix if any.
+    fn parse_name(name: &str) -> Option<Self> {
+        if name == "@" {
+            return Some(Revision::Name("HEAD".to_string()));
+        }
+        let Some((base, inside)) = name
+            .strip_suffix('}')
+            .and_then(|name| name.rsplit_once("@{"))
+        else {
+            return (!name.is_empty()).then(|| Revision::Name(name.to_string()));
+        };
+
+        let base = (!base.is_empty()).then(|| base.to_string());
+        if inside.eq_ignore_ascii_case("upstream") || inside.eq_ignore_ascii_case("u") {
+            return Some(Revision::Upstream(base));
+        }
+        if let Some(count) = inside.strip_prefix('-') {
+            return match base {
+                None => Some(Revision::PreviousCheckout(count.parse().ok()?)),
+                Some(_) => None,
+            };
+        }
+        Some(Revision::ReflogEntry(base, inside.parse().ok()?))
+    }
+}
+
+impl Display for PeelTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PeelTarget::Type(type_) => write!(f, "{}", type_),
+            PeelTarget::Object => write!(f, "object"),
+            PeelTarget::NonTag => Ok(()),
+        }
+    }
+}
+
+impl Display for Revision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let base = |base: &Option<String>| base.clone().unwrap_or_default();
+        match self {
+            Revision::Name(name) => write!(f, "{}", name),
+            Revision::ReflogEntry(name, n) => write!(f, "{}@{{{}}}", base(name), n),
+            Revision::Upstream(name) => write!(f, "{}@{{upstream}}", base(name)),
+            Revision::PreviousCheckout(n) => write!(f, "@{{-{}}}", n),
+            Revision::Ancestor(revision, n) => write!(f, "{}~{}", revision, n),
+            Revision::Parent(revision, n) => write!(f, "{}^{}", revision, n),
+            Revision::Peel(revision, target) => write!(f, "{}^{{{}}}", revision, target),
+            Revision::MessageSearchFrom(revision, pattern) => {
+                write!(f, "{}^{{/{}}}", revision, pattern)
+            }
+            Revision::MessageSearch(pattern) => write!(f, ":/{}", pattern),
+            Revision::TreePath(revision, path) => write!(f, "{}:{}", revision, path),
+            Revision::IndexPath(0, path) => write!(f, ":{}", path),
+            Revision::IndexPath(stage, path) => write!(f, ":{}:{}", stage, path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PeelTarget, Revision};
+    use crate::{error::RevisionError, git_object::Type};
+
+    fn name(name: &str) -> Box<Revision> {
+        Box::new(Revision::Name(name.to_string()))
+    }
+
+    #[test]
+    fn parse_should_read_names_and_their_suffixes() {
+        assert_eq!(
+            Revision::parse("HEAD~3").unwrap(),
+            Revision::Ancestor(name("HEAD"), 3)
+        );
+        assert_eq!(
+            Revision::parse("main^2~").unwrap(),
+            Revision::Ancestor(Box::new(Revision::Parent(name("main"), 2)), 1)
+        );
+        assert_eq!(
+            Revision::parse("v1.0^{commit}").unwrap(),
+            Revision::Peel(name("v1.0"), PeelTarget::Type(Type::Commit))
+        );
+        assert_eq!(
+            Revision::parse("v1.0^{}").unwrap(),
+            Revision::Peel(name("v1.0"), PeelTarget::NonTag)
+        );
+        assert_eq!(
+            Revision::parse("HEAD^{/fix: typo}").unwrap(),
+            Revision::MessageSearchFrom(name("HEAD"), "fix: typo".to_string())
+        );
+        assert_eq!(
+            Revision::parse("refs/remotes/origin/main").unwrap(),
+            *name("refs/remotes/origin/main")
+        );
+        assert!(Revision::parse("HEAD^{nothing}").is_err());
+        assert!(Revision::parse("").is_err());
+    }
+
+    #[test]
+    fn parse_should_read_reflog_upstream_and_path_forms() {
+        assert_eq!(
+            Revision::parse("main@{2}").unwrap(),
+            Revision::ReflogEntry(Some("main".to_string()), 2)
+        );
+        assert_eq!(Revision::parse("@{u}").unwrap(), Revision::Upstream(None));
+        assert_eq!(
+            Revision::parse("@{-1}").unwrap(),
+            Revision::PreviousCheckout(1)
+        );
+        assert_eq!(
+            Revision::parse("@~1").unwrap(),
+            Revision::Ancestor(name("HEAD"), 1)
+        );
+        assert_eq!(
+            Revision::parse("HEAD:src/lib.rs").unwrap(),
+            Revision::TreePath(name("HEAD"), "src/lib.rs".to_string())
+        );
+        assert_eq!(
+            Revision::parse(":2:file").unwrap(),
+            Revision::IndexPath(2, "file".to_string())
+        );
+        assert_eq!(
+            Revision::parse(":file").unwrap(),
+            Revision::IndexPath(0, "file".to_string())
+        );
+        assert_eq!(
+            Revision::parse(":/fix typo").unwrap(),
+            Revision::MessageSearch("fix typo".to_string())
+        );
+        assert!(Revision::parse("main@{-1}").is_err());
+    }
+
+    #[test]
+    fn parse_should_reject_trailing_garbage_after_suffixes() {
+        for revision in [
+            "HEAD~0x",
+            "HEAD^{commit}foo",
+            "HEAD^{/t}}",
+            "HEAD~1é",
+            "HEAD^{}é",
+            "HEAD^é",
+        ] {
+            assert!(
+                matches!(Revision::parse(revision), Err(RevisionError::Invalid(_))),
+                "{}",
+                revision
+            );
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Sparse checkout patterns, read from `.git/info/sparse-checkout` when `core.sparseCheckout` is
+//! set. Index entries outside of them get the skip-worktree bit and have no work tree file.
+//!
+//! In cone mode, set by `core.sparseCheckoutCone`, the patterns name directories: the files at
+//! the top of the work tree are always checked out, along with every file below the directories
+//! named and the files directly inside their leading directories. Otherwise the patterns are
+//! gitignore-like patterns selecting the files checked out, the last matching pattern deciding.
+
+use std::collections::BTreeSet;
+
+use crate::ignore::IgnoreRules;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SparsePatterns {
+    Cone {
+        /// Directories checked out as a whole.
+        recursive: BTreeSet<String>,
+        /// Leading directories of those, whose files directly inside are checked out.
+        parents: BTreeSet<String>,
+    },
+    Full {
+        lines: Vec<String>,
+        rules: IgnoreRules,
+    },
+}
+
+/// Escape the characters of a cone directory that patterns would treat as wildcards.
+fn escape(directory: &str) -> String {
+    let mut escaped = String::with_capacity(directory.len());
+    for c in directory.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The directory of a cone pattern, or `None` if it has wildcards.
+fn unescape(pattern: &str) -> Option<String> {
+    let mut directory = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => directory.push(chars.next()?),
+            '*' | '?' | '[' => return None,
+            c => directory.push(c),
+        }
+    }
+    Some(directory)
+}
+
+impl SparsePatterns {
+    /// Cone mode patterns checking out `directories`, relative to the top of the work tree.
+    pub fn cone(directories: impl IntoIterator<Item = String>) -> Self {
+        let directories: BTreeSet<String> = directories
+            .into_iter()
+            .map(|directory| directory.trim_matches('/').to_string())
+            .filter(|directory| !directory.is_empty())
+            .collect();
+        // Directories below another one are already checked out with it.
+        let recursive: BTreeSet<String> = directories
+            .iter()
+            .filter(|directory| {
+                !directories.iter().any(|other| {
+                    directory
+                        .strip_prefix(other.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+                })
+            })
+            .cloned()
+            .collect();
+        let parents = recursive
+            .iter()
+            .flat_map(|directory| {
+                directory
+                    .match_indices('/')
+                    .map(|(i, _)| directory[..i].to_string())
+            })
+            .collect();
+        Self::Cone { recursive, parents }
+    }
+
+    pub fn full(lines: Vec<String>) -> Self {
+        let mut rules = IgnoreRules::new();
+        rules.add_patterns("", &lines.join("\n"));
+        Self::Full { lines, rules }
+    }
+
+    /// Parse the content of a sparse-checkout file. Cone mode patterns that don't have the
+    /// shape written by [`SparsePatterns::cone`] are read as full patterns.
+    pub fn parse(content: &str, cone: bool) -> Self {
+        let lines: Vec<String> = content
+            .lines()
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        if !cone {
+            return Self::full(lines);
+        }
+
+        let mut directories = BTreeSet::new();
+        let mut parents = BTreeSet::new();
+        for line in &lines {
+            if line == "/*" || line == "!/*/" {
+                continue;
+            }
+            let parent = line
+                .strip_prefix("!/")
+                .and_then(|line| line.strip_suffix("/*/"));
+            let directory = line
+                .strip_prefix('/')
+                .and_then(|line| line.strip_suffix('/'));
+            match (parent.and_then(unescape), directory.and_then(unescape)) {
+                (Some(parent), _) => {
+                    parents.insert(parent);
+                }
+                (None, Some(directory)) if parent.is_none() => {
+                    directories.insert(directory);
+                }
+                _ => return Self::full(lines),
+            }
+        }
+        Self::cone(directories.difference(&parents).cloned())
+    }
+
+    pub fn is_cone(&self) -> bool {
+        matches!(self, Self::Cone { .. })
+    }
+
+    /// These patterns with `patterns` added: directories in cone mode, patterns otherwise.
+    pub fn with(self, patterns: Vec<String>) -> Self {
+        match self {
+            Self::Cone { recursive, .. } => Self::cone(recursive.into_iter().chain(patterns)),
+            Self::Full { mut lines, .. } => {
+                lines.extend(patterns);
+                Self::full(lines)
+            }
+        }
+    }
+
+    /// The directories checked out in cone mode, the patterns otherwise.
+    pub fn list(&self) -> Vec<String> {
+        match self {
+            Self::Cone { recursive, .. } => recursive.iter().cloned().collect(),
+            Self::Full { lines, .. } => lines.clone(),
+        }
+    }
+
+    /// Whether the file at `path`, relative to the top of the work tree, is checked out.
+    pub fn includes(&self, path: &str) -> bool {
+        match self {
+            Self::Cone { recursive, parents } => {
+                let Some((parent, _)) = path.rsplit_once('/') else {
+                    return true;
+                };
+                parents.contains(parent)
+                    || path
+                        .match_indices('/')
+                        .any(|(i, _)| recursive.contains(&path[..i]))
+            }
+            Self::Full { rules, .. } => {
+                // The deepest of the path and its leading directories that a pattern matches
+                // decides.
+                if let Some(included) = rules.decision(path, false) {
+                    return included;
+                }
+                let mut directory = path;
+                while let Some((parent, _)) = directory.rsplit_once('/') {
+                    directory = parent;
+                    if let Some(included) = rules.decision(directory, true) {
+                        return included;
+                    }
+                }
+                false
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SparsePatterns {
+    /// The content of the sparse-checkout file.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cone { recursive, parents } => {
+                writeln!(f, "/*\n!/*/")?;
+                for directory in recursive.union(parents) {
+                    let escaped = escape(directory);
+                    writeln!(f, "/{}/", escaped)?;
+                    if !recursive.contains(directory) {
+                        writeln!(f, "!/{}/*/", escaped)?;
+                    }
+                }
+                Ok(())
+            }
+            Self::Full { lines, .. } => lines.iter().try_for_each(|line| writeln!(f, "{}", line)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SparsePatterns;
+
+    #[test]
+    fn cone_patterns_should_include_whole_directories_and_their_parents_files() {
+        let patterns = SparsePatterns::cone(["a/b/".to_string(), "d".to_string()]);
+
+        assert_eq!(patterns.to_string(), "/*\n!/*/\n/a/\n!/a/*/\n/a/b/\n/d/\n");
+        assert_eq!(SparsePatterns::parse(&patterns.to_string(), true), patterns);
+        for (path, included) in [
+            ("top", true),
+            ("a/x", true),
+            ("a/c/x", false),
+            ("a/b/c/z", true),
+            ("d/w", true),
+            ("e/v", false),
+            ("dd/x", false),
+        ] {
+            assert_eq!(patterns.includes(path), included, "{}", path);
+        }
+        assert_eq!(
+            patterns
+                .with(vec!["a/b/c".to_string(), "e".to_string()])
+                .list(),
+            vec!["a/b", "d", "e"]
+        );
+    }
+
+    #[test]
+    fn full_patterns_should_let_the_deepest_match_decide() {
+        let patterns = SparsePatterns::parse("/*\n!/*/\n/a/\n!/a/b/\n*.txt\n", false);
+
+        for (path, included) in [
+            ("top", true),
+            ("a/x", true),
+            ("a/b/y", false),
+            ("a/b/y.txt", true),
+            ("e/v", false),
+        ] {
+            assert_eq!(patterns.includes(path), included, "{}", path);
+        }
+        // Patterns that cone mode can't read are full patterns.
+        assert!(!SparsePatterns::parse("*.txt\n", true).is_cone());
+    }
+}
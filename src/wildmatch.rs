@@ -0,0 +1,316 @@
+//! Git's glob matching, used by pathspecs, ignore rules and attributes.
+
+/// `*` and `?` don't match `/`, and `**` matches across directories.
+pub const WM_PATHNAME: u8 = 1;
+/// Case-insensitive matching.
+pub const WM_CASEFOLD: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Matched,
+    NoMatch,
+    /// No match, and no later position of an enclosing `*` can match either.
+    AbortAll,
+    /// No match for a `*` that can't cross a slash, but an enclosing `**` may still match.
+    AbortToStarStar,
+}
+
+/// Match `text` against the glob `pattern`, following git's `wildmatch()`.
+pub fn wildmatch(pattern: &str, text: &str, flags: u8) -> bool {
+    dowild(pattern.as_bytes(), text.as_bytes(), flags) == Outcome::Matched
+}
+
+/// Whether `pattern` contains any glob special character.
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.bytes().any(is_glob_special)
+}
+
+fn is_glob_special(c: u8) -> bool {
+    matches!(c, b'*' | b'?' | b'[' | b'\\')
+}
+
+fn at(s: &[u8], i: usize) -> u8 {
+    s.get(i).copied().unwrap_or(0)
+}
+
+fn fold(c: u8, flags: u8) -> u8 {
+    match flags & WM_CASEFOLD != 0 {
+        true => c.to_ascii_lowercase(),
+        false => c,
+    }
+}
+
+fn dowild(pattern: &[u8], text: &[u8], flags: u8) -> Outcome {
+    let (mut p, mut t) = (0, 0);
+
+    while p < pattern.len() {
+        let mut p_ch = fold(pattern[p], flags);
+        let mut t_ch = at(text, t);
+        if t_ch == 0 && p_ch != b'*' {
+            return Outcome::AbortAll;
+        }
+        t_ch = fold(t_ch, flags);
+
+        match p_ch {
+            b'?' => {
+                if flags & WM_PATHNAME != 0 && t_ch == b'/' {
+                    return Outcome::NoMatch;
+                }
+            }
+            b'*' => {
+                p += 1;
+                let match_slash;
+                if at(pattern, p) == b'*' {
+                    let previous_is_slash = p < 2 || pattern[p - 2] == b'/';
+                    while at(pattern, p) == b'*' {
+                        p += 1;
+                    }
+                    let next = at(pattern, p);
+                    if previous_is_slash
+                        && (next == 0
+                            || next == b'/'
+                            || (next == b'\\' && at(pattern, p + 1) == b'/'))
+                    {
+                        // "**/" may match no directory at all.
+                        if next == b'/'
+                            && dowild(&pattern[p + 1..], &text[t..], flags) == Outcome::Matched
+                        {
+                            return Outcome::Matched;
+                        }
+                        match_slash = true;
+                    } else {
+                        match_slash = flags & WM_PATHNAME == 0;
+                    }
+                } else {
+                    match_slash = flags & WM_PATHNAME == 0;
+                }
+
+                if p >= pattern.len() {
+                    // A trailing "**" matches everything, a trailing "*" only if there is no
+                    // slash left.
+                    if !match_slash && text[t..].contains(&b'/') {
+                        return Outcome::NoMatch;
+                    }
+                    return Outcome::Matched;
+                } else if !match_slash && pattern[p] == b'/' {
+                    // A single star followed by a slash matches up to the next slash.
+                    match text[t..].iter().position(|&c| c == b'/') {
+                        Some(slash) => {
+                            t += slash + 1;
+                            p += 1;
+                            continue;
+                        }
+                        None => return Outcome::NoMatch,
+                    }
+                }
+
+                loop {
+                    if t_ch == 0 {
+                        break;
+                    }
+                    // Skip ahead to the next occurrence of a literal following the star.
+                    if !is_glob_special(pattern[p]) {
+                        let literal = fold(pattern[p], flags);
+                        loop {
+                            t_ch = fold(at(text, t), flags);
+                            if t_ch == 0 || (!match_slash && t_ch == b'/') || t_ch == literal {
+                                break;
+                            }
+                            t += 1;
+                        }
+                        if t_ch != literal {
+                            return Outcome::NoMatch;
+                        }
+                    }
+
+                    let matched = dowild(&pattern[p..], &text[t..], flags);
+                    if matched != Outcome::NoMatch {
+                        if !match_slash || matched != Outcome::AbortToStarStar {
+                            return matched;
+                        }
+                    } else if !match_slash && t_ch == b'/' {
+                        return Outcome::AbortToStarStar;
+                    }
+                    t += 1;
+                    t_ch = fold(at(text, t), flags);
+                }
+                return Outcome::AbortAll;
+            }
+            b'[' => {
+                p += 1;
+                p_ch = at(pattern, p);
+                if p_ch == b'^' {
+                    p_ch = b'!';
+                }
+                let negated = p_ch == b'!';
+                if negated {
+                    p += 1;
+                    p_ch = at(pattern, p);
+                }
+
+                let mut previous: u8 = 0;
+                let mut matched = false;
+                loop {
+                    if p_ch == 0 {
+                        return Outcome::AbortAll;
+                    }
+                    if p_ch == b'\\' {
+                        p += 1;
+                        p_ch = at(pattern, p);
+                        if p_ch == 0 {
+                            return Outcome::AbortAll;
+                        }
+                        if t_ch == p_ch {
+                            matched = true;
+                        }
+                    } else if p_ch == b'-'
+                        && previous != 0
+                        && at(pattern, p + 1) != 0
+                        && at(pattern, p + 1) != b']'
+                    {
+                        p += 1;
+                        p_ch = at(pattern, p);
+                        if p_ch == b'\\' {
+                            p += 1;
+                            p_ch = at(pattern, p);
+                            if p_ch == 0 {
+                                return Outcome::AbortAll;
+                            }
+                        }
+                        let upper = t_ch.to_ascii_uppercase();
+                        if (previous..=p_ch).contains(&t_ch)
+                            || (flags & WM_CASEFOLD != 0
+                                && t_ch.is_ascii_lowercase()
+                                && (previous..=p_ch).contains(&upper))
+                        {
+                            matched = true;
+                        }
+                        p_ch = 0;
+                    } else if p_ch == b'[' && at(pattern, p + 1) == b':' {
+                        let start = p + 2;
+                        let mut end = start;
+                        while at(pattern, end) != 0 && pattern[end] != b']' {
+                            end += 1;
+                        }
+                        if at(pattern, end) == 0 {
+                            return Outcome::AbortAll;
+                        }
+                        if end == start || pattern[end - 1] != b':' {
+                            // No ":]", treat the bracket as a normal character.
+                            if t_ch == b'[' {
+                                matched = true;
+                            }
+                            previous = b'[';
+                            p += 1;
+                            p_ch = at(pattern, p);
+                            if p_ch == b']' {
+                                break;
+                            }
+                            continue;
+                        }
+                        p = end;
+                        match character_class(&pattern[start..end - 1], t_ch, flags) {
+                            Some(true) => matched = true,
+                            Some(false) => {}
+                            None => return Outcome::AbortAll,
+                        }
+                        p_ch = 0;
+                    } else if t_ch == p_ch {
+                        matched = true;
+                    }
+
+                    previous = p_ch;
+                    p += 1;
+                    p_ch = at(pattern, p);
+                    if p_ch == b']' {
+                        break;
+                    }
+                }
+                if matched == negated || (flags & WM_PATHNAME != 0 && t_ch == b'/') {
+                    return Outcome::NoMatch;
+                }
+            }
+            b'\\' => {
+                p += 1;
+                if t_ch != fold(at(pattern, p), flags) {
+                    return Outcome::NoMatch;
+                }
+            }
+            _ => {
+                if t_ch != p_ch {
+                    return Outcome::NoMatch;
+                }
+            }
+        }
+        p += 1;
+        t += 1;
+    }
+
+    match t < text.len() {
+        true => Outcome::NoMatch,
+        false => Outcome::Matched,
+    }
+}
+
+/// Whether `c` belongs to the POSIX class `name`, or `None` for an unknown class.
+fn character_class(name: &[u8], c: u8, flags: u8) -> Option<bool> {
+    Some(match name {
+        b"alnum" => c.is_ascii_alphanumeric(),
+        b"alpha" => c.is_ascii_alphabetic(),
+        b"blank" => c == b' ' || c == b'\t',
+        b"cntrl" => c.is_ascii_control(),
+        b"digit" => c.is_ascii_digit(),
+        b"graph" => c.is_ascii_graphic(),
+        b"lower" => c.is_ascii_lowercase(),
+        b"print" => c.is_ascii_graphic() || c == b' ',
+        b"punct" => c.is_ascii_punctuation(),
+        b"space" => c.is_ascii_whitespace() || c == 0x0b,
+        b"upper" => c.is_ascii_uppercase() || (flags & WM_CASEFOLD != 0 && c.is_ascii_lowercase()),
+        b"xdigit" => c.is_ascii_hexdigit(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wildmatch, WM_CASEFOLD, WM_PATHNAME};
+
+    #[test]
+    fn wildmatch_should_follow_git_semantics() {
+        let cases = [
+            ("foo", "foo", 0, true),
+            ("fo?", "foo", 0, true),
+            ("*.c", "dir/file.c", 0, true),
+            ("*.c", "dir/file.c", WM_PATHNAME, false),
+            ("**/file.c", "file.c", WM_PATHNAME, true),
+            ("**/file.c", "a/b/file.c", WM_PATHNAME, true),
+            ("a/**/b", "a/b", WM_PATHNAME, true),
+            ("a/**/b", "a/x/y/b", WM_PATHNAME, true),
+            ("a/*/b", "a/x/y/b", WM_PATHNAME, false),
+            ("a/**", "a/x/y", WM_PATHNAME, true),
+            ("[a-c]at", "bat", 0, true),
+            ("[!a-c]at", "bat", 0, false),
+            ("[[:digit:]]x", "7x", 0, true),
+            ("\\*", "*", 0, true),
+            ("\\*", "a", 0, false),
+            ("FOO", "foo", WM_CASEFOLD, true),
+            ("*foo*bar", "foo/bar", WM_PATHNAME, false),
+            (
+                "-*-*-*-*-*-*-12-*-*-*-m-*-*-*",
+                "-adobe-courier-bold-o-normal--12-120-75-75-m-70-iso8859-1",
+                0,
+                true,
+            ),
+        ];
+
+        for (pattern, text, flags, expected) in cases {
+            assert_eq!(
+                expected,
+                wildmatch(pattern, text, flags),
+                "{} ~ {}",
+                pattern,
+                text
+            );
+        }
+    }
+}
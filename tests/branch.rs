@@ -0,0 +1,126 @@
+mod test_utils;
+
+use rit::{
+    error::BranchError,
+    repository::{ref_backend::RefStorage, GitRepository},
+};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{write_commit, write_tree},
+};
+
+/// A repository with `master` on the second of two commits.
+fn create_repo_with_commits() -> (GitRepository, String, String) {
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let tree = write_tree(&repo, &[]);
+    let first = write_commit(&repo, &tree, &[], 1);
+    let second = write_commit(&repo, &tree, &[&first], 2);
+    repo.update_ref("refs/heads/master", &second).unwrap();
+    (repo, first, second)
+}
+
+fn branch_names(repo: &GitRepository) -> Vec<String> {
+    repo.branches(false)
+        .unwrap()
+        .iter()
+        .map(|branch| branch.path.display().to_string())
+        .collect()
+}
+
+#[test]
+fn delete_branch_should_refuse_unmerged_and_checked_out_branches() {
+    // Arrange
+    let (repo, first, second) = create_repo_with_commits();
+    let tree = write_tree(&repo, &[]);
+    let unmerged = write_commit(&repo, &tree, &[&first], 3);
+    repo.create_branch("merged", &first, "HEAD~1", false)
+        .unwrap();
+    repo.create_branch("unmerged", &unmerged, "unmerged", false)
+        .unwrap();
+
+    // Act & Assert
+    assert!(matches!(
+        repo.create_branch("merged", &second, "HEAD", false),
+        Err(BranchError::AlreadyExists(_))
+    ));
+    assert!(matches!(
+        repo.create_branch("bad..name", &second, "HEAD", false),
+        Err(BranchError::InvalidName(_))
+    ));
+    assert!(matches!(
+        repo.delete_branch("unmerged", false),
+        Err(BranchError::NotFullyMerged(_))
+    ));
+    assert!(matches!(
+        repo.delete_branch("master", true),
+        Err(BranchError::CheckedOut(..))
+    ));
+    assert_eq!(repo.delete_branch("merged", false).unwrap(), first);
+    assert_eq!(repo.delete_branch("unmerged", true).unwrap(), unmerged);
+    assert_eq!(branch_names(&repo), vec!["refs/heads/master"]);
+}
+
+#[test]
+fn rename_branch_should_move_its_reflog_configuration_and_head() {
+    // Arrange
+    let (repo, first, second) = create_repo_with_commits();
+    repo.create_branch("topic", &first, "HEAD~1", false)
+        .unwrap();
+    assert_eq!(
+        repo.set_upstream("master", "topic").unwrap(),
+        "refs/heads/topic"
+    );
+
+    // Act
+    repo.rename_branch("master", "main", false).unwrap();
+    let repo = GitRepository::load(&repo.directory_manager.work_tree).unwrap();
+
+    // Assert
+    assert_eq!(repo.head_branch().unwrap().unwrap(), "refs/heads/main");
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), second);
+    assert_eq!(
+        branch_names(&repo),
+        vec!["refs/heads/main", "refs/heads/topic"]
+    );
+    assert_eq!(repo.upstream("main").unwrap(), "refs/heads/topic");
+    assert_eq!(repo.upstream("master"), None);
+    let messages: Vec<String> = repo
+        .read_reflog("refs/heads/main")
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.message)
+        .collect();
+    assert_eq!(
+        messages,
+        vec!["", "Branch: renamed refs/heads/master to refs/heads/main"]
+    );
+    assert!(repo.read_reflog("refs/heads/master").unwrap().is_empty());
+    assert!(matches!(
+        repo.rename_branch("main", "topic", false),
+        Err(BranchError::AlreadyExists(_))
+    ));
+}
+
+#[test]
+fn rename_branch_should_move_a_branch_below_or_above_itself() {
+    for ref_storage in [RefStorage::Files, RefStorage::Reftable] {
+        // Arrange
+        let repo = GitRepository::create_with(generate_random_path(), ref_storage).unwrap();
+        let repo = GitRepository::load(&repo.directory_manager.work_tree).unwrap();
+        let tree = write_tree(&repo, &[]);
+        let commit = write_commit(&repo, &tree, &[], 1);
+        repo.update_ref("refs/heads/a", &commit).unwrap();
+
+        // Act
+        repo.rename_branch("a", "a/b", false).unwrap();
+        let nested = branch_names(&repo);
+        repo.rename_branch("a/b", "a", false).unwrap();
+
+        // Assert
+        assert_eq!(nested, vec!["refs/heads/a/b"]);
+        assert_eq!(branch_names(&repo), vec!["refs/heads/a"]);
+        assert_eq!(repo.resolve_ref("refs/heads/a").unwrap(), commit);
+        assert_eq!(repo.read_reflog("refs/heads/a").unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,353 @@
+mod test_utils;
+
+use std::os::unix::fs::PermissionsExt;
+
+use rit::{
+    diff, error::CheckoutError, git_config::GitConfig, index::Index, pathspec::Pathspec,
+    repository::GitRepository, sparse::SparsePatterns,
+};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{stage_file, write_blob, write_tree},
+};
+
+#[test]
+fn switch_tree_should_update_changed_files_and_keep_local_changes() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "kept", "kept\n");
+    stage_file(&repo, &mut index, "changed", "old\n");
+    stage_file(&repo, &mut index, "dir/removed", "removed\n");
+    let kept = write_blob(&repo, "kept\n");
+    let old = write_blob(&repo, "old\n");
+    let removed = write_blob(&repo, "removed\n");
+    let new = write_blob(&repo, "new\n");
+    let dir = write_tree(&repo, &[("100644", "removed", &removed)]);
+    let from = write_tree(
+        &repo,
+        &[
+            ("100644", "changed", &old),
+            ("40000", "dir", &dir),
+            ("100644", "kept", &kept),
+        ],
+    );
+    let to = write_tree(
+        &repo,
+        &[
+            ("100644", "added", &new),
+            ("100755", "changed", &new),
+            ("100644", "kept", &kept),
+        ],
+    );
+    std::fs::write(repo.work_tree_path("kept"), "local\n").unwrap();
+
+    // Act
+    repo.switch_tree(
+        &mut index,
+        Some(repo.read_tree(&from).unwrap()),
+        Some(repo.read_tree(&to).unwrap()),
+    )
+    .unwrap();
+
+    // Assert
+    let read = |path: &str| std::fs::read_to_string(repo.work_tree_path(path)).unwrap();
+    assert_eq!(read("added"), "new\n");
+    assert_eq!(read("changed"), "new\n");
+    assert_eq!(read("kept"), "local\n");
+    assert!(!repo.work_tree_path("dir").exists());
+
+    let paths: Vec<&str> = index.entries().iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec!["added", "changed", "kept"]);
+    assert_eq!(index.entry("changed").unwrap().hash, new);
+    assert!(index.entry("changed").unwrap().mode.is_executable());
+}
+
+#[test]
+fn switch_tree_should_refuse_to_overwrite_local_changes_and_untracked_files() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "file", "old\n");
+    let old = write_blob(&repo, "old\n");
+    let new = write_blob(&repo, "new\n");
+    let from = write_tree(&repo, &[("100644", "file", &old)]);
+    let to = write_tree(
+        &repo,
+        &[("100644", "file", &new), ("100644", "untracked", &new)],
+    );
+    std::fs::write(repo.work_tree_path("file"), "local\n").unwrap();
+    std::fs::write(repo.work_tree_path("untracked"), "untracked\n").unwrap();
+    let switch = |index: &mut Index| {
+        repo.switch_tree(
+            index,
+            Some(repo.read_tree(&from).unwrap()),
+            Some(repo.read_tree(&to).unwrap()),
+        )
+    };
+
+    // Act
+    let local_changes = switch(&mut index);
+    std::fs::write(repo.work_tree_path("file"), "old\n").unwrap();
+    let untracked = switch(&mut index);
+
+    // Assert
+    assert!(matches!(
+        local_changes,
+        Err(CheckoutError::LocalChanges(paths)) if paths == vec!["file"]
+    ));
+    assert!(matches!(
+        untracked,
+        Err(CheckoutError::UntrackedOverwritten(paths)) if paths == vec!["untracked"]
+    ));
+    assert_eq!(index.entry("file").unwrap().hash, old);
+    assert_eq!(
+        std::fs::read_to_string(repo.work_tree_path("untracked")).unwrap(),
+        "untracked\n"
+    );
+}
+
+#[test]
+fn reset_index_should_only_reset_entries_matching_the_pathspec() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let old = write_blob(&repo, "old\n");
+    let tree = write_tree(&repo, &[("100644", "a", &old), ("100644", "b", &old)]);
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "a", "new\n");
+    stage_file(&repo, &mut index, "b", "new\n");
+    stage_file(&repo, &mut index, "c", "new\n");
+    let pathspec = Pathspec::new("", &["a".to_string(), "c".to_string()]).unwrap();
+
+    // Act
+    repo.reset_index(&mut index, Some(repo.read_tree(&tree).unwrap()), &pathspec)
+        .unwrap();
+
+    // Assert
+    let paths: Vec<&str> = index.entries().iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec!["a", "b"]);
+    assert_eq!(index.entry("a").unwrap().hash, old);
+    assert_eq!(index.entry("b").unwrap().hash, write_blob(&repo, "new\n"));
+    assert_eq!(
+        std::fs::read_to_string(repo.work_tree_path("a")).unwrap(),
+        "new\n"
+    );
+}
+
+#[test]
+fn reset_work_tree_should_discard_local_changes_and_keep_untracked_files() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let old = write_blob(&repo, "old\n");
+    let tree = write_tree(&repo, &[("100644", "modified", &old)]);
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "modified", "old\n");
+    stage_file(&repo, &mut index, "staged", "staged\n");
+    std::fs::write(repo.work_tree_path("modified"), "local\n").unwrap();
+    std::fs::write(repo.work_tree_path("untracked"), "untracked\n").unwrap();
+
+    // Act
+    repo.reset_work_tree(&mut index, Some(repo.read_tree(&tree).unwrap()))
+        .unwrap();
+
+    // Assert
+    let read = |path: &str| std::fs::read_to_string(repo.work_tree_path(path)).unwrap();
+    assert_eq!(read("modified"), "old\n");
+    assert_eq!(read("untracked"), "untracked\n");
+    assert!(!repo.work_tree_path("staged").exists());
+    let paths: Vec<&str> = index.entries().iter().map(|e| e.path.as_str()).collect();
+    assert_eq!(paths, vec!["modified"]);
+}
+
+#[test]
+fn restore_work_tree_should_write_matching_files_with_their_mode() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let old = write_blob(&repo, "old\n");
+    let tree = write_tree(
+        &repo,
+        &[("100755", "script", &old), ("100644", "other", &old)],
+    );
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "script", "new\n");
+    stage_file(&repo, &mut index, "other", "new\n");
+    stage_file(&repo, &mut index, "added", "new\n");
+    let pathspec = Pathspec::new("", &["script".to_string(), "added".to_string()]).unwrap();
+    let files = diff::tree_files(&repo, Some(repo.read_tree(&tree).unwrap()), &pathspec).unwrap();
+
+    // Act
+    repo.restore_work_tree(&mut index, files, &pathspec)
+        .unwrap();
+
+    // Assert
+    let script = repo.work_tree_path("script");
+    assert_eq!(std::fs::read_to_string(&script).unwrap(), "old\n");
+    assert_ne!(
+        std::fs::metadata(&script).unwrap().permissions().mode() & 0o111,
+        0
+    );
+    assert_eq!(
+        std::fs::read_to_string(repo.work_tree_path("other")).unwrap(),
+        "new\n"
+    );
+    assert!(!repo.work_tree_path("added").exists());
+    // The index isn't restored.
+    assert_eq!(index.entries().len(), 3);
+}
+
+#[test]
+fn restore_work_tree_should_convert_line_endings_both_ways() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, ".gitattributes", "*.txt eol=crlf\n");
+    stage_file(&repo, &mut index, "file.txt", "a\nb\n");
+    stage_file(&repo, &mut index, "file.sh", "a\nb\n");
+    std::fs::remove_file(repo.work_tree_path("file.txt")).unwrap();
+    std::fs::remove_file(repo.work_tree_path("file.sh")).unwrap();
+    let everything = Pathspec::default();
+    let files = diff::index_files(&index, &everything);
+
+    // Act
+    repo.restore_work_tree(&mut index, files, &everything)
+        .unwrap();
+
+    // Assert
+    let read = |path: &str| std::fs::read_to_string(repo.work_tree_path(path)).unwrap();
+    assert_eq!(read("file.txt"), "a\r\nb\r\n");
+    assert_eq!(read("file.sh"), "a\nb\n");
+    // The converted file still matches its blob.
+    let work_tree = diff::work_tree_files(&repo, &index, &everything).unwrap();
+    assert_eq!(work_tree, diff::index_files(&index, &everything));
+}
+
+#[test]
+fn apply_sparse_patterns_should_skip_files_outside_of_them_unless_changed() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let mut index = Index::new();
+    for path in ["top", "a/x", "a/b/y", "a/c/z", "d/w"] {
+        stage_file(&repo, &mut index, path, "content\n");
+    }
+    std::fs::write(repo.work_tree_path("d/w"), "local\n").unwrap();
+    let patterns = SparsePatterns::cone(["a/b".to_string()]);
+
+    // Act
+    let left = repo
+        .apply_sparse_patterns(&mut index, Some(&patterns))
+        .unwrap();
+
+    // Assert
+    assert_eq!(left, vec!["d/w"]);
+    let skipped: Vec<&str> = index
+        .entries()
+        .iter()
+        .filter(|entry| entry.skip_worktree)
+        .map(|entry| entry.path.as_str())
+        .collect();
+    assert_eq!(skipped, vec!["a/c/z"]);
+    assert!(!repo.work_tree_path("a/c").exists());
+    assert!(repo.work_tree_path("a/x").exists());
+
+    // Turning sparse checkout off brings the files back.
+    std::fs::write(repo.work_tree_path("d/w"), "content\n").unwrap();
+    assert!(repo
+        .apply_sparse_patterns(&mut index, None)
+        .unwrap()
+        .is_empty());
+    assert!(index.entries().iter().all(|entry| !entry.skip_worktree));
+    assert_eq!(
+        std::fs::read_to_string(repo.work_tree_path("a/c/z")).unwrap(),
+        "content\n"
+    );
+}
+
+#[test]
+fn switch_tree_should_only_record_files_outside_of_the_sparse_checkout() {
+    // Arrange
+    let path = generate_random_path();
+    let repo = GitRepository::create(&path).unwrap();
+    repo.write_sparse_patterns(Some(&SparsePatterns::full(vec!["/in/".to_string()])))
+        .unwrap();
+    let repo = GitRepository::load(&path).unwrap();
+    let mut index = Index::new();
+    let blob = write_blob(&repo, "content\n");
+    let directory = write_tree(&repo, &[("100644", "file", &blob)]);
+    let to = write_tree(
+        &repo,
+        &[("40000", "in", &directory), ("40000", "out", &directory)],
+    );
+
+    // Act
+    repo.switch_tree(&mut index, None, Some(repo.read_tree(&to).unwrap()))
+        .unwrap();
+
+    // Assert
+    assert!(repo.work_tree_path("in/file").exists());
+    assert!(!repo.work_tree_path("out").exists());
+    assert!(!index.entry("in/file").unwrap().skip_worktree);
+    assert!(index.entry("out/file").unwrap().skip_worktree);
+}
+
+#[test]
+fn switch_tree_should_write_the_same_files_and_index_on_several_workers() {
+    // Arrange
+    let checkout = |workers: &str| {
+        let path = generate_random_path();
+        let repo = GitRepository::create(&path).unwrap();
+        let config_file = &repo.directory_manager.config_file;
+        GitConfig::write_value(config_file, "checkout", "workers", workers).unwrap();
+        GitConfig::write_value(config_file, "checkout", "thresholdForParallelism", "0").unwrap();
+        let repo = GitRepository::load(&path).unwrap();
+        let mut directories = vec![];
+        for d in 0..10 {
+            let files: Vec<(String, String)> = (0..30)
+                .map(|f| {
+                    (
+                        format!("file{}", f),
+                        write_blob(&repo, &format!("{} {}\n", d, f)),
+                    )
+                })
+                .collect();
+            let entries: Vec<(&str, &str, &str)> = files
+                .iter()
+                .map(|(name, hash)| ("100644", name.as_str(), hash.as_str()))
+                .collect();
+            directories.push((format!("dir{}", d), write_tree(&repo, &entries)));
+        }
+        let entries: Vec<(&str, &str, &str)> = directories
+            .iter()
+            .map(|(name, hash)| ("40000", name.as_str(), hash.as_str()))
+            .collect();
+        let tree = write_tree(&repo, &entries);
+
+        // Act
+        let mut index = Index::new();
+        repo.switch_tree(&mut index, None, Some(repo.read_tree(&tree).unwrap()))
+            .unwrap();
+        (repo, index)
+    };
+    let (serial_repo, serial_index) = checkout("1");
+    let (repo, index) = checkout("4");
+
+    // Assert
+    assert_eq!(index.entries().len(), 300);
+    let describe = |index: &Index| -> Vec<(String, String)> {
+        index
+            .entries()
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.hash.clone()))
+            .collect()
+    };
+    assert_eq!(describe(&index), describe(&serial_index));
+    for entry in index.entries() {
+        assert_eq!(
+            std::fs::read(repo.work_tree_path(&entry.path)).unwrap(),
+            std::fs::read(serial_repo.work_tree_path(&entry.path)).unwrap()
+        );
+    }
+    let everything = Pathspec::default();
+    let work_tree = diff::work_tree_files(&repo, &index, &everything).unwrap();
+    assert!(diff::diff_files(diff::index_files(&index, &everything), work_tree).is_empty());
+}
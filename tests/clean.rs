@@ -0,0 +1,135 @@
+mod test_utils;
+
+use rit::{
+    index::Index,
+    pathspec::Pathspec,
+    repository::{
+        clean::{CleanAction, CleanOptions, IgnoredFiles},
+        GitRepository,
+    },
+};
+
+use crate::test_utils::{general::generate_random_path, objects::stage_file};
+
+/// A repository tracking `tracked/file`, with untracked files, ignored files and a nested
+/// repository.
+fn create_repo_with_untracked_files() -> (GitRepository, Index) {
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "tracked/file", "tracked\n");
+    stage_file(&repo, &mut index, ".gitignore", "*.log\n");
+    for (path, content) in [
+        ("tracked/file", "tracked\n"),
+        (".gitignore", "*.log\n"),
+        ("tracked/new", "new\n"),
+        ("top.log", "log\n"),
+        ("untracked/file", "file\n"),
+        ("untracked/build.log", "log\n"),
+        ("logs/1.log", "log\n"),
+        ("nested/.git/HEAD", "ref: refs/heads/master\n"),
+        ("nested/file", "file\n"),
+    ] {
+        let path = repo.work_tree_path(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    (repo, index)
+}
+
+fn paths(repo: &GitRepository, index: &Index, options: CleanOptions) -> Vec<String> {
+    repo.clean_paths(index, &Pathspec::default(), options)
+        .unwrap()
+        .into_iter()
+        .map(|(path, is_dir)| match is_dir {
+            true => format!("{}/", path),
+            false => path,
+        })
+        .collect()
+}
+
+#[test]
+fn clean_paths_should_follow_the_directory_and_ignore_options() {
+    // Arrange
+    let (repo, index) = create_repo_with_untracked_files();
+    let options = |directories, ignored| CleanOptions {
+        dry_run: true,
+        directories,
+        ignored,
+        nested_repositories: false,
+    };
+
+    // Act & Assert
+    assert_eq!(
+        paths(&repo, &index, options(false, IgnoredFiles::Keep)),
+        vec!["tracked/new"]
+    );
+    assert_eq!(
+        paths(&repo, &index, options(true, IgnoredFiles::Keep)),
+        vec!["tracked/new", "untracked/file"]
+    );
+    assert_eq!(
+        paths(&repo, &index, options(true, IgnoredFiles::Remove)),
+        vec!["logs/", "top.log", "tracked/new", "untracked/"]
+    );
+    assert_eq!(
+        paths(&repo, &index, options(false, IgnoredFiles::Only)),
+        vec!["top.log", "untracked/build.log"]
+    );
+    assert_eq!(
+        paths(&repo, &index, options(true, IgnoredFiles::Only)),
+        vec!["logs/", "top.log", "untracked/build.log"]
+    );
+}
+
+#[test]
+fn clean_should_keep_nested_repositories_and_symbolic_link_targets() {
+    // Arrange
+    let (repo, index) = create_repo_with_untracked_files();
+    let outside = generate_random_path();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(outside.join("keep"), "keep\n").unwrap();
+    std::os::unix::fs::symlink(&outside, repo.work_tree_path("untracked/link")).unwrap();
+    std::fs::create_dir_all(repo.work_tree_path("holder/inner/.git")).unwrap();
+    std::fs::write(repo.work_tree_path("holder/file"), "file\n").unwrap();
+    let mut options = CleanOptions {
+        directories: true,
+        ..Default::default()
+    };
+
+    // Act
+    let actions = repo.clean(&index, &Pathspec::default(), options).unwrap();
+
+    // Assert
+    let reported: Vec<String> = actions
+        .iter()
+        .map(|action| match action {
+            CleanAction::Removed(path) => format!("remove {}", path),
+            CleanAction::SkippedRepository(path) => format!("skip {}", path),
+            CleanAction::Failed(path, error) => format!("fail {}: {}", path, error),
+        })
+        .collect();
+    assert_eq!(
+        reported,
+        vec![
+            "skip holder/inner",
+            "remove holder/file",
+            "remove tracked/new",
+            "remove untracked/file",
+            "remove untracked/link",
+        ]
+    );
+    assert!(outside.join("keep").exists());
+    assert!(repo.work_tree_path("nested/file").exists());
+    assert!(repo.work_tree_path("holder/inner/.git").exists());
+    assert!(repo.work_tree_path("untracked/build.log").exists());
+    assert!(!repo.work_tree_path("untracked/link").exists());
+
+    // Act
+    options.nested_repositories = true;
+    repo.clean(&index, &Pathspec::default(), options).unwrap();
+
+    // Assert
+    assert!(!repo.work_tree_path("nested").exists());
+    assert!(!repo.work_tree_path("holder").exists());
+    assert!(repo.work_tree_path("tracked/file").exists());
+}
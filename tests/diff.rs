@@ -0,0 +1,342 @@
+mod test_utils;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rit::{
+    diff::{self, CopyDetection, FileChange, RenameOptions, Status, TreeDiff},
+    git_config::GitConfig,
+    index::Index,
+    pathspec::Pathspec,
+    repository::GitRepository,
+};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{stage_file, write_blob, write_tree},
+};
+
+fn tree_diff(repo: &GitRepository, old: &str, new: &str, recursive: bool) -> Vec<FileChange> {
+    let old = repo.read_tree(old).unwrap();
+    let new = repo.read_tree(new).unwrap();
+    TreeDiff::new(repo, Some(old), Some(new))
+        .recursive(recursive)
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn tree_diff_should_report_added_deleted_modified_and_type_changed_entries() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let one = write_blob(&repo, "one\n");
+    let two = write_blob(&repo, "two\n");
+    let old = write_tree(
+        &repo,
+        &[
+            ("100644", "deleted", &one),
+            ("100644", "modified", &one),
+            ("100644", "link", &one),
+            ("100644", "same", &one),
+        ],
+    );
+    let new = write_tree(
+        &repo,
+        &[
+            ("100644", "added", &two),
+            ("100644", "modified", &two),
+            ("120000", "link", &one),
+            ("100644", "same", &one),
+        ],
+    );
+
+    // Act
+    let changes = tree_diff(&repo, &old, &new, false);
+
+    // Assert
+    let statuses: Vec<(Status, &str)> = changes
+        .iter()
+        .map(|change| (change.status, change.path()))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            (Status::Added, "added"),
+            (Status::Deleted, "deleted"),
+            (Status::TypeChanged, "link"),
+            (Status::Modified, "modified"),
+        ]
+    );
+    assert_eq!(
+        changes[3].raw(),
+        format!(":100644 100644 {} {} M\tmodified", one, two)
+    );
+}
+
+#[test]
+fn tree_diff_should_only_descend_into_sub_trees_when_recursive() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let one = write_blob(&repo, "one\n");
+    let two = write_blob(&repo, "two\n");
+    let old_dir = write_tree(&repo, &[("100644", "file", &one)]);
+    let new_dir = write_tree(&repo, &[("100644", "file", &two)]);
+    let old = write_tree(
+        &repo,
+        &[("40000", "dir", &old_dir), ("100644", "dir.txt", &one)],
+    );
+    let new = write_tree(
+        &repo,
+        &[("40000", "dir", &new_dir), ("100644", "dir.txt", &two)],
+    );
+
+    // Act
+    let shallow = tree_diff(&repo, &old, &new, false);
+    let recursive = tree_diff(&repo, &old, &new, true);
+
+    // Assert
+    let paths = |changes: &[FileChange]| -> Vec<String> {
+        changes.iter().map(|c| c.path().to_string()).collect()
+    };
+    assert_eq!(paths(&shallow), vec!["dir.txt", "dir"]);
+    assert_eq!(
+        shallow[1].raw(),
+        format!(":040000 040000 {} {} M\tdir", old_dir, new_dir)
+    );
+    assert_eq!(paths(&recursive), vec!["dir.txt", "dir/file"]);
+}
+
+#[test]
+fn tree_diff_should_not_report_identical_sub_trees() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let one = write_blob(&repo, "one\n");
+    let dir = write_tree(&repo, &[("100644", "file", &one)]);
+    let old = write_tree(&repo, &[("40000", "dir", &dir)]);
+    let new = write_tree(&repo, &[("40000", "dir", &dir), ("100644", "new", &one)]);
+
+    // Act
+    let changes = tree_diff(&repo, &old, &new, true);
+
+    // Assert
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].status, Status::Added);
+    assert_eq!(changes[0].path(), "new");
+}
+
+#[test]
+fn detect_renames_should_pair_exact_and_similar_files() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let content: String = (0..20).map(|i| format!("line {}\n", i)).collect();
+    let original = write_blob(&repo, &content);
+    let to_edit = write_blob(&repo, &content.to_uppercase());
+    let edited = write_blob(
+        &repo,
+        &content.to_uppercase().replace("LINE 3\n", "LINE 33\n"),
+    );
+    let unrelated = write_blob(&repo, "something else\n");
+    let old = write_tree(
+        &repo,
+        &[
+            ("100644", "moved", &original),
+            ("100644", "edited", &to_edit),
+            ("100644", "gone", &unrelated),
+        ],
+    );
+    let new = write_tree(
+        &repo,
+        &[
+            ("100644", "moved-to", &original),
+            ("100644", "edited-to", &edited),
+            ("100644", "unrelated", &write_blob(&repo, "brand new\n")),
+        ],
+    );
+    let changes = tree_diff(&repo, &old, &new, true);
+
+    // Act
+    let detected = diff::detect_renames(changes, vec![], &RenameOptions::default(), |side| {
+        diff::read_side(&repo, side)
+    })
+    .unwrap();
+
+    // Assert
+    let name_status: Vec<String> = detected.changes.iter().map(|c| c.name_status()).collect();
+    assert_eq!(
+        name_status,
+        vec![
+            "R094\tedited\tedited-to",
+            "D\tgone",
+            "R100\tmoved\tmoved-to",
+            "A\tunrelated",
+        ]
+    );
+    assert_eq!(detected.needed_rename_limit, None);
+}
+
+#[test]
+fn detect_renames_should_report_copies_of_a_deleted_file_before_its_rename() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let blob = write_blob(&repo, "shared\n");
+    let old = write_tree(&repo, &[("100644", "source", &blob)]);
+    let new = write_tree(&repo, &[("100644", "a", &blob), ("100644", "b", &blob)]);
+    let changes = tree_diff(&repo, &old, &new, true);
+    let options = RenameOptions {
+        copies: CopyDetection::Modified,
+        ..Default::default()
+    };
+
+    // Act
+    let detected = diff::detect_renames(changes.clone(), vec![], &options, |side| {
+        diff::read_side(&repo, side)
+    })
+    .unwrap();
+    let renames_only = diff::detect_renames(changes, vec![], &RenameOptions::default(), |side| {
+        diff::read_side(&repo, side)
+    })
+    .unwrap();
+
+    // Assert
+    let statuses = |changes: &[FileChange]| -> Vec<Status> {
+        changes.iter().map(|change| change.status).collect()
+    };
+    assert_eq!(
+        statuses(&detected.changes),
+        vec![Status::Copied(100), Status::Renamed(100)]
+    );
+    assert_eq!(
+        statuses(&renames_only.changes),
+        vec![Status::Renamed(100), Status::Added]
+    );
+}
+
+#[test]
+fn diff_files_should_compare_the_index_with_the_work_tree() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "kept", "kept\n");
+    stage_file(&repo, &mut index, "dir/modified", "before\n");
+    stage_file(&repo, &mut index, "deleted", "deleted\n");
+    std::fs::write(repo.work_tree_path("dir/modified"), "after\n").unwrap();
+    std::fs::remove_file(repo.work_tree_path("deleted")).unwrap();
+    std::fs::write(repo.work_tree_path("untracked"), "untracked\n").unwrap();
+    let everything = Pathspec::default();
+
+    // Act
+    let changes = diff::diff_files(
+        diff::index_files(&index, &everything),
+        diff::work_tree_files(&repo, &index, &everything).unwrap(),
+    );
+
+    // Assert
+    let name_status: Vec<String> = changes.iter().map(FileChange::name_status).collect();
+    assert_eq!(name_status, vec!["D\tdeleted", "M\tdir/modified"]);
+
+    // The work tree side isn't in the object database, it's read from the file.
+    let modified = changes[1].new.as_ref().unwrap();
+    assert_eq!(diff::read_side(&repo, modified).unwrap(), b"after\n");
+}
+
+#[test]
+fn work_tree_files_should_trust_stat_data_unless_racily_clean() {
+    // Arrange
+    let path = generate_random_path();
+    let repo = GitRepository::create(&path).unwrap();
+    // Only the modification time in seconds and the size are compared, as the change time
+    // can't be set back.
+    let config_file = &repo.directory_manager.config_file;
+    GitConfig::write_value(config_file, "core", "checkStat", "minimal").unwrap();
+    let repo = GitRepository::load(&path).unwrap();
+    let index_file = &repo.directory_manager.index_file;
+    let everything = Pathspec::default();
+    let file = repo.work_tree_path("file");
+    let rewrite = |content: &str, mtime: SystemTime| {
+        std::fs::write(&file, content).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    };
+    let work_tree_hash = |index: &Index| {
+        diff::work_tree_files(&repo, index, &everything).unwrap()[0]
+            .hash
+            .clone()
+    };
+
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "file", "old\n");
+    let staged = index.entry("file").unwrap().clone();
+    let staged_mtime = UNIX_EPOCH
+        + Duration::new(
+            staged.stat.mtime_seconds as u64,
+            staged.stat.mtime_nanoseconds,
+        );
+    index.write(index_file).unwrap();
+
+    // Act & Assert
+    // Written in the same second as the file, the entry is smudged.
+    let mut index = Index::load(index_file).unwrap();
+    assert!(index.entry("file").unwrap().is_smudged());
+    rewrite("new\n", staged_mtime);
+    assert_eq!(work_tree_hash(&index), write_blob(&repo, "new\n"));
+
+    // Once refreshed with a file older than the index, the stat data is trusted and a change
+    // that keeps it is missed.
+    let old_mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    rewrite("old\n", old_mtime);
+    assert!(repo.refresh_index(&mut index).unwrap().changed);
+    index.write(index_file).unwrap();
+    let mut index = Index::load(index_file).unwrap();
+    rewrite("mod\n", old_mtime);
+    assert_eq!(work_tree_hash(&index), staged.hash);
+    assert!(!repo.refresh_index(&mut index).unwrap().changed);
+
+    // A file modified no earlier than the index was read is compared by content, even if its
+    // stat data is kept.
+    let future = SystemTime::now() + Duration::from_secs(10);
+    rewrite("old\n", future);
+    assert!(repo.refresh_index(&mut index).unwrap().changed);
+    assert!(index.is_racy(&index.entry("file").unwrap().stat));
+    rewrite("new\n", future);
+    assert_eq!(work_tree_hash(&index), write_blob(&repo, "new\n"));
+    assert_eq!(
+        repo.refresh_index(&mut index).unwrap().needs_update,
+        vec!["file"]
+    );
+}
+
+#[test]
+fn diff_files_should_compare_a_tree_with_the_index_limited_by_pathspec() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let old = write_blob(&repo, "old\n");
+    let src = write_tree(
+        &repo,
+        &[("100644", "lib.rs", &old), ("100644", "main.rs", &old)],
+    );
+    let tree = write_tree(
+        &repo,
+        &[("100644", "README.md", &old), ("40000", "src", &src)],
+    );
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "README.md", "new\n");
+    stage_file(&repo, &mut index, "src/lib.rs", "new\n");
+    stage_file(&repo, &mut index, "src/new.rs", "new\n");
+    let pathspec = Pathspec::new("src", &["*.rs".to_string()]).unwrap();
+
+    // Act
+    let changes = diff::diff_files(
+        diff::tree_files(&repo, Some(repo.read_tree(&tree).unwrap()), &pathspec).unwrap(),
+        diff::index_files(&index, &pathspec),
+    );
+
+    // Assert
+    let name_status: Vec<String> = changes.iter().map(FileChange::name_status).collect();
+    assert_eq!(
+        name_status,
+        vec!["M\tsrc/lib.rs", "D\tsrc/main.rs", "A\tsrc/new.rs"]
+    );
+}
@@ -0,0 +1,115 @@
+mod test_utils;
+
+use rit::{git_object::mode::Mode, repository::GitRepository};
+
+use crate::test_utils::general::generate_random_path;
+
+/// A process filter upper-casing content when cleaning and lower-casing it when smudging.
+const UPPER_PROCESS: &str = r#"
+read_bytes() { dd bs=1 count="$1" 2>/dev/null; }
+read_packet() {
+    length=$(read_bytes 4)
+    [ -z "$length" ] && exit 0
+    [ "$length" = "0000" ] && return 1
+    read_bytes $((0x$length - 4))
+}
+while read_packet >/dev/null; do :; done
+printf '0016git-filter-server\n000eversion=2\n0000'
+while read_packet >/dev/null; do :; done
+printf '0015capability=clean\n0016capability=smudge\n0000'
+while true; do
+    headers=""
+    while packet=$(read_packet); do [ -z "$packet" ] && exit 0; headers="$headers $packet"; done
+    while read_packet; do :; done > "$0.in"
+    case "$headers" in
+        *command=clean*) tr a-z A-Z < "$0.in" > "$0.out" ;;
+        *) tr A-Z a-z < "$0.in" > "$0.out" ;;
+    esac
+    printf '0013status=success\n0000'
+    length=$(wc -c < "$0.out")
+    if [ "$length" -gt 0 ]; then
+        printf '%04x' $((length + 4))
+        cat "$0.out"
+    fi
+    printf '00000000'
+done
+"#;
+
+/// A repository with `config` appended to its configuration and `attributes` as its
+/// `.gitattributes`.
+fn create_repo_with_filters(config: &str, attributes: &str) -> GitRepository {
+    let path = generate_random_path();
+    let repo = GitRepository::create(&path).unwrap();
+    let config_file = repo.directory_manager.dot_git_path.join("config");
+    let mut content = std::fs::read_to_string(&config_file).unwrap();
+    content.push('\n');
+    content.push_str(config);
+    std::fs::write(&config_file, content).unwrap();
+    std::fs::write(repo.work_tree_path(".gitattributes"), attributes).unwrap();
+    GitRepository::load(&path).unwrap()
+}
+
+fn regular_file() -> Mode {
+    Mode::new("100644".to_string()).unwrap()
+}
+
+#[test]
+fn filter_drivers_should_run_their_commands_both_ways() {
+    // Arrange
+    let repo = create_repo_with_filters(
+        "[filter \"rot\"]\n\tclean = tr a-z n-za-m\n\tsmudge = tr n-za-m a-z\n\
+         [filter \"name\"]\n\tclean = echo %f\n\
+         [filter \"broken\"]\n\tclean = false\n\
+         [filter \"required\"]\n\tclean = false\n\trequired = true\n",
+        "*.rot filter=rot\n*.name filter=name\n*.broken filter=broken\n*.req filter=required\n",
+    );
+    for path in ["a.rot", "it's.name", "a.broken", "a.req"] {
+        std::fs::write(repo.work_tree_path(path), "hello\n").unwrap();
+    }
+    let read = |path: &str| {
+        repo.read_work_tree_file(path, None)
+            .map(|file| file.unwrap().1)
+    };
+
+    // Act
+    repo.write_work_tree_file("b.rot", &regular_file(), b"uryyb\n")
+        .unwrap();
+
+    // Assert
+    assert_eq!(read("a.rot").unwrap(), b"uryyb\n");
+    assert_eq!(read("it's.name").unwrap(), b"it's.name\n");
+    assert_eq!(read("a.broken").unwrap(), b"hello\n");
+    assert!(read("a.req").is_err());
+    assert_eq!(
+        std::fs::read_to_string(repo.work_tree_path("b.rot")).unwrap(),
+        "hello\n"
+    );
+}
+
+#[test]
+fn process_filter_should_filter_many_files_both_ways() {
+    // Arrange
+    let script = generate_random_path().with_extension("sh");
+    std::fs::write(&script, UPPER_PROCESS).unwrap();
+    let repo = create_repo_with_filters(
+        &format!("[filter \"upper\"]\n\tprocess = sh {}\n", script.display()),
+        "*.up filter=upper\n",
+    );
+    std::fs::write(repo.work_tree_path("a.up"), "hello\n").unwrap();
+    std::fs::write(repo.work_tree_path("empty.up"), "").unwrap();
+    std::fs::write(repo.work_tree_path("a.txt"), "hello\n").unwrap();
+    let read = |path: &str| repo.read_work_tree_file(path, None).unwrap().unwrap().1;
+
+    // Act
+    repo.write_work_tree_file("b.up", &regular_file(), b"WORLD\n")
+        .unwrap();
+
+    // Assert
+    assert_eq!(read("a.up"), b"HELLO\n");
+    assert_eq!(read("empty.up"), b"");
+    assert_eq!(read("a.txt"), b"hello\n");
+    assert_eq!(
+        std::fs::read_to_string(repo.work_tree_path("b.up")).unwrap(),
+        "world\n"
+    );
+}
@@ -0,0 +1,127 @@
+mod test_utils;
+
+use std::collections::BTreeMap;
+
+use rit::{
+    git_object::{KeyValueList, Tag},
+    ref_format::{RefFilter, RefFormat, SortKey},
+    repository::{refs::Ref, GitRepository},
+    GitObject,
+};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{write_commit, write_object, write_tree},
+};
+
+fn names(refs: &[Ref]) -> Vec<String> {
+    refs.iter()
+        .map(|ref_item| ref_item.path.display().to_string())
+        .collect()
+}
+
+#[test]
+fn refs_should_be_formatted_sorted_and_filtered() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let tree = write_tree(&repo, &[]);
+    let first = write_commit(&repo, &tree, &[], 100);
+    let second = write_commit(&repo, &tree, &[&first], 200);
+    let side = write_commit(&repo, &tree, &[&first], 300);
+    let kvl = BTreeMap::from([
+        ("object".to_string(), first.clone()),
+        ("type".to_string(), "commit".to_string()),
+        ("tag".to_string(), "v1.10".to_string()),
+        (
+            "tagger".to_string(),
+            "A U Thor <author@example.com> 400 +0100".to_string(),
+        ),
+        ("message".to_string(), "Release 1.10\n".to_string()),
+    ]);
+    let tag = write_object(
+        &repo,
+        GitObject::Tag(Tag {
+            kvl: KeyValueList::new(kvl),
+        }),
+    );
+    repo.update_ref("refs/heads/master", &second).unwrap();
+    repo.update_ref("refs/heads/side", &side).unwrap();
+    repo.update_ref("refs/tags/v1.10", &tag).unwrap();
+    repo.update_ref("refs/tags/v1.9", &second).unwrap();
+    let refs = repo.list_refs().unwrap();
+
+    // Act
+    let format = RefFormat::parse(
+        "%(HEAD)%(refname:short) %(objecttype) %(creatordate:short) %(*objectname:short=8)",
+    )
+    .unwrap();
+    let lines: Vec<String> = refs
+        .iter()
+        .map(|ref_item| repo.format_ref(ref_item, &format).unwrap())
+        .collect();
+    let sort = |keys: &[&str]| {
+        let keys: Vec<SortKey> = keys
+            .iter()
+            .map(|key| SortKey::parse(key).unwrap())
+            .collect();
+        names(&repo.sort_refs(repo.list_refs().unwrap(), &keys).unwrap())
+    };
+    let filter = |filter: RefFilter| {
+        names(
+            &repo
+                .filter_refs(repo.list_refs().unwrap(), &filter)
+                .unwrap(),
+        )
+    };
+
+    // Assert
+    assert_eq!(
+        lines,
+        [
+            "*master commit 1970-01-01 ".to_string(),
+            " side commit 1970-01-01 ".to_string(),
+            format!(" v1.10 tag 1970-01-01 {}", &first[..8]),
+            " v1.9 commit 1970-01-01 ".to_string(),
+        ]
+    );
+    assert_eq!(
+        sort(&["-version:refname"]),
+        [
+            "refs/tags/v1.10",
+            "refs/tags/v1.9",
+            "refs/heads/side",
+            "refs/heads/master"
+        ]
+    );
+    assert_eq!(
+        sort(&["refname", "-creatordate"]),
+        [
+            "refs/tags/v1.10",
+            "refs/heads/side",
+            "refs/heads/master",
+            "refs/tags/v1.9"
+        ]
+    );
+    assert_eq!(
+        filter(RefFilter {
+            points_at: Some(first.clone()),
+            ..RefFilter::default()
+        }),
+        ["refs/tags/v1.10"]
+    );
+    assert_eq!(
+        filter(RefFilter {
+            merged: Some("master".to_string()),
+            ..RefFilter::default()
+        }),
+        ["refs/heads/master", "refs/tags/v1.10", "refs/tags/v1.9"]
+    );
+    assert_eq!(
+        filter(RefFilter {
+            patterns: vec!["refs/heads".to_string()],
+            contains: Some(first),
+            ..RefFilter::default()
+        }),
+        ["refs/heads/master", "refs/heads/side"]
+    );
+}
@@ -0,0 +1,376 @@
+mod test_utils;
+
+use std::collections::BTreeMap;
+
+use rit::{
+    diff::NULL_HASH,
+    error::repository::ResolveRefError,
+    git_object::{KeyValueList, Tag},
+    repository::{
+        ref_transaction::RefTransaction,
+        reflog::{self, ReflogEntry, ReflogExpiry},
+        refs::{self, Head, PackedRef},
+        GitRepository,
+    },
+    GitObject,
+};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{write_commit, write_object, write_tree},
+};
+
+/// A repository with two commits, the first one tagged by the annotated tag `v1`.
+fn create_repo_with_commits() -> (GitRepository, String, String, String) {
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let tree = write_tree(&repo, &[]);
+    let first = write_commit(&repo, &tree, &[], 1);
+    let second = write_commit(&repo, &tree, &[&first], 2);
+    let kvl = BTreeMap::from([
+        ("object".to_string(), first.clone()),
+        ("type".to_string(), "commit".to_string()),
+        ("tag".to_string(), "v1".to_string()),
+    ]);
+    let tag = write_object(
+        &repo,
+        GitObject::Tag(Tag {
+            kvl: KeyValueList::new(kvl),
+        }),
+    );
+    (repo, first, second, tag)
+}
+
+fn names(repo: &GitRepository) -> Vec<String> {
+    repo.list_refs()
+        .unwrap()
+        .iter()
+        .map(|ref_item| ref_item.path.display().to_string())
+        .collect()
+}
+
+#[test]
+fn packed_refs_should_be_resolved_and_listed_with_loose_ones_winning() {
+    // Arrange
+    let (repo, first, second, tag) = create_repo_with_commits();
+    std::fs::write(
+        &repo.directory_manager.packed_refs_file,
+        format!(
+            "# pack-refs with: peeled fully-peeled sorted \n\
+             {first} refs/heads/master\n{first} refs/heads/topic\n{tag} refs/tags/v1\n^{first}\n"
+        ),
+    )
+    .unwrap();
+    repo.update_ref("refs/heads/master", &second).unwrap();
+
+    // Act & Assert
+    assert_eq!(repo.resolve_ref("refs/heads/topic").unwrap(), first);
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), second);
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), second);
+    assert_eq!(repo.find_object("v1").unwrap(), tag);
+    assert_eq!(
+        names(&repo),
+        vec!["refs/heads/master", "refs/heads/topic", "refs/tags/v1"]
+    );
+    assert_eq!(repo.list_refs_in("tags".as_ref()).unwrap()[0].hash, tag);
+
+    repo.delete_ref("refs/heads/topic").unwrap();
+    assert!(repo.resolve_ref("refs/heads/topic").is_err());
+    assert_eq!(names(&repo), vec!["refs/heads/master", "refs/tags/v1"]);
+}
+
+#[test]
+fn pack_refs_should_pack_tags_or_every_ref_and_prune_loose_files() {
+    // Arrange
+    let (repo, first, second, tag) = create_repo_with_commits();
+    repo.update_ref("refs/heads/master", &second).unwrap();
+    repo.update_ref("refs/heads/deep/topic", &first).unwrap();
+    repo.update_ref("refs/tags/v1", &tag).unwrap();
+    let read_packed = || refs::read_packed_refs(&repo.directory_manager).unwrap();
+
+    // Act & Assert
+    repo.pack_refs(false, false).unwrap();
+    assert_eq!(
+        read_packed(),
+        vec![PackedRef {
+            name: "refs/tags/v1".to_string(),
+            hash: tag.clone(),
+            peeled: Some(first.clone()),
+        }]
+    );
+    assert!(repo.directory_manager.ref_path("refs/tags/v1").is_file());
+
+    repo.pack_refs(true, true).unwrap();
+    let packed: Vec<String> = read_packed()
+        .into_iter()
+        .map(|packed| packed.name)
+        .collect();
+    assert_eq!(
+        packed,
+        vec!["refs/heads/deep/topic", "refs/heads/master", "refs/tags/v1"]
+    );
+    assert!(!repo.directory_manager.ref_path("refs/heads/deep").exists());
+    assert!(repo.directory_manager.refs_heads_path.is_dir());
+    assert_eq!(repo.resolve_ref("HEAD").unwrap(), second);
+    assert_eq!(repo.resolve_ref("refs/heads/deep/topic").unwrap(), first);
+}
+
+#[test]
+fn ref_transaction_should_apply_every_update_or_none() {
+    // Arrange
+    let (repo, first, second, _) = create_repo_with_commits();
+    repo.update_ref("refs/heads/master", &first).unwrap();
+    repo.update_ref("refs/heads/topic", &first).unwrap();
+    repo.pack_refs(true, true).unwrap();
+
+    // Act & Assert
+    let mut failing = RefTransaction::new();
+    failing
+        .create("refs/heads/new", &second)
+        .update("refs/heads/master", &second, Some(&first))
+        .update("refs/heads/topic", &second, Some(&second));
+    assert!(matches!(
+        repo.commit_ref_transaction(&failing),
+        Err(ResolveRefError::OldValueMismatch(..))
+    ));
+    assert!(repo.resolve_ref("refs/heads/new").is_err());
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), first);
+
+    let lock = repo.directory_manager.ref_path("refs/heads/topic.lock");
+    std::fs::write(&lock, "").unwrap();
+    let mut locked = RefTransaction::new();
+    locked.update("refs/heads/topic", &second, None);
+    assert!(matches!(
+        repo.commit_ref_transaction(&locked),
+        Err(ResolveRefError::Locked(..))
+    ));
+    std::fs::remove_file(&lock).unwrap();
+
+    // `HEAD` is followed to the branch it points to.
+    let mut transaction = RefTransaction::new();
+    transaction
+        .update("HEAD", &second, Some(&first))
+        .delete("refs/heads/topic", Some(&first))
+        .verify("refs/heads/missing", None);
+    repo.commit_ref_transaction(&transaction).unwrap();
+    assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), second);
+    assert_eq!(names(&repo), vec!["refs/heads/master"]);
+    assert!(refs::read_packed_refs(&repo.directory_manager)
+        .unwrap()
+        .iter()
+        .all(|packed| packed.name != "refs/heads/topic"));
+
+    let mut existing = RefTransaction::new();
+    existing.create("refs/heads/master", &first);
+    assert!(matches!(
+        repo.commit_ref_transaction(&existing),
+        Err(ResolveRefError::AlreadyExists(_))
+    ));
+    let mut conflicting = RefTransaction::new();
+    conflicting.create("refs/heads/master/nested", &first);
+    assert!(matches!(
+        repo.commit_ref_transaction(&conflicting),
+        Err(ResolveRefError::NameConflict(..))
+    ));
+}
+
+#[test]
+fn ref_updates_should_be_logged_and_unreachable_entries_expire() {
+    // Arrange
+    let (repo, first, second, _) = create_repo_with_commits();
+    let messages = |name: &str| -> Vec<String> {
+        repo.read_reflog(name)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    };
+
+    // Act
+    repo.update_head(&first, "commit (initial): one").unwrap();
+    repo.update_head(&second, "commit: two").unwrap();
+    repo.update_head(&first, "reset: moving to first").unwrap();
+    repo.detach_head(&second, "checkout: moving from master to second")
+        .unwrap();
+    repo.update_ref("ORIG_HEAD", &first).unwrap();
+
+    // Assert
+    let branch_messages = [
+        "commit (initial): one",
+        "commit: two",
+        "reset: moving to first",
+    ];
+    assert_eq!(messages("refs/heads/master"), branch_messages);
+    assert_eq!(
+        messages("HEAD"),
+        [
+            &branch_messages[..],
+            &["checkout: moving from master to second"]
+        ]
+        .concat()
+    );
+    assert!(messages("ORIG_HEAD").is_empty());
+    assert_eq!(
+        repo.read_reflog("refs/heads/master").unwrap()[0].old,
+        NULL_HASH
+    );
+
+    // The entries moving to `second`, which `master` doesn't reach anymore, and from it expire.
+    let expiry = ReflogExpiry {
+        expire: i64::MIN,
+        expire_unreachable: i64::MAX,
+    };
+    let expired = repo
+        .expire_reflog("refs/heads/master", &expiry, true)
+        .unwrap();
+    assert_eq!(expired.len(), 2);
+    assert_eq!(expired[1].old, second);
+    assert_eq!(expired[1].new, first);
+    assert_eq!(messages("refs/heads/master").len(), 3);
+    repo.expire_reflog("refs/heads/master", &expiry, false)
+        .unwrap();
+    assert_eq!(messages("refs/heads/master"), ["commit (initial): one"]);
+
+    let deleted = repo.delete_reflog_entry("HEAD", 0).unwrap();
+    assert_eq!(deleted.new, second);
+    assert_eq!(messages("HEAD").len(), 3);
+    assert!(repo.delete_reflog_entry("HEAD", 3).is_err());
+}
+
+#[test]
+fn reflog_should_skip_corrupt_lines() {
+    // Arrange
+    let (repo, first, _, _) = create_repo_with_commits();
+    repo.update_head(&first, "commit (initial): one").unwrap();
+    let path = repo.directory_manager.reflog_path("HEAD");
+    let mut content = std::fs::read_to_string(&path).unwrap();
+    content.push_str("abc def A U Thor <a@b> 1700000000 +0000\tbad\n");
+    std::fs::write(&path, content).unwrap();
+
+    // Act
+    let entries = repo.read_reflog("HEAD").unwrap();
+
+    // Assert
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].new, first);
+    assert_eq!(
+        ReflogEntry::parse(&format!(
+            "{} {} A U Thor <a@b> 1700000000 +0000\tok",
+            NULL_HASH, first
+        ))
+        .map(|entry| entry.message),
+        Some("ok".to_string())
+    );
+}
+
+#[test]
+fn parse_expiry_date_should_accept_relative_dates_and_keywords() {
+    let now = 1_000_000_000;
+
+    assert_eq!(
+        reflog::parse_expiry_date("90.days.ago", now),
+        Some(now - 90 * 86400)
+    );
+    assert_eq!(
+        reflog::parse_expiry_date("2 weeks ago", now),
+        Some(now - 14 * 86400)
+    );
+    assert_eq!(
+        reflog::parse_expiry_date("1.hour.ago", now),
+        Some(now - 3600)
+    );
+    assert_eq!(reflog::parse_expiry_date("12345", now), Some(12345));
+    assert_eq!(reflog::parse_expiry_date("never", now), Some(i64::MIN));
+    assert_eq!(reflog::parse_expiry_date("now", now), Some(i64::MAX));
+    assert_eq!(reflog::parse_expiry_date("3.fortnights.ago", now), None);
+}
+
+#[test]
+fn head_should_be_unborn_symbolic_or_detached() {
+    // Arrange
+    let (repo, first, second, _) = create_repo_with_commits();
+
+    // Act & Assert
+    assert_eq!(
+        repo.head().unwrap(),
+        Head::Branch("refs/heads/master".to_string())
+    );
+    assert_eq!(repo.head_commit().unwrap(), None);
+    assert!(matches!(
+        repo.find_object("HEAD")
+            .unwrap_err()
+            .downcast::<ResolveRefError>(),
+        Ok(ResolveRefError::UnbornBranch(branch)) if branch == "master"
+    ));
+
+    repo.update_ref("refs/heads/topic", &first).unwrap();
+    repo.set_symbolic_ref(
+        "HEAD",
+        "refs/heads/topic",
+        "checkout: moving from master to topic",
+    )
+    .unwrap();
+    assert_eq!(repo.read_symbolic_ref("HEAD").unwrap(), "refs/heads/topic");
+    assert_eq!(repo.find_object("HEAD").unwrap(), first);
+    assert!(matches!(
+        repo.set_symbolic_ref("HEAD", "topic", ""),
+        Err(ResolveRefError::SymbolicRefOutsideRefs(_))
+    ));
+
+    repo.detach_head(&second, "checkout: moving from topic to second")
+        .unwrap();
+    assert_eq!(repo.head().unwrap(), Head::Detached(second.clone()));
+    assert_eq!(repo.head_branch().unwrap(), None);
+    assert_eq!(repo.find_object("HEAD").unwrap(), second);
+    assert!(matches!(
+        repo.read_symbolic_ref("HEAD"),
+        Err(ResolveRefError::NotASymbolicRef(_))
+    ));
+    assert_eq!(refs::shorten_ref_name("refs/heads/topic"), "topic");
+}
+
+#[test]
+fn ref_writes_should_reject_names_breaking_git_rules() {
+    // Arrange
+    let (repo, first, _, _) = create_repo_with_commits();
+    let config = repo.directory_manager.config_file.clone();
+    let bad_ref = repo.directory_manager.common_dir.join("refs/heads/a..b");
+
+    // Act & Assert
+    assert!(matches!(
+        repo.update_ref("refs/heads/a..b", &first),
+        Err(ResolveRefError::BadName(_))
+    ));
+    assert!(!bad_ref.exists());
+    assert!(matches!(
+        repo.delete_ref("refs/../config"),
+        Err(ResolveRefError::BadName(_))
+    ));
+    assert!(config.exists());
+    for name in ["config", "hooks/x", "objects/x"] {
+        assert!(matches!(
+            repo.update_ref(name, &first),
+            Err(ResolveRefError::BadName(_))
+        ));
+        assert!(matches!(
+            repo.set_symbolic_ref(name, "refs/heads/master", ""),
+            Err(ResolveRefError::BadName(_))
+        ));
+    }
+    assert!(GitRepository::load(&repo.directory_manager.work_tree).is_ok());
+    assert!(!repo.directory_manager.dot_git_path.join("hooks/x").exists());
+    assert!(matches!(
+        repo.set_symbolic_ref("HEAD", "refs/heads/topic.lock", ""),
+        Err(ResolveRefError::InvalidSymbolicRefTarget(..))
+    ));
+    for name in ["../../config", "foo..bar", "-v1", "v1.lock", "v1@{0}"] {
+        assert!(matches!(
+            repo.create_lightweight_tag(name.to_string(), first.clone())
+                .unwrap_err()
+                .downcast::<ResolveRefError>(),
+            Ok(ResolveRefError::InvalidTagName(_))
+        ));
+    }
+    repo.create_lightweight_tag("release/v1".to_string(), first.clone())
+        .unwrap();
+    assert_eq!(names(&repo), ["refs/tags/release/v1"]);
+}
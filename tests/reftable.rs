@@ -0,0 +1,214 @@
+mod test_utils;
+
+use rit::{
+    error::repository::ResolveRefError,
+    git_object::Signature,
+    reftable::{
+        reader::Table, stack::Stack, writer::write_table, LogRecord, RefRecord, RefRecordValue,
+    },
+    repository::{
+        ref_backend::{FilesBackend, RefBackend, RefStorage, ReftableBackend},
+        ref_transaction::RefTransaction,
+        reflog::ReflogEntry,
+        refs::Ref,
+        GitRepository,
+    },
+};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{write_commit, write_tree},
+};
+
+fn hash(i: usize) -> String {
+    format!("{:040x}", i + 1)
+}
+
+fn names(refs: &[Ref]) -> Vec<(String, String)> {
+    refs.iter()
+        .map(|ref_item| (ref_item.path.display().to_string(), ref_item.hash.clone()))
+        .collect()
+}
+
+#[test]
+fn tables_should_round_trip_through_blocks_restarts_and_indexes() {
+    // Arrange
+    let refs: Vec<RefRecord> = (0..3000)
+        .map(|i| RefRecord {
+            name: format!("refs/heads/branch-{:05}", i),
+            update_index: 1 + (i % 3) as u64,
+            value: match i % 100 {
+                0 => RefRecordValue::Symbolic("refs/heads/branch-00001".to_string()),
+                1 => RefRecordValue::Peeled(hash(i), hash(i + 1)),
+                2 => RefRecordValue::Deletion,
+                _ => RefRecordValue::Hash(hash(i)),
+            },
+        })
+        .collect();
+    let logs: Vec<LogRecord> = (0..300)
+        .map(|i| LogRecord {
+            name: format!("refs/heads/log-{}", i % 3),
+            update_index: 1 + i as u64 / 3,
+            entry: Some(ReflogEntry {
+                old: hash(i),
+                new: hash(i + 1),
+                committer: Signature {
+                    name: "A U Thor".to_string(),
+                    email: "author@example.com".to_string(),
+                    time: 1700000000 + i as i64,
+                    offset: "-0130".to_string(),
+                },
+                message: format!("update {}", i),
+            }),
+        })
+        .collect();
+    let path = generate_random_path();
+    std::fs::write(&path, write_table(256, 1, 100, &refs, &logs).unwrap()).unwrap();
+
+    // Act
+    let table = Table::open(&path).unwrap();
+
+    // Assert
+    assert_eq!((table.min_update_index, table.max_update_index), (1, 100));
+    for i in [0, 1, 2, 3, 15, 16, 17, 1234, 2999] {
+        assert_eq!(
+            table.ref_record(&refs[i].name).unwrap().as_ref(),
+            Some(&refs[i])
+        );
+    }
+    assert_eq!(table.ref_record("refs/heads/branch").unwrap(), None);
+    assert_eq!(table.ref_record("refs/heads/zzz").unwrap(), None);
+    assert_eq!(table.ref_records("").unwrap(), refs);
+    assert_eq!(
+        table.ref_records("refs/heads/branch-012").unwrap(),
+        refs[1200..1300]
+    );
+
+    let log = table.log_records(Some("refs/heads/log-1")).unwrap();
+    assert_eq!(log.len(), 100);
+    assert_eq!(log[0], logs[298]);
+    assert_eq!(log[99], logs[1]);
+    assert_eq!(table.log_records(None).unwrap().len(), 300);
+    assert!(table
+        .log_records(Some("refs/heads/log"))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn refs_should_read_the_same_from_files_and_reftable() {
+    // Arrange
+    let files = GitRepository::create(generate_random_path()).unwrap();
+    let reftable =
+        GitRepository::create_with(generate_random_path(), RefStorage::Reftable).unwrap();
+    assert_eq!(reftable.ref_storage(), RefStorage::Reftable);
+    let reftable = GitRepository::load(&reftable.directory_manager.work_tree).unwrap();
+
+    // Act
+    let mut results = vec![];
+    for repo in [&files, &reftable] {
+        let tree = write_tree(repo, &[]);
+        let first = write_commit(repo, &tree, &[], 1);
+        let second = write_commit(repo, &tree, &[&first], 2);
+        repo.update_ref("refs/heads/master", &first).unwrap();
+        repo.update_head(&second, "commit: second").unwrap();
+        repo.update_ref("refs/heads/side", &first).unwrap();
+        repo.update_ref("refs/tags/v1", &first).unwrap();
+        repo.update_ref("refs/heads/gone", &first).unwrap();
+        repo.delete_ref("refs/heads/gone").unwrap();
+        let mut transaction = RefTransaction::new();
+        transaction
+            .create("refs/heads/side", &second)
+            .create("refs/heads/other", &second);
+        assert!(repo.commit_ref_transaction(&transaction).is_err());
+        for conflicting in ["refs/heads/side/x", "refs/tags/v1/x", "refs/heads"] {
+            assert!(matches!(
+                repo.update_ref(conflicting, &first),
+                Err(ResolveRefError::NameConflict(..))
+            ));
+        }
+        repo.update_ref("refs/heads/gone/x", &first).unwrap();
+        repo.delete_ref("refs/heads/gone/x").unwrap();
+        repo.set_symbolic_ref("refs/heads/alias", "refs/heads/side", "")
+            .unwrap();
+
+        let backend = repo.ref_backend();
+        results.push((
+            repo.resolve_ref("HEAD").unwrap(),
+            backend.resolve_ref("refs/heads/alias").unwrap(),
+            repo.resolve_ref("refs/heads/gone").is_err(),
+            names(&repo.list_refs().unwrap()),
+            names(&backend.list_refs("refs/tags").unwrap()),
+            repo.read_reflog("refs/heads/master")
+                .unwrap()
+                .iter()
+                .map(|entry| entry.message.clone())
+                .collect::<Vec<_>>(),
+            repo.reflog_names().unwrap(),
+        ));
+    }
+
+    // Assert
+    assert_eq!(results[0], results[1]);
+    let (head, alias, gone, refs, tags, log, reflogs) = &results[0];
+    assert_eq!(head, &refs[1].1);
+    assert_eq!(alias, &refs[2].1);
+    assert!(gone);
+    assert_eq!(tags.len(), 1);
+    assert_eq!(log, &["", "commit: second"]);
+    assert_eq!(reflogs[0], "HEAD");
+    assert!(!reftable
+        .directory_manager
+        .refs_heads_path
+        .join("master")
+        .exists());
+    let files_backend = FilesBackend {
+        directory_manager: &files.directory_manager,
+    };
+    let reftable_backend = ReftableBackend::new(&reftable.directory_manager);
+    assert_eq!(
+        names(&files_backend.list_refs("refs/heads").unwrap()),
+        names(&reftable_backend.list_refs("refs/heads").unwrap())
+    );
+}
+
+#[test]
+fn tables_should_be_compacted_as_they_pile_up() {
+    // Arrange
+    let repo = GitRepository::create_with(generate_random_path(), RefStorage::Reftable).unwrap();
+    let tree = write_tree(&repo, &[]);
+    let commits: Vec<String> = (0..3).map(|i| write_commit(&repo, &tree, &[], i)).collect();
+    let reftable = repo.directory_manager.common_dir.join("reftable");
+    let table_count = || {
+        std::fs::read_to_string(reftable.join("tables.list"))
+            .unwrap()
+            .lines()
+            .count()
+    };
+
+    // Act
+    for i in 0..200 {
+        repo.update_ref(&format!("refs/heads/b{}", i % 20), &commits[i % 3])
+            .unwrap();
+    }
+    let stacked = table_count();
+    repo.delete_ref("refs/heads/b0").unwrap();
+    repo.pack_refs(true, true).unwrap();
+
+    // Assert
+    assert!(stacked < 10, "{} tables", stacked);
+    assert_eq!(table_count(), 1);
+    assert_eq!(std::fs::read_dir(&reftable).unwrap().count(), 2);
+    let stack = Stack::open(&reftable).unwrap();
+    assert_eq!(stack.next_update_index(), 203);
+    assert_eq!(repo.list_refs().unwrap().len(), 19);
+    assert_eq!(
+        repo.resolve_ref("refs/heads/b19").unwrap(),
+        commits[199 % 3]
+    );
+    assert!(repo.read_reflog("refs/heads/b0").unwrap().is_empty());
+    let log = repo.read_reflog("refs/heads/b1").unwrap();
+    assert_eq!(log.len(), 10);
+    assert_eq!(log[0].new, commits[1]);
+    assert_eq!(log[9].old, commits[(181 - 20) % 3]);
+}
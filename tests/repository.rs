@@ -1,11 +1,13 @@
 mod test_utils;
 use std::{env::temp_dir, fs};
 
-use rit::{git_config::GitConfig, repository::GitRepository};
+use rit::{attributes::AttributeState, git_config::GitConfig, repository::GitRepository};
 use uuid::Uuid;
 
 use crate::test_utils::{
-    directory_manager::create_directory_manager, general::generate_random_path,
+    directory_manager::create_directory_manager,
+    general::generate_random_path,
+    objects::{write_commit, write_tree},
 };
 
 #[test]
@@ -105,3 +107,57 @@ fn if_none_of_the_parents_contains_a_dot_git_load_should_fail() {
 
     assert!(GitRepository::find(&path).is_err());
 }
+
+#[test]
+fn merge_bases_should_return_the_fork_point_of_two_branches() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let tree = write_tree(&repo, &[]);
+    let root = write_commit(&repo, &tree, &[], 1);
+    let fork = write_commit(&repo, &tree, &[&root], 2);
+    let main = write_commit(&repo, &tree, &[&fork], 3);
+    let topic = write_commit(&repo, &tree, &[&fork], 4);
+    let merge = write_commit(&repo, &tree, &[&main, &topic], 5);
+
+    // Act
+    let bases = repo.merge_bases(&main, &topic).unwrap();
+
+    // Assert
+    assert_eq!(bases, vec![fork.clone()]);
+    assert_eq!(
+        repo.merge_bases(&merge, &topic).unwrap(),
+        vec![topic.clone()]
+    );
+    assert!(repo.is_ancestor(&root, &merge).unwrap());
+    assert!(!repo.is_ancestor(&topic, &main).unwrap());
+}
+
+#[test]
+fn attributes_should_let_deeper_files_and_info_attributes_win() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    fs::write(
+        repo.work_tree_path(".gitattributes"),
+        "*.c text diff=cpp\n*.png binary\n",
+    )
+    .unwrap();
+    fs::create_dir_all(repo.work_tree_path("sub")).unwrap();
+    fs::write(repo.work_tree_path("sub/.gitattributes"), "*.c -text\n").unwrap();
+    let info = repo.directory_manager.dot_git_path.join("info");
+    fs::create_dir_all(&info).unwrap();
+    fs::write(info.join("attributes"), "sub/b.c text\n").unwrap();
+
+    // Act
+    let get = |path: &str, name: &str| repo.attributes(path).get(path, name);
+
+    // Assert
+    assert_eq!(get("a.c", "text"), AttributeState::Set);
+    assert_eq!(get("sub/a.c", "text"), AttributeState::Unset);
+    assert_eq!(
+        get("sub/a.c", "diff"),
+        AttributeState::Value("cpp".to_string())
+    );
+    assert_eq!(get("sub/b.c", "text"), AttributeState::Set);
+    assert_eq!(get("sub/a.png", "diff"), AttributeState::Unset);
+    assert_eq!(get("a.h", "text"), AttributeState::Unspecified);
+}
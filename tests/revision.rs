@@ -0,0 +1,152 @@
+mod test_utils;
+
+use std::collections::HashMap;
+
+use rit::{
+    error::RevisionError,
+    git_object::{Commit, KeyValueList, Type},
+    repository::GitRepository,
+    revision::ExpectedType,
+    GitObject,
+};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{write_blob, write_commit, write_object, write_tree},
+};
+
+/// Write a commit of `tree` on top of `parent` with `message`.
+fn write_commit_with_message(
+    repo: &GitRepository,
+    tree: &str,
+    parent: &str,
+    message: &str,
+) -> String {
+    let identity = "A U Thor <author@example.com> 10 +0000".to_string();
+    let entries = vec![
+        ("tree".to_string(), tree.to_string()),
+        ("parent".to_string(), parent.to_string()),
+        ("author".to_string(), identity.clone()),
+        ("committer".to_string(), identity),
+        ("message".to_string(), message.to_string()),
+    ];
+    write_object(
+        repo,
+        GitObject::Commit(Commit {
+            kvl: KeyValueList::from_entries(entries),
+        }),
+    )
+}
+
+#[test]
+fn resolve_revision_should_follow_history_trees_and_refs() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let blob = write_blob(&repo, "fn main() {}\n");
+    let src = write_tree(&repo, &[("100644", "lib.rs", &blob)]);
+    let tree = write_tree(&repo, &[("40000", "src", &src)]);
+    let first = write_commit(&repo, &tree, &[], 1);
+    let side = write_commit(&repo, &tree, &[&first], 2);
+    let second = write_commit(&repo, &tree, &[&first], 3);
+    let merge = write_commit(&repo, &tree, &[&second, &side], 4);
+    let fix = write_commit_with_message(&repo, &tree, &merge, "fix typo\n");
+    repo.update_head(&merge, "commit: merge").unwrap();
+    repo.update_head(&fix, "commit: fix typo").unwrap();
+    repo.update_ref("refs/remotes/origin/main", &first).unwrap();
+    repo.update_ref("refs/heads/main", &second).unwrap();
+    repo.set_upstream("master", "origin/main").unwrap();
+    repo.set_upstream("main", "master").unwrap();
+    let repo = GitRepository::load(&repo.directory_manager.work_tree).unwrap();
+    let resolve = |revision: &str| repo.resolve_revision(revision).unwrap();
+
+    // Act & Assert
+    assert_eq!(resolve("HEAD~1"), merge);
+    assert_eq!(resolve("@~3"), first);
+    assert_eq!(resolve("HEAD^^2"), side);
+    assert_eq!(resolve("master~1^1"), second);
+    assert_eq!(resolve("HEAD^{tree}"), tree);
+    assert_eq!(resolve("HEAD:src/lib.rs"), blob);
+    assert_eq!(resolve("HEAD~1:src"), src);
+    assert_eq!(resolve("master@{1}"), merge);
+    assert_eq!(resolve("@{0}"), fix);
+    assert_eq!(resolve("@{upstream}"), first);
+    assert_eq!(resolve("main@{u}~1"), merge);
+    assert_eq!(resolve(":/fix"), fix);
+    assert_eq!(resolve("refs/remotes/origin/main"), first);
+    assert_eq!(
+        repo.revision_ref_name("@{u}").unwrap().unwrap(),
+        "refs/remotes/origin/main"
+    );
+    assert_eq!(repo.revision_ref_name("HEAD~1").unwrap(), None);
+
+    assert!(matches!(
+        repo.resolve_revision("HEAD^3"),
+        Err(RevisionError::NoParent(_, 3))
+    ));
+    assert!(matches!(
+        repo.resolve_revision("HEAD:missing"),
+        Err(RevisionError::PathNotInTree(..))
+    ));
+    assert!(matches!(
+        repo.resolve_revision("master@{5}"),
+        Err(RevisionError::NoReflogEntry(_, 2))
+    ));
+    assert!(matches!(
+        repo.resolve_revision("nothing"),
+        Err(RevisionError::Unknown(_))
+    ));
+}
+
+#[test]
+fn resolve_revision_should_follow_ref_order_and_list_ambiguous_candidates() {
+    // Arrange
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let tree = write_tree(&repo, &[]);
+    let first = write_commit(&repo, &tree, &[], 1);
+    let second = write_commit(&repo, &tree, &[&first], 2);
+    repo.update_ref("refs/heads/release", &first).unwrap();
+    repo.update_ref("refs/tags/release", &second).unwrap();
+    repo.update_ref("refs/remotes/origin/HEAD", &first).unwrap();
+
+    // A commit and a blob whose hashes start with the same four digits.
+    let mut blobs = HashMap::new();
+    let (commit, blob) = (3..)
+        .find_map(|i| {
+            let blob = write_blob(&repo, &i.to_string());
+            blobs.insert(blob[..4].to_string(), blob);
+            let commit = write_commit(&repo, &tree, &[], i);
+            blobs
+                .get(&commit[..4])
+                .map(|blob| (commit.clone(), blob.clone()))
+        })
+        .unwrap();
+    let prefix = &commit[..4];
+
+    // Act & Assert
+    assert_eq!(repo.resolve_revision("release").unwrap(), second);
+    assert_eq!(repo.resolve_revision("heads/release").unwrap(), first);
+    assert_eq!(repo.resolve_revision("origin").unwrap(), first);
+    assert!(matches!(
+        repo.resolve_revision("config"),
+        Err(RevisionError::Unknown(_))
+    ));
+
+    match repo.resolve_revision(prefix) {
+        Err(RevisionError::Ambiguous(name, candidates)) => {
+            assert_eq!(name, prefix);
+            assert!(candidates.contains(&(commit.clone(), Type::Commit)));
+            assert!(candidates.contains(&(blob.clone(), Type::Blob)));
+        }
+        result => panic!("expected an ambiguity, got {:?}", result),
+    }
+    assert_eq!(
+        repo.resolve_revision(&format!("{}~0", prefix)).unwrap(),
+        commit
+    );
+    assert_eq!(
+        repo.resolve_revision_as(prefix, ExpectedType::Commit)
+            .unwrap(),
+        commit
+    );
+    assert_eq!(repo.read_commit(prefix).unwrap().0, commit);
+}
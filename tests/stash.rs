@@ -0,0 +1,97 @@
+mod test_utils;
+
+use rit::{diff::NULL_HASH, index::Index, repository::GitRepository};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{stage_file, write_blob, write_commit, write_tree},
+};
+
+/// A repository with an identity to commit with, whose `HEAD` has the single file `file`.
+fn create_repo_with_commit() -> (GitRepository, Index) {
+    let path = generate_random_path();
+    let repo = GitRepository::create(&path).unwrap();
+    let config = repo.directory_manager.dot_git_path.join("config");
+    let mut content = std::fs::read_to_string(&config).unwrap();
+    content.push_str("\n[user]\n\tname = A U Thor\n\temail = author@example.com\n");
+    std::fs::write(&config, content).unwrap();
+    let repo = GitRepository::load(&path).unwrap();
+
+    let mut index = Index::new();
+    stage_file(&repo, &mut index, "file", "1\n2\n3\n");
+    let blob = write_blob(&repo, "1\n2\n3\n");
+    let tree = write_tree(&repo, &[("100644", "file", &blob)]);
+    let commit = write_commit(&repo, &tree, &[], 1);
+    repo.update_head(&commit, "commit (initial): initial")
+        .unwrap();
+    (repo, index)
+}
+
+#[test]
+fn save_stash_then_apply_stash_should_bring_back_the_changes() {
+    // Arrange
+    let (repo, mut index) = create_repo_with_commit();
+    stage_file(&repo, &mut index, "added", "added\n");
+    std::fs::write(repo.work_tree_path("file"), "1\n2\n3\nlocal\n").unwrap();
+    std::fs::write(repo.work_tree_path("untracked"), "untracked\n").unwrap();
+
+    // Act
+    let message = repo.save_stash(&mut index, None, true).unwrap();
+
+    // Assert
+    assert!(message.unwrap().starts_with("WIP on master: "));
+    let read = |path: &str| std::fs::read_to_string(repo.work_tree_path(path)).ok();
+    assert_eq!(read("file").unwrap(), "1\n2\n3\n");
+    assert_eq!(read("added"), None);
+    assert_eq!(read("untracked"), None);
+    assert!(index.entry("added").is_none());
+    assert_eq!(repo.stash_list().unwrap().len(), 1);
+
+    // Act
+    let stash = repo.read_stash(None).unwrap();
+    let merge = repo.apply_stash(&mut index, &stash, false).unwrap();
+
+    // Assert
+    assert!(merge.is_clean());
+    assert!(stash.untracked.is_some());
+    assert_eq!(read("file").unwrap(), "1\n2\n3\nlocal\n");
+    assert_eq!(read("added").unwrap(), "added\n");
+    assert_eq!(read("untracked").unwrap(), "untracked\n");
+    assert!(index.entry("added").is_some());
+    assert_eq!(
+        index.entry("file").unwrap().hash,
+        write_blob(&repo, "1\n2\n3\n")
+    );
+}
+
+#[test]
+fn drop_stash_should_keep_the_stash_list_chained() {
+    // Arrange
+    let (repo, mut index) = create_repo_with_commit();
+    for (content, message) in [
+        ("one\n", "first"),
+        ("two\n", "second"),
+        ("three\n", "third"),
+    ] {
+        std::fs::write(repo.work_tree_path("file"), content).unwrap();
+        repo.save_stash(&mut index, Some(message), false).unwrap();
+    }
+
+    // Act
+    repo.drop_stash(2).unwrap();
+
+    // Assert
+    let entries = repo.stash_list().unwrap();
+    let messages: Vec<&str> = entries.iter().map(|entry| entry.message.as_str()).collect();
+    assert_eq!(messages, vec!["On master: third", "On master: second"]);
+    assert_eq!(entries[1].old, NULL_HASH);
+    assert_eq!(entries[0].old, entries[1].new);
+
+    // Act
+    repo.drop_stash(0).unwrap();
+    repo.drop_stash(0).unwrap();
+
+    // Assert
+    assert!(repo.stash_list().unwrap().is_empty());
+    assert!(repo.resolve_ref("refs/stash").is_err());
+}
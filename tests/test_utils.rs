@@ -1,4 +1,5 @@
 #[cfg(test)]
+#[allow(dead_code)]
 pub mod directory_manager {
     use rit::DirectoryManager;
 
@@ -16,3 +17,76 @@ pub mod general {
         std::env::temp_dir().join(uuid::Uuid::new_v4().to_string())
     }
 }
+
+#[cfg(test)]
+#[allow(dead_code)]
+pub mod objects {
+    use rit::{
+        git_object::{leaf::Leaf, Blob, Commit, KeyValueList, SerializedGitObject, Tree},
+        index::{Index, IndexEntry, StatData},
+        repository::GitRepository,
+        GitObject,
+    };
+
+    pub fn write_blob(repo: &GitRepository, content: &str) -> String {
+        write_object(
+            repo,
+            GitObject::Blob(Blob {
+                blob: content.as_bytes().to_vec(),
+            }),
+        )
+    }
+
+    /// Write a tree made of `(mode, name, hash)` entries.
+    pub fn write_tree(repo: &GitRepository, entries: &[(&str, &str, &str)]) -> String {
+        let leaves = entries
+            .iter()
+            .map(|(mode, name, hash)| {
+                Leaf::new(mode.as_bytes(), name.as_bytes(), hash.to_string()).unwrap()
+            })
+            .collect();
+        write_object(repo, GitObject::Tree(Tree::new(leaves)))
+    }
+
+    /// Write a commit of `tree` with the given parents, committed at `time`.
+    pub fn write_commit(repo: &GitRepository, tree: &str, parents: &[&str], time: u64) -> String {
+        let identity = format!("A U Thor <author@example.com> {} +0000", time);
+        let mut entries = vec![("tree".to_string(), tree.to_string())];
+        entries.extend(
+            parents
+                .iter()
+                .map(|parent| ("parent".to_string(), parent.to_string())),
+        );
+        entries.push(("author".to_string(), identity.clone()));
+        entries.push(("committer".to_string(), identity));
+        entries.push(("message".to_string(), "commit\n".to_string()));
+
+        write_object(
+            repo,
+            GitObject::Commit(Commit {
+                kvl: KeyValueList::from_entries(entries),
+            }),
+        )
+    }
+
+    /// Write `content` to the work tree at `path` and stage it.
+    pub fn stage_file(repo: &GitRepository, index: &mut Index, path: &str, content: &str) {
+        let full_path = repo.work_tree_path(path);
+        std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+        std::fs::write(&full_path, content).unwrap();
+        let metadata = std::fs::metadata(&full_path).unwrap();
+
+        index.add(IndexEntry::new(
+            path.to_string(),
+            rit::git_object::mode::Mode::new("100644".to_string()).unwrap(),
+            write_blob(repo, content),
+            StatData::from(&metadata),
+        ));
+    }
+
+    pub fn write_object(repo: &GitRepository, object: GitObject) -> String {
+        let serialized = SerializedGitObject::try_from(object).unwrap();
+        repo.write_object(&serialized).unwrap();
+        serialized.hash
+    }
+}
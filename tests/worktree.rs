@@ -0,0 +1,117 @@
+mod test_utils;
+
+use rit::{error::WorktreeError, repository::GitRepository};
+
+use crate::test_utils::{
+    general::generate_random_path,
+    objects::{write_blob, write_commit, write_tree},
+};
+
+/// A repository with one commit on `master`, holding `file` and `dir/nested`.
+fn create_repo_with_commit() -> (GitRepository, String) {
+    let repo = GitRepository::create(generate_random_path()).unwrap();
+    let file = write_blob(&repo, "file\n");
+    let nested = write_blob(&repo, "nested\n");
+    let dir = write_tree(&repo, &[("100644", "nested", &nested)]);
+    let tree = write_tree(&repo, &[("40000", "dir", &dir), ("100644", "file", &file)]);
+    let commit = write_commit(&repo, &tree, &[], 1);
+    repo.update_ref("refs/heads/master", &commit).unwrap();
+    (repo, commit)
+}
+
+#[test]
+fn add_worktree_should_share_refs_but_not_head_and_index() {
+    // Arrange
+    let (repo, commit) = create_repo_with_commit();
+    let path = generate_random_path();
+
+    // Act
+    let worktree = repo
+        .add_worktree(&path, Some("refs/heads/topic"), &commit)
+        .unwrap();
+    repo.update_ref("refs/heads/topic", &commit).unwrap();
+    let linked = GitRepository::find(&path.join("dir")).unwrap();
+
+    // Assert
+    assert!(linked.directory_manager.is_linked());
+    assert_eq!(worktree.path, path.canonicalize().unwrap());
+    assert_eq!(
+        std::fs::read_to_string(path.join("dir/nested")).unwrap(),
+        "nested\n"
+    );
+    assert_eq!(linked.head_branch().unwrap().unwrap(), "refs/heads/topic");
+    assert_eq!(repo.head_branch().unwrap().unwrap(), "refs/heads/master");
+    assert!(linked.directory_manager.index_file.is_file());
+    assert!(!repo.directory_manager.index_file.exists());
+
+    // Refs written in one work tree are seen in the other, except per work tree refs.
+    linked.update_ref("refs/heads/other", &commit).unwrap();
+    linked.update_ref("refs/worktree/mine", &commit).unwrap();
+    assert_eq!(repo.resolve_ref("refs/heads/other").unwrap(), commit);
+    assert!(repo.resolve_ref("refs/worktree/mine").is_err());
+
+    let worktrees = repo.worktrees().unwrap();
+    assert_eq!(worktrees.len(), 2);
+    assert!(worktrees[0].is_main());
+    assert_eq!(worktrees[1].path, worktree.path);
+    assert_eq!(worktrees[1].head.as_ref(), Some(&commit));
+    assert_eq!(
+        linked
+            .worktree_with_branch("refs/heads/master")
+            .unwrap()
+            .unwrap()
+            .path,
+        repo.directory_manager.work_tree.canonicalize().unwrap()
+    );
+    assert!(linked
+        .worktree_with_branch("refs/heads/topic")
+        .unwrap()
+        .is_none());
+    assert!(matches!(
+        repo.add_worktree(&generate_random_path(), Some("refs/heads/topic"), &commit),
+        Err(WorktreeError::AlreadyCheckedOut(..))
+    ));
+}
+
+#[test]
+fn worktrees_should_only_be_removed_or_pruned_when_clean_and_unlocked() {
+    // Arrange
+    let (repo, commit) = create_repo_with_commit();
+    let dirty = repo
+        .add_worktree(&generate_random_path(), None, &commit)
+        .unwrap();
+    let gone = repo
+        .add_worktree(&generate_random_path(), None, &commit)
+        .unwrap();
+    std::fs::write(dirty.path.join("untracked"), "untracked\n").unwrap();
+    std::fs::remove_dir_all(&gone.path).unwrap();
+
+    // Act & Assert
+    assert!(matches!(
+        repo.remove_worktree(&dirty, 0),
+        Err(WorktreeError::LocalChanges(_))
+    ));
+    repo.lock_worktree(&gone, Some("on usb")).unwrap();
+    let gone = repo
+        .find_worktree(gone.name.as_ref().unwrap().as_ref())
+        .unwrap();
+    assert_eq!(gone.locked.as_deref(), Some("on usb"));
+    assert!(gone.prunable.is_some());
+    assert!(repo.prune_worktrees(false).unwrap().is_empty());
+    assert!(matches!(
+        repo.remove_worktree(&gone, 1),
+        Err(WorktreeError::Locked(Some(_)))
+    ));
+
+    repo.unlock_worktree(&gone).unwrap();
+    assert_eq!(repo.prune_worktrees(true).unwrap().len(), 1);
+    assert_eq!(repo.worktrees().unwrap().len(), 3);
+    assert_eq!(repo.prune_worktrees(false).unwrap().len(), 1);
+    repo.remove_worktree(&dirty, 1).unwrap();
+    assert!(!dirty.path.exists());
+    assert_eq!(repo.worktrees().unwrap().len(), 1);
+    assert!(matches!(
+        repo.remove_worktree(&repo.worktrees().unwrap()[0], 2),
+        Err(WorktreeError::MainWorktree(_))
+    ));
+}